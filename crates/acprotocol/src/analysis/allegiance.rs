@@ -0,0 +1,565 @@
+//! Allegiance hierarchies
+//!
+//! Merges the `AllegianceHierarchy` carried by `Allegiance_AllegianceUpdate`
+//! and `Allegiance_AllegianceInfoResponseEvent` into a single tree of
+//! patrons and vassals. The server only ever sends part of the hierarchy (the
+//! monarch, the chain up from the player and the player's vassals), so the
+//! tree holds the most recent record seen for each character.
+//!
+//! Info responses can describe anyone's allegiance, so only those whose
+//! monarch is the player's own are merged; the rest are listed among the
+//! updates but left out of the tree.
+//!
+//! Each record carries the XP a vassal has cached for and tithed to their
+//! patron. Changes to those between updates are recorded as passup changes,
+//! which is what you want when checking how an emulator passes XP up.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use serde::Serialize;
+
+use crate::enums::{AllegianceOfficerLevel, Gender, HeritageGroup};
+use crate::gameevents::{AllegianceAllegianceInfoResponseEvent, AllegianceAllegianceUpdate};
+use crate::message::{GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+use crate::types::{AllegianceData, AllegianceProfile};
+
+use super::{game_event, parse_all};
+
+/// A character in an allegiance, as of the last record seen for it
+#[derive(Clone, Debug, Serialize)]
+pub struct AllegianceMember {
+    pub character_id: u32,
+    pub name: String,
+    /// Patron, or `None` for a monarch
+    pub patron_id: Option<u32>,
+    pub rank: u16,
+    pub level: Option<u32>,
+    pub gender: Gender,
+    pub heritage: HeritageGroup,
+    pub loyalty: u16,
+    pub leadership: u16,
+    /// XP earned for the patron but not yet passed up
+    pub xp_cached: u32,
+    /// Total XP passed up to the patron
+    pub xp_tithed: u32,
+    pub officer: Option<AllegianceOfficerLevel>,
+    /// Time of the first and last update this character appeared in
+    pub first_seen: f64,
+    pub last_seen: f64,
+}
+
+impl AllegianceMember {
+    fn new(time: f64, patron_id: Option<u32>, data: &AllegianceData) -> Self {
+        Self {
+            character_id: data.character_id.0,
+            name: data.name.clone(),
+            patron_id,
+            rank: data.rank,
+            level: data.level,
+            gender: data.gender.clone(),
+            heritage: data.heritage.clone(),
+            loyalty: data.loyalty,
+            leadership: data.leadership,
+            xp_cached: data.xp_cached,
+            xp_tithed: data.xp_tithed,
+            officer: None,
+            first_seen: time,
+            last_seen: time,
+        }
+    }
+}
+
+/// A change in the XP a character has cached for or tithed to their patron
+#[derive(Clone, Debug, Serialize)]
+pub struct PassupChange {
+    pub time: f64,
+    pub character_id: u32,
+    pub name: String,
+    pub patron_id: Option<u32>,
+    pub xp_cached_delta: i64,
+    pub xp_tithed_delta: i64,
+}
+
+/// Where an allegiance update came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum UpdateSource {
+    /// `Allegiance_AllegianceUpdate` about the player's own allegiance
+    Update,
+    /// `Allegiance_AllegianceInfoResponseEvent` about another character, who
+    /// may be in a different allegiance
+    InfoResponse,
+}
+
+/// A summary of one allegiance update
+#[derive(Clone, Debug, Serialize)]
+pub struct AllegianceUpdate {
+    pub time: f64,
+    pub source: UpdateSource,
+    /// Character the update describes
+    pub character_id: u32,
+    /// Rank of that character, only sent with `Allegiance_AllegianceUpdate`
+    pub rank: Option<u32>,
+    pub allegiance_name: String,
+    pub monarch_id: Option<u32>,
+    pub total_members: u32,
+    pub total_vassals: u32,
+    /// Characters included in the update's hierarchy
+    pub records: Vec<u32>,
+    /// Officers named in the update's hierarchy
+    pub officers: BTreeMap<u32, AllegianceOfficerLevel>,
+    /// Whether the records were merged into the tree, which is not the case
+    /// for info responses about other allegiances or sent before the first
+    /// `Allegiance_AllegianceUpdate`
+    pub merged: bool,
+}
+
+/// Builds an allegiance tree from messages fed to it in capture order
+#[derive(Default)]
+pub struct AllegianceTracker {
+    members: BTreeMap<u32, AllegianceMember>,
+    updates: Vec<AllegianceUpdate>,
+    passup: Vec<PassupChange>,
+    allegiance_name: Option<String>,
+    monarch_id: Option<u32>,
+}
+
+impl AllegianceTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        let Some((object_id, event)) = game_event(message) else {
+            return;
+        };
+
+        match event {
+            GameEventMessage::AllegianceAllegianceUpdate(AllegianceAllegianceUpdate {
+                rank,
+                profile,
+            }) => {
+                self.merge(time, UpdateSource::Update, object_id, Some(*rank), profile);
+            }
+            GameEventMessage::AllegianceAllegianceInfoResponseEvent(
+                AllegianceAllegianceInfoResponseEvent { target_id, profile },
+            ) => {
+                self.merge(time, UpdateSource::InfoResponse, target_id.0, None, profile);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn finish(self) -> AllegianceTree {
+        AllegianceTree {
+            allegiance_name: self.allegiance_name,
+            monarch_id: self.monarch_id,
+            members: self.members.into_values().collect(),
+            updates: self.updates,
+            passup: self.passup,
+        }
+    }
+
+    fn merge(
+        &mut self,
+        time: f64,
+        source: UpdateSource,
+        character_id: u32,
+        rank: Option<u32>,
+        profile: &AllegianceProfile,
+    ) {
+        let hierarchy = &profile.hierarchy;
+        let monarch_id = hierarchy.monarch_data.as_ref().map(|m| m.character_id.0);
+        let officers: BTreeMap<u32, AllegianceOfficerLevel> = hierarchy
+            .officers
+            .table
+            .iter()
+            .map(|(id, level)| (id.0, level.clone()))
+            .collect();
+
+        if source == UpdateSource::Update && monarch_id.is_some() {
+            self.allegiance_name = Some(hierarchy.allegiance_name.clone());
+            self.monarch_id = monarch_id;
+        }
+        let merged = match source {
+            UpdateSource::Update => true,
+            UpdateSource::InfoResponse => monarch_id.is_some() && monarch_id == self.monarch_id,
+        };
+
+        let mut records = Vec::new();
+        if let Some(monarch) = &hierarchy.monarch_data {
+            if merged {
+                self.record(time, None, monarch, &officers);
+            }
+            records.push(monarch.character_id.0);
+        }
+        for record in &hierarchy.records {
+            if merged {
+                self.record(
+                    time,
+                    Some(record.tree_parent.0),
+                    &record.allegiance_data,
+                    &officers,
+                );
+            }
+            records.push(record.allegiance_data.character_id.0);
+        }
+
+        self.updates.push(AllegianceUpdate {
+            time,
+            source,
+            character_id,
+            rank,
+            allegiance_name: hierarchy.allegiance_name.clone(),
+            monarch_id,
+            total_members: profile.total_members,
+            total_vassals: profile.total_vassals,
+            records,
+            officers,
+            merged,
+        });
+    }
+
+    /// Replace a member's record, taking their officer level from the same
+    /// update so a member no longer listed as an officer loses the title
+    fn record(
+        &mut self,
+        time: f64,
+        patron_id: Option<u32>,
+        data: &AllegianceData,
+        officers: &BTreeMap<u32, AllegianceOfficerLevel>,
+    ) {
+        let mut updated = AllegianceMember::new(time, patron_id, data);
+        updated.officer = officers.get(&updated.character_id).cloned();
+
+        let Some(member) = self.members.get_mut(&data.character_id.0) else {
+            self.members.insert(updated.character_id, updated);
+            return;
+        };
+
+        let xp_cached_delta = i64::from(updated.xp_cached) - i64::from(member.xp_cached);
+        let xp_tithed_delta = i64::from(updated.xp_tithed) - i64::from(member.xp_tithed);
+        if xp_cached_delta != 0 || xp_tithed_delta != 0 {
+            self.passup.push(PassupChange {
+                time,
+                character_id: updated.character_id,
+                name: updated.name.clone(),
+                patron_id: updated.patron_id,
+                xp_cached_delta,
+                xp_tithed_delta,
+            });
+        }
+
+        *member = AllegianceMember {
+            first_seen: member.first_seen,
+            ..updated
+        };
+    }
+}
+
+/// Every allegiance member seen in a capture, linked by patron
+#[derive(Clone, Debug, Serialize)]
+pub struct AllegianceTree {
+    /// Name of the player's allegiance, from the last update about it
+    pub allegiance_name: Option<String>,
+    pub monarch_id: Option<u32>,
+    pub members: Vec<AllegianceMember>,
+    pub updates: Vec<AllegianceUpdate>,
+    pub passup: Vec<PassupChange>,
+}
+
+impl AllegianceTree {
+    /// Build the tree from the allegiance updates in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut tracker = AllegianceTracker::new();
+        for (raw, message) in parse_all(messages) {
+            tracker.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        tracker.finish()
+    }
+
+    pub fn member(&self, character_id: u32) -> Option<&AllegianceMember> {
+        self.members.iter().find(|m| m.character_id == character_id)
+    }
+
+    /// Direct vassals of a character
+    pub fn vassals(&self, patron_id: u32) -> impl Iterator<Item = &AllegianceMember> {
+        self.members
+            .iter()
+            .filter(move |m| m.patron_id == Some(patron_id))
+    }
+
+    /// Render the tree as a GraphViz digraph, with edges from patron to vassal
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph allegiance {\n");
+        if let Some(name) = self.allegiance_name.as_deref().filter(|n| !n.is_empty()) {
+            let _ = writeln!(dot, "    label=\"{}\";", dot_escape(name));
+        }
+        dot.push_str("    node [shape=box];\n");
+
+        for member in &self.members {
+            let mut label = format!("{}\\nrank {}", dot_escape(&member.name), member.rank);
+            if let Some(level) = member.level {
+                let _ = write!(label, ", level {level}");
+            }
+            let _ = write!(
+                label,
+                "\\ncached {} / tithed {}",
+                member.xp_cached, member.xp_tithed
+            );
+            if let Some(officer) = &member.officer {
+                let _ = write!(label, "\\n{officer:?}");
+            }
+            let _ = writeln!(
+                dot,
+                "    \"{:#010x}\" [label=\"{label}\"];",
+                member.character_id
+            );
+        }
+
+        for member in &self.members {
+            if let Some(patron_id) = member.patron_id {
+                let _ = writeln!(
+                    dot,
+                    "    \"{patron_id:#010x}\" -> \"{:#010x}\";",
+                    member.character_id
+                );
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::event_to;
+    use crate::types::{
+        AllegianceHierarchy, AllegianceRecord, Frame, LandcellId, ObjectId, PHashTable,
+        PackableList, Position, Quaternion, Vector3,
+    };
+    use std::collections::HashMap;
+
+    const MONARCH: u32 = 0x50000001;
+    const PATRON: u32 = 0x50000002;
+    const PLAYER: u32 = 0x50000003;
+
+    fn data(id: u32, name: &str, xp_tithed: u32) -> AllegianceData {
+        AllegianceData {
+            character_id: ObjectId(id),
+            xp_cached: 0,
+            xp_tithed,
+            flags: 0,
+            gender: Gender::Female,
+            heritage: HeritageGroup::Aluvian,
+            rank: 1,
+            level: Some(50),
+            loyalty: 10,
+            leadership: 10,
+            allegiance_age: None,
+            time_online: None,
+            name: name.to_string(),
+        }
+    }
+
+    fn update(player_tithed: u32) -> MessageKind {
+        update_with_officers(player_tithed, &[(PATRON, AllegianceOfficerLevel::Speaker)])
+    }
+
+    fn update_with_officers(
+        player_tithed: u32,
+        officers: &[(u32, AllegianceOfficerLevel)],
+    ) -> MessageKind {
+        event_to(
+            PLAYER,
+            GameEventMessage::AllegianceAllegianceUpdate(AllegianceAllegianceUpdate {
+                rank: 1,
+                profile: profile(MONARCH, player_tithed, officers),
+            }),
+        )
+    }
+
+    /// An info response about the patron, in an allegiance under `monarch`
+    fn info_response(monarch: u32, player_tithed: u32) -> MessageKind {
+        event_to(
+            PLAYER,
+            GameEventMessage::AllegianceAllegianceInfoResponseEvent(
+                AllegianceAllegianceInfoResponseEvent {
+                    target_id: ObjectId(PATRON),
+                    profile: profile(monarch, player_tithed, &[]),
+                },
+            ),
+        )
+    }
+
+    /// A monarch, the patron and the player, who tithes to the patron
+    fn profile(
+        monarch: u32,
+        player_tithed: u32,
+        officers: &[(u32, AllegianceOfficerLevel)],
+    ) -> AllegianceProfile {
+        let hierarchy = AllegianceHierarchy {
+            record_count: 3,
+            old_version: 0,
+            officers: PHashTable {
+                packed_size: officers.len() as u32,
+                table: officers
+                    .iter()
+                    .map(|(id, level)| (ObjectId(*id), level.clone()))
+                    .collect::<HashMap<_, _>>(),
+            },
+            officer_titles: PackableList {
+                count: 0,
+                list: Vec::new(),
+            },
+            monarch_broadcast_time: 0,
+            monarch_broadcasts_today: 0,
+            spokes_broadcast_time: 0,
+            spokes_broadcasts_today: 0,
+            motd: String::new(),
+            motd_set_by: String::new(),
+            chat_room_id: 0,
+            bindpoint: Position {
+                landcell: LandcellId(0),
+                frame: Frame {
+                    origin: Vector3 {
+                        x: 0.0,
+                        y: 0.0,
+                        z: 0.0,
+                    },
+                    orientation: Quaternion {
+                        w: 1.0,
+                        x: 0.0,
+                        y: 0.0,
+                        z: 0.0,
+                    },
+                },
+            },
+            allegiance_name: "Order of \"Dereth\"".to_string(),
+            name_last_set_time: 0,
+            is_locked: false,
+            approved_vassal: 0,
+            monarch_data: Some(data(monarch, "Monarch", 0)),
+            records: vec![
+                AllegianceRecord {
+                    tree_parent: ObjectId(monarch),
+                    allegiance_data: data(PATRON, "Patron", 500),
+                },
+                AllegianceRecord {
+                    tree_parent: ObjectId(PATRON),
+                    allegiance_data: data(PLAYER, "Player", player_tithed),
+                },
+            ],
+        };
+
+        AllegianceProfile {
+            total_members: 3,
+            total_vassals: 0,
+            hierarchy,
+        }
+    }
+
+    #[test]
+    fn test_builds_tree_from_hierarchy() {
+        let mut tracker = AllegianceTracker::new();
+        tracker.process(10.0, &update(100));
+        let tree = tracker.finish();
+
+        assert_eq!(tree.monarch_id, Some(MONARCH));
+        assert_eq!(tree.members.len(), 3);
+        assert_eq!(tree.member(MONARCH).unwrap().patron_id, None);
+        assert_eq!(tree.member(PLAYER).unwrap().patron_id, Some(PATRON));
+        assert_eq!(
+            tree.member(PATRON).unwrap().officer,
+            Some(AllegianceOfficerLevel::Speaker)
+        );
+        assert_eq!(
+            tree.vassals(MONARCH)
+                .map(|m| m.character_id)
+                .collect::<Vec<_>>(),
+            vec![PATRON]
+        );
+    }
+
+    #[test]
+    fn test_records_passup_between_updates() {
+        let mut tracker = AllegianceTracker::new();
+        tracker.process(10.0, &update(100));
+        tracker.process(20.0, &update(350));
+        let tree = tracker.finish();
+
+        assert_eq!(tree.updates.len(), 2);
+        assert_eq!(tree.passup.len(), 1);
+        assert_eq!(tree.passup[0].character_id, PLAYER);
+        assert_eq!(tree.passup[0].xp_tithed_delta, 250);
+
+        let player = tree.member(PLAYER).unwrap();
+        assert_eq!(player.first_seen, 10.0);
+        assert_eq!(player.last_seen, 20.0);
+    }
+
+    #[test]
+    fn test_officer_changes_between_updates() {
+        let mut tracker = AllegianceTracker::new();
+        tracker.process(10.0, &update(100));
+        tracker.process(
+            20.0,
+            &update_with_officers(100, &[(PLAYER, AllegianceOfficerLevel::Seneschal)]),
+        );
+        let tree = tracker.finish();
+
+        // The patron is demoted and the player promoted
+        assert_eq!(tree.member(PATRON).unwrap().officer, None);
+        assert_eq!(
+            tree.member(PLAYER).unwrap().officer,
+            Some(AllegianceOfficerLevel::Seneschal)
+        );
+        assert!(tree.updates[0].officers.contains_key(&PATRON));
+        assert!(!tree.updates[1].officers.contains_key(&PATRON));
+        assert!(!tree.to_dot().contains("Speaker"));
+    }
+
+    #[test]
+    fn test_info_responses_about_other_allegiances_kept_apart() {
+        const OTHER_MONARCH: u32 = 0x50000009;
+
+        let mut tracker = AllegianceTracker::new();
+        // Nothing to compare against before the player's own update
+        tracker.process(5.0, &info_response(MONARCH, 50));
+        tracker.process(10.0, &update(100));
+        tracker.process(20.0, &info_response(OTHER_MONARCH, 900));
+        tracker.process(30.0, &info_response(MONARCH, 350));
+        let tree = tracker.finish();
+
+        assert_eq!(
+            tree.updates.iter().map(|u| u.merged).collect::<Vec<_>>(),
+            vec![false, true, false, true]
+        );
+        assert_eq!(tree.monarch_id, Some(MONARCH));
+        assert!(tree.member(OTHER_MONARCH).is_none());
+        assert_eq!(tree.member(PATRON).unwrap().patron_id, Some(MONARCH));
+        assert_eq!(tree.member(PLAYER).unwrap().first_seen, 10.0);
+        assert_eq!(tree.passup.len(), 1);
+        assert_eq!(tree.passup[0].xp_tithed_delta, 250);
+    }
+
+    #[test]
+    fn test_dot_export() {
+        let mut tracker = AllegianceTracker::new();
+        tracker.process(10.0, &update(100));
+        let dot = tracker.finish().to_dot();
+
+        assert!(dot.starts_with("digraph allegiance {"));
+        assert!(dot.contains("label=\"Order of \\\"Dereth\\\"\";"));
+        assert!(dot.contains("\"0x50000001\" -> \"0x50000002\";"));
+        assert!(dot.contains("\"0x50000002\" -> \"0x50000003\";"));
+    }
+}
@@ -0,0 +1,644 @@
+//! Book and inscription text
+//!
+//! Books arrive whole in `Writing_BookOpen` and a page at a time in
+//! `Writing_BookPageDataResponse`. Pages the client adds, changes or deletes
+//! are followed through its `Writing_Book*` actions and the server's
+//! responses. Inscriptions come from `Writing_SetInscription` when the client
+//! writes one and from `Item_GetInscriptionResponse` when it reads one; the
+//! response does not name the item, so it is credited to the item the client
+//! last appraised.
+//!
+//! Feeding the archive several captures merges what each of them saw about
+//! the same book or item.
+
+use std::fmt::Write as _;
+
+use serde::Serialize;
+
+use crate::message::{GameActionMessage, GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+use crate::types::PageData;
+
+use super::objects::ObjectIndex;
+use super::{game_action, game_event, parse_all};
+
+/// One page of a book
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BookPage {
+    pub author_id: u32,
+    pub author_name: String,
+    pub author_account: String,
+    pub version: u32,
+    /// Whether the book hides who wrote the page
+    pub ignore_author: bool,
+    /// Page text, if the server has sent it
+    pub text: Option<String>,
+}
+
+impl BookPage {
+    fn from_data(data: &PageData) -> Self {
+        Self {
+            author_id: data.author_id.0,
+            author_name: data.author_name.clone(),
+            author_account: data.author_account.clone(),
+            version: data.version,
+            ignore_author: data.ignore_author,
+            text: data.page_text.clone(),
+        }
+    }
+}
+
+/// Who last changed a book
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Editor {
+    pub id: u32,
+    pub name: String,
+}
+
+/// A book and its pages
+#[derive(Clone, Debug, Serialize)]
+pub struct Book {
+    pub object_id: u32,
+    pub title: Option<String>,
+    /// Character who inscribed the book
+    pub author_id: Option<u32>,
+    pub author: Option<String>,
+    pub inscription: Option<String>,
+    pub max_pages: Option<u32>,
+    pub max_chars_per_page: Option<u32>,
+    /// Pages in order
+    pub pages: Vec<BookPage>,
+    pub last_editor: Option<Editor>,
+    pub first_seen: f64,
+    pub last_seen: f64,
+}
+
+impl Book {
+    fn new(object_id: u32, time: f64) -> Self {
+        Self {
+            object_id,
+            title: None,
+            author_id: None,
+            author: None,
+            inscription: None,
+            max_pages: None,
+            max_chars_per_page: None,
+            pages: Vec::new(),
+            last_editor: None,
+            first_seen: time,
+            last_seen: time,
+        }
+    }
+
+    /// Store a page, keeping the text already known if the new copy leaves it out
+    fn set_page(&mut self, index: usize, mut page: BookPage) {
+        if self.pages.len() <= index {
+            self.pages.resize_with(index + 1, BookPage::default);
+        }
+        let existing = &mut self.pages[index];
+        if page.text.is_none() && page.version == existing.version {
+            page.text = existing.text.take();
+        }
+        let changed = page.version > existing.version || page.text != existing.text;
+        if changed && !page.author_name.is_empty() && existing.author_id != 0 {
+            self.last_editor = Some(Editor {
+                id: page.author_id,
+                name: page.author_name.clone(),
+            });
+        }
+        self.pages[index] = page;
+    }
+
+    /// The editor recorded during the capture, or else the author of the newest page
+    fn resolve_last_editor(&mut self) {
+        if self.last_editor.is_some() {
+            return;
+        }
+        self.last_editor = self
+            .pages
+            .iter()
+            .filter(|page| !page.author_name.is_empty())
+            .max_by_key(|page| page.version)
+            .map(|page| Editor {
+                id: page.author_id,
+                name: page.author_name.clone(),
+            });
+    }
+
+    fn merge(&mut self, other: Book) {
+        self.title = other.title.or(self.title.take());
+        self.author_id = other.author_id.or(self.author_id);
+        self.author = other.author.or(self.author.take());
+        self.inscription = other.inscription.or(self.inscription.take());
+        self.max_pages = other.max_pages.or(self.max_pages);
+        self.max_chars_per_page = other.max_chars_per_page.or(self.max_chars_per_page);
+        self.first_seen = self.first_seen.min(other.first_seen);
+        if other.last_seen >= self.last_seen {
+            self.last_seen = other.last_seen;
+            if !other.pages.is_empty() {
+                self.pages = other.pages;
+            }
+            self.last_editor = other.last_editor.or(self.last_editor.take());
+        }
+    }
+}
+
+/// Text inscribed on an item
+#[derive(Clone, Debug, Serialize)]
+pub struct Inscription {
+    pub object_id: u32,
+    pub item_name: Option<String>,
+    pub text: String,
+    pub scribe_name: Option<String>,
+    pub scribe_account: Option<String>,
+    pub time: f64,
+}
+
+/// Books and inscriptions collected from one or more captures
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct BookArchive {
+    books: Vec<Book>,
+    inscriptions: Vec<Inscription>,
+    #[serde(skip)]
+    objects: ObjectIndex,
+    /// Character who most recently received a game event, taken to be the writer of client actions
+    #[serde(skip)]
+    observer: Option<u32>,
+    /// Item the client last appraised, which inscription responses belong to
+    #[serde(skip)]
+    appraised: Option<u32>,
+}
+
+impl BookArchive {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build an archive from the books and inscriptions in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut archive = Self::new();
+        archive.add_messages(messages);
+        archive
+    }
+
+    /// Add the books and inscriptions seen in another capture
+    pub fn add_messages(&mut self, messages: &[RawMessage]) {
+        for (raw, message) in parse_all(messages) {
+            self.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        self.resolve();
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.objects.process(message);
+
+        if let Some(action) = game_action(message) {
+            self.process_action(time, action);
+        }
+
+        let Some((object_id, event)) = game_event(message) else {
+            return;
+        };
+        self.observer = Some(object_id);
+
+        match event {
+            GameEventMessage::WritingBookOpen(msg) => {
+                let book = self.book(msg.book_id.0, time);
+                book.max_pages = Some(msg.page_data.max_num_pages);
+                book.max_chars_per_page = Some(msg.page_data.max_num_chars_per_page);
+                if !msg.inscription.is_empty() {
+                    book.inscription = Some(msg.inscription.clone());
+                }
+                if msg.scribe_id.0 != 0 {
+                    book.author_id = Some(msg.scribe_id.0);
+                    book.author = Some(msg.scribe_name.clone());
+                }
+                for (index, data) in msg.page_data.pages.list.iter().enumerate() {
+                    book.set_page(index, BookPage::from_data(data));
+                }
+                book.pages.truncate(msg.page_data.pages.list.len());
+            }
+            GameEventMessage::WritingBookPageDataResponse(msg) => {
+                self.book(msg.object_id.0, time)
+                    .set_page(msg.page as usize, BookPage::from_data(&msg.page_data));
+            }
+            GameEventMessage::WritingBookAddPageResponse(msg) if msg.success => {
+                let author = self.writer();
+                let book = self.book(msg.book_id.0, time);
+                let index = (msg.page_number as usize).min(book.pages.len());
+                book.pages.insert(
+                    index,
+                    BookPage {
+                        author_id: author.id,
+                        author_name: author.name,
+                        ..BookPage::default()
+                    },
+                );
+            }
+            GameEventMessage::WritingBookDeletePageResponse(msg) if msg.success => {
+                let book = self.book(msg.book_id.0, time);
+                let index = msg.page_number as usize;
+                if index < book.pages.len() {
+                    book.pages.remove(index);
+                }
+            }
+            GameEventMessage::ItemGetInscriptionResponse(msg) => {
+                if let Some(item_id) = self.appraised
+                    && !msg.inscription.is_empty()
+                {
+                    self.inscribe(
+                        item_id,
+                        time,
+                        msg.inscription.clone(),
+                        Some(msg.scribe_name.clone()),
+                        Some(msg.scribe_account.clone()),
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Merge another archive into this one, deduplicating by object id
+    pub fn merge(&mut self, other: BookArchive) {
+        for book in other.books {
+            match self
+                .books
+                .iter_mut()
+                .find(|b| b.object_id == book.object_id)
+            {
+                Some(existing) => existing.merge(book),
+                None => self.books.push(book),
+            }
+        }
+        for inscription in other.inscriptions {
+            match self
+                .inscriptions
+                .iter_mut()
+                .find(|i| i.object_id == inscription.object_id)
+            {
+                Some(existing) if existing.time > inscription.time => {}
+                Some(existing) => *existing = inscription,
+                None => self.inscriptions.push(inscription),
+            }
+        }
+        self.sort();
+    }
+
+    pub fn get(&self, object_id: u32) -> Option<&Book> {
+        self.books.iter().find(|book| book.object_id == object_id)
+    }
+
+    /// Books in object id order
+    pub fn books(&self) -> &[Book] {
+        &self.books
+    }
+
+    /// Inscriptions in object id order
+    pub fn inscriptions(&self) -> &[Inscription] {
+        &self.inscriptions
+    }
+
+    /// The archive as a Markdown document, one section per book and inscribed item
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+
+        for book in &self.books {
+            let title = book.title.as_deref().unwrap_or("Untitled");
+            let _ = writeln!(md, "# {title}\n");
+            let _ = writeln!(md, "*Book {:#010x}*\n", book.object_id);
+            if let Some(author) = &book.author {
+                let _ = writeln!(md, "**Author:** {author}  ");
+            }
+            if let Some(editor) = &book.last_editor {
+                let _ = writeln!(md, "**Last edited by:** {}  ", editor.name);
+            }
+            if let Some(inscription) = &book.inscription {
+                let _ = writeln!(md, "\n{}", quote(inscription));
+            }
+            md.push('\n');
+
+            for (index, page) in book.pages.iter().enumerate() {
+                let _ = writeln!(md, "## Page {}\n", index + 1);
+                if !page.ignore_author && !page.author_name.is_empty() {
+                    let _ = writeln!(md, "*{}*\n", page.author_name);
+                }
+                match &page.text {
+                    Some(text) => {
+                        let _ = writeln!(md, "{}\n", text.trim_end());
+                    }
+                    None => md.push_str("*(text not captured)*\n\n"),
+                }
+            }
+        }
+
+        if !self.inscriptions.is_empty() {
+            md.push_str("# Inscriptions\n\n");
+            for inscription in &self.inscriptions {
+                let name = inscription.item_name.as_deref().unwrap_or("Unknown item");
+                let _ = writeln!(md, "## {name} ({:#010x})\n", inscription.object_id);
+                let _ = writeln!(md, "{}", quote(&inscription.text));
+                if let Some(scribe) = &inscription.scribe_name {
+                    let _ = writeln!(md, ">\n> — {scribe}");
+                }
+                md.push('\n');
+            }
+        }
+
+        md
+    }
+
+    fn process_action(&mut self, time: f64, action: &GameActionMessage) {
+        match action {
+            GameActionMessage::ItemAppraise(msg) => self.appraised = Some(msg.object_id.0),
+            GameActionMessage::WritingBookData(msg) => {
+                self.book(msg.object_id.0, time);
+            }
+            GameActionMessage::WritingBookModifyPage(msg) => {
+                // The server does not answer page edits, so they are taken as made
+                let Ok(index) = usize::try_from(msg.page_num) else {
+                    return;
+                };
+                let author = self.writer();
+                let book = self.book(msg.object_id.0, time);
+                let version = book.pages.get(index).map_or(0, |page| page.version + 1);
+                book.set_page(
+                    index,
+                    BookPage {
+                        author_id: author.id,
+                        author_name: author.name.clone(),
+                        version,
+                        text: Some(msg.page_text.clone()),
+                        ..BookPage::default()
+                    },
+                );
+                book.last_editor = Some(author);
+            }
+            GameActionMessage::WritingSetInscription(msg) => {
+                let author = self.writer();
+                self.inscribe(
+                    msg.object_id.0,
+                    time,
+                    msg.inscription.clone(),
+                    Some(author.name),
+                    None,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    /// The character writing through the client's actions
+    fn writer(&self) -> Editor {
+        let id = self.observer.unwrap_or_default();
+        Editor {
+            id,
+            name: self.objects.name(id).unwrap_or_default().to_string(),
+        }
+    }
+
+    fn book(&mut self, object_id: u32, time: f64) -> &mut Book {
+        let index = match self.books.iter().position(|b| b.object_id == object_id) {
+            Some(index) => index,
+            None => {
+                self.books.push(Book::new(object_id, time));
+                self.books.len() - 1
+            }
+        };
+        let book = &mut self.books[index];
+        book.last_seen = time;
+        book
+    }
+
+    fn inscribe(
+        &mut self,
+        object_id: u32,
+        time: f64,
+        text: String,
+        scribe_name: Option<String>,
+        scribe_account: Option<String>,
+    ) {
+        let inscription = Inscription {
+            object_id,
+            item_name: None,
+            text,
+            scribe_name: scribe_name.filter(|name| !name.is_empty()),
+            scribe_account: scribe_account.filter(|account| !account.is_empty()),
+            time,
+        };
+        match self
+            .inscriptions
+            .iter_mut()
+            .find(|i| i.object_id == object_id)
+        {
+            Some(existing) => *existing = inscription,
+            None => self.inscriptions.push(inscription),
+        }
+    }
+
+    /// Fill in names from object descriptions and put everything in id order
+    fn resolve(&mut self) {
+        for book in &mut self.books {
+            if let Some(name) = self.objects.name(book.object_id) {
+                book.title = Some(name.to_string());
+            }
+            book.resolve_last_editor();
+        }
+        for inscription in &mut self.inscriptions {
+            if let Some(name) = self.objects.name(inscription.object_id) {
+                inscription.item_name = Some(name.to_string());
+            }
+        }
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        self.books.sort_by_key(|book| book.object_id);
+        self.inscriptions.sort_by_key(|i| i.object_id);
+    }
+}
+
+/// Text as a Markdown block quote
+fn quote(text: &str) -> String {
+    text.trim_end()
+        .lines()
+        .map(|line| format!("> {line}").trim_end().to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{PLAYER, action, event};
+    use crate::gameactions::{ItemAppraise, WritingBookModifyPage};
+    use crate::gameevents::{
+        ItemGetInscriptionResponse, WritingBookAddPageResponse, WritingBookOpen,
+        WritingBookPageDataResponse,
+    };
+
+    use crate::types::{ObjectId, PackableList, PageDataList};
+
+    const SCRIBE: u32 = 0x50000002;
+    const BOOK: u32 = 0x80000001;
+    const SWORD: u32 = 0x80000002;
+
+    fn page(text: Option<&str>, version: u32) -> PageData {
+        PageData {
+            author_id: ObjectId(SCRIBE),
+            author_name: "Scribe".to_string(),
+            author_account: "account".to_string(),
+            version,
+            text_included: text.is_some(),
+            ignore_author: false,
+            page_text: text.map(str::to_string),
+        }
+    }
+
+    fn open(pages: Vec<PageData>) -> MessageKind {
+        event(GameEventMessage::WritingBookOpen(WritingBookOpen {
+            book_id: ObjectId(BOOK),
+            max_num_pages: 10,
+            page_data: PageDataList {
+                max_num_pages: 10,
+                max_num_chars_per_page: 1000,
+                pages: PackableList {
+                    count: pages.len() as u32,
+                    list: pages,
+                },
+            },
+            inscription: "For the archive".to_string(),
+            scribe_id: ObjectId(SCRIBE),
+            scribe_name: "Scribe".to_string(),
+        }))
+    }
+
+    #[test]
+    fn test_book_pages_in_order() {
+        let mut archive = BookArchive::new();
+        archive.objects.insert(BOOK, "History of Dereth");
+        archive.process(
+            1.0,
+            &open(vec![page(Some("First"), 1), page(Some("Second"), 3)]),
+        );
+        // Reopening without text keeps the text already seen
+        archive.process(2.0, &open(vec![page(None, 1), page(None, 3)]));
+        archive.resolve();
+
+        let book = archive.get(BOOK).unwrap();
+        assert_eq!(book.title.as_deref(), Some("History of Dereth"));
+        assert_eq!(book.author.as_deref(), Some("Scribe"));
+        assert_eq!(
+            book.pages
+                .iter()
+                .map(|p| p.text.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("First"), Some("Second")]
+        );
+        assert_eq!(book.last_editor.as_ref().map(|e| e.id), Some(SCRIBE));
+    }
+
+    #[test]
+    fn test_client_edits() {
+        let mut archive = BookArchive::new();
+        archive.objects.insert(PLAYER, "Player");
+        archive.process(1.0, &open(vec![page(Some("First"), 1)]));
+        archive.process(
+            2.0,
+            &event(GameEventMessage::WritingBookAddPageResponse(
+                WritingBookAddPageResponse {
+                    book_id: ObjectId(BOOK),
+                    page_number: 1,
+                    success: true,
+                },
+            )),
+        );
+        archive.process(
+            3.0,
+            &action(GameActionMessage::WritingBookModifyPage(
+                WritingBookModifyPage {
+                    object_id: ObjectId(BOOK),
+                    page_num: 1,
+                    page_text: "Added".to_string(),
+                },
+            )),
+        );
+        archive.resolve();
+
+        let book = archive.get(BOOK).unwrap();
+        assert_eq!(book.pages.len(), 2);
+        assert_eq!(book.pages[1].text.as_deref(), Some("Added"));
+        assert_eq!(book.pages[1].author_name, "Player");
+        assert_eq!(
+            book.last_editor,
+            Some(Editor {
+                id: PLAYER,
+                name: "Player".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_inscription_markdown() {
+        let mut archive = BookArchive::new();
+        archive.objects.insert(SWORD, "Sword");
+        archive.process(
+            1.0,
+            &action(GameActionMessage::ItemAppraise(ItemAppraise {
+                object_id: ObjectId(SWORD),
+            })),
+        );
+        archive.process(
+            2.0,
+            &event(GameEventMessage::ItemGetInscriptionResponse(
+                ItemGetInscriptionResponse {
+                    inscription: "Forged in fire".to_string(),
+                    scribe_name: "Smith".to_string(),
+                    scribe_account: String::new(),
+                },
+            )),
+        );
+        archive.resolve();
+
+        assert_eq!(archive.inscriptions()[0].object_id, SWORD);
+        assert_eq!(
+            archive.to_markdown(),
+            "# Inscriptions\n\n## Sword (0x80000002)\n\n> Forged in fire\n>\n> — Smith\n\n"
+        );
+    }
+
+    #[test]
+    fn test_pages_out_of_order() {
+        let mut archive = BookArchive::new();
+        let response = |index: u32, text: &str| {
+            event(GameEventMessage::WritingBookPageDataResponse(
+                WritingBookPageDataResponse {
+                    object_id: ObjectId(BOOK),
+                    page: index,
+                    page_data: page(Some(text), 1),
+                },
+            ))
+        };
+        // Pages read without the book being opened in the capture
+        archive.process(1.0, &response(2, "Third"));
+        archive.process(2.0, &response(0, "First"));
+        archive.resolve();
+
+        let book = archive.get(BOOK).unwrap();
+        assert_eq!(book.title, None);
+        assert_eq!(book.max_pages, None);
+        assert_eq!(
+            book.pages
+                .iter()
+                .map(|p| p.text.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("First"), None, Some("Third")]
+        );
+        assert!(
+            archive
+                .to_markdown()
+                .contains("## Page 2\n\n*(text not captured)*")
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Chat transcript
+//!
+//! Collects the lines the client would print to its chat windows, in capture
+//! order: local, ranged and direct speech, emotes, channel and Turbine chat,
+//! system text, and error and status codes rendered by [`crate::text`].
+
+use std::fmt::Write as _;
+
+use serde::Serialize;
+
+use crate::message::{GameEventMessage, MessageKind, S2CMessage};
+use crate::messages::s2c::{
+    CommunicationTurbineChat, CommunicationTurbineChatType1BlobDispatchTypeVariant,
+};
+use crate::network::RawMessage;
+use crate::text::display_text;
+
+use super::parse_all;
+
+/// One line of chat
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ChatLine {
+    pub time: f64,
+    /// Chat type, channel or kind of message the line came from, e.g. "Speech" or "Error"
+    pub kind: String,
+    pub sender_id: Option<u32>,
+    pub sender_name: Option<String>,
+    pub text: String,
+}
+
+impl ChatLine {
+    fn new(time: f64, kind: impl ToString, text: &str) -> Self {
+        Self {
+            time,
+            kind: kind.to_string(),
+            sender_id: None,
+            sender_name: None,
+            text: text.to_string(),
+        }
+    }
+
+    fn with_sender(mut self, sender_id: u32, sender_name: &str) -> Self {
+        self.sender_id = Some(sender_id);
+        self.sender_name = Some(sender_name.to_string()).filter(|name| !name.is_empty());
+        self
+    }
+}
+
+/// Every chat line in a capture
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ChatTranscript {
+    pub lines: Vec<ChatLine>,
+}
+
+impl ChatTranscript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the transcript from the messages in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut transcript = Self::new();
+        for (raw, message) in parse_all(messages) {
+            transcript.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        transcript
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        if let Some(text) = display_text(message) {
+            self.lines.push(ChatLine::new(time, "Error", &text));
+            return;
+        }
+
+        let MessageKind::S2C(msg) = message else {
+            return;
+        };
+        let line = match msg.as_ref() {
+            S2CMessage::CommunicationHearSpeech(msg) => {
+                ChatLine::new(time, &msg.type_, &msg.message)
+                    .with_sender(msg.sender_id.0, &msg.sender_name)
+            }
+            S2CMessage::CommunicationHearRangedSpeech(msg) => {
+                ChatLine::new(time, &msg.type_, &msg.message)
+                    .with_sender(msg.sender_id.0, &msg.sender_name)
+            }
+            S2CMessage::CommunicationHearEmote(msg) => ChatLine::new(time, "Emote", &msg.text)
+                .with_sender(msg.sender_id.0, &msg.sender_name),
+            S2CMessage::CommunicationHearSoulEmote(msg) => {
+                ChatLine::new(time, "SoulEmote", &msg.text)
+                    .with_sender(msg.sender_id.0, &msg.sender_name)
+            }
+            S2CMessage::CommunicationTextboxString(msg) => {
+                ChatLine::new(time, &msg.type_, &msg.text)
+            }
+            S2CMessage::CommunicationTurbineChat(CommunicationTurbineChat::Type1(msg)) => {
+                let CommunicationTurbineChatType1BlobDispatchTypeVariant::Type1(chat) =
+                    &msg.blob_dispatch_type;
+                ChatLine::new(time, &chat.chat_type, &chat.text.0)
+                    .with_sender(chat.speaker_id.0, &chat.display_name.0)
+            }
+            S2CMessage::OrderedGameEvent { event, .. } => match event.as_ref() {
+                GameEventMessage::CommunicationHearDirectSpeech(msg) => {
+                    ChatLine::new(time, &msg.type_, &msg.message)
+                        .with_sender(msg.sender_id.0, &msg.sender_name)
+                }
+                GameEventMessage::CommunicationChannelBroadcast(msg) => {
+                    ChatLine::new(time, format!("{:?}", msg.channel), &msg.message)
+                }
+                GameEventMessage::CommunicationTransientString(msg) => {
+                    ChatLine::new(time, "Transient", &msg.message)
+                }
+                GameEventMessage::CommunicationPopUpString(msg) => {
+                    ChatLine::new(time, "PopUp", &msg.message)
+                }
+                _ => return,
+            },
+            _ => return,
+        };
+        self.lines.push(line);
+    }
+
+    /// The transcript as text, one line per message with seconds since the first
+    pub fn to_text(&self) -> String {
+        let start = self.lines.first().map_or(0.0, |line| line.time);
+        let mut text = String::new();
+        for line in &self.lines {
+            let _ = write!(text, "{:>10.3} [{}] ", line.time - start, line.kind);
+            if let Some(name) = &line.sender_name {
+                let _ = write!(text, "{name}: ");
+            }
+            let _ = writeln!(text, "{}", line.text.trim_end());
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::event;
+    use crate::enums::{ChatFragmentType, WeenieError};
+    use crate::gameevents::{CommunicationTransientString, CommunicationWeenieError};
+    use crate::messages::s2c::CommunicationHearSpeech;
+    use crate::types::ObjectId;
+
+    #[test]
+    fn test_transcript_includes_speech_and_errors() {
+        let mut transcript = ChatTranscript::new();
+        transcript.process(
+            10.0,
+            &MessageKind::S2C(Box::new(S2CMessage::CommunicationHearSpeech(
+                CommunicationHearSpeech {
+                    message: "Hello".to_string(),
+                    sender_name: "Town Crier".to_string(),
+                    sender_id: ObjectId(0x80000001),
+                    type_: ChatFragmentType::Speech,
+                },
+            ))),
+        );
+        transcript.process(
+            11.5,
+            &event(GameEventMessage::CommunicationWeenieError(
+                CommunicationWeenieError {
+                    type_: WeenieError::YourSpellFizzled,
+                },
+            )),
+        );
+        transcript.process(
+            12.0,
+            &event(GameEventMessage::CommunicationWeenieError(
+                CommunicationWeenieError {
+                    type_: WeenieError::CantSwearAllegianceInsufficientXp,
+                },
+            )),
+        );
+        transcript.process(
+            13.0,
+            &event(GameEventMessage::CommunicationTransientString(
+                CommunicationTransientString {
+                    message: "You are now resting.".to_string(),
+                },
+            )),
+        );
+
+        assert_eq!(transcript.lines.len(), 4);
+        assert_eq!(transcript.lines[0].sender_id, Some(0x80000001));
+        assert_eq!(
+            transcript.to_text(),
+            "     0.000 [Speech] Town Crier: Hello\n\
+             \x20    1.500 [Error] Your spell fizzled.\n\
+             \x20    2.000 [Error] CantSwearAllegianceInsufficientXp\n\
+             \x20    3.000 [Transient] You are now resting.\n"
+        );
+    }
+}
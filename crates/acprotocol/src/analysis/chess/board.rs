@@ -0,0 +1,805 @@
+//! Chess board, move validation and notation
+//!
+//! Coordinates follow the game's protocol: `x` is the file (0 = a) and `y` is
+//! the rank (0 = 1), with white starting on ranks 1 and 2. Pawns always
+//! promote to queens, as they do in the game.
+
+use std::fmt;
+
+use serde::Serialize;
+
+/// Side of the board
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    /// The color for a team number used by the protocol (0 is white, 1 is black)
+    pub fn from_team(team: i32) -> Option<Self> {
+        match team {
+            0 => Some(Self::White),
+            1 => Some(Self::Black),
+            _ => None,
+        }
+    }
+
+    pub fn opponent(self) -> Self {
+        match self {
+            Self::White => Self::Black,
+            Self::Black => Self::White,
+        }
+    }
+
+    /// Rank the color's pieces start on
+    fn home_rank(self) -> u8 {
+        match self {
+            Self::White => 0,
+            Self::Black => 7,
+        }
+    }
+
+    /// Direction the color's pawns move in
+    fn forward(self) -> i32 {
+        match self {
+            Self::White => 1,
+            Self::Black => -1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub enum PieceKind {
+    King,
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+    Pawn,
+}
+
+impl PieceKind {
+    /// Letter used for the piece in algebraic notation and FEN
+    fn letter(self) -> char {
+        match self {
+            Self::King => 'K',
+            Self::Queen => 'Q',
+            Self::Rook => 'R',
+            Self::Bishop => 'B',
+            Self::Knight => 'N',
+            Self::Pawn => 'P',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct Piece {
+    pub color: Color,
+    pub kind: PieceKind,
+}
+
+impl Piece {
+    fn fen_char(self) -> char {
+        match self.color {
+            Color::White => self.kind.letter(),
+            Color::Black => self.kind.letter().to_ascii_lowercase(),
+        }
+    }
+}
+
+/// A square on the board
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+pub struct Square {
+    pub x: u8,
+    pub y: u8,
+}
+
+impl Square {
+    /// The square at the given file and rank, if it is on the board
+    pub fn new(x: i32, y: i32) -> Option<Self> {
+        ((0..8).contains(&x) && (0..8).contains(&y)).then_some(Self {
+            x: x as u8,
+            y: y as u8,
+        })
+    }
+
+    fn offset(self, dx: i32, dy: i32) -> Option<Self> {
+        Self::new(i32::from(self.x) + dx, i32::from(self.y) + dy)
+    }
+
+    fn file_char(self) -> char {
+        (b'a' + self.x) as char
+    }
+
+    fn rank_char(self) -> char {
+        (b'1' + self.y) as char
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.file_char(), self.rank_char())
+    }
+}
+
+/// A move from one square to another
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+    pub promotion: Option<PieceKind>,
+}
+
+/// Why a move is not legal
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum MoveError {
+    EmptySquare,
+    NotYourTurn,
+    AttackingOwnPiece,
+    /// The piece cannot move that way, or its path is blocked
+    InvalidMove,
+    /// The move would leave the mover's king in check
+    MovesIntoCheck,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::EmptySquare => "no piece on the starting square",
+            Self::NotYourTurn => "piece belongs to the side not on move",
+            Self::AttackingOwnPiece => "destination holds a piece of the same color",
+            Self::InvalidMove => "piece cannot move there",
+            Self::MovesIntoCheck => "move leaves the king in check",
+        };
+        write!(f, "{s}")
+    }
+}
+
+const KNIGHT_STEPS: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const ROOK_DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_DIRECTIONS: [(i32, i32); 4] = [(1, 1), (-1, 1), (-1, -1), (1, -1)];
+
+/// A chess position
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Board {
+    /// Pieces by `[y][x]`
+    squares: [[Option<Piece>; 8]; 8],
+    to_move: Color,
+    /// Castling rights: white king side, white queen side, black king side, black queen side
+    castling: [bool; 4],
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Board {
+    /// The starting position
+    pub fn new() -> Self {
+        use PieceKind::*;
+        let back_rank = [Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook];
+
+        let mut squares = [[None; 8]; 8];
+        for (x, kind) in back_rank.into_iter().enumerate() {
+            squares[0][x] = Some(Piece {
+                color: Color::White,
+                kind,
+            });
+            squares[1][x] = Some(Piece {
+                color: Color::White,
+                kind: Pawn,
+            });
+            squares[6][x] = Some(Piece {
+                color: Color::Black,
+                kind: Pawn,
+            });
+            squares[7][x] = Some(Piece {
+                color: Color::Black,
+                kind,
+            });
+        }
+
+        Self {
+            squares,
+            to_move: Color::White,
+            castling: [true; 4],
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        self.squares[square.y as usize][square.x as usize]
+    }
+
+    pub fn to_move(&self) -> Color {
+        self.to_move
+    }
+
+    pub fn fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    /// The position in Forsyth-Edwards Notation
+    pub fn fen(&self) -> String {
+        let mut fen = String::new();
+        for y in (0..8).rev() {
+            let mut empty = 0;
+            for x in 0..8 {
+                match self.squares[y][x] {
+                    Some(piece) => {
+                        if empty > 0 {
+                            fen.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        fen.push(piece.fen_char());
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if y > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen.push(' ');
+        fen.push(match self.to_move {
+            Color::White => 'w',
+            Color::Black => 'b',
+        });
+
+        fen.push(' ');
+        let rights = self
+            .castling
+            .iter()
+            .zip(['K', 'Q', 'k', 'q'])
+            .filter(|(allowed, _)| **allowed)
+            .map(|(_, c)| c)
+            .collect::<String>();
+        fen.push_str(if rights.is_empty() { "-" } else { &rights });
+
+        fen.push(' ');
+        match self.en_passant {
+            Some(square) => fen.push_str(&square.to_string()),
+            None => fen.push('-'),
+        }
+
+        fen.push_str(&format!(
+            " {} {}",
+            self.halfmove_clock, self.fullmove_number
+        ));
+        fen
+    }
+
+    /// Whether the side to move is in check
+    pub fn in_check(&self) -> bool {
+        self.king_square(self.to_move)
+            .is_some_and(|king| self.is_attacked(king, self.to_move.opponent()))
+    }
+
+    pub fn is_checkmate(&self) -> bool {
+        self.in_check() && self.legal_moves().is_empty()
+    }
+
+    pub fn is_stalemate(&self) -> bool {
+        !self.in_check() && self.legal_moves().is_empty()
+    }
+
+    /// Every legal move for the side to move
+    pub fn legal_moves(&self) -> Vec<Move> {
+        self.pseudo_legal_moves()
+            .into_iter()
+            .filter(|mv| !self.leaves_king_in_check(mv))
+            .collect()
+    }
+
+    /// Check a move for the side to move
+    pub fn validate(&self, from: Square, to: Square) -> Result<Move, MoveError> {
+        let piece = self.piece_at(from).ok_or(MoveError::EmptySquare)?;
+        if piece.color != self.to_move {
+            return Err(MoveError::NotYourTurn);
+        }
+        if self.piece_at(to).is_some_and(|p| p.color == piece.color) {
+            return Err(MoveError::AttackingOwnPiece);
+        }
+
+        let mv = self
+            .pseudo_legal_moves_from(from)
+            .into_iter()
+            .find(|mv| mv.to == to)
+            .ok_or(MoveError::InvalidMove)?;
+        if self.leaves_king_in_check(&mv) {
+            return Err(MoveError::MovesIntoCheck);
+        }
+        Ok(mv)
+    }
+
+    /// Standard algebraic notation for a legal move in this position
+    pub fn san(&self, mv: &Move) -> String {
+        let Some(piece) = self.piece_at(mv.from) else {
+            return format!("{}{}", mv.from, mv.to);
+        };
+
+        let mut san = String::new();
+        let castle = piece.kind == PieceKind::King && mv.from.x.abs_diff(mv.to.x) == 2;
+        if castle {
+            san.push_str(if mv.to.x > mv.from.x { "O-O" } else { "O-O-O" });
+        } else {
+            let capture = self.piece_at(mv.to).is_some()
+                || (piece.kind == PieceKind::Pawn && Some(mv.to) == self.en_passant);
+
+            if piece.kind == PieceKind::Pawn {
+                if capture {
+                    san.push(mv.from.file_char());
+                }
+            } else {
+                san.push(piece.kind.letter());
+                san.push_str(&self.disambiguation(mv, piece.kind));
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&mv.to.to_string());
+            if let Some(promotion) = mv.promotion {
+                san.push('=');
+                san.push(promotion.letter());
+            }
+        }
+
+        let mut after = self.clone();
+        after.apply(mv);
+        if after.is_checkmate() {
+            san.push('#');
+        } else if after.in_check() {
+            san.push('+');
+        }
+        san
+    }
+
+    /// Make a move without checking it, updating castling, en passant and move counters
+    pub fn apply(&mut self, mv: &Move) {
+        let Some(piece) = self.piece_at(mv.from) else {
+            return;
+        };
+        let mut capture = self.piece_at(mv.to).is_some();
+
+        // En passant removes the pawn beside the destination
+        if piece.kind == PieceKind::Pawn && Some(mv.to) == self.en_passant && mv.from.x != mv.to.x {
+            self.squares[mv.from.y as usize][mv.to.x as usize] = None;
+            capture = true;
+        }
+
+        // Castling moves the rook as well
+        if piece.kind == PieceKind::King && mv.from.x.abs_diff(mv.to.x) == 2 {
+            let (rook_from, rook_to) = if mv.to.x > mv.from.x { (7, 5) } else { (0, 3) };
+            let y = mv.from.y as usize;
+            self.squares[y][rook_to] = self.squares[y][rook_from].take();
+        }
+
+        self.squares[mv.from.y as usize][mv.from.x as usize] = None;
+        self.squares[mv.to.y as usize][mv.to.x as usize] = Some(Piece {
+            color: piece.color,
+            kind: mv.promotion.unwrap_or(piece.kind),
+        });
+
+        self.en_passant =
+            (piece.kind == PieceKind::Pawn && mv.from.y.abs_diff(mv.to.y) == 2).then(|| Square {
+                x: mv.from.x,
+                y: (mv.from.y + mv.to.y) / 2,
+            });
+
+        for square in [mv.from, mv.to] {
+            match (square.x, square.y) {
+                (4, 0) => self.castling[..2].fill(false),
+                (4, 7) => self.castling[2..].fill(false),
+                (7, 0) => self.castling[0] = false,
+                (0, 0) => self.castling[1] = false,
+                (7, 7) => self.castling[2] = false,
+                (0, 7) => self.castling[3] = false,
+                _ => {}
+            }
+        }
+
+        if piece.kind == PieceKind::Pawn || capture {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if piece.color == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.to_move = piece.color.opponent();
+    }
+
+    fn disambiguation(&self, mv: &Move, kind: PieceKind) -> String {
+        let others = self
+            .legal_moves()
+            .into_iter()
+            .filter(|other| {
+                other.to == mv.to
+                    && other.from != mv.from
+                    && self.piece_at(other.from).is_some_and(|p| p.kind == kind)
+            })
+            .collect::<Vec<_>>();
+
+        if others.is_empty() {
+            String::new()
+        } else if others.iter().all(|other| other.from.x != mv.from.x) {
+            mv.from.file_char().to_string()
+        } else if others.iter().all(|other| other.from.y != mv.from.y) {
+            mv.from.rank_char().to_string()
+        } else {
+            mv.from.to_string()
+        }
+    }
+
+    fn king_square(&self, color: Color) -> Option<Square> {
+        self.squares_with(|p| p.color == color && p.kind == PieceKind::King)
+            .next()
+    }
+
+    fn squares_with<'a>(
+        &'a self,
+        predicate: impl Fn(Piece) -> bool + 'a,
+    ) -> impl Iterator<Item = Square> + 'a {
+        (0..8u8)
+            .flat_map(|y| (0..8u8).map(move |x| Square { x, y }))
+            .filter(move |&square| self.piece_at(square).is_some_and(&predicate))
+    }
+
+    fn leaves_king_in_check(&self, mv: &Move) -> bool {
+        let mut after = self.clone();
+        after.apply(mv);
+        after
+            .king_square(self.to_move)
+            .is_some_and(|king| after.is_attacked(king, self.to_move.opponent()))
+    }
+
+    /// Whether any piece of `by` attacks a square
+    fn is_attacked(&self, square: Square, by: Color) -> bool {
+        let holds = |sq: Option<Square>, kinds: &[PieceKind]| {
+            sq.and_then(|sq| self.piece_at(sq))
+                .is_some_and(|p| p.color == by && kinds.contains(&p.kind))
+        };
+
+        if KNIGHT_STEPS
+            .iter()
+            .any(|&(dx, dy)| holds(square.offset(dx, dy), &[PieceKind::Knight]))
+        {
+            return true;
+        }
+        if KING_STEPS
+            .iter()
+            .any(|&(dx, dy)| holds(square.offset(dx, dy), &[PieceKind::King]))
+        {
+            return true;
+        }
+        // Pawns attack diagonally forward, so look backwards from the square
+        let dy = -by.forward();
+        if holds(square.offset(-1, dy), &[PieceKind::Pawn])
+            || holds(square.offset(1, dy), &[PieceKind::Pawn])
+        {
+            return true;
+        }
+
+        let slides = [
+            (ROOK_DIRECTIONS, [PieceKind::Rook, PieceKind::Queen]),
+            (BISHOP_DIRECTIONS, [PieceKind::Bishop, PieceKind::Queen]),
+        ];
+        slides.iter().any(|(directions, kinds)| {
+            directions.iter().any(|&(dx, dy)| {
+                let mut current = square.offset(dx, dy);
+                while let Some(sq) = current {
+                    if self.piece_at(sq).is_some() {
+                        return holds(Some(sq), kinds);
+                    }
+                    current = sq.offset(dx, dy);
+                }
+                false
+            })
+        })
+    }
+
+    fn pseudo_legal_moves(&self) -> Vec<Move> {
+        self.squares_with(|p| p.color == self.to_move)
+            .flat_map(|from| self.pseudo_legal_moves_from(from))
+            .collect()
+    }
+
+    fn pseudo_legal_moves_from(&self, from: Square) -> Vec<Move> {
+        let Some(piece) = self.piece_at(from) else {
+            return Vec::new();
+        };
+
+        let mut targets = Vec::new();
+        let step = |targets: &mut Vec<Square>, to: Option<Square>| -> bool {
+            match to {
+                Some(to) => match self.piece_at(to) {
+                    None => {
+                        targets.push(to);
+                        true
+                    }
+                    Some(other) => {
+                        if other.color != piece.color {
+                            targets.push(to);
+                        }
+                        false
+                    }
+                },
+                None => false,
+            }
+        };
+
+        match piece.kind {
+            PieceKind::Knight => {
+                for (dx, dy) in KNIGHT_STEPS {
+                    step(&mut targets, from.offset(dx, dy));
+                }
+            }
+            PieceKind::King => {
+                for (dx, dy) in KING_STEPS {
+                    step(&mut targets, from.offset(dx, dy));
+                }
+                targets.extend(self.castling_targets(from, piece.color));
+            }
+            PieceKind::Rook | PieceKind::Bishop | PieceKind::Queen => {
+                let directions = match piece.kind {
+                    PieceKind::Rook => ROOK_DIRECTIONS.to_vec(),
+                    PieceKind::Bishop => BISHOP_DIRECTIONS.to_vec(),
+                    _ => [ROOK_DIRECTIONS, BISHOP_DIRECTIONS].concat(),
+                };
+                for (dx, dy) in directions {
+                    let mut current = from.offset(dx, dy);
+                    while step(&mut targets, current) {
+                        current = current.and_then(|sq| sq.offset(dx, dy));
+                    }
+                }
+            }
+            PieceKind::Pawn => {
+                let forward = piece.color.forward();
+                if let Some(one) = from.offset(0, forward)
+                    && self.piece_at(one).is_none()
+                {
+                    targets.push(one);
+                    let start_rank = piece.color.home_rank() as i32 + forward;
+                    if i32::from(from.y) == start_rank
+                        && let Some(two) = from.offset(0, 2 * forward)
+                        && self.piece_at(two).is_none()
+                    {
+                        targets.push(two);
+                    }
+                }
+                for dx in [-1, 1] {
+                    if let Some(to) = from.offset(dx, forward) {
+                        let captures = self.piece_at(to).is_some_and(|p| p.color != piece.color);
+                        if captures || Some(to) == self.en_passant {
+                            targets.push(to);
+                        }
+                    }
+                }
+            }
+        }
+
+        let last_rank = piece.color.opponent().home_rank();
+        targets
+            .into_iter()
+            .map(|to| Move {
+                from,
+                to,
+                promotion: (piece.kind == PieceKind::Pawn && to.y == last_rank)
+                    .then_some(PieceKind::Queen),
+            })
+            .collect()
+    }
+
+    fn castling_targets(&self, from: Square, color: Color) -> Vec<Square> {
+        let rank = color.home_rank();
+        if from != (Square { x: 4, y: rank }) || self.is_attacked(from, color.opponent()) {
+            return Vec::new();
+        }
+
+        let (king_side, queen_side) = match color {
+            Color::White => (self.castling[0], self.castling[1]),
+            Color::Black => (self.castling[2], self.castling[3]),
+        };
+        let empty = |xs: &[u8]| {
+            xs.iter()
+                .all(|&x| self.piece_at(Square { x, y: rank }).is_none())
+        };
+        let safe = |xs: &[u8]| {
+            xs.iter()
+                .all(|&x| !self.is_attacked(Square { x, y: rank }, color.opponent()))
+        };
+        let rook = |x: u8| {
+            self.piece_at(Square { x, y: rank })
+                == Some(Piece {
+                    color,
+                    kind: PieceKind::Rook,
+                })
+        };
+
+        let mut targets = Vec::new();
+        if king_side && rook(7) && empty(&[5, 6]) && safe(&[5, 6]) {
+            targets.push(Square { x: 6, y: rank });
+        }
+        if queen_side && rook(0) && empty(&[1, 2, 3]) && safe(&[2, 3]) {
+            targets.push(Square { x: 2, y: rank });
+        }
+        targets
+    }
+}
+
+/// The board as a diagram, white at the bottom and black pieces in lower case
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in (0..8).rev() {
+            write!(f, "{} ", y + 1)?;
+            for x in 0..8 {
+                let c = self.squares[y][x].map_or('.', Piece::fen_char);
+                write!(f, " {c}")?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "   a b c d e f g h")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sq(name: &str) -> Square {
+        let bytes = name.as_bytes();
+        Square::new(i32::from(bytes[0] - b'a'), i32::from(bytes[1] - b'1')).unwrap()
+    }
+
+    fn play(board: &mut Board, moves: &[(&str, &str)]) -> Vec<String> {
+        moves
+            .iter()
+            .map(|(from, to)| {
+                let mv = board.validate(sq(from), sq(to)).unwrap();
+                let san = board.san(&mv);
+                board.apply(&mv);
+                san
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_starting_position() {
+        let board = Board::new();
+        assert_eq!(
+            board.fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert_eq!(board.legal_moves().len(), 20);
+    }
+
+    #[test]
+    fn test_rejects_illegal_moves() {
+        let board = Board::new();
+        assert_eq!(
+            board.validate(sq("e3"), sq("e4")),
+            Err(MoveError::EmptySquare)
+        );
+        assert_eq!(
+            board.validate(sq("e7"), sq("e5")),
+            Err(MoveError::NotYourTurn)
+        );
+        assert_eq!(
+            board.validate(sq("a1"), sq("a3")),
+            Err(MoveError::InvalidMove)
+        );
+        assert_eq!(
+            board.validate(sq("d1"), sq("d2")),
+            Err(MoveError::AttackingOwnPiece)
+        );
+    }
+
+    #[test]
+    fn test_scholars_mate() {
+        let mut board = Board::new();
+        let sans = play(
+            &mut board,
+            &[
+                ("e2", "e4"),
+                ("e7", "e5"),
+                ("f1", "c4"),
+                ("b8", "c6"),
+                ("d1", "h5"),
+                ("g8", "f6"),
+                ("h5", "f7"),
+            ],
+        );
+        assert_eq!(sans, ["e4", "e5", "Bc4", "Nc6", "Qh5", "Nf6", "Qxf7#"]);
+        assert!(board.is_checkmate());
+    }
+
+    #[test]
+    fn test_castling_and_en_passant() {
+        let mut board = Board::new();
+        let sans = play(
+            &mut board,
+            &[
+                ("e2", "e4"),
+                ("a7", "a6"),
+                ("e4", "e5"),
+                ("d7", "d5"),
+                ("e5", "d6"),
+                ("a6", "a5"),
+                ("g1", "f3"),
+                ("a5", "a4"),
+                ("f1", "e2"),
+                ("a4", "a3"),
+                ("e1", "g1"),
+            ],
+        );
+        assert_eq!(sans[4], "exd6");
+        assert_eq!(sans[10], "O-O");
+        assert_eq!(board.piece_at(sq("f1")).unwrap().kind, PieceKind::Rook);
+        assert!(board.piece_at(sq("d5")).is_none());
+    }
+
+    #[test]
+    fn test_must_answer_check() {
+        let mut board = Board::new();
+        play(
+            &mut board,
+            &[
+                ("e2", "e4"),
+                ("d7", "d6"),
+                ("d2", "d4"),
+                ("e8", "d7"),
+                ("f1", "b5"),
+            ],
+        );
+        assert!(board.in_check());
+        assert_eq!(
+            board.validate(sq("a7"), sq("a6")),
+            Err(MoveError::MovesIntoCheck)
+        );
+        assert!(board.validate(sq("c7"), sq("c6")).is_ok());
+    }
+
+    #[test]
+    fn test_san_disambiguates_by_file() {
+        let mut board = Board::new();
+        play(
+            &mut board,
+            &[("g1", "f3"), ("a7", "a6"), ("d2", "d4"), ("a6", "a5")],
+        );
+        let mv = board.validate(sq("b1"), sq("d2")).unwrap();
+        assert_eq!(board.san(&mv), "Nbd2");
+        let mv = board.validate(sq("b1"), sq("c3")).unwrap();
+        assert_eq!(board.san(&mv), "Nc3");
+    }
+}
@@ -0,0 +1,701 @@
+//! Chess games played at the in-game chess boards
+//!
+//! Replays the `Game_*` events and actions into board positions. The client's
+//! own moves come from its `Game_Move` action once the server's
+//! `Game_MoveResponse` reports success, and the opponent's moves come from
+//! `Game_OpponentTurn`. Every move is checked against the rules in [`board`],
+//! so a move the server accepted but the rules reject is flagged rather than
+//! dropped, which is what makes the replay useful for comparing server
+//! implementations.
+//!
+//! Team 0 plays white and team 1 plays black.
+
+pub mod board;
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use serde::Serialize;
+
+use crate::enums::ChessMoveResult;
+use crate::message::{GameActionMessage, GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+use crate::types::GameMoveData;
+
+use super::objects::ObjectIndex;
+use super::{game_action, game_event, parse_all};
+
+pub use board::{Board, Color, Move, MoveError, Piece, PieceKind, Square};
+
+/// A move that was played on the board
+#[derive(Clone, Debug, Serialize)]
+pub struct ChessMove {
+    pub time: f64,
+    pub color: Color,
+    pub from: Square,
+    pub to: Square,
+    /// Standard algebraic notation, or the squares in coordinate form if the move is invalid
+    pub notation: String,
+    /// The position after the move, in Forsyth-Edwards Notation
+    pub fen: String,
+    /// Why the rules reject a move the server played anyway
+    pub error: Option<MoveError>,
+    /// The server's response, for the client's own moves
+    pub result: Option<ChessMoveResult>,
+}
+
+/// A move the client tried that the server refused
+#[derive(Clone, Debug, Serialize)]
+pub struct RejectedMove {
+    pub time: f64,
+    pub from: Option<Square>,
+    pub to: Option<Square>,
+    pub result: ChessMoveResult,
+    /// Whether the rules here agree the move was illegal
+    pub expected: bool,
+}
+
+/// A team turning its stalemate (draw) offer on or off
+#[derive(Clone, Debug, Serialize)]
+pub struct StalemateOffer {
+    pub time: f64,
+    pub team: i32,
+    pub on: bool,
+}
+
+/// How a game ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    /// The result as written in PGN
+    pub fn pgn(result: Option<Self>) -> &'static str {
+        match result {
+            Some(Self::WhiteWins) => "1-0",
+            Some(Self::BlackWins) => "0-1",
+            Some(Self::Draw) => "1/2-1/2",
+            None => "*",
+        }
+    }
+}
+
+/// One game of chess as seen by one client
+#[derive(Clone, Debug, Serialize)]
+pub struct ChessGame {
+    pub game_id: u32,
+    /// Character whose client captured the game
+    pub observer_id: u32,
+    /// Team the observer plays for
+    pub team: Option<i32>,
+    pub white_id: Option<u32>,
+    pub white_name: Option<String>,
+    pub black_id: Option<u32>,
+    pub black_name: Option<String>,
+    pub start: f64,
+    pub end: Option<f64>,
+    pub moves: Vec<ChessMove>,
+    pub rejected: Vec<RejectedMove>,
+    /// Move responses that can't be tied to a move: none was waiting, as when
+    /// it was sent before the capture started, or an accepted one was off the board
+    pub unmatched_responses: u32,
+    pub stalemate_offers: Vec<StalemateOffer>,
+    /// When the observer left the game (`Game_Quit`)
+    pub quit: Option<f64>,
+    pub result: Option<GameResult>,
+    #[serde(skip)]
+    board: Board,
+}
+
+impl ChessGame {
+    fn new(game_id: u32, observer_id: u32, time: f64) -> Self {
+        Self {
+            game_id,
+            observer_id,
+            team: None,
+            white_id: None,
+            white_name: None,
+            black_id: None,
+            black_name: None,
+            start: time,
+            end: None,
+            moves: Vec::new(),
+            rejected: Vec::new(),
+            unmatched_responses: 0,
+            stalemate_offers: Vec::new(),
+            quit: None,
+            result: None,
+            board: Board::new(),
+        }
+    }
+
+    /// The position after the last move
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// The position after the given number of half-moves
+    pub fn position(&self, ply: usize) -> Board {
+        let mut board = Board::new();
+        for mv in self.moves.iter().take(ply) {
+            board.apply(&force(&board, mv.from, mv.to));
+        }
+        board
+    }
+
+    /// Moves the server played that the rules reject
+    pub fn invalid_moves(&self) -> impl Iterator<Item = &ChessMove> {
+        self.moves.iter().filter(|mv| mv.error.is_some())
+    }
+
+    /// The game in Portable Game Notation
+    pub fn to_pgn(&self) -> String {
+        let result = GameResult::pgn(self.result);
+        let mut pgn = String::new();
+        let tags = [
+            ("Event", "Asheron's Call chess".to_string()),
+            ("Site", "Dereth".to_string()),
+            ("Date", pgn_date(self.start)),
+            ("Round", "-".to_string()),
+            (
+                "White",
+                player_tag(self.white_name.as_deref(), self.white_id),
+            ),
+            (
+                "Black",
+                player_tag(self.black_name.as_deref(), self.black_id),
+            ),
+            ("Result", result.to_string()),
+            ("GameId", format!("{:#010x}", self.game_id)),
+        ];
+        for (name, value) in tags {
+            let _ = writeln!(pgn, "[{name} \"{}\"]", value.replace('"', "'"));
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        let mut board = Board::new();
+        for (ply, mv) in self.moves.iter().enumerate() {
+            if mv.color == Color::White {
+                tokens.push(format!("{}.", board.fullmove_number()));
+            } else if ply == 0 || self.moves[ply - 1].error.is_some() {
+                tokens.push(format!("{}...", board.fullmove_number()));
+            }
+            tokens.push(mv.notation.clone());
+            if let Some(error) = mv.error {
+                tokens.push(format!("{{invalid: {error}}}"));
+            }
+            board.apply(&force(&board, mv.from, mv.to));
+        }
+        tokens.push(result.to_string());
+
+        // PGN lines are kept under 80 characters
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() + 1 > 79 {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+
+    fn set_player(&mut self, team: i32, player_id: u32) {
+        match Color::from_team(team) {
+            Some(Color::White) => self.white_id = Some(player_id),
+            Some(Color::Black) => self.black_id = Some(player_id),
+            None => {}
+        }
+    }
+
+    /// Play a move the server accepted, whether or not the rules allow it
+    fn play(&mut self, time: f64, from: Square, to: Square, result: Option<ChessMoveResult>) {
+        let (mv, notation, error) = match self.board.validate(from, to) {
+            Ok(mv) => (mv, self.board.san(&mv), None),
+            Err(error) => (
+                force(&self.board, from, to),
+                format!("{from}{to}"),
+                Some(error),
+            ),
+        };
+        let color = self
+            .board
+            .piece_at(from)
+            .map_or(self.board.to_move(), |piece| piece.color);
+
+        self.board.apply(&mv);
+        self.moves.push(ChessMove {
+            time,
+            color,
+            from,
+            to,
+            notation,
+            fen: self.board.fen(),
+            error,
+            result,
+        });
+    }
+
+    /// Whether a move is the one just played, as the server echoes moves back
+    fn is_last_move(&self, from: Square, to: Square) -> bool {
+        self.board.piece_at(from).is_none()
+            && self
+                .moves
+                .last()
+                .is_some_and(|mv| mv.from == from && mv.to == to)
+    }
+}
+
+/// A move between two squares as the board would make it, legal or not
+fn force(board: &Board, from: Square, to: Square) -> Move {
+    board.validate(from, to).unwrap_or_else(|_| Move {
+        from,
+        to,
+        promotion: board
+            .piece_at(from)
+            .is_some_and(|piece| piece.kind == PieceKind::Pawn && (to.y == 0 || to.y == 7))
+            .then_some(PieceKind::Queen),
+    })
+}
+
+fn player_tag(name: Option<&str>, id: Option<u32>) -> String {
+    match (name, id) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(id)) => format!("{id:#010x}"),
+        (None, None) => "?".to_string(),
+    }
+}
+
+/// Format a Unix timestamp as a PGN date (YYYY.MM.DD)
+fn pgn_date(time: f64) -> String {
+    if time <= 0.0 {
+        return "????.??.??".to_string();
+    }
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let days = (time / 86400.0).floor() as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}.{month:02}.{day:02}")
+}
+
+fn is_success(result: &ChessMoveResult) -> bool {
+    matches!(
+        result,
+        ChessMoveResult::Success
+            | ChessMoveResult::OpponentInCheck
+            | ChessMoveResult::CheckMatedOpponent
+    )
+}
+
+/// Builds chess games from messages fed to it in capture order
+#[derive(Default)]
+pub struct ChessTracker {
+    games: Vec<ChessGame>,
+    /// Game in progress by game id, as an index into `games`
+    active: HashMap<u32, usize>,
+    /// Game the client's actions apply to, since they carry no game id
+    current: Option<u32>,
+    /// The client's last move, waiting for the server's response
+    pending: Option<(Option<Square>, Option<Square>)>,
+    objects: ObjectIndex,
+}
+
+impl ChessTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.objects.process(message);
+
+        if let Some(action) = game_action(message) {
+            self.process_action(time, action);
+        }
+
+        let Some((object_id, event)) = game_event(message) else {
+            return;
+        };
+
+        match event {
+            // A negative team means the join was refused
+            GameEventMessage::GameJoinGameResponse(msg) if msg.team >= 0 => {
+                self.join(object_id, msg.game_id, msg.team, time);
+            }
+            GameEventMessage::GameStartGame(msg) => {
+                self.join(object_id, msg.game_id, msg.team, time);
+            }
+            GameEventMessage::GameMoveResponse(msg) => {
+                let pending = self.pending.take();
+                let game = self.game(object_id, msg.game_id, time);
+                let success = is_success(&msg.move_result);
+                match pending {
+                    Some((Some(from), Some(to))) if success => {
+                        game.play(time, from, to, Some(msg.move_result.clone()));
+                    }
+                    // The move was sent before the capture started
+                    None => game.unmatched_responses += 1,
+                    // Accepted, but its squares are off the board
+                    Some(_) if success => game.unmatched_responses += 1,
+                    Some((from, to)) => {
+                        let expected = match (from, to) {
+                            (Some(from), Some(to)) => game.board.validate(from, to).is_err(),
+                            _ => true,
+                        };
+                        game.rejected.push(RejectedMove {
+                            time,
+                            from,
+                            to,
+                            result: msg.move_result.clone(),
+                            expected,
+                        });
+                    }
+                }
+            }
+            GameEventMessage::GameOpponentTurn(msg) => {
+                // Only the from-to form describes a move; the others carry a single square
+                let GameMoveData::Type5(data) = &msg.game_move else {
+                    return;
+                };
+                // protocol.xml names the type 5 fields IdPieceToMove/YGrid, but
+                // they are the from square's x and y, as in the Game_Move action
+                let (Some(from), Some(to)) = (
+                    Square::new(data.id_piece_to_move, data.y_grid),
+                    Square::new(data.x_to, data.y_to),
+                ) else {
+                    return;
+                };
+                let game = self.game(object_id, msg.game_id, time);
+                if !game.is_last_move(from, to) {
+                    game.set_player(data.team, data.player_id.0);
+                    game.play(time, from, to, None);
+                }
+            }
+            GameEventMessage::GameOpponentStalemateState(msg) => {
+                let game = self.game(object_id, msg.game_id, time);
+                game.stalemate_offers.push(StalemateOffer {
+                    time,
+                    team: msg.team,
+                    on: msg.on,
+                });
+            }
+            GameEventMessage::GameGameOver(msg) => {
+                let game = self.game(object_id, msg.game_id, time);
+                game.end = Some(time);
+                game.result = Some(match Color::from_team(msg.team_winner) {
+                    Some(Color::White) => GameResult::WhiteWins,
+                    Some(Color::Black) => GameResult::BlackWins,
+                    None => GameResult::Draw,
+                });
+                self.active.remove(&msg.game_id);
+                if self.current == Some(msg.game_id) {
+                    self.current = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn finish(self) -> ChessLog {
+        let objects = self.objects;
+        let mut games = self.games;
+        for game in &mut games {
+            game.white_name = game
+                .white_id
+                .and_then(|id| objects.name(id))
+                .map(str::to_string);
+            game.black_name = game
+                .black_id
+                .and_then(|id| objects.name(id))
+                .map(str::to_string);
+        }
+
+        ChessLog { games }
+    }
+
+    fn process_action(&mut self, time: f64, action: &GameActionMessage) {
+        match action {
+            GameActionMessage::GameMove(msg) => {
+                self.pending = Some((
+                    Square::new(msg.x_from, msg.y_from),
+                    Square::new(msg.x_to, msg.y_to),
+                ));
+            }
+            GameActionMessage::GameStalemate(msg) => {
+                if let Some(game) = self.current_game() {
+                    let team = game.team.unwrap_or(-1);
+                    game.stalemate_offers.push(StalemateOffer {
+                        time,
+                        team,
+                        on: msg.on,
+                    });
+                }
+            }
+            GameActionMessage::GameQuit(_) => {
+                if let Some(game) = self.current_game() {
+                    game.quit = Some(time);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn join(&mut self, object_id: u32, game_id: u32, team: i32, time: f64) {
+        self.current = Some(game_id);
+        let game = self.game(object_id, game_id, time);
+        game.team = Some(team);
+        game.set_player(team, object_id);
+    }
+
+    fn current_game(&mut self) -> Option<&mut ChessGame> {
+        let index = *self.active.get(&self.current?)?;
+        Some(&mut self.games[index])
+    }
+
+    /// The game in progress with an id, starting one if there is none
+    fn game(&mut self, object_id: u32, game_id: u32, time: f64) -> &mut ChessGame {
+        let index = match self.active.get(&game_id) {
+            Some(&index) => index,
+            None => {
+                self.games.push(ChessGame::new(game_id, object_id, time));
+                self.active.insert(game_id, self.games.len() - 1);
+                self.games.len() - 1
+            }
+        };
+        &mut self.games[index]
+    }
+}
+
+/// Every chess game seen in a capture
+#[derive(Clone, Debug, Serialize)]
+pub struct ChessLog {
+    pub games: Vec<ChessGame>,
+}
+
+impl ChessLog {
+    /// Build the log from the chess messages in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut tracker = ChessTracker::new();
+        for (raw, message) in parse_all(messages) {
+            tracker.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        tracker.finish()
+    }
+
+    /// Every game in Portable Game Notation, separated by blank lines
+    pub fn to_pgn(&self) -> String {
+        self.games
+            .iter()
+            .map(ChessGame::to_pgn)
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{PLAYER, event};
+    use crate::gameactions::GameMove;
+    use crate::gameevents::{GameGameOver, GameMoveResponse, GameOpponentTurn, GameStartGame};
+    use crate::message::C2SMessage;
+    use crate::types::{GameMoveDataType5, ObjectId};
+
+    const OPPONENT: u32 = 0x50000002;
+    const GAME: u32 = 0x7A000001;
+
+    fn start(team: i32) -> MessageKind {
+        event(GameEventMessage::GameStartGame(GameStartGame {
+            game_id: GAME,
+            team,
+        }))
+    }
+
+    /// The client's move followed by the server's response
+    fn own_move(
+        tracker: &mut ChessTracker,
+        time: f64,
+        (x_from, y_from): (i32, i32),
+        (x_to, y_to): (i32, i32),
+        move_result: ChessMoveResult,
+    ) {
+        tracker.process(
+            time,
+            &MessageKind::C2S(Box::new(C2SMessage::OrderedGameAction {
+                sequence: 0,
+                action: GameActionMessage::GameMove(GameMove {
+                    x_from,
+                    y_from,
+                    x_to,
+                    y_to,
+                }),
+            })),
+        );
+        tracker.process(
+            time,
+            &event(GameEventMessage::GameMoveResponse(GameMoveResponse {
+                game_id: GAME,
+                move_result,
+            })),
+        );
+    }
+
+    fn opponent_move(
+        team: i32,
+        (x_from, y_from): (i32, i32),
+        (x_to, y_to): (i32, i32),
+    ) -> MessageKind {
+        event(GameEventMessage::GameOpponentTurn(GameOpponentTurn {
+            game_id: GAME,
+            team,
+            game_move: GameMoveData::Type5(GameMoveDataType5 {
+                player_id: ObjectId(OPPONENT),
+                team,
+                // Mislabelled in protocol.xml; this is the from square's x
+                id_piece_to_move: x_from,
+                y_grid: y_from,
+                x_to,
+                y_to,
+            }),
+        }))
+    }
+
+    #[test]
+    fn test_fools_mate() {
+        let mut tracker = ChessTracker::new();
+        tracker.objects.insert(PLAYER, "Player");
+        tracker.objects.insert(OPPONENT, "Opponent");
+        tracker.process(1.0, &start(0));
+        own_move(&mut tracker, 2.0, (5, 1), (5, 2), ChessMoveResult::Success);
+        tracker.process(3.0, &opponent_move(1, (4, 6), (4, 4)));
+        own_move(&mut tracker, 4.0, (6, 1), (6, 3), ChessMoveResult::Success);
+        // The server echoes the client's own move back
+        tracker.process(4.5, &opponent_move(0, (6, 1), (6, 3)));
+        tracker.process(5.0, &opponent_move(1, (3, 7), (7, 3)));
+        tracker.process(
+            6.0,
+            &event(GameEventMessage::GameGameOver(GameGameOver {
+                game_id: GAME,
+                team_winner: 1,
+            })),
+        );
+        let log = tracker.finish();
+
+        assert_eq!(log.games.len(), 1);
+        let game = &log.games[0];
+        assert_eq!(
+            game.moves
+                .iter()
+                .map(|mv| mv.notation.as_str())
+                .collect::<Vec<_>>(),
+            ["f3", "e5", "g4", "Qh4#"]
+        );
+        assert!(game.board().is_checkmate());
+        assert_eq!(game.result, Some(GameResult::BlackWins));
+        assert_eq!(game.white_name.as_deref(), Some("Player"));
+        assert_eq!(game.black_name.as_deref(), Some("Opponent"));
+        assert_eq!(
+            game.position(2).fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/8/5P2/PPPPP1PP/RNBQKBNR w KQkq e6 0 2"
+        );
+    }
+
+    #[test]
+    fn test_rejected_and_invalid_moves() {
+        let mut tracker = ChessTracker::new();
+        tracker.process(1.0, &start(0));
+        own_move(
+            &mut tracker,
+            2.0,
+            (0, 0),
+            (0, 3),
+            ChessMoveResult::FailurePathBlocked,
+        );
+        own_move(
+            &mut tracker,
+            3.0,
+            (4, 1),
+            (4, 3),
+            ChessMoveResult::FailureNotYourTurn,
+        );
+        // A server that lets black move first
+        tracker.process(4.0, &opponent_move(1, (6, 7), (4, 5)));
+        let log = tracker.finish();
+
+        let game = &log.games[0];
+        assert_eq!(game.rejected.len(), 2);
+        assert!(game.rejected[0].expected);
+        assert!(!game.rejected[1].expected);
+        assert_eq!(game.moves.len(), 1);
+        assert_eq!(game.moves[0].error, Some(MoveError::NotYourTurn));
+        assert_eq!(game.invalid_moves().count(), 1);
+    }
+
+    #[test]
+    fn test_response_without_a_pending_move() {
+        let mut tracker = ChessTracker::new();
+        tracker.process(1.0, &start(0));
+        // The move was sent before the capture started
+        tracker.process(
+            2.0,
+            &event(GameEventMessage::GameMoveResponse(GameMoveResponse {
+                game_id: GAME,
+                move_result: ChessMoveResult::Success,
+            })),
+        );
+        let log = tracker.finish();
+
+        let game = &log.games[0];
+        assert!(game.rejected.is_empty());
+        assert!(game.moves.is_empty());
+        assert_eq!(game.unmatched_responses, 1);
+    }
+
+    #[test]
+    fn test_pgn_export() {
+        let mut tracker = ChessTracker::new();
+        tracker.objects.insert(PLAYER, "Player \"One\"");
+        tracker.process(1_763_490_291.0, &start(0));
+        own_move(
+            &mut tracker,
+            1_763_490_292.0,
+            (4, 1),
+            (4, 3),
+            ChessMoveResult::Success,
+        );
+        tracker.process(1_763_490_293.0, &opponent_move(1, (4, 6), (4, 4)));
+        let log = tracker.finish();
+
+        assert_eq!(
+            log.to_pgn(),
+            "[Event \"Asheron's Call chess\"]\n\
+             [Site \"Dereth\"]\n\
+             [Date \"2025.11.18\"]\n\
+             [Round \"-\"]\n\
+             [White \"Player 'One'\"]\n\
+             [Black \"0x50000002\"]\n\
+             [Result \"*\"]\n\
+             [GameId \"0x7a000001\"]\n\
+             \n\
+             1. e4 e5 *\n"
+        );
+    }
+}
@@ -0,0 +1,402 @@
+//! Parse coverage across many captures
+//!
+//! Each capture is reduced to a [`FileSummary`] of message type counts and
+//! parse results, which can be built independently (and in parallel) and
+//! then added to a [`Corpus`] in a fixed order. The resulting
+//! [`CorpusReport`] is sorted by opcode throughout so two reports, say from
+//! before and after a protocol.xml change, can be compared with a plain diff.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+
+use serde::Serialize;
+
+use crate::enums::{C2SMessage, GameAction, GameEvent, S2CMessage};
+use crate::network::RawMessage;
+
+use super::stats::{ParseOutcome, ParseTotals, inner_opcode};
+
+/// Identifies a message type: outer opcode, game event or action type, and name
+type TypeKey = (u32, Option<u32>, String);
+
+/// A message in a particular capture
+#[derive(Clone, Debug, Serialize)]
+pub struct Example {
+    pub file: String,
+    pub id: u32,
+    /// The parse error, how many bytes were left unread, or "first seen"
+    pub detail: String,
+}
+
+#[derive(Clone, Debug)]
+struct FileType {
+    first_id: u32,
+    direction: String,
+    count: usize,
+    parse: ParseTotals,
+    failure: Option<(u32, String)>,
+    trailing: Option<(u32, usize)>,
+}
+
+/// Message type counts and parse results for one capture
+#[derive(Clone, Debug)]
+pub struct FileSummary {
+    path: String,
+    messages: usize,
+    types: BTreeMap<TypeKey, FileType>,
+}
+
+impl FileSummary {
+    pub fn from_messages(path: &str, messages: &[RawMessage]) -> Self {
+        let mut types: BTreeMap<TypeKey, FileType> = BTreeMap::new();
+
+        for message in messages {
+            let key = (
+                message.opcode,
+                inner_opcode(message),
+                message.message_type.clone(),
+            );
+            let entry = types.entry(key).or_insert_with(|| FileType {
+                first_id: message.id,
+                direction: message.direction.clone(),
+                count: 0,
+                parse: ParseTotals::default(),
+                failure: None,
+                trailing: None,
+            });
+
+            let outcome = ParseOutcome::of(message);
+            entry.count += 1;
+            entry.parse.add(&outcome);
+            match outcome {
+                ParseOutcome::Failed(error) if entry.failure.is_none() => {
+                    entry.failure = Some((message.id, error));
+                }
+                ParseOutcome::Trailing(remainder) if entry.trailing.is_none() => {
+                    entry.trailing = Some((message.id, remainder));
+                }
+                _ => {}
+            }
+        }
+
+        Self {
+            path: path.to_string(),
+            messages: messages.len(),
+            types,
+        }
+    }
+}
+
+/// A capture that couldn't be read
+#[derive(Clone, Debug, Serialize)]
+pub struct FileError {
+    pub file: String,
+    pub error: String,
+}
+
+/// Totals for one message type across the corpus
+#[derive(Clone, Debug, Serialize)]
+pub struct CorpusType {
+    pub message_type: String,
+    pub opcode: u32,
+    pub inner_opcode: Option<u32>,
+    pub direction: String,
+    pub count: usize,
+    /// Number of captures the type appears in
+    pub files: usize,
+    pub parse: ParseTotals,
+    pub failure_rate: f64,
+    /// The first message that failed to parse
+    pub example_failure: Option<Example>,
+    /// The first message that parsed with bytes left over
+    pub example_trailing: Option<Example>,
+    /// The first message of this type, for unknown types
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub example: Option<Example>,
+}
+
+/// How much of a protocol enum appears in the corpus
+#[derive(Clone, Debug, Serialize)]
+pub struct EnumCoverage {
+    pub name: &'static str,
+    pub total: usize,
+    pub seen: usize,
+    /// Variants seen at least once without a parse failure
+    pub parsed: usize,
+    pub coverage: f64,
+    /// Variants never seen
+    pub missing: Vec<String>,
+}
+
+/// Parse coverage across a set of captures
+#[derive(Clone, Debug, Serialize)]
+pub struct CorpusReport {
+    pub files: usize,
+    pub unreadable: Vec<FileError>,
+    pub messages: usize,
+    pub parse: ParseTotals,
+    /// Known message types, by opcode
+    pub types: Vec<CorpusType>,
+    /// Opcodes and game event or action types missing from protocol.xml
+    pub unknown: Vec<CorpusType>,
+    pub coverage: Vec<EnumCoverage>,
+}
+
+/// Accumulates file summaries into a corpus report
+#[derive(Debug, Default)]
+pub struct Corpus {
+    files: usize,
+    unreadable: Vec<FileError>,
+    messages: usize,
+    types: BTreeMap<TypeKey, CorpusType>,
+}
+
+impl Corpus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a capture's summary; examples come from the first file added that has one
+    pub fn add(&mut self, summary: FileSummary) {
+        self.files += 1;
+        self.messages += summary.messages;
+
+        for ((opcode, inner, message_type), file) in summary.types {
+            let example = |id: u32, detail: String| Example {
+                file: summary.path.clone(),
+                id,
+                detail,
+            };
+            let key = (opcode, inner, message_type.clone());
+            let entry = self.types.entry(key).or_insert_with(|| CorpusType {
+                message_type,
+                opcode,
+                inner_opcode: inner,
+                direction: file.direction.clone(),
+                count: 0,
+                files: 0,
+                parse: ParseTotals::default(),
+                failure_rate: 0.0,
+                example_failure: None,
+                example_trailing: None,
+                example: None,
+            });
+
+            entry.count += file.count;
+            entry.files += 1;
+            entry.parse.merge(&file.parse);
+            if entry.example.is_none() {
+                entry.example = Some(example(file.first_id, "first seen".to_string()));
+            }
+            if entry.example_failure.is_none()
+                && let Some((id, error)) = file.failure
+            {
+                entry.example_failure = Some(example(id, error));
+            }
+            if entry.example_trailing.is_none()
+                && let Some((id, remainder)) = file.trailing
+            {
+                entry.example_trailing = Some(example(id, format!("{remainder} trailing bytes")));
+            }
+        }
+    }
+
+    /// Record a capture that couldn't be read
+    pub fn add_error(&mut self, file: &str, error: impl ToString) {
+        self.files += 1;
+        self.unreadable.push(FileError {
+            file: file.to_string(),
+            error: error.to_string(),
+        });
+    }
+
+    pub fn report(&self) -> CorpusReport {
+        let mut parse = ParseTotals::default();
+        let mut types = Vec::new();
+        let mut unknown = Vec::new();
+
+        for entry in self.types.values() {
+            parse.merge(&entry.parse);
+            let mut entry = entry.clone();
+            entry.failure_rate = entry.parse.failed as f64 / entry.count.max(1) as f64;
+            if entry.parse.unknown > 0 {
+                unknown.push(entry);
+            } else {
+                entry.example = None;
+                types.push(entry);
+            }
+        }
+
+        let seen = |enum_of: &dyn Fn(&CorpusType) -> Option<String>, parsed_only: bool| {
+            self.types
+                .values()
+                .filter(|t| !parsed_only || t.parse.ok + t.parse.trailing > 0)
+                .filter_map(enum_of)
+                .collect::<BTreeSet<_>>()
+        };
+        let outer_c2s = |t: &CorpusType| name_of::<C2SMessage>(t.opcode);
+        let outer_s2c = |t: &CorpusType| name_of::<S2CMessage>(t.opcode);
+        let event = |t: &CorpusType| match t.opcode {
+            0xF7B0 => t.inner_opcode.and_then(name_of::<GameEvent>),
+            _ => None,
+        };
+        let action = |t: &CorpusType| match t.opcode {
+            0xF7B1 => t.inner_opcode.and_then(name_of::<GameAction>),
+            _ => None,
+        };
+
+        let coverage = vec![
+            coverage::<C2SMessage>(
+                "C2SMessage",
+                &seen(&outer_c2s, false),
+                &seen(&outer_c2s, true),
+            ),
+            coverage::<S2CMessage>(
+                "S2CMessage",
+                &seen(&outer_s2c, false),
+                &seen(&outer_s2c, true),
+            ),
+            coverage::<GameEvent>("GameEvent", &seen(&event, false), &seen(&event, true)),
+            coverage::<GameAction>("GameAction", &seen(&action, false), &seen(&action, true)),
+        ];
+
+        CorpusReport {
+            files: self.files,
+            unreadable: self.unreadable.clone(),
+            messages: self.messages,
+            parse,
+            types,
+            unknown,
+            coverage,
+        }
+    }
+}
+
+/// The variant name for an opcode, if the enum has one
+fn name_of<E: TryFrom<u32> + Debug>(opcode: u32) -> Option<String> {
+    E::try_from(opcode).ok().map(|v| format!("{v:?}"))
+}
+
+/// Every variant of a protocol enum, found by trying each 16-bit opcode
+fn variants<E: TryFrom<u32> + Debug>() -> Vec<String> {
+    (0..=u16::MAX as u32).filter_map(name_of::<E>).collect()
+}
+
+fn coverage<E: TryFrom<u32> + Debug>(
+    name: &'static str,
+    seen: &BTreeSet<String>,
+    parsed: &BTreeSet<String>,
+) -> EnumCoverage {
+    let all = variants::<E>();
+    let missing: Vec<String> = all.iter().filter(|v| !seen.contains(*v)).cloned().collect();
+    let seen_count = all.len() - missing.len();
+
+    EnumCoverage {
+        name,
+        total: all.len(),
+        seen: seen_count,
+        parsed: all.iter().filter(|v| parsed.contains(*v)).count(),
+        coverage: seen_count as f64 / all.len().max(1) as f64,
+        missing,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u32, data: Vec<u8>) -> RawMessage {
+        RawMessage::from_fragment(data, id, id).unwrap()
+    }
+
+    fn delete_object(id: u32, extra: &[u8]) -> RawMessage {
+        let mut data = 0xF747u32.to_le_bytes().to_vec();
+        data.extend(0x80000001u32.to_le_bytes());
+        data.extend([7, 0, 0, 0]);
+        data.extend(extra);
+        message(id, data)
+    }
+
+    fn unknown_event(id: u32) -> RawMessage {
+        let mut data = 0xF7B0u32.to_le_bytes().to_vec();
+        data.extend(0x50000001u32.to_le_bytes());
+        data.extend(1u32.to_le_bytes());
+        data.extend(0xFFFFu32.to_le_bytes());
+        message(id, data)
+    }
+
+    #[test]
+    fn test_examples_come_from_first_file() {
+        let mut corpus = Corpus::new();
+        corpus.add(FileSummary::from_messages(
+            "a.pcap",
+            &[delete_object(1, &[]), delete_object(2, &[])],
+        ));
+        corpus.add(FileSummary::from_messages(
+            "b.pcap",
+            &[delete_object(1, &[]), delete_object(5, &[9])],
+        ));
+        corpus.add(FileSummary::from_messages(
+            "c.pcap",
+            &[delete_object(8, &[1, 2])],
+        ));
+        corpus.add_error("d.pcap", "truncated");
+        let report = corpus.report();
+
+        assert_eq!(report.files, 4);
+        assert_eq!(report.messages, 5);
+        assert_eq!(report.unreadable[0].file, "d.pcap");
+
+        let delete = &report.types[0];
+        assert_eq!(delete.message_type, "ItemDeleteObject");
+        assert_eq!((delete.count, delete.files), (5, 3));
+        assert_eq!((delete.parse.ok, delete.parse.trailing), (3, 2));
+        let example = delete.example_trailing.as_ref().unwrap();
+        assert_eq!((example.file.as_str(), example.id), ("b.pcap", 5));
+        assert!(delete.example_failure.is_none());
+    }
+
+    #[test]
+    fn test_unknown_event_types() {
+        let mut corpus = Corpus::new();
+        corpus.add(FileSummary::from_messages(
+            "a.pcap",
+            &[unknown_event(1), delete_object(2, &[])],
+        ));
+        let report = corpus.report();
+
+        assert_eq!(report.unknown.len(), 1);
+        assert_eq!(report.unknown[0].message_type, "OrderedGameEvent");
+        assert_eq!(report.unknown[0].inner_opcode, Some(0xFFFF));
+        assert_eq!(report.unknown[0].example.as_ref().unwrap().id, 1);
+        assert_eq!(report.types.len(), 1);
+    }
+
+    #[test]
+    fn test_enum_coverage() {
+        let mut corpus = Corpus::new();
+        corpus.add(FileSummary::from_messages(
+            "a.pcap",
+            &[delete_object(1, &[]), unknown_event(2)],
+        ));
+        let report = corpus.report();
+
+        let s2c = report
+            .coverage
+            .iter()
+            .find(|c| c.name == "S2CMessage")
+            .unwrap();
+        assert_eq!(s2c.total, variants::<S2CMessage>().len());
+        assert_eq!((s2c.seen, s2c.parsed), (2, 1));
+        assert!(!s2c.missing.contains(&"ItemDeleteObject".to_string()));
+        assert!(s2c.missing.contains(&"ItemCreateObject".to_string()));
+
+        let events = report
+            .coverage
+            .iter()
+            .find(|c| c.name == "GameEvent")
+            .unwrap();
+        assert_eq!(events.seen, 0);
+        assert_eq!(events.missing.len(), events.total);
+    }
+}
@@ -0,0 +1,743 @@
+//! Salvaging and tinkering log
+//!
+//! Salvage operations pair the client's `Inventory_CreateTinkeringTool`
+//! action, which lists the items put into the salvage panel, with the
+//! `Inventory_SalvageOperationsResultData` event that reports the bags made.
+//!
+//! Tinkering has no dedicated message. An attempt starts with
+//! `Inventory_UseWithTargetEvent` using a tinkering material or tool on an
+//! item, may go through a `Craft` confirmation carrying the success chance, and
+//! ends with what the server does to the target: a new `NumTimesTinkered`
+//! when the attempt succeeds, or the target's removal when it fails. A craft
+//! `WeenieError` means the attempt was refused before it was rolled. A use is
+//! only counted as a tinker when the tool's item type marks it as tinkering
+//! material or a tinkering tool, or the server asks for a craft confirmation.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::enums::{
+    ChatFragmentType, ConfirmationType, ItemType, MaterialType, PropertyInt, WeenieError,
+};
+use crate::message::{GameActionMessage, GameEventMessage, MessageKind, S2CMessage};
+use crate::network::RawMessage;
+use crate::types::PublicWeenieDesc;
+
+use super::csv::{opt, write_row};
+use super::objects::ObjectIndex;
+use super::{game_action, game_event, parse_all};
+
+/// Seconds without any news after which a tinkering attempt is closed
+pub const TINKER_WINDOW: f64 = 30.0;
+
+/// An item put into the salvage panel
+#[derive(Clone, Debug, Serialize)]
+pub struct SalvagedItem {
+    pub object_id: u32,
+    pub name: Option<String>,
+}
+
+/// A bag of salvage produced by an operation
+#[derive(Clone, Debug, Serialize)]
+pub struct SalvageYield {
+    pub material: MaterialType,
+    pub workmanship: f64,
+    pub units: u32,
+}
+
+/// One use of the salvage panel
+#[derive(Clone, Debug, Serialize)]
+pub struct SalvageOperation {
+    pub time: f64,
+    pub skill: String,
+    /// Items that were salvaged
+    pub items: Vec<SalvagedItem>,
+    /// Items the server refused to salvage
+    pub not_salvageable: Vec<SalvagedItem>,
+    pub results: Vec<SalvageYield>,
+    pub aug_bonus: i32,
+}
+
+/// How a tinkering attempt ended
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum TinkerOutcome {
+    Succeeded,
+    Failed,
+    /// The craft confirmation was declined
+    Cancelled,
+    /// The server answered with a craft error instead of making the attempt
+    Refused,
+    Unknown,
+}
+
+impl std::fmt::Display for TinkerOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// A property the server changed on the tinkered item
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PropertyChange {
+    pub property: String,
+    pub value: String,
+}
+
+/// Applying a salvage bag to an item
+#[derive(Clone, Debug, Serialize)]
+pub struct TinkerAttempt {
+    pub time: f64,
+    pub tool_id: u32,
+    pub tool_name: Option<String>,
+    pub material: Option<MaterialType>,
+    pub workmanship: Option<f32>,
+    pub target_id: u32,
+    pub target_name: Option<String>,
+    /// Text of the craft confirmation, which states the success chance
+    pub confirmation: Option<String>,
+    pub outcome: TinkerOutcome,
+    pub changes: Vec<PropertyChange>,
+    /// Craft chat messages received during the attempt
+    pub messages: Vec<String>,
+    /// Error the server ended the use with
+    pub error: Option<WeenieError>,
+}
+
+/// Salvage and tinkering totals for one material
+#[derive(Clone, Debug, Serialize)]
+pub struct MaterialSummary {
+    pub material: MaterialType,
+    pub bags_salvaged: usize,
+    pub units_salvaged: u32,
+    pub mean_salvage_workmanship: Option<f64>,
+    pub tinkers: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Fraction of finished attempts that succeeded
+    pub success_rate: Option<f64>,
+}
+
+impl MaterialSummary {
+    fn new(material: MaterialType) -> Self {
+        Self {
+            material,
+            bags_salvaged: 0,
+            units_salvaged: 0,
+            mean_salvage_workmanship: None,
+            tinkers: 0,
+            succeeded: 0,
+            failed: 0,
+            success_rate: None,
+        }
+    }
+}
+
+/// Builds the crafting log from messages fed to it in capture order
+#[derive(Default)]
+pub struct CraftingTracker {
+    salvage: Vec<SalvageOperation>,
+    tinkers: Vec<TinkerAttempt>,
+    /// Items in the salvage panel when it was last submitted
+    panel: Option<Vec<u32>>,
+    /// The attempt still collecting updates, and when it last heard anything
+    open: Option<(TinkerAttempt, f64)>,
+    /// Material and workmanship of each tinkering material or tool, from object descriptions
+    tools: HashMap<u32, (Option<MaterialType>, Option<f32>)>,
+    objects: ObjectIndex,
+}
+
+impl CraftingTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.objects.process(message);
+
+        if let Some((_, seen)) = &self.open
+            && time - seen > TINKER_WINDOW
+        {
+            self.close();
+        }
+
+        if let Some(action) = game_action(message) {
+            self.process_action(time, action);
+            return;
+        }
+
+        let MessageKind::S2C(msg) = message else {
+            return;
+        };
+        match msg.as_ref() {
+            S2CMessage::ItemCreateObject(msg) => {
+                self.describe(msg.object_id.0, &msg.weenie_description);
+            }
+            S2CMessage::ItemUpdateObject(msg) => {
+                self.describe(msg.object_id.0, &msg.weenie_desc);
+            }
+            S2CMessage::QualitiesUpdateInt(msg) => {
+                self.change(time, msg.object_id.0, &msg.key, &msg.value);
+                if msg.key == PropertyInt::NumTimesTinkered
+                    && let Some((attempt, _)) = &mut self.open
+                    && attempt.target_id == msg.object_id.0
+                {
+                    attempt.outcome = TinkerOutcome::Succeeded;
+                }
+            }
+            S2CMessage::QualitiesUpdateInt64(msg) => {
+                self.change(time, msg.object_id.0, &msg.key, &msg.value);
+            }
+            S2CMessage::QualitiesUpdateFloat(msg) => {
+                self.change(time, msg.object_id.0, &msg.key, &msg.value);
+            }
+            S2CMessage::QualitiesUpdateBool(msg) => {
+                self.change(time, msg.object_id.0, &msg.key, &msg.value);
+            }
+            S2CMessage::QualitiesUpdateString(msg) => {
+                self.change(time, msg.object_id.0, &msg.key, &msg.value);
+            }
+            S2CMessage::QualitiesUpdateDataId(msg) => {
+                self.change(time, msg.object_id.0, &msg.key, &msg.value);
+            }
+            S2CMessage::ItemDeleteObject(msg) => self.removed(msg.object_id.0),
+            S2CMessage::ItemServerSaysRemove(msg) => self.removed(msg.object_id.0),
+            S2CMessage::CommunicationTextboxString(msg) if msg.type_ == ChatFragmentType::Craft => {
+                if let Some((attempt, seen)) = &mut self.open {
+                    attempt.messages.push(msg.text.clone());
+                    *seen = time;
+                }
+            }
+            _ => {}
+        }
+
+        let Some((_, event)) = game_event(message) else {
+            return;
+        };
+        match event {
+            GameEventMessage::InventorySalvageOperationsResultData(msg) => {
+                let result = &msg.result;
+                let refused = result
+                    .not_salvagable
+                    .list
+                    .iter()
+                    .map(|id| id.0)
+                    .collect::<Vec<_>>();
+                let item = |object_id: u32| SalvagedItem {
+                    object_id,
+                    name: self.objects.name(object_id).map(str::to_string),
+                };
+                let items = self
+                    .panel
+                    .take()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|id| !refused.contains(id))
+                    .map(item)
+                    .collect();
+                let not_salvageable = refused.iter().copied().map(item).collect();
+                self.salvage.push(SalvageOperation {
+                    time,
+                    skill: result.skill_used.to_string(),
+                    items,
+                    not_salvageable,
+                    results: result
+                        .salvage_results
+                        .list
+                        .iter()
+                        .map(|r| SalvageYield {
+                            material: r.material.clone(),
+                            workmanship: r.workmanship,
+                            units: r.units,
+                        })
+                        .collect(),
+                    aug_bonus: result.aug_bonus,
+                });
+            }
+            GameEventMessage::CharacterConfirmationRequest(msg)
+                if msg.confirmation_type == ConfirmationType::Craft =>
+            {
+                if let Some((attempt, seen)) = &mut self.open {
+                    attempt.confirmation = Some(msg.text.clone());
+                    *seen = time;
+                }
+            }
+            GameEventMessage::ItemUseDone(msg) if msg.failure_type != WeenieError::None => {
+                self.error(&msg.failure_type);
+            }
+            GameEventMessage::CommunicationWeenieError(msg) => self.error(&msg.type_),
+            _ => {}
+        }
+    }
+
+    pub fn finish(mut self) -> CraftingLog {
+        self.close();
+
+        let mut materials: BTreeMap<u32, (MaterialSummary, Vec<f64>)> = BTreeMap::new();
+        fn summary<'a>(
+            materials: &'a mut BTreeMap<u32, (MaterialSummary, Vec<f64>)>,
+            material: &MaterialType,
+        ) -> &'a mut (MaterialSummary, Vec<f64>) {
+            materials
+                .entry(material.clone() as u32)
+                .or_insert_with(|| (MaterialSummary::new(material.clone()), Vec::new()))
+        }
+        for bag in self.salvage.iter().flat_map(|op| &op.results) {
+            let (entry, workmanship) = summary(&mut materials, &bag.material);
+            entry.bags_salvaged += 1;
+            entry.units_salvaged += bag.units;
+            workmanship.push(bag.workmanship);
+        }
+        for attempt in &self.tinkers {
+            let Some(material) = &attempt.material else {
+                continue;
+            };
+            let (entry, _) = summary(&mut materials, material);
+            entry.tinkers += 1;
+            match attempt.outcome {
+                TinkerOutcome::Succeeded => entry.succeeded += 1,
+                TinkerOutcome::Failed => entry.failed += 1,
+                TinkerOutcome::Cancelled | TinkerOutcome::Refused | TinkerOutcome::Unknown => {}
+            }
+        }
+
+        let materials = materials
+            .into_values()
+            .map(|(mut entry, workmanship)| {
+                if !workmanship.is_empty() {
+                    entry.mean_salvage_workmanship =
+                        Some(workmanship.iter().sum::<f64>() / workmanship.len() as f64);
+                }
+                let finished = entry.succeeded + entry.failed;
+                if finished > 0 {
+                    entry.success_rate = Some(entry.succeeded as f64 / finished as f64);
+                }
+                entry
+            })
+            .collect();
+
+        CraftingLog {
+            materials,
+            salvage: self.salvage,
+            tinkers: self.tinkers,
+        }
+    }
+
+    fn process_action(&mut self, time: f64, action: &GameActionMessage) {
+        match action {
+            GameActionMessage::InventoryCreateTinkeringTool(msg) => {
+                self.panel = Some(msg.items.list.iter().map(|id| id.0).collect());
+            }
+            GameActionMessage::InventoryUseWithTargetEvent(msg) => {
+                self.close();
+                let tool_id = msg.object_id.0;
+                let target_id = msg.target_id.0;
+                let (material, workmanship) = self.tools.get(&tool_id).cloned().unwrap_or_default();
+                let attempt = TinkerAttempt {
+                    time,
+                    tool_id,
+                    tool_name: self.objects.name(tool_id).map(str::to_string),
+                    material,
+                    workmanship,
+                    target_id,
+                    target_name: self.objects.name(target_id).map(str::to_string),
+                    confirmation: None,
+                    outcome: TinkerOutcome::Unknown,
+                    changes: Vec::new(),
+                    messages: Vec::new(),
+                    error: None,
+                };
+                self.open = Some((attempt, time));
+            }
+            GameActionMessage::CharacterConfirmationResponse(msg)
+                if msg.type_ == ConfirmationType::Craft && !msg.accepted =>
+            {
+                if let Some((attempt, _)) = &mut self.open {
+                    attempt.outcome = TinkerOutcome::Cancelled;
+                }
+                self.close();
+            }
+            GameActionMessage::CharacterConfirmationResponse(msg)
+                if msg.type_ == ConfirmationType::Craft =>
+            {
+                if let Some((_, seen)) = &mut self.open {
+                    *seen = time;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn describe(&mut self, object_id: u32, desc: &PublicWeenieDesc) {
+        if desc
+            .type_
+            .intersects(ItemType::TINKERING_MATERIAL | ItemType::TINKERING_TOOL)
+        {
+            self.tools
+                .insert(object_id, (desc.material.clone(), desc.workmanship));
+        }
+    }
+
+    fn change(&mut self, time: f64, object_id: u32, key: &impl ToString, value: &impl ToString) {
+        if let Some((attempt, seen)) = &mut self.open
+            && attempt.target_id == object_id
+        {
+            attempt.changes.push(PropertyChange {
+                property: key.to_string(),
+                value: value.to_string(),
+            });
+            *seen = time;
+        }
+    }
+
+    fn removed(&mut self, object_id: u32) {
+        if let Some((attempt, _)) = &mut self.open
+            && attempt.target_id == object_id
+        {
+            attempt.outcome = TinkerOutcome::Failed;
+            self.close();
+        }
+    }
+
+    /// Record an error the server ended the open attempt's use with
+    fn error(&mut self, error: &WeenieError) {
+        let Some((attempt, _)) = &mut self.open else {
+            return;
+        };
+        attempt.error = Some(error.clone());
+        if is_craft_error(error) {
+            attempt.outcome = TinkerOutcome::Refused;
+            self.close();
+        }
+    }
+
+    /// Finish the open attempt, keeping it if it was a tinker
+    fn close(&mut self) {
+        let Some((attempt, _)) = self.open.take() else {
+            return;
+        };
+        if self.tools.contains_key(&attempt.tool_id) || attempt.confirmation.is_some() {
+            self.tinkers.push(attempt);
+        }
+    }
+}
+
+/// Whether an error is one the server refuses a craft attempt with
+fn is_craft_error(error: &WeenieError) -> bool {
+    matches!(
+        error,
+        WeenieError::UnableToMakeCraftReq
+            | WeenieError::CraftAnimationFailed
+            | WeenieError::YouCantCraftWithThatNumberOfItems
+            | WeenieError::CraftGeneralErrorUiMsg
+            | WeenieError::CraftGeneralErrorNoUiMsg
+            | WeenieError::YouDoNotPassCraftingRequirements
+    )
+}
+
+/// Every salvage operation and tinkering attempt in a capture
+#[derive(Clone, Debug, Serialize)]
+pub struct CraftingLog {
+    pub materials: Vec<MaterialSummary>,
+    pub salvage: Vec<SalvageOperation>,
+    pub tinkers: Vec<TinkerAttempt>,
+}
+
+impl CraftingLog {
+    /// Build the log from the crafting messages in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut tracker = CraftingTracker::new();
+        for (raw, message) in parse_all(messages) {
+            tracker.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        tracker.finish()
+    }
+
+    /// Write the log as CSV, one row per salvage bag or tinkering attempt
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &[
+                "time",
+                "operation",
+                "material",
+                "workmanship",
+                "units",
+                "target_id",
+                "target_name",
+                "outcome",
+                "changes",
+            ],
+        )?;
+
+        for operation in &self.salvage {
+            let items = operation
+                .items
+                .iter()
+                .map(|item| format!("{:#010x}", item.object_id))
+                .collect::<Vec<_>>()
+                .join("|");
+            for bag in &operation.results {
+                write_row(
+                    &mut writer,
+                    &[
+                        operation.time.to_string(),
+                        "Salvage".to_string(),
+                        bag.material.to_string(),
+                        bag.workmanship.to_string(),
+                        bag.units.to_string(),
+                        items.clone(),
+                        String::new(),
+                        String::new(),
+                        String::new(),
+                    ],
+                )?;
+            }
+        }
+
+        for attempt in &self.tinkers {
+            let changes = attempt
+                .changes
+                .iter()
+                .map(|c| format!("{}={}", c.property, c.value))
+                .collect::<Vec<_>>()
+                .join("|");
+            write_row(
+                &mut writer,
+                &[
+                    attempt.time.to_string(),
+                    "Tinker".to_string(),
+                    opt(attempt.material.as_ref()),
+                    opt(attempt.workmanship),
+                    String::new(),
+                    format!("{:#010x}", attempt.target_id),
+                    attempt.target_name.clone().unwrap_or_default(),
+                    attempt.outcome.to_string(),
+                    changes,
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{action, description, event, list};
+    use crate::enums::SkillId;
+    use crate::gameactions::{
+        CharacterConfirmationResponse, InventoryCreateTinkeringTool, InventoryUseWithTargetEvent,
+    };
+    use crate::gameevents::{
+        CharacterConfirmationRequest, CommunicationWeenieError,
+        InventorySalvageOperationsResultData,
+    };
+    use crate::messages::s2c::{CommunicationTextboxString, ItemDeleteObject, QualitiesUpdateInt};
+    use crate::types::{ObjectId, SalvageOperationsResultData, SalvageResult};
+
+    const SWORD: u32 = 0x80000001;
+    const AXE: u32 = 0x80000002;
+    const BAG: u32 = 0x80000003;
+
+    fn tinker(target: u32) -> MessageKind {
+        action(GameActionMessage::InventoryUseWithTargetEvent(
+            InventoryUseWithTargetEvent {
+                object_id: ObjectId(BAG),
+                target_id: ObjectId(target),
+            },
+        ))
+    }
+
+    fn tracker_with_bag() -> CraftingTracker {
+        let mut tracker = CraftingTracker::new();
+        tracker.objects.insert(BAG, "Salvaged Iron");
+        tracker.objects.insert(SWORD, "Sword");
+        tracker
+            .tools
+            .insert(BAG, (Some(MaterialType::Iron), Some(7.5)));
+        tracker
+    }
+
+    #[test]
+    fn test_salvage_operation() {
+        let mut tracker = CraftingTracker::new();
+        tracker.objects.insert(SWORD, "Sword");
+        tracker.process(
+            0.0,
+            &action(GameActionMessage::InventoryCreateTinkeringTool(
+                InventoryCreateTinkeringTool {
+                    tool_id: ObjectId(0x80000010),
+                    items: list(vec![ObjectId(SWORD), ObjectId(AXE)]),
+                },
+            )),
+        );
+        tracker.process(
+            1.0,
+            &event(GameEventMessage::InventorySalvageOperationsResultData(
+                InventorySalvageOperationsResultData {
+                    result: SalvageOperationsResultData {
+                        skill_used: SkillId::Salvaging,
+                        not_salvagable: list(vec![ObjectId(AXE)]),
+                        salvage_results: list(vec![SalvageResult {
+                            material: MaterialType::Iron,
+                            workmanship: 6.0,
+                            units: 12,
+                        }]),
+                        aug_bonus: 0,
+                    },
+                },
+            )),
+        );
+        let log = tracker.finish();
+
+        let operation = &log.salvage[0];
+        assert_eq!(operation.items.len(), 1);
+        assert_eq!(operation.items[0].name.as_deref(), Some("Sword"));
+        assert_eq!(operation.not_salvageable[0].object_id, AXE);
+        assert_eq!(log.materials[0].material, MaterialType::Iron);
+        assert_eq!(log.materials[0].units_salvaged, 12);
+        assert_eq!(log.materials[0].mean_salvage_workmanship, Some(6.0));
+    }
+
+    #[test]
+    fn test_tinker_success_and_failure() {
+        let mut tracker = tracker_with_bag();
+        tracker.process(0.0, &tinker(SWORD));
+        tracker.process(
+            0.5,
+            &event(GameEventMessage::CharacterConfirmationRequest(
+                CharacterConfirmationRequest {
+                    confirmation_type: ConfirmationType::Craft,
+                    context_id: 1,
+                    text: "You have a 67% chance of success.".to_string(),
+                },
+            )),
+        );
+        tracker.process(
+            2.0,
+            &MessageKind::S2C(Box::new(S2CMessage::QualitiesUpdateInt(
+                QualitiesUpdateInt {
+                    sequence: 0,
+                    object_id: ObjectId(SWORD),
+                    key: PropertyInt::NumTimesTinkered,
+                    value: 1,
+                },
+            ))),
+        );
+        tracker.process(5.0, &tinker(AXE));
+        tracker.process(
+            6.0,
+            &MessageKind::S2C(Box::new(S2CMessage::ItemDeleteObject(ItemDeleteObject {
+                object_id: ObjectId(AXE),
+                object_instance_sequence: 0,
+            }))),
+        );
+        let log = tracker.finish();
+
+        assert_eq!(log.tinkers.len(), 2);
+        let success = &log.tinkers[0];
+        assert_eq!(success.outcome, TinkerOutcome::Succeeded);
+        assert_eq!(success.target_name.as_deref(), Some("Sword"));
+        assert_eq!(success.material, Some(MaterialType::Iron));
+        assert_eq!(
+            success.confirmation.as_deref(),
+            Some("You have a 67% chance of success.")
+        );
+        assert_eq!(success.changes[0].value, "1");
+        assert_eq!(log.tinkers[1].outcome, TinkerOutcome::Failed);
+
+        assert_eq!(log.materials[0].tinkers, 2);
+        assert_eq!(log.materials[0].success_rate, Some(0.5));
+    }
+
+    #[test]
+    fn test_cancelled_and_ordinary_uses() {
+        let mut tracker = tracker_with_bag();
+        // Using a key on a door is not tinkering
+        tracker.objects.insert(0x80000020, "Key");
+        tracker.process(
+            0.0,
+            &action(GameActionMessage::InventoryUseWithTargetEvent(
+                InventoryUseWithTargetEvent {
+                    object_id: ObjectId(0x80000020),
+                    target_id: ObjectId(0x80000021),
+                },
+            )),
+        );
+        tracker.process(1.0, &tinker(SWORD));
+        tracker.process(
+            2.0,
+            &action(GameActionMessage::CharacterConfirmationResponse(
+                CharacterConfirmationResponse {
+                    type_: ConfirmationType::Craft,
+                    context: 1,
+                    accepted: false,
+                },
+            )),
+        );
+        let log = tracker.finish();
+
+        assert_eq!(log.tinkers.len(), 1);
+        assert_eq!(log.tinkers[0].outcome, TinkerOutcome::Cancelled);
+        assert_eq!(log.materials[0].success_rate, None);
+    }
+
+    #[test]
+    fn test_outcomes_come_from_structured_results() {
+        let mut tracker = CraftingTracker::new();
+        // Tinkering material is recognised by its item type, whatever it is called
+        tracker.describe(
+            BAG,
+            &PublicWeenieDesc {
+                type_: ItemType::TINKERING_MATERIAL,
+                material: Some(MaterialType::Granite),
+                workmanship: Some(9.0),
+                ..description(0x5000, "Bag of Failing")
+            },
+        );
+        tracker.process(0.0, &tinker(SWORD));
+        // Neither craft text nor unrelated property changes decide the outcome
+        tracker.process(
+            0.5,
+            &MessageKind::S2C(Box::new(S2CMessage::CommunicationTextboxString(
+                CommunicationTextboxString {
+                    text: "You fail to notice anything.".to_string(),
+                    type_: ChatFragmentType::Craft,
+                },
+            ))),
+        );
+        tracker.process(
+            1.0,
+            &MessageKind::S2C(Box::new(S2CMessage::QualitiesUpdateInt(
+                QualitiesUpdateInt {
+                    sequence: 0,
+                    object_id: ObjectId(SWORD),
+                    key: PropertyInt::Value,
+                    value: 900,
+                },
+            ))),
+        );
+        tracker.process(5.0, &tinker(AXE));
+        tracker.process(
+            5.5,
+            &event(GameEventMessage::CommunicationWeenieError(
+                CommunicationWeenieError {
+                    type_: WeenieError::YouDoNotPassCraftingRequirements,
+                },
+            )),
+        );
+        let log = tracker.finish();
+
+        assert_eq!(log.tinkers.len(), 2);
+        assert_eq!(log.tinkers[0].outcome, TinkerOutcome::Unknown);
+        assert_eq!(log.tinkers[0].material, Some(MaterialType::Granite));
+        assert_eq!(log.tinkers[0].messages.len(), 1);
+        assert_eq!(log.tinkers[1].outcome, TinkerOutcome::Refused);
+        assert_eq!(log.materials[0].tinkers, 2);
+        assert_eq!(log.materials[0].success_rate, None);
+    }
+}
@@ -0,0 +1,60 @@
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// Quote a field if it contains a comma, quote or line break
+pub(crate) fn escape(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Write a single CSV record
+pub(crate) fn write_row<W: Write, S: AsRef<str>>(writer: &mut W, fields: &[S]) -> io::Result<()> {
+    let line = fields
+        .iter()
+        .map(|f| escape(f.as_ref()))
+        .collect::<Vec<_>>()
+        .join(",");
+    writeln!(writer, "{line}")
+}
+
+/// Format a set of flags as their names joined with `|`
+pub(crate) fn flags<B>(value: &B) -> io::Result<String>
+where
+    B: bitflags::Flags,
+    B::Bits: bitflags::parser::WriteHex,
+{
+    let mut out = String::new();
+    bitflags::parser::to_writer(value, &mut out).map_err(io::Error::other)?;
+    Ok(out)
+}
+
+/// Format an optional value, leaving the field empty for `None`
+pub(crate) fn opt<T: ToString>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_plain_field() {
+        assert_eq!(escape("Pyreal"), "Pyreal");
+    }
+
+    #[test]
+    fn test_escape_quotes_and_commas() {
+        assert_eq!(escape("a,b"), "\"a,b\"");
+        assert_eq!(escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_write_row() {
+        let mut out = Vec::new();
+        write_row(&mut out, &["1", "two, three", ""]).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1,\"two, three\",\n");
+    }
+}
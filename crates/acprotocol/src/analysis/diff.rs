@@ -0,0 +1,455 @@
+//! Semantic diff between two captures
+//!
+//! The message sequences are aligned by direction and message type with a
+//! shortest edit script (as `diff` does for lines). Messages only in the
+//! first capture are reported as missing and messages only in the second as
+//! inserted, except that a missing and an inserted message of the same type
+//! are paired up as moved. Aligned and moved pairs are then compared field by
+//! field on their decoded contents.
+//!
+//! Field paths are dotted, starting inside the message, with game event and
+//! action fields lifted to the top: `IntProperties.Table.19`, `sequence`.
+//! Fields can be ignored with globs where `*` matches within a segment and
+//! `**` matches any number of segments, compared without regard to case.
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::network::RawMessage;
+
+/// Globs for fields that differ between sessions without meaning anything:
+/// object ids, sequence numbers and timestamps
+pub const VOLATILE_FIELDS: &[&str] = &[
+    "**.*objectid",
+    "**.object_id",
+    "**.*sequence",
+    "**.*timestamp",
+    "**.*time",
+];
+
+/// A field whose value differs between two messages
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldDiff {
+    pub path: String,
+    /// The value in the first capture, or null if absent
+    pub a: Value,
+    /// The value in the second capture, or null if absent
+    pub b: Value,
+}
+
+/// One difference between the captures, in the order of the first capture
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiffEntry {
+    /// Aligned messages whose fields differ
+    Changed {
+        a: u32,
+        b: u32,
+        message_type: String,
+        fields: Vec<FieldDiff>,
+    },
+    /// A message only in the first capture
+    Missing { a: u32, message_type: String },
+    /// A message only in the second capture
+    Inserted { b: u32, message_type: String },
+    /// A message found in both captures but in a different place
+    Moved {
+        a: u32,
+        b: u32,
+        message_type: String,
+        fields: Vec<FieldDiff>,
+    },
+}
+
+/// Counts of each kind of difference
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DiffSummary {
+    pub equal: usize,
+    pub changed: usize,
+    pub missing: usize,
+    pub inserted: usize,
+    pub moved: usize,
+}
+
+/// The differences between two captures
+#[derive(Clone, Debug, Serialize)]
+pub struct CaptureDiff {
+    pub a_messages: usize,
+    pub b_messages: usize,
+    pub ignored: Vec<String>,
+    pub summary: DiffSummary,
+    pub entries: Vec<DiffEntry>,
+}
+
+impl CaptureDiff {
+    /// Diff two captures, skipping fields that match any of the globs
+    pub fn new(a: &[RawMessage], b: &[RawMessage], ignore: &[String]) -> Self {
+        let key = |m: &RawMessage| (m.direction.clone(), m.message_type.clone());
+        let a_keys: Vec<_> = a.iter().map(key).collect();
+        let b_keys: Vec<_> = b.iter().map(key).collect();
+        let ops = align(&a_keys, &b_keys);
+
+        // Pair each missing message with the first unclaimed insertion of the same type
+        let mut inserted: Vec<usize> = ops
+            .iter()
+            .filter_map(|op| match op {
+                Op::Insert(j) => Some(*j),
+                _ => None,
+            })
+            .collect();
+        let mut moved_to = vec![None; a.len()];
+        for op in &ops {
+            if let Op::Delete(i) = *op
+                && let Some(pos) = inserted.iter().position(|&j| b_keys[j] == a_keys[i])
+            {
+                moved_to[i] = Some(inserted.remove(pos));
+            }
+        }
+
+        let inserted: HashSet<usize> = inserted.into_iter().collect();
+        let mut summary = DiffSummary::default();
+        let mut entries = Vec::new();
+        for op in ops {
+            match op {
+                Op::Equal(i, j) => {
+                    let fields = diff_messages(&a[i], &b[j], ignore);
+                    if fields.is_empty() {
+                        summary.equal += 1;
+                    } else {
+                        summary.changed += 1;
+                        entries.push(DiffEntry::Changed {
+                            a: a[i].id,
+                            b: b[j].id,
+                            message_type: a[i].message_type.clone(),
+                            fields,
+                        });
+                    }
+                }
+                Op::Delete(i) => match moved_to[i] {
+                    Some(j) => {
+                        summary.moved += 1;
+                        entries.push(DiffEntry::Moved {
+                            a: a[i].id,
+                            b: b[j].id,
+                            message_type: a[i].message_type.clone(),
+                            fields: diff_messages(&a[i], &b[j], ignore),
+                        });
+                    }
+                    None => {
+                        summary.missing += 1;
+                        entries.push(DiffEntry::Missing {
+                            a: a[i].id,
+                            message_type: a[i].message_type.clone(),
+                        });
+                    }
+                },
+                Op::Insert(j) => {
+                    if inserted.contains(&j) {
+                        summary.inserted += 1;
+                        entries.push(DiffEntry::Inserted {
+                            b: b[j].id,
+                            message_type: b[j].message_type.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Self {
+            a_messages: a.len(),
+            b_messages: b.len(),
+            ignored: ignore.to_vec(),
+            summary,
+            entries,
+        }
+    }
+}
+
+/// A step in an edit script from one sequence to another
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    /// Element `i` of the first sequence matches element `j` of the second
+    Equal(usize, usize),
+    /// Element `i` of the first sequence is not in the second
+    Delete(usize),
+    /// Element `j` of the second sequence is not in the first
+    Insert(usize),
+}
+
+/// A shortest edit script between two sequences, using Myers' algorithm
+fn align<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Op> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    // The furthest reaching x on each diagonal before each step, for k in -(d+1)..=d+1
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let down =
+                k == -d || (k != d && v[(offset + k - 1) as usize] < v[(offset + k + 1) as usize]);
+            let mut x = if down {
+                v[(offset + k + 1) as usize]
+            } else {
+                v[(offset + k - 1) as usize] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[(offset + k) as usize] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, snapshot) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| snapshot[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Equal((x - 1) as usize, (y - 1) as usize));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == prev_x {
+                ops.push(Op::Insert((y - 1) as usize));
+            } else {
+                ops.push(Op::Delete((x - 1) as usize));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.reverse();
+    ops
+}
+
+/// The decoded fields of a message, with ordered message envelopes flattened
+fn body(message: &RawMessage) -> Value {
+    let Ok(mut value) = serde_json::to_value(message) else {
+        return Value::Null;
+    };
+    let data = value["data"].take();
+
+    // {"S2C": {"Variant": {...}}}
+    let inner = match data {
+        Value::Object(map) if map.len() == 1 => map.into_iter().next().map(|(_, v)| v),
+        other => return other,
+    };
+    let Some(Value::Object(variant)) = inner else {
+        return Value::Null;
+    };
+    let Some((_, Value::Object(mut fields))) = variant.into_iter().next() else {
+        return Value::Null;
+    };
+
+    // Lift {"event": {"Type": {...}}} and {"action": {...}} to the top level
+    for envelope in ["event", "action"] {
+        if let Some(Value::Object(wrapped)) = fields.remove(envelope) {
+            for (_, inner) in wrapped {
+                match inner {
+                    Value::Object(inner) => fields.extend(inner),
+                    other => {
+                        fields.insert(envelope.to_string(), other);
+                    }
+                }
+            }
+        }
+    }
+    Value::Object(fields)
+}
+
+/// The fields that differ between two messages
+pub fn diff_messages(a: &RawMessage, b: &RawMessage, ignore: &[String]) -> Vec<FieldDiff> {
+    let mut out = Vec::new();
+    diff_values("", &body(a), &body(b), ignore, &mut out);
+    out
+}
+
+fn join(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{path}.{segment}")
+    }
+}
+
+fn diff_values(path: &str, a: &Value, b: &Value, ignore: &[String], out: &mut Vec<FieldDiff>) {
+    if !path.is_empty() && ignore.iter().any(|glob| glob_matches(glob, path)) {
+        return;
+    }
+
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let keys = a.keys().chain(b.keys().filter(|k| !a.contains_key(*k)));
+            for key in keys {
+                diff_values(
+                    &join(path, key),
+                    a.get(key).unwrap_or(&Value::Null),
+                    b.get(key).unwrap_or(&Value::Null),
+                    ignore,
+                    out,
+                );
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            for i in 0..a.len().max(b.len()) {
+                diff_values(
+                    &join(path, &i.to_string()),
+                    a.get(i).unwrap_or(&Value::Null),
+                    b.get(i).unwrap_or(&Value::Null),
+                    ignore,
+                    out,
+                );
+            }
+        }
+        _ if a == b => {}
+        _ => out.push(FieldDiff {
+            path: path.to_string(),
+            a: a.clone(),
+            b: b.clone(),
+        }),
+    }
+}
+
+/// Whether a dotted path matches a glob
+pub fn glob_matches(glob: &str, path: &str) -> bool {
+    let glob: Vec<String> = glob.split('.').map(str::to_lowercase).collect();
+    let path: Vec<String> = path.split('.').map(str::to_lowercase).collect();
+    let glob: Vec<&str> = glob.iter().map(String::as_str).collect();
+    let path: Vec<&str> = path.iter().map(String::as_str).collect();
+    match_segments(&glob, &path)
+}
+
+fn match_segments(glob: &[&str], path: &[&str]) -> bool {
+    match glob.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| match_segments(rest, &path[skip..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path)) => {
+                match_segment(first.as_bytes(), segment.as_bytes()) && match_segments(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Match one segment against a pattern with `*` and `?` wildcards
+fn match_segment(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| match_segment(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && match_segment(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && match_segment(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delete_object(id: u32, object_id: u32, sequence: u16) -> RawMessage {
+        let mut data = 0xF747u32.to_le_bytes().to_vec();
+        data.extend(object_id.to_le_bytes());
+        data.extend(sequence.to_le_bytes());
+        data.extend([0, 0]);
+        RawMessage::from_fragment(data, id, id).unwrap()
+    }
+
+    fn play_script(id: u32, object_id: u32) -> RawMessage {
+        let mut data = 0xF755u32.to_le_bytes().to_vec();
+        data.extend(object_id.to_le_bytes());
+        data.extend(7u32.to_le_bytes());
+        data.extend(1.0f32.to_le_bytes());
+        RawMessage::from_fragment(data, id, id).unwrap()
+    }
+
+    #[test]
+    fn test_align_shortest_edit_script() {
+        let a: Vec<char> = "ABCABBA".chars().collect();
+        let b: Vec<char> = "CBABAC".chars().collect();
+        let ops = align(&a, &b);
+
+        let equal = ops.iter().filter(|op| matches!(op, Op::Equal(..))).count();
+        assert_eq!(equal, 4);
+        assert_eq!(ops.len(), 4 + (7 - 4) + (6 - 4));
+        for op in &ops {
+            if let Op::Equal(i, j) = *op {
+                assert_eq!(a[i], b[j]);
+            }
+        }
+
+        assert_eq!(align::<char>(&[], &[]), vec![]);
+        assert_eq!(align(&['x'], &[]), vec![Op::Delete(0)]);
+        assert_eq!(align(&[], &['x']), vec![Op::Insert(0)]);
+    }
+
+    #[test]
+    fn test_changed_moved_missing_and_inserted() {
+        let unknown = RawMessage::from_fragment(vec![0xEF, 0xBE, 0xAD, 0xDE], 4, 4).unwrap();
+        let a = vec![
+            delete_object(1, 0x80000001, 1),
+            play_script(2, 0x50000005),
+            play_script(3, 0x50000006),
+            unknown,
+        ];
+        let b = vec![
+            play_script(10, 0x50000005),
+            play_script(11, 0x50000006),
+            delete_object(12, 0x80000001, 2),
+            delete_object(13, 0x80000002, 1),
+        ];
+        let diff = CaptureDiff::new(&a, &b, &[]);
+
+        assert_eq!(diff.summary.equal, 2);
+        assert_eq!(diff.summary.moved, 1);
+        assert_eq!(diff.summary.missing, 1);
+        assert_eq!(diff.summary.inserted, 1);
+
+        let DiffEntry::Moved { a, b, fields, .. } = &diff.entries[0] else {
+            panic!("expected a move: {:?}", diff.entries);
+        };
+        assert_eq!((*a, *b), (1, 12));
+        assert_eq!(fields[0].path, "ObjectInstanceSequence");
+        assert_eq!(
+            (fields[0].a.clone(), fields[0].b.clone()),
+            (1.into(), 2.into())
+        );
+    }
+
+    #[test]
+    fn test_ignored_fields() {
+        let a = [delete_object(1, 0x80000001, 1)];
+        let b = [delete_object(1, 0x80000002, 2)];
+
+        assert_eq!(diff_messages(&a[0], &b[0], &[]).len(), 2);
+        let volatile: Vec<String> = VOLATILE_FIELDS.iter().map(|g| g.to_string()).collect();
+        assert!(diff_messages(&a[0], &b[0], &volatile).is_empty());
+        assert!(diff_messages(&a[0], &b[0], &["ObjectId".into(), "*Sequence".into()]).is_empty());
+
+        assert!(glob_matches("**.objectid", "ObjectId"));
+        assert!(glob_matches(
+            "IntProperties.Table.*",
+            "IntProperties.Table.19"
+        ));
+        assert!(!glob_matches("IntProperties.*", "IntProperties.Table.19"));
+        assert!(glob_matches("**.Table.?9", "a.b.Table.19"));
+    }
+}
@@ -0,0 +1,655 @@
+//! Enchantment timelines
+//!
+//! Follows `Magic_UpdateEnchantment`, the remove/dispel/purge events and the
+//! enchantment registry sent in `Login_PlayerDescription` to work out which
+//! enchantments were active on each object at any point in a capture.
+//!
+//! Enchantments are keyed by their `LayeredSpellId`, so several layers of the
+//! same spell are tracked independently. Within a `SpellCategory` only one
+//! enchantment is in effect at a time: the one with the highest power level,
+//! with ties going to the most recently cast. The rest are still active but
+//! surpassed, which the timeline records as stacking segments.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::enums::{EnchantmentTypeFlags, SpellCategory};
+use crate::message::{GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+use crate::types::{Enchantment, EnchantmentRegistry, LayeredSpellId};
+
+use super::csv::{flags, opt, write_row};
+use super::{game_event, parse_all};
+
+/// Why an enchantment stopped being active
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum EndReason {
+    /// Its duration ran out
+    Expired,
+    /// Removed by the server (`Magic_RemoveEnchantment`)
+    Removed,
+    /// Dispelled (`Magic_DispelEnchantment`)
+    Dispelled,
+    /// Removed by `Magic_PurgeEnchantments` or `Magic_PurgeBadEnchantments`
+    Purged,
+    /// Replaced by a new cast of the same spell and layer
+    Refreshed,
+    /// Missing from a later enchantment registry in `Login_PlayerDescription`
+    Reset,
+}
+
+/// A stretch of time during which an enchantment's stacking state did not change
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct StackingSegment {
+    pub start: f64,
+    /// End of the segment, or `None` if it was still running when the capture ended
+    pub end: Option<f64>,
+    /// Whether the enchantment was the one in effect for its spell category
+    pub effective: bool,
+}
+
+/// When an enchantment received at `time` runs out
+///
+/// `StartTime` is relative to the time the message was sent and is zero or
+/// negative, and a negative `Duration` means the enchantment never expires.
+fn expiry(time: f64, enchantment: &Enchantment) -> Option<f64> {
+    (enchantment.duration >= 0.0).then_some(time + enchantment.start_time + enchantment.duration)
+}
+
+/// A single enchantment from the time it was applied until it ended
+#[derive(Clone, Debug, Serialize)]
+pub struct EnchantmentInterval {
+    /// Object the enchantment is on
+    pub object_id: u32,
+    pub spell_id: u16,
+    pub layer: u16,
+    pub category: SpellCategory,
+    pub power_level: u32,
+    pub caster_id: u32,
+    pub stat_mod_type: EnchantmentTypeFlags,
+    pub stat_mod_key: u32,
+    pub stat_mod_value: f32,
+    /// When the server told the client about the enchantment
+    pub applied: f64,
+    /// When the spell was cast, derived from the enchantment's start time
+    pub cast: f64,
+    /// Duration in seconds, or `None` for enchantments that never expire
+    pub duration: Option<f64>,
+    /// When the enchantment is due to run out
+    pub expires: Option<f64>,
+    /// When the enchantment ended, or `None` if it outlived the capture
+    pub end: Option<f64>,
+    pub end_reason: Option<EndReason>,
+    pub segments: Vec<StackingSegment>,
+}
+
+impl EnchantmentInterval {
+    fn new(object_id: u32, time: f64, enchantment: &Enchantment) -> Self {
+        let duration = (enchantment.duration >= 0.0).then_some(enchantment.duration);
+
+        Self {
+            object_id,
+            spell_id: enchantment.id.id.0,
+            layer: enchantment.id.layer,
+            category: enchantment.spell_category.clone(),
+            power_level: enchantment.power_level,
+            caster_id: enchantment.caster_id.0,
+            stat_mod_type: enchantment.stat_mod.type_,
+            stat_mod_key: enchantment.stat_mod.key,
+            stat_mod_value: enchantment.stat_mod.value,
+            applied: time,
+            cast: time + enchantment.start_time,
+            duration,
+            expires: expiry(time, enchantment),
+            end: None,
+            end_reason: None,
+            segments: Vec::new(),
+        }
+    }
+
+    fn category_id(&self) -> u16 {
+        self.category.clone() as u16
+    }
+
+    /// Whether the enchantment is beneficial to the object it is on
+    pub fn is_beneficial(&self) -> bool {
+        self.stat_mod_type
+            .contains(EnchantmentTypeFlags::BENEFICIAL)
+    }
+
+    /// Vitae and spell cooldowns survive purges
+    fn is_purgeable(&self) -> bool {
+        !self
+            .stat_mod_type
+            .intersects(EnchantmentTypeFlags::VITAE | EnchantmentTypeFlags::COOLDOWN)
+    }
+
+    /// Whether the enchantment was active at the given time
+    pub fn is_active_at(&self, time: f64) -> bool {
+        self.applied <= time && self.end.is_none_or(|end| time < end)
+    }
+
+    /// Whether the enchantment was the one in effect for its category at the given time
+    pub fn is_effective_at(&self, time: f64) -> bool {
+        self.segments
+            .iter()
+            .any(|s| s.effective && s.start <= time && s.end.is_none_or(|end| time < end))
+    }
+
+    fn close(&mut self, time: f64, reason: EndReason) {
+        self.end = Some(time);
+        self.end_reason = Some(reason);
+        if let Some(segment) = self.segments.last_mut()
+            && segment.end.is_none()
+        {
+            segment.end = Some(time);
+        }
+    }
+}
+
+/// Builds enchantment timelines from messages fed to it in capture order
+#[derive(Default)]
+pub struct EnchantmentTracker {
+    intervals: Vec<EnchantmentInterval>,
+    /// Open intervals per object, by index into `intervals`
+    active: HashMap<u32, HashMap<LayeredSpellId, usize>>,
+    first_time: Option<f64>,
+    last_time: f64,
+}
+
+impl EnchantmentTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.first_time.get_or_insert(time);
+        self.advance(time);
+
+        let Some((object_id, event)) = game_event(message) else {
+            return;
+        };
+
+        match event {
+            GameEventMessage::MagicUpdateEnchantment(msg) => {
+                self.apply(object_id, time, &msg.enchantment);
+            }
+            GameEventMessage::MagicUpdateMultipleEnchantments(msg) => {
+                for enchantment in &msg.enchantments.list {
+                    self.apply(object_id, time, enchantment);
+                }
+            }
+            GameEventMessage::MagicRemoveEnchantment(msg) => {
+                self.end(object_id, &msg.spell_id, time, EndReason::Removed);
+            }
+            GameEventMessage::MagicRemoveMultipleEnchantments(msg) => {
+                for spell_id in &msg.enchantments.list {
+                    self.end(object_id, spell_id, time, EndReason::Removed);
+                }
+            }
+            GameEventMessage::MagicDispelEnchantment(msg) => {
+                self.end(object_id, &msg.spell_id, time, EndReason::Dispelled);
+            }
+            GameEventMessage::MagicDispelMultipleEnchantments(msg) => {
+                for spell_id in &msg.enchantments.list {
+                    self.end(object_id, spell_id, time, EndReason::Dispelled);
+                }
+            }
+            GameEventMessage::MagicPurgeEnchantments(_) => {
+                self.purge(object_id, time, |_| true);
+            }
+            GameEventMessage::MagicPurgeBadEnchantments(_) => {
+                self.purge(object_id, time, |interval| !interval.is_beneficial());
+            }
+            GameEventMessage::LoginPlayerDescription(msg) => {
+                if let Some(registry) = &msg.qualities.enchantments {
+                    self.reset(object_id, time, registry);
+                }
+            }
+            _ => return,
+        }
+
+        self.restack(object_id, time);
+    }
+
+    /// Finish tracking and return the timeline
+    ///
+    /// Enchantments still running at the end of the capture are left open.
+    pub fn finish(mut self) -> EnchantmentTimeline {
+        self.advance(self.last_time);
+
+        EnchantmentTimeline {
+            start: self.first_time.unwrap_or_default(),
+            end: self.last_time,
+            intervals: self.intervals,
+        }
+    }
+
+    /// Expire every enchantment due to run out at or before `time`, in order
+    fn advance(&mut self, time: f64) {
+        loop {
+            let next = self
+                .active
+                .iter()
+                .flat_map(|(object_id, spells)| {
+                    spells.iter().filter_map(|(spell_id, &index)| {
+                        let expires = self.intervals[index].expires?;
+                        (expires <= time).then_some((expires, *object_id, spell_id.clone()))
+                    })
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+
+            let Some((expires, object_id, spell_id)) = next else {
+                break;
+            };
+            self.end(object_id, &spell_id, expires, EndReason::Expired);
+            self.restack(object_id, expires);
+        }
+
+        self.last_time = self.last_time.max(time);
+    }
+
+    fn apply(&mut self, object_id: u32, time: f64, enchantment: &Enchantment) {
+        self.end(object_id, &enchantment.id, time, EndReason::Refreshed);
+
+        let index = self.intervals.len();
+        self.intervals
+            .push(EnchantmentInterval::new(object_id, time, enchantment));
+        self.active
+            .entry(object_id)
+            .or_default()
+            .insert(enchantment.id.clone(), index);
+    }
+
+    fn end(&mut self, object_id: u32, spell_id: &LayeredSpellId, time: f64, reason: EndReason) {
+        if let Some(index) = self
+            .active
+            .get_mut(&object_id)
+            .and_then(|spells| spells.remove(spell_id))
+        {
+            self.intervals[index].close(time, reason);
+        }
+    }
+
+    fn purge(&mut self, object_id: u32, time: f64, filter: impl Fn(&EnchantmentInterval) -> bool) {
+        let Some(spells) = self.active.get_mut(&object_id) else {
+            return;
+        };
+
+        spells.retain(|_, &mut index| {
+            let interval = &mut self.intervals[index];
+            if interval.is_purgeable() && filter(interval) {
+                interval.close(time, EndReason::Purged);
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    /// Replace the object's enchantments with those in a registry
+    fn reset(&mut self, object_id: u32, time: f64, registry: &EnchantmentRegistry) {
+        let enchantments: Vec<&Enchantment> = [&registry.life_spells, &registry.creature_spells]
+            .into_iter()
+            .chain([&registry.cooldowns])
+            .flatten()
+            .flat_map(|list| list.list.iter())
+            .chain(registry.vitae.as_ref())
+            .collect();
+
+        if let Some(spells) = self.active.get_mut(&object_id) {
+            spells.retain(|spell_id, &mut index| {
+                if enchantments.iter().any(|e| &e.id == spell_id) {
+                    true
+                } else {
+                    self.intervals[index].close(time, EndReason::Reset);
+                    false
+                }
+            });
+        }
+
+        for enchantment in enchantments {
+            // Registries repeat enchantments we already know about; only
+            // start a new interval if something about them has changed
+            let expires = expiry(time, enchantment);
+            let unchanged = self
+                .active
+                .get(&object_id)
+                .and_then(|spells| spells.get(&enchantment.id))
+                .is_some_and(|&index| {
+                    let interval = &self.intervals[index];
+                    interval.power_level == enchantment.power_level
+                        && match (interval.expires, expires) {
+                            (Some(a), Some(b)) => (a - b).abs() < 1.0,
+                            (a, b) => a.is_none() && b.is_none(),
+                        }
+                });
+            if !unchanged {
+                self.apply(object_id, time, enchantment);
+            }
+        }
+    }
+
+    /// Recompute which enchantment is in effect for each of the object's spell categories
+    fn restack(&mut self, object_id: u32, time: f64) {
+        let Some(spells) = self.active.get(&object_id) else {
+            return;
+        };
+
+        let mut winners: HashMap<u16, usize> = HashMap::new();
+        for &index in spells.values() {
+            let interval = &self.intervals[index];
+            let category = interval.category_id();
+            // Enchantments without a category never compete with each other
+            if category == SpellCategory::Undef as u16 {
+                continue;
+            }
+            let beats = |current: &EnchantmentInterval| {
+                (interval.power_level, interval.cast, interval.layer)
+                    > (current.power_level, current.cast, current.layer)
+            };
+            match winners.get(&category) {
+                Some(&current) if !beats(&self.intervals[current]) => {}
+                _ => {
+                    winners.insert(category, index);
+                }
+            }
+        }
+
+        let indices: Vec<usize> = spells.values().copied().collect();
+        for index in indices {
+            let interval = &mut self.intervals[index];
+            let category = interval.category_id();
+            let effective =
+                category == SpellCategory::Undef as u16 || winners.get(&category) == Some(&index);
+
+            match interval.segments.last_mut() {
+                Some(segment) if segment.effective == effective => {}
+                Some(segment) => {
+                    segment.end = Some(time);
+                    interval.segments.push(StackingSegment {
+                        start: time,
+                        end: None,
+                        effective,
+                    });
+                }
+                None => interval.segments.push(StackingSegment {
+                    start: time,
+                    end: None,
+                    effective,
+                }),
+            }
+        }
+    }
+}
+
+/// Every enchantment seen in a capture, in the order they were applied
+#[derive(Clone, Debug, Serialize)]
+pub struct EnchantmentTimeline {
+    /// Time of the first message in the capture
+    pub start: f64,
+    /// Time of the last message in the capture
+    pub end: f64,
+    pub intervals: Vec<EnchantmentInterval>,
+}
+
+impl EnchantmentTimeline {
+    /// Build a timeline from a capture's messages
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut tracker = EnchantmentTracker::new();
+        for (raw, message) in parse_all(messages) {
+            tracker.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        tracker.finish()
+    }
+
+    /// Enchantments active on an object at the given time
+    pub fn active_at(&self, object_id: u32, time: f64) -> Vec<&EnchantmentInterval> {
+        self.intervals
+            .iter()
+            .filter(|i| i.object_id == object_id && i.is_active_at(time))
+            .collect()
+    }
+
+    /// Enchantments in effect on an object at the given time, after stacking
+    pub fn effective_at(&self, object_id: u32, time: f64) -> Vec<&EnchantmentInterval> {
+        self.intervals
+            .iter()
+            .filter(|i| i.object_id == object_id && i.is_effective_at(time))
+            .collect()
+    }
+
+    /// Write the timeline as CSV, one row per stacking segment
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &[
+                "object_id",
+                "spell_id",
+                "layer",
+                "category",
+                "power_level",
+                "caster_id",
+                "stat_mod_type",
+                "stat_mod_key",
+                "stat_mod_value",
+                "applied",
+                "cast",
+                "duration",
+                "expires",
+                "end",
+                "end_reason",
+                "segment_start",
+                "segment_end",
+                "effective",
+            ],
+        )?;
+
+        for interval in &self.intervals {
+            let stat_mod_type = flags(&interval.stat_mod_type)?;
+
+            for segment in &interval.segments {
+                write_row(
+                    &mut writer,
+                    &[
+                        format!("0x{:08X}", interval.object_id),
+                        interval.spell_id.to_string(),
+                        interval.layer.to_string(),
+                        format!("{:?}", interval.category),
+                        interval.power_level.to_string(),
+                        format!("0x{:08X}", interval.caster_id),
+                        stat_mod_type.clone(),
+                        interval.stat_mod_key.to_string(),
+                        interval.stat_mod_value.to_string(),
+                        interval.applied.to_string(),
+                        interval.cast.to_string(),
+                        opt(interval.duration),
+                        opt(interval.expires),
+                        opt(interval.end),
+                        opt(interval.end_reason.map(|r| format!("{r:?}"))),
+                        segment.start.to_string(),
+                        opt(segment.end),
+                        segment.effective.to_string(),
+                    ],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{PLAYER, event};
+    use crate::gameevents::{MagicDispelEnchantment, MagicUpdateEnchantment};
+
+    use crate::types::{ObjectId, SpellId, StatMod};
+
+    fn enchantment(spell: u16, category: SpellCategory, power: u32, duration: f64) -> Enchantment {
+        Enchantment {
+            id: LayeredSpellId {
+                id: SpellId(spell),
+                layer: 1,
+            },
+            has_equipment_set: 0,
+            spell_category: category,
+            power_level: power,
+            start_time: 0.0,
+            duration,
+            caster_id: ObjectId(PLAYER),
+            degrade_modifier: 0.0,
+            degrade_limit: -666.0,
+            last_time_degraded: 0.0,
+            stat_mod: StatMod {
+                type_: EnchantmentTypeFlags::BENEFICIAL,
+                key: 1,
+                value: 10.0,
+            },
+            equipment_set: None,
+        }
+    }
+
+    fn update(enchantment: Enchantment) -> MessageKind {
+        event(GameEventMessage::MagicUpdateEnchantment(
+            MagicUpdateEnchantment { enchantment },
+        ))
+    }
+
+    #[test]
+    fn test_enchantment_expires_after_duration() {
+        let mut tracker = EnchantmentTracker::new();
+        tracker.process(
+            100.0,
+            &update(enchantment(2, SpellCategory::StrengthRaising, 1, 60.0)),
+        );
+        tracker.process(
+            200.0,
+            &event(GameEventMessage::MagicPurgeBadEnchantments(
+                crate::gameevents::MagicPurgeBadEnchantments {},
+            )),
+        );
+        let timeline = tracker.finish();
+
+        let interval = &timeline.intervals[0];
+        assert_eq!(interval.expires, Some(160.0));
+        assert_eq!(interval.end, Some(160.0));
+        assert_eq!(interval.end_reason, Some(EndReason::Expired));
+        assert_eq!(timeline.active_at(PLAYER, 150.0).len(), 1);
+        assert!(timeline.active_at(PLAYER, 170.0).is_empty());
+    }
+
+    #[test]
+    fn test_stronger_enchantment_surpasses_weaker_in_same_category() {
+        let mut tracker = EnchantmentTracker::new();
+        tracker.process(
+            0.0,
+            &update(enchantment(2, SpellCategory::StrengthRaising, 1, -1.0)),
+        );
+        tracker.process(
+            10.0,
+            &update(enchantment(1328, SpellCategory::StrengthRaising, 6, -1.0)),
+        );
+        tracker.process(
+            20.0,
+            &event(GameEventMessage::MagicDispelEnchantment(
+                MagicDispelEnchantment {
+                    spell_id: LayeredSpellId {
+                        id: SpellId(1328),
+                        layer: 1,
+                    },
+                },
+            )),
+        );
+        let timeline = tracker.finish();
+
+        let weak = &timeline.intervals[0];
+        assert_eq!(
+            weak.segments,
+            vec![
+                StackingSegment {
+                    start: 0.0,
+                    end: Some(10.0),
+                    effective: true
+                },
+                StackingSegment {
+                    start: 10.0,
+                    end: Some(20.0),
+                    effective: false
+                },
+                StackingSegment {
+                    start: 20.0,
+                    end: None,
+                    effective: true
+                },
+            ]
+        );
+        assert_eq!(timeline.intervals[1].end_reason, Some(EndReason::Dispelled));
+        assert_eq!(timeline.effective_at(PLAYER, 15.0)[0].spell_id, 1328);
+    }
+
+    #[test]
+    fn test_layers_are_tracked_separately() {
+        let mut tracker = EnchantmentTracker::new();
+        let first = enchantment(2, SpellCategory::StrengthRaising, 1, -1.0);
+        let mut second = first.clone();
+        second.id.layer = 2;
+        tracker.process(0.0, &update(first));
+        tracker.process(1.0, &update(second));
+        let timeline = tracker.finish();
+
+        assert_eq!(timeline.active_at(PLAYER, 2.0).len(), 2);
+        assert_eq!(timeline.effective_at(PLAYER, 2.0)[0].layer, 2);
+    }
+
+    #[test]
+    fn test_csv_has_row_per_segment() {
+        let mut tracker = EnchantmentTracker::new();
+        tracker.process(
+            0.0,
+            &update(enchantment(2, SpellCategory::StrengthRaising, 1, -1.0)),
+        );
+        let mut out = Vec::new();
+        tracker.finish().write_csv(&mut out).unwrap();
+
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("0x50000001,2,1,StrengthRaising,1,"));
+    }
+
+    #[test]
+    fn test_capture_cut_at_both_ends() {
+        let mut tracker = EnchantmentTracker::new();
+        // Removing an enchantment cast before the capture started
+        tracker.process(
+            0.0,
+            &event(GameEventMessage::MagicRemoveEnchantment(
+                crate::gameevents::MagicRemoveEnchantment {
+                    spell_id: LayeredSpellId {
+                        id: SpellId(1328),
+                        layer: 1,
+                    },
+                },
+            )),
+        );
+        // An enchantment outlasting the capture
+        tracker.process(
+            10.0,
+            &update(enchantment(2, SpellCategory::StrengthRaising, 1, 3600.0)),
+        );
+        let timeline = tracker.finish();
+
+        assert_eq!(timeline.intervals.len(), 1);
+        let interval = &timeline.intervals[0];
+        assert_eq!(interval.expires, Some(3610.0));
+        assert_eq!(interval.end, None);
+        assert_eq!(interval.end_reason, None);
+        assert_eq!(interval.segments[0].end, None);
+        assert_eq!(timeline.end, 10.0);
+    }
+}
@@ -0,0 +1,567 @@
+//! Fellowship timelines
+//!
+//! Rebuilds each fellowship the player was in from `Fellowship_FullUpdate`,
+//! `Fellowship_UpdateFellow` and the disband, quit and dismiss events. The
+//! result records who was in the fellowship and when, and how XP was being
+//! shared at every point.
+//!
+//! Fellowships are tracked per recipient of the events, so a capture with
+//! several clients in it produces a timeline for each of them.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::message::{GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+use crate::types::Fellowship;
+
+use super::csv::{opt, write_row};
+use super::{game_event, parse_all};
+
+/// Why a fellowship ended for the player
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum FellowshipEnd {
+    /// The fellowship was disbanded (`Fellowship_Disband`, or `Fellowship_Quit` with `Disband` set)
+    Disbanded,
+    /// The player left (`Fellowship_Quit`)
+    Quit,
+    /// The player was dismissed (`Fellowship_Dismiss`)
+    Dismissed,
+    /// A full update arrived for a different fellowship
+    Replaced,
+}
+
+/// Why a member left a fellowship
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum LeaveReason {
+    /// Dismissed by the leader (`Fellowship_Dismiss`)
+    Dismissed,
+    /// Missing from a later `Fellowship_FullUpdate`
+    Departed,
+    /// The fellowship ended for the player
+    FellowshipEnded,
+}
+
+/// A character's time in a fellowship
+#[derive(Clone, Debug, Serialize)]
+pub struct FellowshipMember {
+    pub character_id: u32,
+    pub name: String,
+    /// Level from the most recent update about the member
+    pub level: u32,
+    pub joined: f64,
+    /// When the member left, or `None` if they were still in the fellowship when the capture ended
+    pub left: Option<f64>,
+    pub leave_reason: Option<LeaveReason>,
+}
+
+/// A stretch of time during which a fellowship's membership and settings did not change
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct XpShareSegment {
+    pub start: f64,
+    pub end: Option<f64>,
+    pub leader_id: u32,
+    pub share_xp: bool,
+    pub even_xp_split: bool,
+    pub open: bool,
+    pub locked: bool,
+    /// Members during the segment, by character id
+    pub members: Vec<u32>,
+}
+
+impl XpShareSegment {
+    fn same_state(&self, other: &XpShareSegment) -> bool {
+        self.leader_id == other.leader_id
+            && self.share_xp == other.share_xp
+            && self.even_xp_split == other.even_xp_split
+            && self.open == other.open
+            && self.locked == other.locked
+            && self.members == other.members
+    }
+}
+
+/// One fellowship, from the first update the player received about it until it ended
+#[derive(Clone, Debug, Serialize)]
+pub struct FellowshipSession {
+    /// Player the fellowship events were sent to
+    pub observer_id: u32,
+    pub name: String,
+    pub start: f64,
+    pub end: Option<f64>,
+    pub end_reason: Option<FellowshipEnd>,
+    pub members: Vec<FellowshipMember>,
+    pub segments: Vec<XpShareSegment>,
+}
+
+impl FellowshipSession {
+    fn new(observer_id: u32, time: f64, fellowship: &Fellowship) -> Self {
+        let mut session = Self {
+            observer_id,
+            name: fellowship.name.clone(),
+            start: time,
+            end: None,
+            end_reason: None,
+            members: Vec::new(),
+            segments: Vec::new(),
+        };
+        session.sync(time, fellowship);
+        session
+    }
+
+    /// Members still in the fellowship
+    pub fn current_members(&self) -> impl Iterator<Item = &FellowshipMember> {
+        self.members.iter().filter(|m| m.left.is_none())
+    }
+
+    /// The segment in effect at the given time
+    pub fn segment_at(&self, time: f64) -> Option<&XpShareSegment> {
+        self.segments
+            .iter()
+            .find(|s| s.start <= time && s.end.is_none_or(|end| time < end))
+    }
+
+    fn member_mut(&mut self, character_id: u32) -> Option<&mut FellowshipMember> {
+        self.members
+            .iter_mut()
+            .find(|m| m.character_id == character_id && m.left.is_none())
+    }
+
+    /// Bring membership and settings in line with a full update
+    fn sync(&mut self, time: f64, fellowship: &Fellowship) {
+        for member in self.members.iter_mut().filter(|m| m.left.is_none()) {
+            if !fellowship
+                .members
+                .table
+                .keys()
+                .any(|id| id.0 == member.character_id)
+            {
+                member.left = Some(time);
+                member.leave_reason = Some(LeaveReason::Departed);
+            }
+        }
+
+        let mut fellows = fellowship.members.table.iter().collect::<Vec<_>>();
+        fellows.sort_unstable_by_key(|(id, _)| id.0);
+        for (id, fellow) in fellows {
+            match self.member_mut(id.0) {
+                Some(member) => {
+                    member.name = fellow.name.clone();
+                    member.level = fellow.level;
+                }
+                None => self.members.push(FellowshipMember {
+                    character_id: id.0,
+                    name: fellow.name.clone(),
+                    level: fellow.level,
+                    joined: time,
+                    left: None,
+                    leave_reason: None,
+                }),
+            }
+        }
+
+        let leader_id = fellowship.leader_id.0;
+        let (share_xp, even_xp_split, open, locked) = (
+            fellowship.share_xp,
+            fellowship.even_xp_split,
+            fellowship.open,
+            fellowship.locked,
+        );
+        self.push_segment(time, |segment| {
+            segment.leader_id = leader_id;
+            segment.share_xp = share_xp;
+            segment.even_xp_split = even_xp_split;
+            segment.open = open;
+            segment.locked = locked;
+        });
+    }
+
+    fn remove(&mut self, time: f64, character_id: u32, reason: LeaveReason) {
+        if let Some(member) = self.member_mut(character_id) {
+            member.left = Some(time);
+            member.leave_reason = Some(reason);
+            self.push_segment(time, |_| {});
+        }
+    }
+
+    /// Start a new segment if the state after `change` differs from the current one
+    fn push_segment(&mut self, time: f64, change: impl FnOnce(&mut XpShareSegment)) {
+        let mut next = match self.segments.last() {
+            Some(last) => XpShareSegment {
+                start: time,
+                end: None,
+                ..last.clone()
+            },
+            None => XpShareSegment {
+                start: time,
+                end: None,
+                leader_id: 0,
+                share_xp: false,
+                even_xp_split: false,
+                open: false,
+                locked: false,
+                members: Vec::new(),
+            },
+        };
+        change(&mut next);
+        next.members = self.current_members().map(|m| m.character_id).collect();
+        next.members.sort_unstable();
+
+        if let Some(last) = self.segments.last_mut() {
+            if last.same_state(&next) {
+                return;
+            }
+            last.end = Some(time);
+        }
+        self.segments.push(next);
+    }
+
+    fn close(&mut self, time: f64, reason: FellowshipEnd) {
+        self.end = Some(time);
+        self.end_reason = Some(reason);
+        for member in self.members.iter_mut().filter(|m| m.left.is_none()) {
+            member.left = Some(time);
+            member.leave_reason = Some(LeaveReason::FellowshipEnded);
+        }
+        if let Some(segment) = self.segments.last_mut() {
+            segment.end = Some(time);
+        }
+    }
+}
+
+/// Builds fellowship timelines from messages fed to it in capture order
+#[derive(Default)]
+pub struct FellowshipTracker {
+    sessions: Vec<FellowshipSession>,
+    /// Current fellowship per player, by index into `sessions`
+    active: HashMap<u32, usize>,
+    first_time: Option<f64>,
+    last_time: f64,
+}
+
+impl FellowshipTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.first_time.get_or_insert(time);
+        self.last_time = time;
+
+        let Some((object_id, event)) = game_event(message) else {
+            return;
+        };
+
+        match event {
+            GameEventMessage::FellowshipFullUpdate(msg) => {
+                let fellowship = &msg.fellowship;
+                match self.active.get(&object_id) {
+                    Some(&index) if self.sessions[index].name == fellowship.name => {
+                        self.sessions[index].sync(time, fellowship);
+                    }
+                    _ => {
+                        self.end(object_id, time, FellowshipEnd::Replaced);
+                        self.active.insert(object_id, self.sessions.len());
+                        self.sessions
+                            .push(FellowshipSession::new(object_id, time, fellowship));
+                    }
+                }
+            }
+            GameEventMessage::FellowshipUpdateFellow(msg) => {
+                // Fellow records carry no id, so match them to members by name
+                if let Some(&index) = self.active.get(&object_id)
+                    && let Some(member) = self.sessions[index]
+                        .members
+                        .iter_mut()
+                        .find(|m| m.left.is_none() && m.name == msg.fellow.name)
+                {
+                    member.level = msg.fellow.level;
+                }
+            }
+            GameEventMessage::FellowshipDisband(_) => {
+                self.end(object_id, time, FellowshipEnd::Disbanded);
+            }
+            GameEventMessage::FellowshipQuit(msg) => {
+                let reason = if msg.disband {
+                    FellowshipEnd::Disbanded
+                } else {
+                    FellowshipEnd::Quit
+                };
+                self.end(object_id, time, reason);
+            }
+            GameEventMessage::FellowshipDismiss(msg) => {
+                if msg.object_id.0 == object_id {
+                    self.end(object_id, time, FellowshipEnd::Dismissed);
+                } else if let Some(&index) = self.active.get(&object_id) {
+                    self.sessions[index].remove(time, msg.object_id.0, LeaveReason::Dismissed);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn finish(self) -> FellowshipTimeline {
+        FellowshipTimeline {
+            start: self.first_time.unwrap_or_default(),
+            end: self.last_time,
+            fellowships: self.sessions,
+        }
+    }
+
+    fn end(&mut self, object_id: u32, time: f64, reason: FellowshipEnd) {
+        if let Some(index) = self.active.remove(&object_id) {
+            self.sessions[index].close(time, reason);
+        }
+    }
+}
+
+/// Every fellowship seen in a capture
+#[derive(Clone, Debug, Serialize)]
+pub struct FellowshipTimeline {
+    pub start: f64,
+    pub end: f64,
+    pub fellowships: Vec<FellowshipSession>,
+}
+
+impl FellowshipTimeline {
+    /// Build the timeline from the fellowship events in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut tracker = FellowshipTracker::new();
+        for (raw, message) in parse_all(messages) {
+            tracker.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        tracker.finish()
+    }
+
+    /// Write the timeline as CSV, one row per XP-share segment
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &[
+                "observer_id",
+                "fellowship",
+                "start",
+                "end",
+                "leader_id",
+                "share_xp",
+                "even_xp_split",
+                "open",
+                "locked",
+                "members",
+            ],
+        )?;
+
+        for session in &self.fellowships {
+            for segment in &session.segments {
+                let names = segment
+                    .members
+                    .iter()
+                    .filter_map(|id| {
+                        session
+                            .members
+                            .iter()
+                            .find(|m| m.character_id == *id)
+                            .map(|m| m.name.as_str())
+                    })
+                    .collect::<Vec<_>>()
+                    .join(";");
+
+                write_row(
+                    &mut writer,
+                    &[
+                        format!("{:#010x}", session.observer_id),
+                        session.name.clone(),
+                        segment.start.to_string(),
+                        opt(segment.end),
+                        format!("{:#010x}", segment.leader_id),
+                        segment.share_xp.to_string(),
+                        segment.even_xp_split.to_string(),
+                        segment.open.to_string(),
+                        segment.locked.to_string(),
+                        names,
+                    ],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{PLAYER, event};
+    use crate::gameevents::{
+        FellowshipDisband, FellowshipDismiss, FellowshipFullUpdate, FellowshipQuit,
+    };
+
+    use crate::types::{Fellow, ObjectId, PackableHashTable};
+
+    const FRIEND: u32 = 0x50000002;
+    const OTHER: u32 = 0x50000003;
+
+    fn fellow(name: &str) -> Fellow {
+        Fellow {
+            xp_cached: 0,
+            lum_cached: 0,
+            level: 100,
+            max_health: 100,
+            max_stamina: 100,
+            max_mana: 100,
+            current_health: 100,
+            current_stamina: 100,
+            current_mana: 100,
+            share_loot: true,
+            name: name.to_string(),
+        }
+    }
+
+    fn full_update(members: &[(u32, &str)], share_xp: bool) -> MessageKind {
+        let table = members
+            .iter()
+            .map(|(id, name)| (ObjectId(*id), fellow(name)))
+            .collect::<HashMap<_, _>>();
+
+        event(GameEventMessage::FellowshipFullUpdate(
+            FellowshipFullUpdate {
+                fellowship: Fellowship {
+                    members: PackableHashTable {
+                        count: table.len() as u16,
+                        max_size: 16,
+                        table,
+                    },
+                    name: "Hunters".to_string(),
+                    leader_id: ObjectId(PLAYER),
+                    share_xp,
+                    even_xp_split: true,
+                    open: false,
+                    locked: false,
+                    recently_departed: PackableHashTable {
+                        count: 0,
+                        max_size: 0,
+                        table: HashMap::new(),
+                    },
+                    locks: PackableHashTable {
+                        count: 0,
+                        max_size: 0,
+                        table: HashMap::new(),
+                    },
+                },
+            },
+        ))
+    }
+
+    #[test]
+    fn test_membership_follows_full_updates() {
+        let mut tracker = FellowshipTracker::new();
+        tracker.process(10.0, &full_update(&[(PLAYER, "Player")], true));
+        tracker.process(
+            20.0,
+            &full_update(&[(PLAYER, "Player"), (FRIEND, "Friend")], true),
+        );
+        tracker.process(30.0, &full_update(&[(PLAYER, "Player")], true));
+        let timeline = tracker.finish();
+
+        let session = &timeline.fellowships[0];
+        assert_eq!(session.members.len(), 2);
+        let friend = session
+            .members
+            .iter()
+            .find(|m| m.character_id == FRIEND)
+            .unwrap();
+        assert_eq!(friend.joined, 20.0);
+        assert_eq!(friend.left, Some(30.0));
+        assert_eq!(friend.leave_reason, Some(LeaveReason::Departed));
+
+        assert_eq!(session.segments.len(), 3);
+        assert_eq!(
+            session.segment_at(25.0).unwrap().members,
+            vec![PLAYER, FRIEND]
+        );
+    }
+
+    #[test]
+    fn test_xp_share_change_starts_segment() {
+        let mut tracker = FellowshipTracker::new();
+        tracker.process(10.0, &full_update(&[(PLAYER, "Player")], false));
+        tracker.process(10.5, &full_update(&[(PLAYER, "Player")], false));
+        tracker.process(20.0, &full_update(&[(PLAYER, "Player")], true));
+        let timeline = tracker.finish();
+
+        let segments = &timeline.fellowships[0].segments;
+        assert_eq!(segments.len(), 2);
+        assert!(!segments[0].share_xp);
+        assert_eq!(segments[0].end, Some(20.0));
+        assert!(segments[1].share_xp);
+    }
+
+    #[test]
+    fn test_dismiss_and_quit() {
+        let mut tracker = FellowshipTracker::new();
+        tracker.process(
+            10.0,
+            &full_update(
+                &[(PLAYER, "Player"), (FRIEND, "Friend"), (OTHER, "Other")],
+                true,
+            ),
+        );
+        tracker.process(
+            20.0,
+            &event(GameEventMessage::FellowshipDismiss(FellowshipDismiss {
+                object_id: ObjectId(OTHER),
+            })),
+        );
+        tracker.process(
+            30.0,
+            &event(GameEventMessage::FellowshipQuit(FellowshipQuit {
+                disband: false,
+            })),
+        );
+        let timeline = tracker.finish();
+
+        let session = &timeline.fellowships[0];
+        assert_eq!(session.end, Some(30.0));
+        assert_eq!(session.end_reason, Some(FellowshipEnd::Quit));
+        let other = session
+            .members
+            .iter()
+            .find(|m| m.character_id == OTHER)
+            .unwrap();
+        assert_eq!(other.leave_reason, Some(LeaveReason::Dismissed));
+        assert_eq!(session.current_members().count(), 0);
+        assert_eq!(
+            session.segments.last().unwrap().members,
+            vec![PLAYER, FRIEND]
+        );
+    }
+
+    #[test]
+    fn test_events_before_the_first_full_update() {
+        let mut tracker = FellowshipTracker::new();
+        // The capture started with the player already in a fellowship
+        tracker.process(
+            5.0,
+            &event(GameEventMessage::FellowshipDismiss(FellowshipDismiss {
+                object_id: ObjectId(FRIEND),
+            })),
+        );
+        tracker.process(
+            6.0,
+            &event(GameEventMessage::FellowshipDisband(FellowshipDisband {})),
+        );
+        tracker.process(10.0, &full_update(&[(PLAYER, "Player")], true));
+        let timeline = tracker.finish();
+
+        assert_eq!(timeline.start, 5.0);
+        assert_eq!(timeline.fellowships.len(), 1);
+        let session = &timeline.fellowships[0];
+        assert_eq!(session.members.len(), 1);
+        // Still in the fellowship when the capture ended
+        assert_eq!(session.end, None);
+        assert_eq!(session.end_reason, None);
+        assert_eq!(session.segments[0].end, None);
+    }
+}
@@ -0,0 +1,220 @@
+//! Every message that refers to one object
+//!
+//! Answers "what happened to this item" by finding each message with the
+//! object's id anywhere in it, using [`object_refs`], and naming the part the
+//! object played from the field the id was found in.
+
+use serde::Serialize;
+
+use crate::message::MessageKind;
+use crate::network::RawMessage;
+
+use super::objects::ObjectIndex;
+use super::references::{ObjectRef, object_refs};
+use super::{game_action, game_event, parse_all};
+
+/// The part an object played in a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub enum ObjectRole {
+    /// The object the message is about
+    Subject,
+    /// The object holds another one
+    Container,
+    /// The object is the target of a use, spell or attack
+    Target,
+    /// The object wields another one
+    Wielder,
+    /// The object was put in, taken from or listed in a trade
+    TradeItem,
+    /// The game event was sent to the object, which is usually the player
+    Recipient,
+    /// Any other field
+    Other,
+}
+
+impl ObjectRole {
+    /// The role implied by the field an id was found in
+    fn of(reference: &ObjectRef, message_type: &str) -> Self {
+        let trade = message_type.starts_with("Trade");
+        match reference.field() {
+            "ContainerId" | "Container" => Self::Container,
+            "TargetId" | "Target" => Self::Target,
+            "WielderId" | "Wielder" => Self::Wielder,
+            "ObjectId" | "Items" if trade => Self::TradeItem,
+            "ObjectId" if reference.path == "ObjectId" => Self::Subject,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl std::fmt::Display for ObjectRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// A message that refers to the followed object
+#[derive(Clone, Debug, Serialize)]
+pub struct ObjectMention {
+    pub id: u32,
+    pub time: Option<f64>,
+    pub direction: String,
+    pub message_type: String,
+    /// Each part the object played, once each
+    pub roles: Vec<ObjectRole>,
+    /// Paths of the fields holding the object's id
+    pub fields: Vec<String>,
+}
+
+/// The messages referring to one object, in capture order
+#[derive(Clone, Debug, Serialize)]
+pub struct ObjectHistory {
+    pub object_id: u32,
+    /// The object's name, if the capture describes it
+    pub name: Option<String>,
+    pub mentions: Vec<ObjectMention>,
+}
+
+impl ObjectHistory {
+    /// Find every message referring to an object
+    pub fn from_messages(messages: &[RawMessage], object_id: u32) -> Self {
+        let mut objects = ObjectIndex::new();
+        let mut mentions = Vec::new();
+
+        for (raw, message) in parse_all(messages) {
+            objects.process(&message);
+
+            let mut roles = Vec::new();
+            let mut fields = Vec::new();
+            for reference in references(&message)
+                .into_iter()
+                .filter(|r| r.object_id == object_id)
+            {
+                roles.push(ObjectRole::of(&reference, &raw.message_type));
+                fields.push(reference.path);
+            }
+            if game_event(&message).is_some_and(|(recipient, _)| recipient == object_id) {
+                roles.push(ObjectRole::Recipient);
+                fields.push("object_id".to_string());
+            }
+            if fields.is_empty() {
+                continue;
+            }
+
+            roles.sort();
+            roles.dedup();
+            mentions.push(ObjectMention {
+                id: raw.id,
+                time: raw.timestamp,
+                direction: raw.direction.clone(),
+                message_type: raw.message_type.clone(),
+                roles,
+                fields,
+            });
+        }
+
+        Self {
+            object_id,
+            name: objects.name(object_id).map(str::to_string),
+            mentions,
+        }
+    }
+}
+
+/// The object ids in a message, with ordered events and actions unwrapped so
+/// paths start at the event's or action's own fields
+fn references(message: &MessageKind) -> Vec<ObjectRef> {
+    if let Some((_, event)) = game_event(message) {
+        object_refs(event)
+    } else if let Some(action) = game_action(message) {
+        object_refs(action)
+    } else {
+        object_refs(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{PLAYER, raw};
+
+    use crate::gameactions::InventoryPutItemInContainer;
+    use crate::message::{C2SMessage, GameActionMessage, GameEventMessage, S2CMessage};
+    use crate::types::ObjectId;
+
+    const SWORD: u32 = 0x8000_1234;
+    const PACK: u32 = 0x8000_2000;
+
+    fn put_in_container() -> MessageKind {
+        MessageKind::C2S(Box::new(C2SMessage::OrderedGameAction {
+            sequence: 1,
+            action: GameActionMessage::InventoryPutItemInContainer(InventoryPutItemInContainer {
+                object_id: ObjectId(SWORD),
+                container_id: ObjectId(PACK),
+                slot_index: 0,
+            }),
+        }))
+    }
+
+    fn wield() -> MessageKind {
+        MessageKind::S2C(Box::new(S2CMessage::OrderedGameEvent {
+            object_id: PLAYER,
+            sequence: 1,
+            event: Box::new(GameEventMessage::ItemWearItem(
+                crate::gameevents::ItemWearItem {
+                    object_id: ObjectId(SWORD),
+                    slot: crate::enums::EquipMask::MELEE_WEAPON,
+                },
+            )),
+        }))
+    }
+
+    fn delete(object_id: u32) -> MessageKind {
+        MessageKind::S2C(Box::new(S2CMessage::ItemDeleteObject(
+            crate::messages::s2c::ItemDeleteObject {
+                object_id: ObjectId(object_id),
+                object_instance_sequence: 1,
+            },
+        )))
+    }
+
+    #[test]
+    fn test_follow_item() {
+        let messages = vec![
+            raw(1, &put_in_container()),
+            raw(2, &delete(0x8000_9999)),
+            raw(3, &wield()),
+            raw(4, &delete(SWORD)),
+        ];
+        let history = ObjectHistory::from_messages(&messages, SWORD);
+
+        let ids: Vec<_> = history.mentions.iter().map(|m| m.id).collect();
+        assert_eq!(ids, vec![1, 3, 4]);
+        assert!(
+            history
+                .mentions
+                .iter()
+                .all(|m| m.roles == vec![ObjectRole::Subject])
+        );
+        assert_eq!(history.mentions[0].fields, vec!["ObjectId"]);
+    }
+
+    #[test]
+    fn test_container_role() {
+        let messages = vec![raw(1, &put_in_container())];
+        let history = ObjectHistory::from_messages(&messages, PACK);
+
+        assert_eq!(history.mentions.len(), 1);
+        assert_eq!(history.mentions[0].roles, vec![ObjectRole::Container]);
+        assert_eq!(history.mentions[0].fields, vec!["ContainerId"]);
+    }
+
+    #[test]
+    fn test_event_recipient() {
+        let messages = vec![raw(1, &wield()), raw(2, &delete(SWORD))];
+        let history = ObjectHistory::from_messages(&messages, PLAYER);
+
+        assert_eq!(history.mentions.len(), 1);
+        assert_eq!(history.mentions[0].roles, vec![ObjectRole::Recipient]);
+    }
+}
@@ -0,0 +1,707 @@
+//! Houses and the housing market
+//!
+//! Appraising a house's covenant crystal returns `House_HouseProfile`, which
+//! is how houses owned by other players are seen. The client's own house is
+//! described by `House_HouseData` and kept current by the rent, guest list
+//! (`House_UpdateHAR`) and restriction updates, none of which name the house,
+//! so they are applied to the house owned by the character receiving them.
+//!
+//! `House_AvailableHouses` lists the landcells of houses for sale of one
+//! type; these are counted by landblock so captures taken at different times
+//! can be compared.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::enums::{HouseBitfield, HouseType};
+use crate::message::{GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+use crate::types::{HAR, HousePayment, PackableList, Position, RestrictionDB};
+
+use super::csv::{opt, write_row};
+use super::objects::ObjectIndex;
+use super::{game_event, parse_all};
+
+/// An item and amount needed to buy a house or pay its rent
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Payment {
+    pub weenie_class_id: u32,
+    pub name: String,
+    pub required: u32,
+    pub paid: u32,
+}
+
+impl Payment {
+    fn list(payments: &PackableList<HousePayment>) -> Vec<Self> {
+        payments
+            .list
+            .iter()
+            .map(|payment| Self {
+                weenie_class_id: payment.weenie_class_id,
+                name: if payment.required == 1 || payment.plural_name.is_empty() {
+                    payment.name.clone()
+                } else {
+                    payment.plural_name.clone()
+                },
+                required: payment.required,
+                paid: payment.paid,
+            })
+            .collect()
+    }
+
+    /// The payments as `amount name` pairs joined with `; `
+    fn summary(payments: &[Self]) -> String {
+        payments
+            .iter()
+            .map(|p| format!("{} {}", p.required, p.name))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// Someone allowed into a house
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Guest {
+    pub object_id: u32,
+    pub name: String,
+    pub storage: bool,
+}
+
+/// A house and everything known about it
+#[derive(Clone, Debug, Serialize)]
+pub struct House {
+    /// The dwelling's object id, when a profile has named it
+    pub house_id: Option<u32>,
+    /// The covenant crystal the profile was read from
+    pub slumlord_id: Option<u32>,
+    pub house_type: Option<HouseType>,
+    pub owner_id: Option<u32>,
+    pub owner_name: Option<String>,
+    pub location: Option<Position>,
+    pub flags: Option<HouseBitfield>,
+    pub min_level: Option<i32>,
+    pub max_level: Option<i32>,
+    pub min_allegiance_rank: Option<i32>,
+    pub max_allegiance_rank: Option<i32>,
+    pub maintenance_free: bool,
+    pub buy: Vec<Payment>,
+    pub rent: Vec<Payment>,
+    /// When the owner bought the house, as a Unix time
+    pub buy_time: Option<u32>,
+    /// When rent was last due, as a Unix time
+    pub rent_time: Option<u32>,
+    /// House access rights bitmask from the guest list
+    pub access_bitmask: Option<u32>,
+    pub allegiance_monarch_id: Option<u32>,
+    pub guests: Vec<Guest>,
+    pub roommates: Vec<u32>,
+    /// Restriction flags from the access control list
+    pub restriction_flags: Option<u32>,
+    /// Permission bits for each object on the access control list
+    pub permissions: BTreeMap<u32, u32>,
+    /// Notices from `House_HouseStatus`
+    pub notices: Vec<u32>,
+    pub first_seen: f64,
+    pub last_seen: f64,
+}
+
+impl House {
+    fn new(time: f64) -> Self {
+        Self {
+            house_id: None,
+            slumlord_id: None,
+            house_type: None,
+            owner_id: None,
+            owner_name: None,
+            location: None,
+            flags: None,
+            min_level: None,
+            max_level: None,
+            min_allegiance_rank: None,
+            max_allegiance_rank: None,
+            maintenance_free: false,
+            buy: Vec::new(),
+            rent: Vec::new(),
+            buy_time: None,
+            rent_time: None,
+            access_bitmask: None,
+            allegiance_monarch_id: None,
+            guests: Vec::new(),
+            roommates: Vec::new(),
+            restriction_flags: None,
+            permissions: BTreeMap::new(),
+            notices: Vec::new(),
+            first_seen: time,
+            last_seen: time,
+        }
+    }
+
+    /// The landblock the house is in
+    pub fn landblock(&self) -> Option<u16> {
+        self.location
+            .as_ref()
+            .map(|position| (position.landcell.0 >> 16) as u16)
+    }
+
+    /// Whether every rent item has been paid in full
+    pub fn rent_paid(&self) -> bool {
+        self.maintenance_free || self.rent.iter().all(|p| p.paid >= p.required)
+    }
+
+    fn update_guests(&mut self, har: &HAR) {
+        self.access_bitmask = Some(har.bitmask);
+        self.allegiance_monarch_id = (har.monarch_id.0 != 0).then_some(har.monarch_id.0);
+        self.guests = har
+            .guest_list
+            .table
+            .iter()
+            .map(|(id, info)| Guest {
+                object_id: id.0,
+                name: info.guest_name.clone(),
+                storage: info.has_storage_permission,
+            })
+            .collect();
+        self.guests.sort_by_key(|guest| guest.object_id);
+        self.roommates = har.roommate_list.list.iter().map(|id| id.0).collect();
+    }
+
+    fn update_restrictions(&mut self, restrictions: &RestrictionDB) {
+        self.restriction_flags = Some(restrictions.flags);
+        if restrictions.monarch_id.0 != 0 {
+            self.allegiance_monarch_id = Some(restrictions.monarch_id.0);
+        }
+        self.permissions = restrictions
+            .permissions
+            .table
+            .iter()
+            .map(|(id, bits)| (id.0, *bits))
+            .collect();
+    }
+
+    fn merge(&mut self, other: House) {
+        let newer = other.last_seen >= self.last_seen;
+        macro_rules! take {
+            ($($field:ident),*) => {$(
+                if other.$field.is_some() && (newer || self.$field.is_none()) {
+                    self.$field = other.$field;
+                }
+            )*};
+        }
+        take!(
+            house_id,
+            slumlord_id,
+            house_type,
+            owner_id,
+            owner_name,
+            location,
+            flags,
+            min_level,
+            max_level,
+            min_allegiance_rank,
+            max_allegiance_rank,
+            buy_time,
+            rent_time,
+            access_bitmask,
+            allegiance_monarch_id,
+            restriction_flags
+        );
+        if newer {
+            self.maintenance_free = other.maintenance_free;
+            if !other.buy.is_empty() {
+                self.buy = other.buy;
+            }
+            if !other.rent.is_empty() {
+                self.rent = other.rent;
+            }
+            if other.access_bitmask.is_some() {
+                self.guests = other.guests;
+                self.roommates = other.roommates;
+            }
+            if other.restriction_flags.is_some() {
+                self.permissions = other.permissions;
+            }
+        }
+        self.notices.extend(other.notices);
+        self.first_seen = self.first_seen.min(other.first_seen);
+        self.last_seen = self.last_seen.max(other.last_seen);
+    }
+}
+
+/// How many houses of a type were listed for sale in a landblock
+#[derive(Clone, Debug, Serialize)]
+pub struct AvailableHouses {
+    pub house_type: HouseType,
+    pub landblock: u16,
+    pub count: usize,
+    pub first_seen: f64,
+    pub last_seen: f64,
+}
+
+/// Houses and housing availability collected from one or more captures
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct HousingReport {
+    houses: Vec<House>,
+    /// Houses for sale, from the latest listing of each type
+    available: Vec<AvailableHouses>,
+    /// Total houses for sale of each type, as reported by the server
+    available_totals: BTreeMap<String, i32>,
+    #[serde(skip)]
+    objects: ObjectIndex,
+}
+
+impl HousingReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a report from the housing messages in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut report = Self::new();
+        report.add_messages(messages);
+        report
+    }
+
+    /// Add the houses seen in another capture
+    pub fn add_messages(&mut self, messages: &[RawMessage]) {
+        for (raw, message) in parse_all(messages) {
+            self.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.objects.process(message);
+
+        let Some((object_id, event)) = game_event(message) else {
+            return;
+        };
+
+        match event {
+            GameEventMessage::HouseHouseProfile(msg) => {
+                let profile = &msg.profile;
+                let owner_id = (profile.owner_id.0 != 0).then_some(profile.owner_id.0);
+                let location = self
+                    .objects
+                    .position(profile.dwelling_id)
+                    .or_else(|| self.objects.position(msg.object_id.0))
+                    .cloned();
+
+                let index = match self
+                    .houses
+                    .iter()
+                    .position(|h| h.house_id == Some(profile.dwelling_id))
+                {
+                    Some(index) => index,
+                    // The owner's own house may already be known from its house data
+                    None => self.owned_house(owner_id.unwrap_or_default(), time),
+                };
+                let house = &mut self.houses[index];
+                house.last_seen = time;
+                house.house_id = Some(profile.dwelling_id);
+                house.slumlord_id = Some(msg.object_id.0);
+                house.house_type = Some(profile.type_.clone());
+                house.owner_id = owner_id;
+                house.owner_name = owner_id.map(|_| profile.owner_name.clone());
+                house.flags = Some(profile.flags.clone());
+                house.min_level = Some(profile.min_level);
+                house.max_level = Some(profile.max_level);
+                house.min_allegiance_rank = Some(profile.min_alleg_rank);
+                house.max_allegiance_rank = Some(profile.max_alleg_rank);
+                house.maintenance_free = profile.maintenance_free;
+                house.buy = Payment::list(&profile.buy);
+                house.rent = Payment::list(&profile.rent);
+                if location.is_some() {
+                    house.location = location;
+                }
+            }
+            GameEventMessage::HouseHouseData(msg) => {
+                let owner_name = self.objects.name(object_id).map(str::to_string);
+                let data = &msg.data;
+                let house = self.owned(object_id, time);
+                house.owner_name = owner_name.or(house.owner_name.take());
+                house.house_type = Some(data.type_.clone());
+                house.maintenance_free = data.maintenance_free;
+                house.buy = Payment::list(&data.buy);
+                house.rent = Payment::list(&data.rent);
+                house.buy_time = Some(data.buy_time);
+                house.rent_time = Some(data.rent_time);
+                house.location = Some(data.position.clone());
+            }
+            GameEventMessage::HouseUpdateRentPayment(msg) => {
+                self.owned(object_id, time).rent = Payment::list(&msg.rent);
+            }
+            GameEventMessage::HouseUpdateRentTime(msg) => {
+                self.owned(object_id, time).rent_time = Some(msg.rent_time);
+            }
+            GameEventMessage::HouseUpdateHAR(msg) => {
+                self.owned(object_id, time).update_guests(&msg.guest_list);
+            }
+            GameEventMessage::HouseUpdateRestrictions(msg) => {
+                // Restrictions name the dwelling they belong to
+                let house_id = msg.sender_id.0;
+                let index = match self
+                    .houses
+                    .iter()
+                    .position(|h| h.house_id == Some(house_id))
+                {
+                    Some(index) => index,
+                    None => self.owned_house(object_id, time),
+                };
+                let house = &mut self.houses[index];
+                house.last_seen = time;
+                house.house_id = Some(house_id);
+                house.update_restrictions(&msg.restrictions);
+            }
+            GameEventMessage::HouseHouseStatus(msg) => {
+                self.owned(object_id, time).notices.push(msg.notice_type);
+            }
+            GameEventMessage::HouseAvailableHouses(msg) => {
+                self.list_available(time, &msg.type_, &msg.houses.list, msg.num_houses);
+            }
+            _ => {}
+        }
+    }
+
+    /// Merge another report into this one, deduplicating houses by id
+    pub fn merge(&mut self, other: HousingReport) {
+        for house in other.houses {
+            let existing = house
+                .house_id
+                .and_then(|id| self.houses.iter_mut().find(|h| h.house_id == Some(id)));
+            match existing {
+                Some(existing) => existing.merge(house),
+                None => self.houses.push(house),
+            }
+        }
+        for entry in other.available {
+            match self
+                .available
+                .iter_mut()
+                .find(|a| a.house_type == entry.house_type && a.landblock == entry.landblock)
+            {
+                Some(existing) if existing.last_seen > entry.last_seen => {}
+                Some(existing) => *existing = entry,
+                None => self.available.push(entry),
+            }
+        }
+        self.available_totals.extend(other.available_totals);
+    }
+
+    /// Houses in the order they were first seen
+    pub fn houses(&self) -> &[House] {
+        &self.houses
+    }
+
+    /// Houses for sale by type and landblock
+    pub fn available(&self) -> &[AvailableHouses] {
+        &self.available
+    }
+
+    /// Write one row per house
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &[
+                "house_id",
+                "slumlord_id",
+                "house_type",
+                "owner_id",
+                "owner_name",
+                "landblock",
+                "landcell",
+                "flags",
+                "min_level",
+                "max_level",
+                "maintenance_free",
+                "buy",
+                "rent",
+                "rent_paid",
+                "rent_time",
+                "guests",
+                "roommates",
+                "access_bitmask",
+                "restriction_flags",
+                "last_seen",
+            ],
+        )?;
+
+        for house in &self.houses {
+            let hex = |value: Option<u32>| opt(value.map(|v| format!("{v:#010x}")));
+            write_row(
+                &mut writer,
+                &[
+                    hex(house.house_id),
+                    hex(house.slumlord_id),
+                    opt(house.house_type.as_ref()),
+                    hex(house.owner_id),
+                    house.owner_name.clone().unwrap_or_default(),
+                    opt(house.landblock().map(|lb| format!("{lb:#06x}"))),
+                    hex(house.location.as_ref().map(|p| p.landcell.0)),
+                    opt(house.flags.as_ref()),
+                    opt(house.min_level),
+                    opt(house.max_level),
+                    house.maintenance_free.to_string(),
+                    Payment::summary(&house.buy),
+                    Payment::summary(&house.rent),
+                    house.rent_paid().to_string(),
+                    opt(house.rent_time),
+                    house.guests.len().to_string(),
+                    house.roommates.len().to_string(),
+                    hex(house.access_bitmask),
+                    hex(house.restriction_flags),
+                    house.last_seen.to_string(),
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write one row per house type and landblock with houses for sale
+    pub fn write_available_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &[
+                "house_type",
+                "landblock",
+                "count",
+                "first_seen",
+                "last_seen",
+            ],
+        )?;
+
+        for entry in &self.available {
+            write_row(
+                &mut writer,
+                &[
+                    entry.house_type.to_string(),
+                    format!("{:#06x}", entry.landblock),
+                    entry.count.to_string(),
+                    entry.first_seen.to_string(),
+                    entry.last_seen.to_string(),
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Replace the listing for a house type with a newer one
+    fn list_available(&mut self, time: f64, house_type: &HouseType, cells: &[u32], total: i32) {
+        let mut counts = BTreeMap::new();
+        for cell in cells {
+            *counts.entry((cell >> 16) as u16).or_insert(0) += 1;
+        }
+
+        let previous = std::mem::take(&mut self.available);
+        let (same_type, others): (Vec<_>, Vec<_>) = previous
+            .into_iter()
+            .partition(|entry| entry.house_type == *house_type);
+        self.available = others;
+        for (landblock, count) in counts {
+            let first_seen = same_type
+                .iter()
+                .find(|entry| entry.landblock == landblock)
+                .map_or(time, |entry| entry.first_seen);
+            self.available.push(AvailableHouses {
+                house_type: house_type.clone(),
+                landblock,
+                count,
+                first_seen,
+                last_seen: time,
+            });
+        }
+        self.available.sort_by(|a, b| {
+            (a.house_type.to_string(), a.landblock).cmp(&(b.house_type.to_string(), b.landblock))
+        });
+        self.available_totals.insert(house_type.to_string(), total);
+    }
+
+    /// The house owned by a character
+    fn owned(&mut self, owner_id: u32, time: f64) -> &mut House {
+        let index = self.owned_house(owner_id, time);
+        let house = &mut self.houses[index];
+        house.last_seen = time;
+        house
+    }
+
+    /// Index of the house owned by a character, adding one if none is known
+    fn owned_house(&mut self, owner_id: u32, time: f64) -> usize {
+        if let Some(index) = self
+            .houses
+            .iter()
+            .position(|h| owner_id != 0 && h.owner_id == Some(owner_id))
+        {
+            return index;
+        }
+        let mut house = House::new(time);
+        house.owner_id = (owner_id != 0).then_some(owner_id);
+        self.houses.push(house);
+        self.houses.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{PLAYER, event};
+    use crate::gameevents::{
+        HouseAvailableHouses, HouseHouseProfile, HouseUpdateHAR, HouseUpdateRentPayment,
+        HouseUpdateRentTime,
+    };
+
+    use crate::types::{GuestInfo, HouseProfile, ObjectId, PackableHashTable};
+
+    const HOUSE: u32 = 0x7A9B4001;
+    const CRYSTAL: u32 = 0x7A9B4002;
+
+    fn payments(required: u32, paid: u32) -> PackableList<HousePayment> {
+        PackableList {
+            count: 1,
+            list: vec![HousePayment {
+                required,
+                paid,
+                weenie_class_id: 273,
+                name: "Pyreal".to_string(),
+                plural_name: "Pyreals".to_string(),
+            }],
+        }
+    }
+
+    fn profile(owner: u32) -> MessageKind {
+        event(GameEventMessage::HouseHouseProfile(HouseHouseProfile {
+            object_id: ObjectId(CRYSTAL),
+            profile: HouseProfile {
+                dwelling_id: HOUSE,
+                owner_id: ObjectId(owner),
+                flags: HouseBitfield::Active,
+                min_level: 20,
+                max_level: -1,
+                min_alleg_rank: -1,
+                max_alleg_rank: -1,
+                maintenance_free: false,
+                type_: HouseType::Cottage,
+                owner_name: "Player".to_string(),
+                buy: payments(50_000, 0),
+                rent: payments(10_000, 0),
+            },
+        }))
+    }
+
+    #[test]
+    fn test_profile_and_rent() {
+        let mut report = HousingReport::new();
+        report.process(1.0, &profile(PLAYER));
+        assert!(!report.houses()[0].rent_paid());
+
+        report.process(
+            2.0,
+            &event(GameEventMessage::HouseUpdateRentPayment(
+                HouseUpdateRentPayment {
+                    rent: payments(10_000, 10_000),
+                },
+            )),
+        );
+
+        assert_eq!(report.houses().len(), 1);
+        let house = &report.houses()[0];
+        assert_eq!(house.house_id, Some(HOUSE));
+        assert_eq!(house.slumlord_id, Some(CRYSTAL));
+        assert_eq!(house.house_type, Some(HouseType::Cottage));
+        assert_eq!(house.rent[0].name, "Pyreals");
+        assert!(house.rent_paid());
+    }
+
+    #[test]
+    fn test_guest_list() {
+        let mut report = HousingReport::new();
+        report.process(1.0, &profile(PLAYER));
+        let mut guests = std::collections::HashMap::new();
+        guests.insert(
+            ObjectId(0x50000002),
+            GuestInfo {
+                has_storage_permission: true,
+                guest_name: "Friend".to_string(),
+            },
+        );
+        report.process(
+            2.0,
+            &event(GameEventMessage::HouseUpdateHAR(HouseUpdateHAR {
+                guest_list: HAR {
+                    version: 1,
+                    bitmask: 0x1,
+                    monarch_id: ObjectId(0),
+                    guest_list: PackableHashTable {
+                        count: 1,
+                        max_size: 16,
+                        table: guests,
+                    },
+                    roommate_list: PackableList {
+                        count: 0,
+                        list: Vec::new(),
+                    },
+                },
+            })),
+        );
+
+        let house = &report.houses()[0];
+        assert_eq!(house.access_bitmask, Some(0x1));
+        assert_eq!(
+            house.guests,
+            vec![Guest {
+                object_id: 0x50000002,
+                name: "Friend".to_string(),
+                storage: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_available_by_landblock() {
+        let mut report = HousingReport::new();
+        let listing = |houses: Vec<u32>| {
+            event(GameEventMessage::HouseAvailableHouses(
+                HouseAvailableHouses {
+                    type_: HouseType::Cottage,
+                    num_houses: houses.len() as i32,
+                    houses: PackableList {
+                        count: houses.len() as u32,
+                        list: houses,
+                    },
+                },
+            ))
+        };
+        report.process(1.0, &listing(vec![0xA9B40001, 0xA9B40002, 0xC6A90001]));
+        report.process(2.0, &listing(vec![0xA9B40001]));
+
+        let available = report.available();
+        assert_eq!(available.len(), 1);
+        assert_eq!(available[0].landblock, 0xA9B4);
+        assert_eq!(available[0].count, 1);
+        assert_eq!(available[0].first_seen, 1.0);
+        assert_eq!(available[0].last_seen, 2.0);
+    }
+
+    #[test]
+    fn test_update_before_the_house_is_known() {
+        let mut report = HousingReport::new();
+        report.process(
+            1.0,
+            &event(GameEventMessage::HouseUpdateRentTime(HouseUpdateRentTime {
+                rent_time: 1_700_000_000,
+            })),
+        );
+        // Appraising the crystal fills in the house the update was applied to
+        report.process(2.0, &profile(PLAYER));
+
+        let houses = report.houses();
+        assert_eq!(houses.len(), 1);
+        let house = &houses[0];
+        assert_eq!(house.house_id, Some(HOUSE));
+        assert_eq!(house.owner_id, Some(PLAYER));
+        assert_eq!(house.rent_time, Some(1_700_000_000));
+        assert_eq!(house.first_seen, 1.0);
+    }
+}
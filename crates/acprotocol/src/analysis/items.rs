@@ -0,0 +1,559 @@
+//! Item and creature database
+//!
+//! Combines what the client learns about an object from `Item_CreateObject`
+//! (its `PublicWeenieDesc`) with everything returned by appraising it in
+//! `Item_SetAppraiseInfo`: the property tables, spell book and the armor,
+//! weapon, creature and hook profiles. Properties are keyed by their names
+//! from the `Property*` enums.
+//!
+//! Records are keyed by weenie class id, taken from the object's description,
+//! so feeding the database several captures merges what each of them saw
+//! about the same kind of object. Later information replaces earlier values
+//! property by property. Object ids only mean something within one session,
+//! so they are kept as a secondary index: each record lists the ids it was
+//! seen with, and appraisals are matched to their record through the ids
+//! described in the same capture.
+//!
+//! An object appraised without ever being described has no known weenie
+//! class. Its record is kept apart, one per object and capture.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::Hash;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::enums::ItemType;
+use crate::gameevents::ItemSetAppraiseInfo;
+use crate::message::{GameEventMessage, MessageKind, S2CMessage};
+use crate::network::RawMessage;
+use crate::types::{
+    ArmorProfile, CreatureAppraisalProfile, HookAppraisalProfile, LayeredSpellId,
+    PackableHashTable, PublicWeenieDesc, WeaponProfile,
+};
+
+use super::csv::{flags, opt, write_row};
+use super::{game_event, parse_all};
+
+/// Whether a record describes an item or a creature
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum ItemKind {
+    Item,
+    Creature,
+}
+
+/// Everything known about one kind of object
+#[derive(Clone, Debug, Serialize)]
+pub struct ItemRecord {
+    /// Weenie class id, or `None` if the object was never described
+    pub weenie_class_id: Option<u32>,
+    /// Object ids this record was seen with, in the order first seen
+    pub object_ids: Vec<u32>,
+    pub name: Option<String>,
+    pub icon: Option<u32>,
+    pub kind: ItemKind,
+    /// Description from the most recent `Item_CreateObject` or `Item_UpdateObject`
+    pub description: Option<PublicWeenieDesc>,
+    pub int_properties: BTreeMap<String, i32>,
+    pub int64_properties: BTreeMap<String, i64>,
+    pub bool_properties: BTreeMap<String, bool>,
+    pub float_properties: BTreeMap<String, f64>,
+    pub string_properties: BTreeMap<String, String>,
+    pub data_id_properties: BTreeMap<String, u32>,
+    pub spell_book: Vec<LayeredSpellId>,
+    pub armor_profile: Option<ArmorProfile>,
+    pub weapon_profile: Option<WeaponProfile>,
+    pub creature_profile: Option<CreatureAppraisalProfile>,
+    pub hook_profile: Option<HookAppraisalProfile>,
+    /// Base armor level per body part, for creatures
+    pub base_armor: BTreeMap<String, u32>,
+    /// Number of successful appraisals merged into the record
+    pub appraisals: u32,
+}
+
+impl ItemRecord {
+    fn new(weenie_class_id: Option<u32>, object_id: u32) -> Self {
+        Self {
+            weenie_class_id,
+            object_ids: vec![object_id],
+            name: None,
+            icon: None,
+            kind: ItemKind::Item,
+            description: None,
+            int_properties: BTreeMap::new(),
+            int64_properties: BTreeMap::new(),
+            bool_properties: BTreeMap::new(),
+            float_properties: BTreeMap::new(),
+            string_properties: BTreeMap::new(),
+            data_id_properties: BTreeMap::new(),
+            spell_book: Vec::new(),
+            armor_profile: None,
+            weapon_profile: None,
+            creature_profile: None,
+            hook_profile: None,
+            base_armor: BTreeMap::new(),
+            appraisals: 0,
+        }
+    }
+
+    /// The object's type, from its description
+    pub fn item_type(&self) -> Option<ItemType> {
+        self.description.as_ref().map(|d| d.type_)
+    }
+
+    fn describe(&mut self, description: &PublicWeenieDesc) {
+        if !description.name.is_empty() {
+            self.name = Some(description.name.clone());
+        }
+        if description.type_.contains(ItemType::CREATURE) {
+            self.kind = ItemKind::Creature;
+        }
+        self.icon = Some(description.icon.0);
+        self.description = Some(description.clone());
+    }
+
+    fn seen_as(&mut self, object_id: u32) {
+        if !self.object_ids.contains(&object_id) {
+            self.object_ids.push(object_id);
+        }
+    }
+
+    fn appraise(&mut self, info: &ItemSetAppraiseInfo) {
+        if info.success {
+            self.appraisals += 1;
+        }
+
+        merge_table(&mut self.int_properties, &info.int_properties, |v| *v);
+        merge_table(&mut self.int64_properties, &info.int64_properties, |v| *v);
+        merge_table(&mut self.bool_properties, &info.bool_properties, |v| *v);
+        merge_table(&mut self.float_properties, &info.float_properties, |v| *v);
+        merge_table(&mut self.string_properties, &info.string_properties, |v| {
+            v.clone()
+        });
+        merge_table(
+            &mut self.data_id_properties,
+            &info.data_id_properties,
+            |v| v.0,
+        );
+
+        if let Some(name) = self.string_properties.get("Name") {
+            self.name = Some(name.clone());
+        }
+        if let Some(spells) = &info.spell_book {
+            self.spell_book = spells.list.clone();
+        }
+        if let Some(profile) = &info.armor_profile {
+            self.armor_profile = Some(profile.clone());
+        }
+        if let Some(profile) = &info.weapon_profile {
+            self.weapon_profile = Some(profile.clone());
+        }
+        if let Some(profile) = &info.creature_profile {
+            self.creature_profile = Some(profile.clone());
+            self.kind = ItemKind::Creature;
+        }
+        if let Some(profile) = &info.hook_profile {
+            self.hook_profile = Some(profile.clone());
+        }
+
+        let base_armor = [
+            ("Head", info.base_armor_head),
+            ("Chest", info.base_armor_chest),
+            ("Groin", info.base_armor_groin),
+            ("Bicep", info.base_armor_bicep),
+            ("Wrist", info.base_armor_wrist),
+            ("Hand", info.base_armor_hand),
+            ("Thigh", info.base_armor_thigh),
+            ("Shin", info.base_armor_shin),
+            ("Foot", info.base_armor_foot),
+        ];
+        for (part, value) in base_armor {
+            if let Some(value) = value {
+                self.base_armor.insert(part.to_string(), value);
+            }
+        }
+    }
+
+    /// Fold another record for the same kind of object into this one
+    fn merge(&mut self, other: ItemRecord) {
+        for object_id in other.object_ids {
+            self.seen_as(object_id);
+        }
+        if other.name.is_some() {
+            self.name = other.name;
+        }
+        if other.icon.is_some() {
+            self.icon = other.icon;
+        }
+        if other.kind == ItemKind::Creature {
+            self.kind = ItemKind::Creature;
+        }
+        if other.description.is_some() {
+            self.description = other.description;
+        }
+        self.int_properties.extend(other.int_properties);
+        self.int64_properties.extend(other.int64_properties);
+        self.bool_properties.extend(other.bool_properties);
+        self.float_properties.extend(other.float_properties);
+        self.string_properties.extend(other.string_properties);
+        self.data_id_properties.extend(other.data_id_properties);
+        if !other.spell_book.is_empty() {
+            self.spell_book = other.spell_book;
+        }
+        self.armor_profile = other.armor_profile.or(self.armor_profile.take());
+        self.weapon_profile = other.weapon_profile.or(self.weapon_profile.take());
+        self.creature_profile = other.creature_profile.or(self.creature_profile.take());
+        self.hook_profile = other.hook_profile.or(self.hook_profile.take());
+        self.base_armor.extend(other.base_armor);
+        self.appraisals += other.appraisals;
+    }
+
+    /// Every property as a `Table.Name` column name and its formatted value
+    fn property_columns(&self) -> impl Iterator<Item = (String, String)> + '_ {
+        fn columns<'a, V: ToString>(
+            table: &'static str,
+            values: &'a BTreeMap<String, V>,
+        ) -> impl Iterator<Item = (String, String)> + 'a {
+            values
+                .iter()
+                .map(move |(name, value)| (format!("{table}.{name}"), value.to_string()))
+        }
+
+        columns("Int", &self.int_properties)
+            .chain(columns("Int64", &self.int64_properties))
+            .chain(columns("Bool", &self.bool_properties))
+            .chain(columns("Float", &self.float_properties))
+            .chain(columns("String", &self.string_properties))
+            .chain(columns("DataId", &self.data_id_properties))
+    }
+}
+
+/// Copy a property table into a record, naming each property
+fn merge_table<K, V, T>(
+    into: &mut BTreeMap<String, T>,
+    table: &Option<PackableHashTable<K, V>>,
+    value: impl Fn(&V) -> T,
+) where
+    K: Eq + Hash + ToString,
+{
+    if let Some(table) = table {
+        for (key, v) in &table.table {
+            into.insert(key.to_string(), value(v));
+        }
+    }
+}
+
+/// Items and creatures seen across one or more captures
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ItemDatabase {
+    /// Records by weenie class id
+    items: BTreeMap<u32, ItemRecord>,
+    /// Records of objects never described, from captures already finished
+    unclassified: Vec<ItemRecord>,
+    /// Weenie class of each object described in the current capture
+    #[serde(skip)]
+    classes: HashMap<u32, u32>,
+    /// Appraisals of objects not yet described in the current capture
+    #[serde(skip)]
+    pending: BTreeMap<u32, ItemRecord>,
+}
+
+impl ItemDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a database from the object descriptions and appraisals in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut database = Self::new();
+        database.add_messages(messages);
+        database
+    }
+
+    /// Add the objects seen in another capture
+    pub fn add_messages(&mut self, messages: &[RawMessage]) {
+        for (_, message) in parse_all(messages) {
+            self.process(&message);
+        }
+        self.end_capture();
+    }
+
+    /// Process a single message from the current capture
+    pub fn process(&mut self, message: &MessageKind) {
+        if let MessageKind::S2C(msg) = message {
+            match msg.as_ref() {
+                S2CMessage::ItemCreateObject(msg) => {
+                    self.describe(msg.object_id.0, &msg.weenie_description);
+                }
+                S2CMessage::ItemUpdateObject(msg) => {
+                    self.describe(msg.object_id.0, &msg.weenie_desc);
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((_, GameEventMessage::ItemSetAppraiseInfo(info))) = game_event(message) {
+            let object_id = info.object_id.0;
+            let record = match self.classes.get(&object_id) {
+                Some(weenie_class_id) => self
+                    .items
+                    .get_mut(weenie_class_id)
+                    .expect("described objects have a record"),
+                None => self
+                    .pending
+                    .entry(object_id)
+                    .or_insert_with(|| ItemRecord::new(None, object_id)),
+            };
+            record.appraise(info);
+        }
+    }
+
+    /// Finish the current capture, after which its object ids no longer
+    /// refer to anything
+    pub fn end_capture(&mut self) {
+        self.classes.clear();
+        self.unclassified
+            .extend(std::mem::take(&mut self.pending).into_values());
+    }
+
+    /// Merge another database into this one, deduplicating by weenie class
+    pub fn merge(&mut self, mut other: ItemDatabase) {
+        other.end_capture();
+        for (weenie_class_id, record) in other.items {
+            match self.items.get_mut(&weenie_class_id) {
+                Some(existing) => existing.merge(record),
+                None => {
+                    self.items.insert(weenie_class_id, record);
+                }
+            }
+        }
+        self.unclassified.extend(other.unclassified);
+    }
+
+    /// The record for a weenie class
+    pub fn get(&self, weenie_class_id: u32) -> Option<&ItemRecord> {
+        self.items.get(&weenie_class_id)
+    }
+
+    /// The records an object id was seen with, in any capture
+    pub fn find_object(&self, object_id: u32) -> impl Iterator<Item = &ItemRecord> {
+        self.items()
+            .filter(move |item| item.object_ids.contains(&object_id))
+    }
+
+    /// Records in weenie class id order, then the records of objects never
+    /// described
+    pub fn items(&self) -> impl Iterator<Item = &ItemRecord> {
+        self.items
+            .values()
+            .chain(&self.unclassified)
+            .chain(self.pending.values())
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len() + self.unclassified.len() + self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Write the database as CSV, one row per object with a column per property
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let properties = self
+            .items()
+            .flat_map(|item| item.property_columns().map(|(column, _)| column))
+            .collect::<BTreeSet<_>>();
+
+        let mut header = [
+            "weenie_class_id",
+            "object_ids",
+            "name",
+            "icon",
+            "kind",
+            "item_type",
+            "value",
+            "burden",
+            "spells",
+            "appraisals",
+        ]
+        .map(str::to_string)
+        .to_vec();
+        header.extend(properties.iter().cloned());
+        write_row(&mut writer, &header)?;
+
+        for item in self.items() {
+            let description = item.description.as_ref();
+            let item_type = item.item_type().map(|t| flags(&t)).transpose()?;
+            let spells = item
+                .spell_book
+                .iter()
+                .map(|s| s.id.0.to_string())
+                .collect::<Vec<_>>()
+                .join(";");
+
+            let object_ids = item
+                .object_ids
+                .iter()
+                .map(|id| format!("{id:#010x}"))
+                .collect::<Vec<_>>()
+                .join(";");
+
+            let mut row = vec![
+                opt(item.weenie_class_id),
+                object_ids,
+                item.name.clone().unwrap_or_default(),
+                item.icon
+                    .map(|icon| format!("{icon:#010x}"))
+                    .unwrap_or_default(),
+                format!("{:?}", item.kind),
+                item_type.unwrap_or_default(),
+                opt(description.and_then(|d| d.value)),
+                opt(description.and_then(|d| d.burden)),
+                spells,
+                item.appraisals.to_string(),
+            ];
+            let values = item.property_columns().collect::<HashMap<_, _>>();
+            row.extend(
+                properties
+                    .iter()
+                    .map(|column| values.get(column).cloned().unwrap_or_default()),
+            );
+            write_row(&mut writer, &row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Record an object's description under its weenie class, folding in
+    /// anything appraised about it before it was described
+    fn describe(&mut self, object_id: u32, description: &PublicWeenieDesc) {
+        let weenie_class_id = description.weenie_class_id.0;
+        self.classes.insert(object_id, weenie_class_id);
+
+        let record = self
+            .items
+            .entry(weenie_class_id)
+            .or_insert_with(|| ItemRecord::new(Some(weenie_class_id), object_id));
+        record.seen_as(object_id);
+        if let Some(mut appraised) = self.pending.remove(&object_id) {
+            appraised.weenie_class_id = Some(weenie_class_id);
+            record.merge(appraised);
+        }
+        record.describe(description);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{appraise_info, description, event, table};
+    use crate::enums::{PropertyFloat, PropertyInt, PropertyString};
+
+    const SWORD: u32 = 0x80000001;
+    const OTHER_SWORD: u32 = 0x80000002;
+    const LONGSWORD: u32 = 351;
+
+    fn appraisal(object_id: u32, value: i32, damage_variance: Option<f64>) -> MessageKind {
+        let info = ItemSetAppraiseInfo {
+            int_properties: Some(table(vec![(PropertyInt::Value, value)])),
+            float_properties: damage_variance
+                .map(|v| table(vec![(PropertyFloat::DamageVariance, v)])),
+            string_properties: Some(table(vec![(PropertyString::Name, "Sword".to_string())])),
+            ..appraise_info(object_id)
+        };
+
+        event(GameEventMessage::ItemSetAppraiseInfo(Box::new(info)))
+    }
+
+    #[test]
+    fn test_appraisal_uses_property_names() {
+        let mut database = ItemDatabase::new();
+        database.describe(SWORD, &description(LONGSWORD, "Longsword"));
+        database.process(&appraisal(SWORD, 500, Some(0.5)));
+
+        let item = database.get(LONGSWORD).unwrap();
+        assert_eq!(item.object_ids, vec![SWORD]);
+        assert_eq!(item.icon, Some(0x06001234));
+        assert_eq!(item.name.as_deref(), Some("Sword"));
+        assert_eq!(item.int_properties.get("Value"), Some(&500));
+        assert_eq!(item.float_properties.get("DamageVariance"), Some(&0.5));
+        assert_eq!(item.appraisals, 1);
+    }
+
+    #[test]
+    fn test_merge_deduplicates_by_weenie_class() {
+        let mut first = ItemDatabase::new();
+        first.describe(SWORD, &description(LONGSWORD, "Longsword"));
+        first.process(&appraisal(SWORD, 500, Some(0.5)));
+        let mut second = ItemDatabase::new();
+        second.describe(OTHER_SWORD, &description(LONGSWORD, "Longsword"));
+        second.process(&appraisal(OTHER_SWORD, 750, None));
+
+        first.merge(second);
+
+        assert_eq!(first.len(), 1);
+        let item = first.get(LONGSWORD).unwrap();
+        assert_eq!(item.object_ids, vec![SWORD, OTHER_SWORD]);
+        assert_eq!(item.int_properties.get("Value"), Some(&750));
+        assert_eq!(item.float_properties.get("DamageVariance"), Some(&0.5));
+        assert_eq!(item.appraisals, 2);
+        assert_eq!(first.find_object(OTHER_SWORD).count(), 1);
+    }
+
+    #[test]
+    fn test_appraisal_before_description() {
+        let mut database = ItemDatabase::new();
+        database.process(&appraisal(SWORD, 500, None));
+        assert_eq!(database.items().next().unwrap().weenie_class_id, None);
+
+        database.describe(SWORD, &description(LONGSWORD, "Longsword"));
+        assert_eq!(database.len(), 1);
+        let item = database.get(LONGSWORD).unwrap();
+        assert_eq!(item.int_properties.get("Value"), Some(&500));
+        assert_eq!(item.appraisals, 1);
+    }
+
+    #[test]
+    fn test_object_ids_do_not_carry_across_captures() {
+        let mut database = ItemDatabase::new();
+        database.describe(SWORD, &description(LONGSWORD, "Longsword"));
+        database.end_capture();
+
+        // In a later session the same id is some other, undescribed object
+        database.process(&appraisal(SWORD, 20, None));
+        database.end_capture();
+        database.process(&appraisal(SWORD, 30, None));
+        database.end_capture();
+
+        assert_eq!(database.len(), 3);
+        assert_eq!(database.get(LONGSWORD).unwrap().appraisals, 0);
+        let unclassified: Vec<_> = database
+            .items()
+            .filter(|item| item.weenie_class_id.is_none())
+            .map(|item| item.int_properties["Value"])
+            .collect();
+        assert_eq!(unclassified, vec![20, 30]);
+    }
+
+    #[test]
+    fn test_csv_has_column_per_property() {
+        let mut database = ItemDatabase::new();
+        database.describe(SWORD, &description(LONGSWORD, "Longsword"));
+        database.process(&appraisal(SWORD, 500, None));
+
+        let mut out = Vec::new();
+        database.write_csv(&mut out).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let mut lines = csv.lines();
+
+        assert_eq!(
+            lines.next().unwrap(),
+            "weenie_class_id,object_ids,name,icon,kind,item_type,value,burden,spells,appraisals,Int.Value,String.Name"
+        );
+        assert_eq!(
+            lines.next().unwrap(),
+            "351,0x80000001,Sword,0x06001234,Item,MELEE_WEAPON,500,90,,1,500,Sword"
+        );
+    }
+}
@@ -0,0 +1,448 @@
+//! Request/response correlation and server latency
+//!
+//! Many game actions are answered by a specific game event. The pairs are
+//! listed in a table of [`Pairing`]s naming the request and response message
+//! types (as in [`RawMessage::message_type`]) and how the two are matched:
+//! either by the object both name, or in order for messages that carry no
+//! object. Requests left without an answer for longer than the timeout are
+//! reported as unanswered, and responses with no request waiting as
+//! unsolicited.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::message::{GameActionMessage, GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+
+use super::csv::{opt, write_row};
+use super::{game_action, game_event, parse_all};
+
+/// How a response is matched to its request
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum PairKey {
+    /// The oldest request waiting is answered first
+    Order,
+    /// The request and response name the same object
+    Object,
+}
+
+/// A request message type and the response that answers it
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Pairing {
+    pub name: &'static str,
+    pub request: &'static str,
+    pub response: &'static str,
+    pub key: PairKey,
+}
+
+/// Requests and responses known to pair up
+pub const PAIRINGS: &[Pairing] = &[
+    Pairing {
+        name: "appraise",
+        request: "ItemAppraise",
+        response: "ItemSetAppraiseInfo",
+        key: PairKey::Object,
+    },
+    Pairing {
+        name: "query_health",
+        request: "CombatQueryHealth",
+        response: "CombatQueryHealthResponse",
+        key: PairKey::Object,
+    },
+    Pairing {
+        name: "query_age",
+        request: "CharacterQueryAge",
+        response: "CharacterQueryAgeResponse",
+        key: PairKey::Order,
+    },
+    Pairing {
+        name: "ping",
+        request: "CharacterRequestPing",
+        response: "CharacterReturnPing",
+        key: PairKey::Order,
+    },
+    Pairing {
+        name: "use",
+        request: "InventoryUseEvent",
+        response: "ItemUseDone",
+        key: PairKey::Order,
+    },
+    Pairing {
+        name: "query_mana",
+        request: "ItemQueryItemMana",
+        response: "ItemQueryItemManaResponse",
+        key: PairKey::Object,
+    },
+];
+
+/// How long a request may wait before it is counted as unanswered, in seconds
+pub const DEFAULT_TIMEOUT: f64 = 30.0;
+
+/// The object a request or response is about, for the message types that name one
+pub fn object_id(message: &MessageKind) -> Option<u32> {
+    if let Some(action) = game_action(message) {
+        return match action {
+            GameActionMessage::ItemAppraise(msg) => Some(msg.object_id.0),
+            GameActionMessage::CombatQueryHealth(msg) => Some(msg.object_id.0),
+            GameActionMessage::CharacterQueryAge(msg) => Some(msg.object_id.0),
+            GameActionMessage::InventoryUseEvent(msg) => Some(msg.object_id.0),
+            GameActionMessage::ItemQueryItemMana(msg) => Some(msg.object_id.0),
+            _ => None,
+        };
+    }
+
+    match game_event(message)?.1 {
+        GameEventMessage::ItemSetAppraiseInfo(msg) => Some(msg.object_id.0),
+        GameEventMessage::CombatQueryHealthResponse(msg) => Some(msg.object_id.0),
+        GameEventMessage::ItemQueryItemManaResponse(msg) => Some(msg.object_id.0),
+        _ => None,
+    }
+}
+
+/// One side of an exchange
+#[derive(Clone, Debug, Serialize)]
+pub struct Endpoint {
+    /// Message id within the capture
+    pub id: u32,
+    pub time: f64,
+}
+
+/// A request and what became of it
+#[derive(Clone, Debug, Serialize)]
+pub struct Exchange {
+    pub pairing: &'static str,
+    pub object_id: Option<u32>,
+    pub request: Option<Endpoint>,
+    pub response: Option<Endpoint>,
+}
+
+impl Exchange {
+    /// Seconds between the request and its response
+    pub fn latency(&self) -> Option<f64> {
+        Some(self.response.as_ref()?.time - self.request.as_ref()?.time)
+    }
+
+    fn status(&self) -> &'static str {
+        match (&self.request, &self.response) {
+            (Some(_), Some(_)) => "answered",
+            (Some(_), None) => "unanswered",
+            _ => "unsolicited",
+        }
+    }
+}
+
+/// Latency statistics for one pairing
+#[derive(Clone, Debug, Serialize)]
+pub struct PairingSummary {
+    pub pairing: &'static str,
+    pub answered: usize,
+    pub unanswered: usize,
+    pub unsolicited: usize,
+    pub min: Option<f64>,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub p95: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// Pairs requests with responses from messages fed to it in capture order
+pub struct Correlator {
+    pairings: Vec<Pairing>,
+    timeout: f64,
+    /// Requests waiting for an answer, by pairing
+    pending: Vec<Vec<Exchange>>,
+    answered: Vec<Exchange>,
+    unanswered: Vec<Exchange>,
+    unsolicited: Vec<Exchange>,
+}
+
+impl Default for Correlator {
+    fn default() -> Self {
+        Self::new(PAIRINGS, DEFAULT_TIMEOUT)
+    }
+}
+
+impl Correlator {
+    pub fn new(pairings: &[Pairing], timeout: f64) -> Self {
+        Self {
+            pairings: pairings.to_vec(),
+            timeout,
+            pending: vec![Vec::new(); pairings.len()],
+            answered: Vec::new(),
+            unanswered: Vec::new(),
+            unsolicited: Vec::new(),
+        }
+    }
+
+    /// Process a message along with its raw form, which gives its type, id and time
+    pub fn process(&mut self, raw: &RawMessage, message: &MessageKind) {
+        let time = raw.timestamp.unwrap_or_default();
+        self.expire(time);
+
+        let endpoint = Endpoint { id: raw.id, time };
+        for (index, pairing) in self.pairings.iter().enumerate() {
+            if raw.message_type == pairing.request {
+                self.pending[index].push(Exchange {
+                    pairing: pairing.name,
+                    object_id: object_id(message),
+                    request: Some(endpoint.clone()),
+                    response: None,
+                });
+            } else if raw.message_type == pairing.response {
+                let object_id = object_id(message);
+                let pending = &mut self.pending[index];
+                let waiting = match pairing.key {
+                    PairKey::Order => (!pending.is_empty()).then_some(0),
+                    PairKey::Object => pending.iter().position(|e| e.object_id == object_id),
+                };
+                match waiting {
+                    Some(position) => {
+                        let mut exchange = pending.remove(position);
+                        exchange.response = Some(endpoint.clone());
+                        self.answered.push(exchange);
+                    }
+                    None => self.unsolicited.push(Exchange {
+                        pairing: pairing.name,
+                        object_id,
+                        request: None,
+                        response: Some(endpoint.clone()),
+                    }),
+                }
+            }
+        }
+    }
+
+    pub fn finish(mut self) -> LatencyReport {
+        for pending in &mut self.pending {
+            self.unanswered.append(pending);
+        }
+
+        let mut exchanges = self.answered;
+        exchanges.append(&mut self.unanswered);
+        exchanges.append(&mut self.unsolicited);
+        exchanges.sort_by(|a, b| {
+            let start = |e: &Exchange| e.request.as_ref().or(e.response.as_ref()).map(|p| p.id);
+            start(a).cmp(&start(b))
+        });
+
+        let summary = self
+            .pairings
+            .iter()
+            .map(|pairing| summarize(pairing.name, &exchanges))
+            .collect();
+
+        LatencyReport { summary, exchanges }
+    }
+
+    /// Give up on requests that have waited longer than the timeout
+    fn expire(&mut self, now: f64) {
+        for pending in &mut self.pending {
+            let (expired, waiting): (Vec<_>, Vec<_>) = pending.drain(..).partition(|e| {
+                e.request
+                    .as_ref()
+                    .is_some_and(|r| now - r.time > self.timeout)
+            });
+            *pending = waiting;
+            self.unanswered.extend(expired);
+        }
+    }
+}
+
+fn summarize(pairing: &'static str, exchanges: &[Exchange]) -> PairingSummary {
+    let exchanges = exchanges
+        .iter()
+        .filter(|e| e.pairing == pairing)
+        .collect::<Vec<_>>();
+    let mut latencies = exchanges
+        .iter()
+        .filter_map(|e| e.latency())
+        .collect::<Vec<_>>();
+    latencies.sort_by(f64::total_cmp);
+
+    let percentile = |p: f64| {
+        let last = latencies.len().checked_sub(1)?;
+        Some(latencies[((last as f64) * p).round() as usize])
+    };
+
+    PairingSummary {
+        pairing,
+        answered: latencies.len(),
+        unanswered: exchanges.iter().filter(|e| e.response.is_none()).count(),
+        unsolicited: exchanges.iter().filter(|e| e.request.is_none()).count(),
+        min: latencies.first().copied(),
+        mean: (!latencies.is_empty())
+            .then(|| latencies.iter().sum::<f64>() / latencies.len() as f64),
+        median: percentile(0.5),
+        p95: percentile(0.95),
+        max: latencies.last().copied(),
+    }
+}
+
+/// Every request/response exchange in a capture with latency statistics
+#[derive(Clone, Debug, Serialize)]
+pub struct LatencyReport {
+    pub summary: Vec<PairingSummary>,
+    /// Exchanges in the order they started
+    pub exchanges: Vec<Exchange>,
+}
+
+impl LatencyReport {
+    /// Correlate the messages in a capture using the default pairings and timeout
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        Self::with_pairings(messages, PAIRINGS, DEFAULT_TIMEOUT)
+    }
+
+    /// Correlate the messages in a capture using the given pairings and timeout
+    pub fn with_pairings(messages: &[RawMessage], pairings: &[Pairing], timeout: f64) -> Self {
+        let mut correlator = Correlator::new(pairings, timeout);
+        for (raw, message) in parse_all(messages) {
+            correlator.process(raw, &message);
+        }
+        correlator.finish()
+    }
+
+    pub fn answered(&self) -> impl Iterator<Item = &Exchange> {
+        self.exchanges.iter().filter(|e| e.latency().is_some())
+    }
+
+    pub fn unanswered(&self) -> impl Iterator<Item = &Exchange> {
+        self.exchanges.iter().filter(|e| e.response.is_none())
+    }
+
+    pub fn unsolicited(&self) -> impl Iterator<Item = &Exchange> {
+        self.exchanges.iter().filter(|e| e.request.is_none())
+    }
+
+    /// Write the report as CSV, one row per exchange
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &[
+                "pairing",
+                "status",
+                "object_id",
+                "request_id",
+                "request_time",
+                "response_id",
+                "response_time",
+                "latency",
+            ],
+        )?;
+
+        for exchange in &self.exchanges {
+            let request = exchange.request.as_ref();
+            let response = exchange.response.as_ref();
+            write_row(
+                &mut writer,
+                &[
+                    exchange.pairing.to_string(),
+                    exchange.status().to_string(),
+                    opt(exchange.object_id.map(|id| format!("{id:#010x}"))),
+                    opt(request.map(|r| r.id)),
+                    opt(request.map(|r| r.time)),
+                    opt(response.map(|r| r.id)),
+                    opt(response.map(|r| r.time)),
+                    opt(exchange.latency()),
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{action, appraise_info, event, raw};
+    use crate::gameactions::{CharacterRequestPing, ItemAppraise};
+    use crate::gameevents::CharacterReturnPing;
+    use crate::types::ObjectId;
+
+    const SWORD: u32 = 0x80000001;
+    const SHIELD: u32 = 0x80000002;
+
+    fn appraise(object_id: u32) -> MessageKind {
+        action(GameActionMessage::ItemAppraise(ItemAppraise {
+            object_id: ObjectId(object_id),
+        }))
+    }
+
+    fn appraisal(object_id: u32) -> MessageKind {
+        event(GameEventMessage::ItemSetAppraiseInfo(Box::new(
+            appraise_info(object_id),
+        )))
+    }
+
+    /// Feed messages to a correlator, each at the time given by its id
+    fn correlate(correlator: &mut Correlator, messages: &[(u32, MessageKind)]) {
+        for (id, message) in messages {
+            correlator.process(&raw(*id, message), message);
+        }
+    }
+
+    #[test]
+    fn test_ping_latency_in_order() {
+        let ping = || {
+            action(GameActionMessage::CharacterRequestPing(
+                CharacterRequestPing {},
+            ))
+        };
+        let pong = || {
+            event(GameEventMessage::CharacterReturnPing(
+                CharacterReturnPing {},
+            ))
+        };
+        let mut correlator = Correlator::default();
+        correlate(
+            &mut correlator,
+            &[(10, ping()), (11, ping()), (12, pong()), (13, pong())],
+        );
+        let report = correlator.finish();
+
+        let latencies = report
+            .answered()
+            .map(|e| e.latency().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(latencies, [2.0, 2.0]);
+        let ping = report.summary.iter().find(|s| s.pairing == "ping").unwrap();
+        assert_eq!(ping.answered, 2);
+        assert_eq!(ping.median, Some(2.0));
+    }
+
+    #[test]
+    fn test_matches_by_object() {
+        let mut correlator = Correlator::default();
+        correlate(
+            &mut correlator,
+            &[
+                (1, appraise(SWORD)),
+                (2, appraise(SHIELD)),
+                (3, appraisal(SHIELD)),
+            ],
+        );
+        let report = correlator.finish();
+
+        let answered = report.answered().collect::<Vec<_>>();
+        assert_eq!(answered.len(), 1);
+        assert_eq!(answered[0].object_id, Some(SHIELD));
+        assert_eq!(answered[0].latency(), Some(1.0));
+        assert_eq!(report.unanswered().count(), 1);
+    }
+
+    #[test]
+    fn test_timeout_and_unsolicited() {
+        let mut correlator = Correlator::new(PAIRINGS, 5.0);
+        correlate(
+            &mut correlator,
+            &[(1, appraise(SWORD)), (10, appraisal(SWORD))],
+        );
+        let report = correlator.finish();
+
+        assert_eq!(report.unanswered().count(), 1);
+        let unsolicited = report.unsolicited().collect::<Vec<_>>();
+        assert_eq!(unsolicited.len(), 1);
+        assert_eq!(unsolicited[0].response.as_ref().unwrap().id, 10);
+    }
+}
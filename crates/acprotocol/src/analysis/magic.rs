@@ -0,0 +1,574 @@
+//! Spell casting timeline
+//!
+//! Follows each `Magic_CastTargetedSpell` and `Magic_CastUntargetedSpell`
+//! action through to what the server sent back: the enchantment the spell
+//! applied, the `Communication_WeenieError` explaining why it failed, and the
+//! drop in the caster's mana from `Qualities_PrivateUpdateAttribute2ndLevel`.
+//!
+//! The client only has one spell in flight at a time, so every response is
+//! credited to the most recent cast. A cast stays open for mana updates until
+//! the next cast or [`CAST_WINDOW`] seconds have passed, since the server may
+//! report the mana before or after the enchantment.
+//!
+//! Only enchantments that land on the caster are visible, so war spells and
+//! buffs on other players end up as [`CastOutcome::Cast`] when mana was spent.
+//! The spell category of those casts is filled in from any enchantment of the
+//! same spell seen elsewhere in the capture.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::enums::{CurVitalId, SpellCategory, WeenieError};
+use crate::message::{GameActionMessage, GameEventMessage, MessageKind, S2CMessage};
+use crate::network::RawMessage;
+use crate::types::Enchantment;
+
+use super::csv::{opt, write_row};
+use super::objects::ObjectIndex;
+use super::{game_action, game_event, parse_all};
+
+/// Seconds after a cast during which responses are still credited to it
+pub const CAST_WINDOW: f64 = 10.0;
+
+/// What became of a cast
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub enum CastOutcome {
+    /// The spell's enchantment was applied to the caster
+    Landed,
+    /// Mana was spent but no enchantment reached the caster
+    Cast,
+    Fizzled,
+    OutOfRange,
+    NotEnoughMana,
+    /// Any other magic error
+    Failed(WeenieError),
+    /// Nothing was heard about the cast
+    Unknown,
+}
+
+impl CastOutcome {
+    fn from_error(error: &WeenieError) -> Option<Self> {
+        let code = error.clone() as u32;
+        let outcome = match error {
+            WeenieError::YourSpellFizzled => Self::Fizzled,
+            WeenieError::MagicTargetOutOfRange => Self::OutOfRange,
+            WeenieError::YouDontHaveEnoughManaToCast => Self::NotEnoughMana,
+            _ if (0x3FA..=0x40A).contains(&code) => Self::Failed(error.clone()),
+            WeenieError::MagicInvalidPosition
+            | WeenieError::YourAllegianceRankIsTooLowToUseMagic
+            | WeenieError::YourArcaneLoreIsTooLowToUseMagic => Self::Failed(error.clone()),
+            _ => return None,
+        };
+        Some(outcome)
+    }
+}
+
+impl std::fmt::Display for CastOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Failed(error) => write!(f, "Failed({error})"),
+            other => write!(f, "{other:?}"),
+        }
+    }
+}
+
+/// A single cast and its result
+#[derive(Clone, Debug, Serialize)]
+pub struct SpellCast {
+    pub time: f64,
+    pub spell_id: u16,
+    pub layer: u16,
+    /// Target of a targeted cast
+    pub target_id: Option<u32>,
+    pub target_name: Option<String>,
+    pub outcome: CastOutcome,
+    pub category: Option<SpellCategory>,
+    /// Mana when the cast was sent, if known
+    pub mana_before: Option<u32>,
+    /// Total mana lost while the cast was open
+    pub mana_cost: Option<u32>,
+    /// Seconds from the cast to its enchantment or error
+    pub cast_to_effect: Option<f64>,
+}
+
+/// Cast counts and timings for a group of casts
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CastStats {
+    pub casts: usize,
+    pub landed: usize,
+    pub cast: usize,
+    pub fizzled: usize,
+    pub out_of_range: usize,
+    pub not_enough_mana: usize,
+    pub failed: usize,
+    pub unknown: usize,
+    /// Fraction of casts that fizzled
+    pub fizzle_rate: f64,
+    pub mean_cast_to_effect: Option<f64>,
+    pub mean_mana_cost: Option<f64>,
+}
+
+impl CastStats {
+    fn from_casts<'a>(casts: impl IntoIterator<Item = &'a SpellCast>) -> Self {
+        let mut stats = Self::default();
+        let mut times = Vec::new();
+        let mut costs = Vec::new();
+        for cast in casts {
+            stats.casts += 1;
+            match cast.outcome {
+                CastOutcome::Landed => stats.landed += 1,
+                CastOutcome::Cast => stats.cast += 1,
+                CastOutcome::Fizzled => stats.fizzled += 1,
+                CastOutcome::OutOfRange => stats.out_of_range += 1,
+                CastOutcome::NotEnoughMana => stats.not_enough_mana += 1,
+                CastOutcome::Failed(_) => stats.failed += 1,
+                CastOutcome::Unknown => stats.unknown += 1,
+            }
+            times.extend(cast.cast_to_effect);
+            costs.extend(cast.mana_cost.map(f64::from));
+        }
+
+        let mean = |values: &[f64]| {
+            (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+        };
+        if stats.casts > 0 {
+            stats.fizzle_rate = stats.fizzled as f64 / stats.casts as f64;
+        }
+        stats.mean_cast_to_effect = mean(&times);
+        stats.mean_mana_cost = mean(&costs);
+        stats
+    }
+}
+
+/// Statistics for every cast of one spell
+#[derive(Clone, Debug, Serialize)]
+pub struct SpellSummary {
+    pub spell_id: u16,
+    pub category: Option<SpellCategory>,
+    pub stats: CastStats,
+}
+
+/// Statistics for every cast in one spell category
+#[derive(Clone, Debug, Serialize)]
+pub struct CategorySummary {
+    /// `None` groups the casts whose category was never seen
+    pub category: Option<SpellCategory>,
+    pub stats: CastStats,
+}
+
+/// Builds the cast timeline from messages fed to it in capture order
+#[derive(Default)]
+pub struct MagicTracker {
+    casts: Vec<SpellCast>,
+    /// Index of the cast still collecting responses
+    open: Option<usize>,
+    /// Whether the open cast already has its outcome
+    resolved: bool,
+    mana: Option<u32>,
+    /// Category of each spell, from the enchantments seen
+    categories: HashMap<u16, SpellCategory>,
+    objects: ObjectIndex,
+}
+
+impl MagicTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.objects.process(message);
+
+        if let Some(index) = self.open
+            && time - self.casts[index].time > CAST_WINDOW
+        {
+            self.open = None;
+        }
+
+        if let Some(action) = game_action(message) {
+            let (target_id, spell_id) = match action {
+                GameActionMessage::MagicCastTargetedSpell(msg) => {
+                    (Some(msg.object_id.0), &msg.spell_id)
+                }
+                GameActionMessage::MagicCastUntargetedSpell(msg) => (None, &msg.spell_id),
+                _ => return,
+            };
+            self.open = Some(self.casts.len());
+            self.resolved = false;
+            self.casts.push(SpellCast {
+                time,
+                spell_id: spell_id.id.0,
+                layer: spell_id.layer,
+                target_id,
+                target_name: None,
+                outcome: CastOutcome::Unknown,
+                category: None,
+                mana_before: self.mana,
+                mana_cost: None,
+                cast_to_effect: None,
+            });
+            return;
+        }
+
+        if let MessageKind::S2C(msg) = message
+            && let S2CMessage::QualitiesPrivateUpdateAttribute2ndLevel(msg) = msg.as_ref()
+            && msg.key == CurVitalId::CurrentMana
+        {
+            self.update_mana(msg.value);
+            return;
+        }
+
+        let Some((_, event)) = game_event(message) else {
+            return;
+        };
+
+        match event {
+            GameEventMessage::MagicUpdateEnchantment(msg) => {
+                self.enchantment(time, &msg.enchantment);
+            }
+            GameEventMessage::MagicUpdateMultipleEnchantments(msg) => {
+                for enchantment in &msg.enchantments.list {
+                    self.enchantment(time, enchantment);
+                }
+            }
+            GameEventMessage::CommunicationWeenieError(msg) => {
+                if let Some(outcome) = CastOutcome::from_error(&msg.type_) {
+                    self.resolve(time, outcome);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn finish(self) -> CastLog {
+        let mut casts = self.casts;
+        for cast in &mut casts {
+            if cast.outcome == CastOutcome::Unknown && cast.mana_cost.is_some() {
+                cast.outcome = CastOutcome::Cast;
+            }
+            if cast.category.is_none() {
+                cast.category = self.categories.get(&cast.spell_id).cloned();
+            }
+            cast.target_name = cast
+                .target_id
+                .and_then(|id| self.objects.name(id))
+                .map(str::to_string);
+        }
+
+        let mut by_spell: BTreeMap<u16, Vec<&SpellCast>> = BTreeMap::new();
+        let mut by_category: BTreeMap<Option<u16>, Vec<&SpellCast>> = BTreeMap::new();
+        for cast in &casts {
+            by_spell.entry(cast.spell_id).or_default().push(cast);
+            let category = cast.category.clone().map(|c| c as u16);
+            by_category.entry(category).or_default().push(cast);
+        }
+
+        let spells = by_spell
+            .into_values()
+            .map(|group| SpellSummary {
+                spell_id: group[0].spell_id,
+                category: group[0].category.clone(),
+                stats: CastStats::from_casts(group),
+            })
+            .collect();
+        let categories = by_category
+            .into_values()
+            .map(|group| CategorySummary {
+                category: group[0].category.clone(),
+                stats: CastStats::from_casts(group),
+            })
+            .collect();
+
+        CastLog {
+            spells,
+            categories,
+            casts,
+        }
+    }
+
+    fn update_mana(&mut self, value: u32) {
+        if let Some(index) = self.open
+            && let Some(before) = self.mana
+            && value < before
+        {
+            let cast = &mut self.casts[index];
+            *cast.mana_cost.get_or_insert(0) += before - value;
+        }
+        self.mana = Some(value);
+    }
+
+    fn enchantment(&mut self, time: f64, enchantment: &Enchantment) {
+        let spell_id = enchantment.id.id.0;
+        self.categories
+            .insert(spell_id, enchantment.spell_category.clone());
+
+        if let Some(index) = self.open
+            && self.casts[index].spell_id == spell_id
+        {
+            self.casts[index].category = Some(enchantment.spell_category.clone());
+            self.resolve(time, CastOutcome::Landed);
+        }
+    }
+
+    fn resolve(&mut self, time: f64, outcome: CastOutcome) {
+        let Some(index) = self.open else {
+            return;
+        };
+        if self.resolved {
+            return;
+        }
+        let cast = &mut self.casts[index];
+        cast.outcome = outcome;
+        cast.cast_to_effect = Some(time - cast.time);
+        self.resolved = true;
+    }
+}
+
+/// Every spell cast in a capture with per-spell and per-category statistics
+#[derive(Clone, Debug, Serialize)]
+pub struct CastLog {
+    pub spells: Vec<SpellSummary>,
+    pub categories: Vec<CategorySummary>,
+    /// Casts in the order they were sent
+    pub casts: Vec<SpellCast>,
+}
+
+impl CastLog {
+    /// Build the log from the magic messages in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut tracker = MagicTracker::new();
+        for (raw, message) in parse_all(messages) {
+            tracker.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        tracker.finish()
+    }
+
+    /// Write the log as CSV, one row per cast
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &[
+                "time",
+                "spell_id",
+                "layer",
+                "category",
+                "target_id",
+                "target_name",
+                "outcome",
+                "mana_before",
+                "mana_cost",
+                "cast_to_effect",
+            ],
+        )?;
+
+        for cast in &self.casts {
+            write_row(
+                &mut writer,
+                &[
+                    cast.time.to_string(),
+                    cast.spell_id.to_string(),
+                    cast.layer.to_string(),
+                    opt(cast.category.as_ref()),
+                    opt(cast.target_id.map(|id| format!("{id:#010x}"))),
+                    cast.target_name.clone().unwrap_or_default(),
+                    cast.outcome.to_string(),
+                    opt(cast.mana_before),
+                    opt(cast.mana_cost),
+                    opt(cast.cast_to_effect),
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{PLAYER, action, event};
+    use crate::enums::EnchantmentTypeFlags;
+    use crate::gameactions::{MagicCastTargetedSpell, MagicCastUntargetedSpell};
+    use crate::gameevents::{CommunicationWeenieError, MagicUpdateEnchantment};
+
+    use crate::messages::s2c::QualitiesPrivateUpdateAttribute2ndLevel;
+    use crate::types::{LayeredSpellId, ObjectId, SpellId, StatMod};
+
+    const MONSTER: u32 = 0x80000001;
+    const STRENGTH_SELF: u16 = 2;
+    const FLAME_BOLT: u16 = 27;
+
+    fn spell(id: u16) -> LayeredSpellId {
+        LayeredSpellId {
+            id: SpellId(id),
+            layer: 1,
+        }
+    }
+
+    fn cast_at(target: u32, id: u16) -> MessageKind {
+        action(GameActionMessage::MagicCastTargetedSpell(
+            MagicCastTargetedSpell {
+                object_id: ObjectId(target),
+                spell_id: spell(id),
+            },
+        ))
+    }
+
+    fn mana(value: u32) -> MessageKind {
+        MessageKind::S2C(Box::new(
+            S2CMessage::QualitiesPrivateUpdateAttribute2ndLevel(
+                QualitiesPrivateUpdateAttribute2ndLevel {
+                    sequence: 0,
+                    key: CurVitalId::CurrentMana,
+                    value,
+                },
+            ),
+        ))
+    }
+
+    fn error(type_: WeenieError) -> MessageKind {
+        event(GameEventMessage::CommunicationWeenieError(
+            CommunicationWeenieError { type_ },
+        ))
+    }
+
+    fn update(id: u16, category: SpellCategory) -> MessageKind {
+        event(GameEventMessage::MagicUpdateEnchantment(
+            MagicUpdateEnchantment {
+                enchantment: Enchantment {
+                    id: spell(id),
+                    has_equipment_set: 0,
+                    spell_category: category,
+                    power_level: 10,
+                    start_time: 0.0,
+                    duration: 1800.0,
+                    caster_id: ObjectId(PLAYER),
+                    degrade_modifier: 0.0,
+                    degrade_limit: -666.0,
+                    last_time_degraded: 0.0,
+                    stat_mod: StatMod {
+                        type_: EnchantmentTypeFlags::BENEFICIAL,
+                        key: 1,
+                        value: 10.0,
+                    },
+                    equipment_set: None,
+                },
+            },
+        ))
+    }
+
+    #[test]
+    fn test_landed_cast_with_mana_and_timing() {
+        let mut tracker = MagicTracker::new();
+        tracker.process(0.0, &mana(100));
+        tracker.process(1.0, &cast_at(PLAYER, STRENGTH_SELF));
+        tracker.process(2.5, &mana(85));
+        tracker.process(2.75, &update(STRENGTH_SELF, SpellCategory::StrengthRaising));
+        let log = tracker.finish();
+
+        let cast = &log.casts[0];
+        assert_eq!(cast.outcome, CastOutcome::Landed);
+        assert_eq!(cast.target_id, Some(PLAYER));
+        assert_eq!(cast.mana_before, Some(100));
+        assert_eq!(cast.mana_cost, Some(15));
+        assert_eq!(cast.cast_to_effect, Some(1.75));
+        assert_eq!(cast.category, Some(SpellCategory::StrengthRaising));
+    }
+
+    #[test]
+    fn test_errors_end_casts() {
+        let mut tracker = MagicTracker::new();
+        tracker.process(0.0, &cast_at(MONSTER, FLAME_BOLT));
+        tracker.process(1.0, &error(WeenieError::YourSpellFizzled));
+        tracker.process(3.0, &cast_at(MONSTER, FLAME_BOLT));
+        tracker.process(3.5, &error(WeenieError::MagicTargetOutOfRange));
+        tracker.process(5.0, &cast_at(MONSTER, FLAME_BOLT));
+        tracker.process(5.5, &error(WeenieError::YouDontHaveEnoughManaToCast));
+        // Not about magic, so it must not touch the open cast
+        tracker.process(
+            6.0,
+            &action(GameActionMessage::MagicCastUntargetedSpell(
+                MagicCastUntargetedSpell {
+                    spell_id: spell(FLAME_BOLT),
+                },
+            )),
+        );
+        tracker.process(6.5, &error(WeenieError::YouAreTooFatiguedToAttack));
+        let log = tracker.finish();
+
+        let outcomes: Vec<_> = log.casts.iter().map(|c| c.outcome.clone()).collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                CastOutcome::Fizzled,
+                CastOutcome::OutOfRange,
+                CastOutcome::NotEnoughMana,
+                CastOutcome::Unknown,
+            ]
+        );
+        assert_eq!(log.casts[0].cast_to_effect, Some(1.0));
+        assert_eq!(log.casts[3].target_id, None);
+    }
+
+    #[test]
+    fn test_aggregates_by_spell_and_category() {
+        let mut tracker = MagicTracker::new();
+        tracker.process(0.0, &mana(200));
+        tracker.process(1.0, &cast_at(PLAYER, STRENGTH_SELF));
+        tracker.process(1.5, &error(WeenieError::YourSpellFizzled));
+        tracker.process(3.0, &cast_at(PLAYER, STRENGTH_SELF));
+        tracker.process(4.0, &update(STRENGTH_SELF, SpellCategory::StrengthRaising));
+        // A war spell never lands on the caster, but the mana shows it went off
+        tracker.process(5.0, &cast_at(MONSTER, FLAME_BOLT));
+        tracker.process(6.0, &mana(190));
+        let log = tracker.finish();
+
+        assert_eq!(log.casts[2].outcome, CastOutcome::Cast);
+        assert_eq!(log.casts[0].category, Some(SpellCategory::StrengthRaising));
+
+        assert_eq!(log.spells.len(), 2);
+        let strength = &log.spells[0];
+        assert_eq!(strength.spell_id, STRENGTH_SELF);
+        assert_eq!(strength.stats.casts, 2);
+        assert_eq!(strength.stats.landed, 1);
+        assert_eq!(strength.stats.fizzle_rate, 0.5);
+        assert_eq!(strength.stats.mean_cast_to_effect, Some(0.75));
+
+        assert_eq!(log.categories.len(), 2);
+        assert_eq!(log.categories[0].category, None);
+        assert_eq!(log.categories[0].stats.mean_mana_cost, Some(10.0));
+        assert_eq!(
+            log.categories[1].category,
+            Some(SpellCategory::StrengthRaising)
+        );
+    }
+
+    #[test]
+    fn test_responses_without_an_open_cast() {
+        let mut tracker = MagicTracker::new();
+        // Answers to a cast sent before the capture started
+        tracker.process(0.0, &error(WeenieError::YourSpellFizzled));
+        tracker.process(0.5, &update(STRENGTH_SELF, SpellCategory::StrengthRaising));
+        tracker.process(1.0, &cast_at(MONSTER, FLAME_BOLT));
+        // Too late to belong to the cast
+        tracker.process(
+            1.0 + CAST_WINDOW + 1.0,
+            &error(WeenieError::YourSpellFizzled),
+        );
+        tracker.process(20.0, &cast_at(PLAYER, STRENGTH_SELF));
+        tracker.process(20.5, &error(WeenieError::YourSpellFizzled));
+        // A second answer doesn't change the first
+        tracker.process(21.0, &update(STRENGTH_SELF, SpellCategory::StrengthRaising));
+        let log = tracker.finish();
+
+        assert_eq!(log.casts.len(), 2);
+        assert_eq!(log.casts[0].outcome, CastOutcome::Unknown);
+        assert_eq!(log.casts[0].cast_to_effect, None);
+        assert_eq!(log.casts[1].outcome, CastOutcome::Fizzled);
+        assert_eq!(log.casts[1].cast_to_effect, Some(0.5));
+        // The category is still learned from the enchantment
+        assert_eq!(log.casts[1].category, Some(SpellCategory::StrengthRaising));
+    }
+}
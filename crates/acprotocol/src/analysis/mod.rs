@@ -0,0 +1,215 @@
+//! Analyses that reconstruct game state from a stream of parsed messages
+//!
+//! Each analysis consumes messages in capture order and produces a
+//! serializable report. They all share the helpers below for getting at the
+//! game events and actions wrapped inside ordered messages.
+
+use crate::message::{C2SMessage, GameActionMessage, GameEventMessage, MessageKind, S2CMessage};
+use crate::network::RawMessage;
+
+pub mod allegiance;
+pub mod books;
+pub mod chat;
+pub mod chess;
+pub mod corpus;
+pub mod crafting;
+pub(crate) mod csv;
+#[cfg(feature = "diff")]
+pub mod diff;
+pub mod enchantments;
+pub mod fellowship;
+pub mod follow;
+pub mod housing;
+pub mod items;
+pub mod latency;
+pub mod magic;
+pub mod objects;
+pub mod quests;
+pub mod references;
+pub mod social;
+pub mod stats;
+pub mod trades;
+pub mod vendors;
+
+/// Parse every message that can be parsed, pairing it with its raw form
+///
+/// Messages that fail to parse are skipped.
+pub fn parse_all(messages: &[RawMessage]) -> impl Iterator<Item = (&RawMessage, MessageKind)> {
+    messages
+        .iter()
+        .filter_map(|raw| raw.parse().ok().map(|message| (raw, message)))
+}
+
+/// Get the game event carried by a message, along with the object it was sent to
+pub fn game_event(message: &MessageKind) -> Option<(u32, &GameEventMessage)> {
+    match message {
+        MessageKind::S2C(msg) => match msg.as_ref() {
+            S2CMessage::OrderedGameEvent {
+                object_id, event, ..
+            } => Some((*object_id, event.as_ref())),
+            _ => None,
+        },
+        MessageKind::C2S(_) => None,
+    }
+}
+
+/// Get the game action carried by a message
+pub fn game_action(message: &MessageKind) -> Option<&GameActionMessage> {
+    match message {
+        MessageKind::C2S(msg) => match msg.as_ref() {
+            C2SMessage::OrderedGameAction { action, .. } => Some(action),
+            _ => None,
+        },
+        MessageKind::S2C(_) => None,
+    }
+}
+
+/// Builders for the messages analysis tests feed their trackers
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::collections::HashMap;
+    use std::hash::Hash;
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::enums::{ItemType, ObjectDescriptionFlag};
+    use crate::gameevents::ItemSetAppraiseInfo;
+    use crate::types::{ObjectId, PackableHashTable, PackableList, PackedDWORD, PublicWeenieDesc};
+    use crate::writers::ACWritable;
+
+    /// The character the capture was recorded by
+    pub const PLAYER: u32 = 0x50000001;
+
+    /// A game event sent to the player
+    pub fn event(event: GameEventMessage) -> MessageKind {
+        event_to(PLAYER, event)
+    }
+
+    /// A game event sent to another object
+    pub fn event_to(object_id: u32, event: GameEventMessage) -> MessageKind {
+        MessageKind::S2C(Box::new(S2CMessage::OrderedGameEvent {
+            object_id,
+            sequence: 0,
+            event: Box::new(event),
+        }))
+    }
+
+    /// A game action sent by the player
+    pub fn action(action: GameActionMessage) -> MessageKind {
+        MessageKind::C2S(Box::new(C2SMessage::OrderedGameAction {
+            sequence: 0,
+            action,
+        }))
+    }
+
+    /// A message as it would be captured, with its id as its timestamp
+    pub fn raw(id: u32, message: &MessageKind) -> RawMessage {
+        let mut data = Cursor::new(Vec::new());
+        match message {
+            MessageKind::S2C(msg) => msg.write(&mut data).unwrap(),
+            MessageKind::C2S(msg) => msg.write(&mut data).unwrap(),
+        }
+        let mut raw = RawMessage::from_fragment(data.into_inner(), id, id).unwrap();
+        raw.timestamp = Some(f64::from(id));
+        raw
+    }
+
+    /// Description of an item with a value and burden
+    pub fn description(weenie_class_id: u32, name: &str) -> PublicWeenieDesc {
+        PublicWeenieDesc {
+            header: 0,
+            name: name.to_string(),
+            weenie_class_id: PackedDWORD(weenie_class_id),
+            icon: PackedDWORD(0x06001234),
+            type_: ItemType::MELEE_WEAPON,
+            behavior: ObjectDescriptionFlag::empty(),
+            header2: None,
+            plural_name: None,
+            items_capacity: None,
+            container_capacity: None,
+            ammunition_type: None,
+            value: Some(500),
+            useability: None,
+            use_radius: None,
+            target_type: None,
+            effects: None,
+            combat_use: None,
+            structure: None,
+            max_structure: None,
+            stack_size: None,
+            max_stack_size: None,
+            container_id: None,
+            wielder_id: None,
+            valid_slots: None,
+            slot: None,
+            priority: None,
+            blip_color: None,
+            radar_enum: None,
+            physics_script: None,
+            workmanship: None,
+            burden: Some(90),
+            spell_id: None,
+            owner_id: None,
+            restrictions: None,
+            hook_item_types: None,
+            monarch_id: None,
+            hook_type: None,
+            icon_overlay: None,
+            icon_underlay: None,
+            material: None,
+            cooldown_id: None,
+            cooldown_duration: None,
+            pet_owner_id: None,
+        }
+    }
+
+    /// A successful appraisal of an object, with nothing filled in
+    pub fn appraise_info(object_id: u32) -> ItemSetAppraiseInfo {
+        ItemSetAppraiseInfo {
+            object_id: ObjectId(object_id),
+            flags: 0,
+            success: true,
+            int_properties: None,
+            int64_properties: None,
+            bool_properties: None,
+            float_properties: None,
+            string_properties: None,
+            data_id_properties: None,
+            spell_book: None,
+            armor_profile: None,
+            creature_profile: None,
+            weapon_profile: None,
+            hook_profile: None,
+            armor_highlight: None,
+            armor_color: None,
+            weapon_highlight: None,
+            weapon_color: None,
+            resist_highlight: None,
+            resist_color: None,
+            base_armor_head: None,
+            base_armor_chest: None,
+            base_armor_groin: None,
+            base_armor_bicep: None,
+            base_armor_wrist: None,
+            base_armor_hand: None,
+            base_armor_thigh: None,
+            base_armor_shin: None,
+            base_armor_foot: None,
+        }
+    }
+
+    pub fn list<T>(list: Vec<T>) -> PackableList<T> {
+        PackableList {
+            count: list.len() as u32,
+            list,
+        }
+    }
+
+    pub fn table<K: Eq + Hash, V>(entries: Vec<(K, V)>) -> PackableHashTable<K, V> {
+        PackableHashTable {
+            count: entries.len() as u16,
+            max_size: 0,
+            table: entries.into_iter().collect::<HashMap<_, _>>(),
+        }
+    }
+}
@@ -0,0 +1,73 @@
+//! Names and positions of objects seen in a capture
+//!
+//! Most events refer to objects by id only. The server describes each object
+//! to the client with `Item_CreateObject` (and later `Item_UpdateObject`)
+//! before it is used, so those descriptions are where names and locations
+//! come from.
+
+use std::collections::HashMap;
+
+use crate::message::{MessageKind, S2CMessage};
+use crate::types::{PhysicsDesc, Position};
+
+/// Object names and positions collected from object descriptions
+#[derive(Clone, Debug, Default)]
+pub struct ObjectIndex {
+    names: HashMap<u32, String>,
+    positions: HashMap<u32, Position>,
+}
+
+impl ObjectIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record what a message says about any object it describes
+    pub fn process(&mut self, message: &MessageKind) {
+        let MessageKind::S2C(msg) = message else {
+            return;
+        };
+
+        match msg.as_ref() {
+            S2CMessage::ItemCreateObject(msg) => {
+                self.insert(msg.object_id.0, &msg.weenie_description.name);
+                self.locate(msg.object_id.0, &msg.physics_description);
+            }
+            S2CMessage::ItemUpdateObject(msg) => {
+                self.insert(msg.object_id.0, &msg.weenie_desc.name);
+                self.locate(msg.object_id.0, &msg.physics_desc);
+            }
+            _ => {}
+        }
+    }
+
+    /// The most recent name given to an object
+    pub fn name(&self, object_id: u32) -> Option<&str> {
+        self.names.get(&object_id).map(String::as_str)
+    }
+
+    /// Where an object was when it was last described
+    pub fn position(&self, object_id: u32) -> Option<&Position> {
+        self.positions.get(&object_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub(crate) fn insert(&mut self, object_id: u32, name: &str) {
+        if !name.is_empty() {
+            self.names.insert(object_id, name.to_string());
+        }
+    }
+
+    fn locate(&mut self, object_id: u32, physics: &PhysicsDesc) {
+        if let Some(position) = &physics.position {
+            self.positions.insert(object_id, position.clone());
+        }
+    }
+}
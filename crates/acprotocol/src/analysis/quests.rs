@@ -0,0 +1,494 @@
+//! Contract (quest) tracker history
+//!
+//! The server sends the whole contract table with
+//! `Social_SendClientContractTrackerTable` at login and single entries with
+//! `Social_SendClientContractTracker` as they change. Each contract keeps the
+//! history of its stage and timers so quest flag behavior can be compared
+//! between servers. Contract ids and stages are kept as the raw values sent,
+//! with names from the `ContractId` and `ContractStage` enums where they are
+//! known; stages above 3 are used for contract specific updates.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::enums::{ContractId, ContractStage};
+use crate::message::{GameActionMessage, GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+use crate::types::ContractTracker;
+
+use super::csv::{opt, write_row};
+use super::objects::ObjectIndex;
+use super::{game_action, game_event, parse_all};
+
+/// Readable name for a contract, e.g. "The Shadows of Bitter Winter"
+pub fn contract_name(contract: &ContractId) -> String {
+    let key = contract.to_string();
+    let name = key
+        .strip_prefix("Contract_")
+        .and_then(|rest| rest.split_once('_'))
+        .map_or(key.as_str(), |(_, name)| name);
+    name.split('_')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Where a contract change came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum ContractSource {
+    /// The full table sent at login
+    Table,
+    /// A single contract update
+    Update,
+    /// The client abandoning the contract
+    Abandoned,
+}
+
+/// A contract's state at one point in the session
+#[derive(Clone, Debug, Serialize)]
+pub struct ContractStep {
+    pub time: f64,
+    pub source: ContractSource,
+    pub version: u32,
+    pub stage: u32,
+    /// The stage's name, when it is one the protocol definition lists
+    pub known_stage: Option<ContractStage>,
+    /// Timer for the contract's completion, as sent by the server
+    pub time_when_done: i64,
+    /// Timer for when the contract can be repeated, as sent by the server
+    pub time_when_repeats: i64,
+    pub deleted: bool,
+    /// Whether the server made this the contract shown in the tracker
+    pub displayed: bool,
+}
+
+/// One contract in a character's quest log
+#[derive(Clone, Debug, Serialize)]
+pub struct Contract {
+    pub contract_id: u32,
+    /// `ContractId` key, when the id is one the protocol definition lists
+    pub key: Option<String>,
+    pub name: Option<String>,
+    pub steps: Vec<ContractStep>,
+}
+
+impl Contract {
+    /// The contract's most recent state
+    pub fn current(&self) -> Option<&ContractStep> {
+        self.steps.last()
+    }
+
+    /// Stages in the order the contract went through them, without repeats
+    pub fn stage_transitions(&self) -> Vec<u32> {
+        let mut stages: Vec<u32> = Vec::new();
+        for step in &self.steps {
+            if stages.last() != Some(&step.stage) {
+                stages.push(step.stage);
+            }
+        }
+        stages
+    }
+}
+
+/// Every contract one character was seen with
+#[derive(Clone, Debug, Serialize)]
+pub struct QuestLog {
+    pub character_id: u32,
+    pub character_name: Option<String>,
+    pub contracts: Vec<Contract>,
+}
+
+impl QuestLog {
+    fn contract(&mut self, contract_id: u32) -> &mut Contract {
+        let index = match self
+            .contracts
+            .iter()
+            .position(|c| c.contract_id == contract_id)
+        {
+            Some(index) => index,
+            None => {
+                let known = ContractId::try_from(contract_id).ok();
+                self.contracts.push(Contract {
+                    contract_id,
+                    key: known.as_ref().map(ToString::to_string),
+                    name: known.as_ref().map(contract_name),
+                    steps: Vec::new(),
+                });
+                self.contracts.len() - 1
+            }
+        };
+        &mut self.contracts[index]
+    }
+
+    fn update(
+        &mut self,
+        time: f64,
+        source: ContractSource,
+        tracker: &ContractTracker,
+        deleted: bool,
+        displayed: bool,
+    ) {
+        let contract = self.contract(tracker.contract_id);
+        let step = ContractStep {
+            time,
+            source,
+            version: tracker.version,
+            stage: tracker.contract_stage,
+            known_stage: ContractStage::try_from(tracker.contract_stage).ok(),
+            time_when_done: tracker.time_when_done,
+            time_when_repeats: tracker.time_when_repeats,
+            deleted,
+            displayed,
+        };
+
+        // The table is resent on every login; only keep it when something changed
+        let unchanged = contract.current().is_some_and(|last| {
+            last.stage == step.stage
+                && last.time_when_done == step.time_when_done
+                && last.time_when_repeats == step.time_when_repeats
+                && last.deleted == step.deleted
+                && last.displayed == step.displayed
+        });
+        if !unchanged {
+            contract.steps.push(step);
+        }
+    }
+}
+
+/// Builds quest logs from messages fed to it in capture order
+#[derive(Default)]
+pub struct QuestTracker {
+    logs: Vec<QuestLog>,
+    /// Character who most recently received a contract update, which client actions are attributed to
+    observer: Option<u32>,
+    objects: ObjectIndex,
+}
+
+impl QuestTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.objects.process(message);
+
+        if let Some(GameActionMessage::SocialAbandonContract(msg)) = game_action(message)
+            && let Some(observer) = self.observer
+        {
+            let contract_id = msg.contract_id;
+            if let Some(contract) = self
+                .log(observer)
+                .contracts
+                .iter_mut()
+                .find(|c| c.contract_id == contract_id)
+                && let Some(last) = contract.current()
+            {
+                let step = ContractStep {
+                    time,
+                    source: ContractSource::Abandoned,
+                    ..last.clone()
+                };
+                contract.steps.push(step);
+            }
+        }
+
+        let Some((object_id, event)) = game_event(message) else {
+            return;
+        };
+
+        match event {
+            GameEventMessage::SocialSendClientContractTrackerTable(msg) => {
+                self.observer = Some(object_id);
+                let log = self.log(object_id);
+                let mut trackers = msg
+                    .contract_tracker
+                    .contact_trackers
+                    .table
+                    .values()
+                    .collect::<Vec<_>>();
+                trackers.sort_by_key(|tracker| tracker.contract_id);
+                for tracker in trackers {
+                    log.update(time, ContractSource::Table, tracker, false, false);
+                }
+            }
+            GameEventMessage::SocialSendClientContractTracker(msg) => {
+                self.observer = Some(object_id);
+                self.log(object_id).update(
+                    time,
+                    ContractSource::Update,
+                    &msg.contract_tracker,
+                    msg.delete_contract,
+                    msg.set_as_display_contract,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    pub fn finish(self) -> QuestLogs {
+        let objects = self.objects;
+        let mut characters = self.logs;
+        for log in &mut characters {
+            log.character_name = objects.name(log.character_id).map(str::to_string);
+            log.contracts.sort_by_key(|contract| contract.contract_id);
+        }
+
+        QuestLogs { characters }
+    }
+
+    fn log(&mut self, character_id: u32) -> &mut QuestLog {
+        let index = match self
+            .logs
+            .iter()
+            .position(|log| log.character_id == character_id)
+        {
+            Some(index) => index,
+            None => {
+                self.logs.push(QuestLog {
+                    character_id,
+                    character_name: None,
+                    contracts: Vec::new(),
+                });
+                self.logs.len() - 1
+            }
+        };
+        &mut self.logs[index]
+    }
+}
+
+/// Quest logs for every character in a capture
+#[derive(Clone, Debug, Serialize)]
+pub struct QuestLogs {
+    pub characters: Vec<QuestLog>,
+}
+
+impl QuestLogs {
+    /// Build the logs from the contract messages in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut tracker = QuestTracker::new();
+        for (raw, message) in parse_all(messages) {
+            tracker.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        tracker.finish()
+    }
+
+    /// Write the logs as CSV, one row per contract change
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &[
+                "character_id",
+                "character_name",
+                "contract_id",
+                "contract_name",
+                "time",
+                "source",
+                "version",
+                "stage",
+                "time_when_done",
+                "time_when_repeats",
+                "deleted",
+                "displayed",
+            ],
+        )?;
+
+        for log in &self.characters {
+            for contract in &log.contracts {
+                for step in &contract.steps {
+                    write_row(
+                        &mut writer,
+                        &[
+                            format!("{:#010x}", log.character_id),
+                            opt(log.character_name.as_ref()),
+                            contract.contract_id.to_string(),
+                            opt(contract.name.as_ref()),
+                            step.time.to_string(),
+                            format!("{:?}", step.source),
+                            step.version.to_string(),
+                            step.known_stage
+                                .as_ref()
+                                .map_or(step.stage.to_string(), ToString::to_string),
+                            step.time_when_done.to_string(),
+                            step.time_when_repeats.to_string(),
+                            step.deleted.to_string(),
+                            step.displayed.to_string(),
+                        ],
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{PLAYER, event, table};
+    use crate::gameactions::SocialAbandonContract;
+    use crate::gameevents::{
+        SocialSendClientContractTracker, SocialSendClientContractTrackerTable,
+    };
+    use crate::message::C2SMessage;
+    use crate::types::{ContractTrackerTable, PackableHashTable};
+
+    fn tracker(contract_id: ContractId, stage: ContractStage, done: i64) -> ContractTracker {
+        raw_tracker(contract_id as u32, stage as u32, done)
+    }
+
+    fn raw_tracker(contract_id: u32, stage: u32, done: i64) -> ContractTracker {
+        ContractTracker {
+            version: 0,
+            contract_id,
+            contract_stage: stage,
+            time_when_done: done,
+            time_when_repeats: 0,
+        }
+    }
+
+    fn update(tracker: ContractTracker) -> MessageKind {
+        event(GameEventMessage::SocialSendClientContractTracker(
+            SocialSendClientContractTracker {
+                contract_tracker: tracker,
+                delete_contract: false,
+                set_as_display_contract: false,
+            },
+        ))
+    }
+
+    #[test]
+    fn test_contract_names() {
+        assert_eq!(
+            contract_name(&ContractId::Contract1TheShadowsOfBitterWinter),
+            "The Shadows of Bitter Winter"
+        );
+        assert_eq!(contract_name(&ContractId::Undef), "Undef");
+    }
+
+    #[test]
+    fn test_stage_transitions() {
+        let mut tracker_state = QuestTracker::new();
+        tracker_state.objects.insert(PLAYER, "Player");
+        let mut table = std::collections::HashMap::new();
+        table.insert(
+            1,
+            tracker(ContractId::Contract5ReignOfTerror, ContractStage::New, 0),
+        );
+        tracker_state.process(
+            1.0,
+            &event(GameEventMessage::SocialSendClientContractTrackerTable(
+                SocialSendClientContractTrackerTable {
+                    contract_tracker: ContractTrackerTable {
+                        contact_trackers: PackableHashTable {
+                            count: 1,
+                            max_size: 16,
+                            table,
+                        },
+                    },
+                },
+            )),
+        );
+        let progress = tracker(
+            ContractId::Contract5ReignOfTerror,
+            ContractStage::InProgress,
+            0,
+        );
+        tracker_state.process(2.0, &update(progress.clone()));
+        // Repeated updates with nothing new are not recorded
+        tracker_state.process(3.0, &update(progress));
+        tracker_state.process(
+            4.0,
+            &update(tracker(
+                ContractId::Contract5ReignOfTerror,
+                ContractStage::DoneOrPendingRepeat,
+                72000,
+            )),
+        );
+        let logs = tracker_state.finish();
+
+        let log = &logs.characters[0];
+        assert_eq!(log.character_name.as_deref(), Some("Player"));
+        let contract = &log.contracts[0];
+        assert_eq!(contract.name.as_deref(), Some("Reign of Terror"));
+        assert_eq!(contract.steps.len(), 3);
+        assert_eq!(
+            contract.stage_transitions(),
+            vec![
+                ContractStage::New as u32,
+                ContractStage::InProgress as u32,
+                ContractStage::DoneOrPendingRepeat as u32,
+            ]
+        );
+        assert_eq!(contract.current().unwrap().time_when_done, 72000);
+    }
+
+    #[test]
+    fn test_abandoned_contract() {
+        let mut tracker_state = QuestTracker::new();
+        tracker_state.process(
+            1.0,
+            &update(tracker(
+                ContractId::Contract9FrozenFury,
+                ContractStage::InProgress,
+                0,
+            )),
+        );
+        tracker_state.process(
+            2.0,
+            &MessageKind::C2S(Box::new(C2SMessage::OrderedGameAction {
+                sequence: 0,
+                action: GameActionMessage::SocialAbandonContract(SocialAbandonContract {
+                    contract_id: ContractId::Contract9FrozenFury as u32,
+                }),
+            })),
+        );
+        let logs = tracker_state.finish();
+
+        let steps = &logs.characters[0].contracts[0].steps;
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[1].source, ContractSource::Abandoned);
+        assert_eq!(steps[1].time, 2.0);
+    }
+
+    #[test]
+    fn test_unknown_contract_and_stage() {
+        let mut tracker_state = QuestTracker::new();
+        tracker_state.process(
+            1.0,
+            &event(GameEventMessage::SocialSendClientContractTrackerTable(
+                SocialSendClientContractTrackerTable {
+                    contract_tracker: ContractTrackerTable {
+                        contact_trackers: table(vec![
+                            (0x7fff, raw_tracker(0x7fff, 1, 0)),
+                            (
+                                ContractId::Contract9FrozenFury as u32,
+                                tracker(ContractId::Contract9FrozenFury, ContractStage::New, 0),
+                            ),
+                        ]),
+                    },
+                },
+            )),
+        );
+        tracker_state.process(2.0, &update(raw_tracker(0x7fff, 4, 0)));
+        let logs = tracker_state.finish();
+
+        let contracts = &logs.characters[0].contracts;
+        assert_eq!(contracts.len(), 2);
+        let unknown = &contracts[1];
+        assert_eq!(unknown.contract_id, 0x7fff);
+        assert_eq!(unknown.key, None);
+        assert_eq!(unknown.name, None);
+        assert_eq!(unknown.stage_transitions(), vec![1, 4]);
+        let current = unknown.current().unwrap();
+        assert_eq!(current.known_stage, None);
+
+        let mut csv = Vec::new();
+        logs.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.contains(",New,"));
+        assert!(csv.lines().last().unwrap().contains(",Update,0,4,"));
+    }
+}
@@ -0,0 +1,616 @@
+//! Every `ObjectId` a value refers to
+//!
+//! Messages refer to objects through fields of the `ObjectId` type, which can
+//! sit anywhere: top-level fields, optional fields, lists, hash table keys and
+//! values, and types nested several levels deep. Rather than matching on every
+//! message, [`object_refs`] walks a value through its `Serialize` impl and
+//! picks out each id along with the path of the field holding it.
+//!
+//! `ObjectId` serializes as a plain number, so the fields holding one are
+//! listed below by their serialized names. The lists cover every `ObjectId`
+//! field in the generated types and need extending when protocol.xml adds one.
+
+use std::fmt;
+
+use serde::Serialize;
+use serde::ser::{
+    self, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant,
+};
+
+/// Where in a field its object ids are
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Place {
+    /// The field is an `ObjectId`, or an optional one
+    Field,
+    /// The field is a `PackableList` of them
+    List,
+    /// The field is a hash table keyed by them
+    Keys,
+    /// The field is a hash table with them as values
+    Values,
+}
+
+/// Fields holding object ids wherever they appear
+const OBJECT_ID_FIELDS: &[(&str, Place)] = &[
+    ("AuthorId", Place::Field),
+    ("BookId", Place::Field),
+    ("CasterId", Place::Field),
+    ("CharacterHash", Place::Keys),
+    ("CharacterId", Place::Field),
+    ("ChildId", Place::Field),
+    ("ContainerId", Place::Field),
+    ("FriendId", Place::Field),
+    ("GuestList", Place::Keys),
+    ("HouseOwnerId", Place::Field),
+    ("InFriends", Place::List),
+    ("InitiatorId", Place::Field),
+    ("InstanceProperties", Place::Values),
+    ("Items", Place::List),
+    ("KilledId", Place::Field),
+    ("KillerId", Place::Field),
+    ("LeaderId", Place::Field),
+    ("Members", Place::Keys),
+    ("MonarchId", Place::Field),
+    ("NotSalvagable", Place::List),
+    ("ObjectId", Place::Field),
+    ("Officers", Place::Keys),
+    ("OutFriends", Place::List),
+    ("OwnerId", Place::Field),
+    ("ParentId", Place::Field),
+    ("PartnerId", Place::Field),
+    ("Permissions", Place::Keys),
+    ("PetOwnerId", Place::Field),
+    ("PlayerId", Place::Field),
+    ("RecentlyDeparted", Place::Keys),
+    ("RoommateList", Place::List),
+    ("ScribeId", Place::Field),
+    ("SenderId", Place::Field),
+    ("SpeakerId", Place::Field),
+    ("StickyObject", Place::Field),
+    ("Target", Place::Field),
+    ("TargetId", Place::Field),
+    ("ToolId", Place::Field),
+    ("TreeParent", Place::Field),
+    ("WielderId", Place::Field),
+];
+
+/// Fields whose name other types use for other data, by the type holding them
+const TYPE_OBJECT_ID_FIELDS: &[(&str, &str, Place)] = &[
+    ("Qualities_PrivateUpdateInstanceId", "Value", Place::Field),
+    ("Qualities_UpdateInstanceId", "Value", Place::Field),
+];
+
+/// Where the object ids in a field of a type are, if it holds any
+///
+/// Only unsigned numbers are taken as ids, which rules out the fields sharing
+/// a name with an id field but holding a string or a signed number, such as
+/// `AdvocateTeleport.ObjectId` and the Turbine chat `TargetId`.
+fn object_id_place(type_name: &str, field: &str) -> Option<Place> {
+    TYPE_OBJECT_ID_FIELDS
+        .iter()
+        .find(|(name, f, _)| *name == type_name && *f == field)
+        .map(|(_, _, place)| *place)
+        .or_else(|| {
+            OBJECT_ID_FIELDS
+                .iter()
+                .find(|(f, _)| *f == field)
+                .map(|(_, place)| *place)
+        })
+}
+
+/// An object id found in a value
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ObjectRef {
+    /// Path of the field holding the id, e.g. "Items.List[2]" or
+    /// "WeenieDescription.ContainerId"
+    pub path: String,
+    pub object_id: u32,
+}
+
+impl ObjectRef {
+    /// The name of the field holding the id, without list indexes or keys
+    pub fn field(&self) -> &str {
+        let last = self.path.rsplit('.').next().unwrap_or(&self.path);
+        last.split('[').next().unwrap_or(last)
+    }
+}
+
+/// Find every `ObjectId` in a value, in serialization order
+///
+/// Enum variant names are left out of paths, so the ids of a message come out
+/// with paths relative to the message's own fields.
+pub fn object_refs<T: Serialize + ?Sized>(value: &T) -> Vec<ObjectRef> {
+    let mut walker = Walker::default();
+    // The walker itself never fails; an error can only come from a
+    // Serialize impl, and then the ids found so far are still good
+    let _ = value.serialize(&mut walker);
+    walker.refs
+}
+
+/// Whether a value refers to an object anywhere
+pub fn refers_to<T: Serialize + ?Sized>(value: &T, object_id: u32) -> bool {
+    object_refs(value).iter().any(|r| r.object_id == object_id)
+}
+
+#[derive(Default)]
+struct Walker {
+    path: Vec<String>,
+    refs: Vec<ObjectRef>,
+    /// Names of the structs being serialized, innermost last
+    types: Vec<&'static str>,
+    /// Object id fields being serialized, by the path length inside them
+    fields: Vec<(usize, Place)>,
+    /// Set while serializing a map key
+    in_key: bool,
+    /// The last scalar serialized, used to name map entries by their key
+    scalar: Option<String>,
+    /// Element counters of the sequences being serialized, innermost last
+    indexes: Vec<usize>,
+    /// Key of the map entry whose value is next
+    key: Option<String>,
+}
+
+impl Walker {
+    fn path(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.path {
+            if !path.is_empty() && !segment.starts_with('[') {
+                path.push('.');
+            }
+            path.push_str(segment);
+        }
+        path
+    }
+
+    fn scalar(&mut self, value: impl fmt::Display) {
+        self.scalar = Some(value.to_string());
+    }
+
+    /// Whether the number being serialized is an object id
+    fn at_object_id(&self) -> bool {
+        let Some(&(depth, place)) = self.fields.last() else {
+            return false;
+        };
+        let rest = &self.path[depth..];
+        match place {
+            Place::Field => rest.is_empty(),
+            Place::List => rest.len() == 2 && rest[0] == "List",
+            Place::Keys => self.in_key && rest.len() == 1 && rest[0] == "Table",
+            Place::Values => !self.in_key && rest.len() == 2 && rest[0] == "Table",
+        }
+    }
+
+    fn integer(&mut self, value: u64) {
+        if self.at_object_id() {
+            let object_id = value as u32;
+            let mut path = self.path();
+            if self.in_key {
+                path.push_str(&format!("[{object_id}]"));
+            }
+            self.refs.push(ObjectRef { path, object_id });
+        }
+        self.scalar(value);
+    }
+
+    fn nested<T: Serialize + ?Sized>(&mut self, segment: String, value: &T) -> fmt::Result {
+        self.path.push(segment);
+        let result = value.serialize(&mut *self);
+        self.path.pop();
+        result
+    }
+
+    fn field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> fmt::Result {
+        let place = self
+            .types
+            .last()
+            .and_then(|type_name| object_id_place(type_name, key));
+        if let Some(place) = place {
+            self.fields.push((self.path.len() + 1, place));
+        }
+        let result = self.nested(key.to_string(), value);
+        if place.is_some() {
+            self.fields.pop();
+        }
+        result
+    }
+
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> fmt::Result {
+        let index = self.indexes.last_mut().map_or(0, |i| {
+            *i += 1;
+            *i - 1
+        });
+        self.nested(format!("[{index}]"), value)
+    }
+}
+
+impl ser::Serializer for &mut Walker {
+    type Ok = ();
+    type Error = fmt::Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> fmt::Result {
+        self.scalar(v);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> fmt::Result {
+        self.scalar(v);
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> fmt::Result {
+        self.scalar(v);
+        Ok(())
+    }
+
+    fn serialize_i32(self, v: i32) -> fmt::Result {
+        self.scalar(v);
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> fmt::Result {
+        self.scalar(v);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> fmt::Result {
+        self.integer(v.into());
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> fmt::Result {
+        self.integer(v.into());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> fmt::Result {
+        self.integer(v.into());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> fmt::Result {
+        self.integer(v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> fmt::Result {
+        self.scalar(v);
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> fmt::Result {
+        self.scalar(v);
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> fmt::Result {
+        self.scalar(v);
+        Ok(())
+    }
+
+    fn serialize_str(self, v: &str) -> fmt::Result {
+        self.scalar(v);
+        Ok(())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> fmt::Result {
+        self.scalar = None;
+        Ok(())
+    }
+
+    fn serialize_none(self) -> fmt::Result {
+        self.scalar = None;
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> fmt::Result {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> fmt::Result {
+        self.scalar = None;
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> fmt::Result {
+        self.scalar(name);
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> fmt::Result {
+        self.scalar(variant);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> fmt::Result {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> fmt::Result {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self, fmt::Error> {
+        self.indexes.push(0);
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self, fmt::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<Self, fmt::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        len: usize,
+    ) -> Result<Self, fmt::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self, fmt::Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<Self, fmt::Error> {
+        self.types.push(name);
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, fmt::Error> {
+        self.types.push(variant);
+        Ok(self)
+    }
+}
+
+impl SerializeSeq for &mut Walker {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> fmt::Result {
+        self.element(value)
+    }
+
+    fn end(self) -> fmt::Result {
+        self.indexes.pop();
+        Ok(())
+    }
+}
+
+impl SerializeTuple for &mut Walker {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> fmt::Result {
+        self.element(value)
+    }
+
+    fn end(self) -> fmt::Result {
+        self.indexes.pop();
+        Ok(())
+    }
+}
+
+impl SerializeTupleStruct for &mut Walker {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> fmt::Result {
+        self.element(value)
+    }
+
+    fn end(self) -> fmt::Result {
+        self.indexes.pop();
+        Ok(())
+    }
+}
+
+impl SerializeTupleVariant for &mut Walker {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> fmt::Result {
+        self.element(value)
+    }
+
+    fn end(self) -> fmt::Result {
+        self.indexes.pop();
+        Ok(())
+    }
+}
+
+impl SerializeMap for &mut Walker {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> fmt::Result {
+        self.scalar = None;
+        self.in_key = true;
+        let result = key.serialize(&mut **self);
+        self.in_key = false;
+        self.key = self.scalar.take();
+        result
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> fmt::Result {
+        let key = self.key.take().unwrap_or_else(|| "?".to_string());
+        self.nested(format!("[{key}]"), value)
+    }
+
+    fn end(self) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl SerializeStruct for &mut Walker {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> fmt::Result {
+        self.field(key, value)
+    }
+
+    fn end(self) -> fmt::Result {
+        self.types.pop();
+        Ok(())
+    }
+}
+
+impl SerializeStructVariant for &mut Walker {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> fmt::Result {
+        self.field(key, value)
+    }
+
+    fn end(self) -> fmt::Result {
+        self.types.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::list;
+    use crate::message::{GameEventMessage, S2CMessage};
+    use crate::types::ObjectId;
+
+    #[test]
+    fn test_top_level_and_nested_ids() {
+        let message = S2CMessage::ItemDeleteObject(crate::messages::s2c::ItemDeleteObject {
+            object_id: ObjectId(0x8000_1234),
+            object_instance_sequence: 3,
+        });
+        assert_eq!(
+            object_refs(&message),
+            vec![ObjectRef {
+                path: "ObjectId".to_string(),
+                object_id: 0x8000_1234
+            }]
+        );
+    }
+
+    #[test]
+    fn test_ids_in_lists() {
+        let event = GameEventMessage::ItemOnViewContents(crate::gameevents::ItemOnViewContents {
+            container_id: ObjectId(1),
+            items: list(vec![
+                crate::types::ContentProfile {
+                    object_id: ObjectId(2),
+                    container_type: crate::enums::ContainerProperties::None,
+                },
+                crate::types::ContentProfile {
+                    object_id: ObjectId(3),
+                    container_type: crate::enums::ContainerProperties::Container,
+                },
+            ]),
+        });
+        let refs = object_refs(&event);
+        let paths: Vec<_> = refs
+            .iter()
+            .map(|r| (r.path.as_str(), r.object_id))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("ContainerId", 1),
+                ("Items.List[0].ObjectId", 2),
+                ("Items.List[1].ObjectId", 3),
+            ]
+        );
+        assert_eq!(refs[1].field(), "ObjectId");
+        assert!(refers_to(&event, 3));
+        assert!(!refers_to(&event, 4));
+    }
+
+    #[test]
+    fn test_ids_in_hash_tables() {
+        use std::collections::HashMap;
+
+        use crate::types::{PHashTable, RestrictionDB};
+
+        let restrictions = RestrictionDB {
+            version: 1,
+            flags: 0,
+            monarch_id: ObjectId(4),
+            permissions: PHashTable {
+                packed_size: 8,
+                table: HashMap::from([(ObjectId(5), 6)]),
+            },
+        };
+        let refs = object_refs(&restrictions);
+        let paths: Vec<_> = refs
+            .iter()
+            .map(|r| (r.path.as_str(), r.object_id))
+            .collect();
+        // Permission levels and version numbers are not ids
+        assert_eq!(paths, vec![("MonarchId", 4), ("Permissions.Table[5]", 5)]);
+    }
+
+    #[test]
+    fn test_ids_by_field_and_type() {
+        use crate::messages::s2c::{QualitiesUpdateInstanceId, QualitiesUpdateInt};
+
+        let instance = S2CMessage::QualitiesUpdateInstanceId(QualitiesUpdateInstanceId {
+            sequence: 1,
+            object_id: ObjectId(7),
+            key: crate::enums::PropertyInstanceId::Container,
+            value: ObjectId(8),
+        });
+        let ids: Vec<_> = object_refs(&instance).iter().map(|r| r.object_id).collect();
+        assert_eq!(ids, vec![7, 8]);
+
+        // The same field name holding a plain number is not an id
+        let int = S2CMessage::QualitiesUpdateInt(QualitiesUpdateInt {
+            sequence: 1,
+            object_id: ObjectId(7),
+            key: crate::enums::PropertyInt::Value,
+            value: 9,
+        });
+        let ids: Vec<_> = object_refs(&int).iter().map(|r| r.object_id).collect();
+        assert_eq!(ids, vec![7]);
+    }
+}
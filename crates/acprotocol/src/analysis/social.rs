@@ -0,0 +1,801 @@
+//! Character titles, squelches and friends
+//!
+//! Keeps the social state the client's UI is built from: the title list from
+//! `Social_CharacterTitleTable` and `Social_AddOrSetCharacterTitle`, the
+//! squelch database from `Communication_SetSquelchDB` and the friends list
+//! from `Social_FriendsUpdate`. The client's title, squelch and friend actions
+//! are logged next to the server's answers so a request that was never
+//! confirmed stands out.
+//!
+//! The report has both the state at the end of the capture and every change
+//! that led to it.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::enums::{ChatFragmentType, FriendsUpdateType, LogTextType};
+use crate::message::{GameActionMessage, GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+use crate::types::{FriendData, SquelchDB};
+
+use super::csv::{opt, write_row};
+use super::objects::ObjectIndex;
+use super::{game_action, game_event, parse_all};
+
+/// A friends list entry
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Friend {
+    pub friend_id: u32,
+    pub name: String,
+    pub online: bool,
+    pub appear_offline: bool,
+}
+
+impl From<&FriendData> for Friend {
+    fn from(data: &FriendData) -> Self {
+        Self {
+            friend_id: data.friend_id.0,
+            name: data.name.clone(),
+            online: data.online,
+            appear_offline: data.appear_offline,
+        }
+    }
+}
+
+/// A squelched character, or every character on a squelched account
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SquelchedCharacter {
+    pub object_id: u32,
+    pub name: String,
+    pub account: bool,
+    /// Kinds of text squelched from them
+    pub filters: Vec<LogTextType>,
+}
+
+/// Social state of one character
+#[derive(Clone, Debug, Serialize)]
+pub struct SocialState {
+    pub character_id: u32,
+    pub character_name: Option<String>,
+    pub titles: Vec<u32>,
+    pub display_title: Option<u32>,
+    pub friends: Vec<Friend>,
+    /// Squelched account names, sorted
+    pub squelched_accounts: Vec<String>,
+    pub squelched_characters: Vec<SquelchedCharacter>,
+    /// Kinds of text squelched from everyone
+    pub global_filters: Vec<LogTextType>,
+    /// Whether a squelch database has been received yet
+    #[serde(skip)]
+    has_squelches: bool,
+}
+
+impl SocialState {
+    fn new(character_id: u32) -> Self {
+        Self {
+            character_id,
+            character_name: None,
+            titles: Vec::new(),
+            display_title: None,
+            friends: Vec::new(),
+            squelched_accounts: Vec::new(),
+            squelched_characters: Vec::new(),
+            global_filters: Vec::new(),
+            has_squelches: false,
+        }
+    }
+
+    fn friend_name(&self, friend_id: u32) -> Option<String> {
+        self.friends
+            .iter()
+            .find(|f| f.friend_id == friend_id)
+            .map(|f| f.name.clone())
+    }
+
+    /// Replace the squelch database, returning what changed
+    fn set_squelches(&mut self, db: &SquelchDB) -> Vec<SocialChange> {
+        let mut accounts = db.account_hash.table.keys().cloned().collect::<Vec<_>>();
+        accounts.sort();
+        let mut characters = db
+            .character_hash
+            .table
+            .iter()
+            .map(|(id, info)| SquelchedCharacter {
+                object_id: id.0,
+                name: info.name.clone(),
+                account: info.account,
+                filters: info.filters.list.clone(),
+            })
+            .collect::<Vec<_>>();
+        characters.sort_by_key(|c| c.object_id);
+        let global_filters = db.global_info.filters.list.clone();
+
+        let mut changes = Vec::new();
+        if !self.has_squelches {
+            changes.push(SocialChange::SquelchesReceived {
+                accounts: accounts.len(),
+                characters: characters.len(),
+            });
+        } else {
+            for name in accounts
+                .iter()
+                .filter(|a| !self.squelched_accounts.contains(a))
+            {
+                changes.push(SocialChange::SquelchAdded {
+                    object_id: None,
+                    name: name.clone(),
+                    account: true,
+                });
+            }
+            for name in self
+                .squelched_accounts
+                .iter()
+                .filter(|a| !accounts.contains(a))
+            {
+                changes.push(SocialChange::SquelchRemoved {
+                    object_id: None,
+                    name: name.clone(),
+                    account: true,
+                });
+            }
+            for character in characters
+                .iter()
+                .filter(|c| !self.squelched_characters.contains(c))
+            {
+                changes.push(SocialChange::SquelchAdded {
+                    object_id: Some(character.object_id),
+                    name: character.name.clone(),
+                    account: character.account,
+                });
+            }
+            for character in self
+                .squelched_characters
+                .iter()
+                .filter(|c| !characters.iter().any(|n| n.object_id == c.object_id))
+            {
+                changes.push(SocialChange::SquelchRemoved {
+                    object_id: Some(character.object_id),
+                    name: character.name.clone(),
+                    account: character.account,
+                });
+            }
+            if global_filters != self.global_filters {
+                changes.push(SocialChange::GlobalFiltersChanged {
+                    filters: global_filters.clone(),
+                });
+            }
+        }
+
+        self.has_squelches = true;
+        self.squelched_accounts = accounts;
+        self.squelched_characters = characters;
+        self.global_filters = global_filters;
+        changes
+    }
+
+    /// Apply a friends list update, returning what changed
+    fn update_friends(
+        &mut self,
+        friends: &[FriendData],
+        update: FriendsUpdateType,
+    ) -> Vec<SocialChange> {
+        // FULL is zero, so it can't be tested with contains
+        if update.is_empty() {
+            self.friends = friends.iter().map(Friend::from).collect();
+            return vec![SocialChange::FriendsReceived {
+                count: self.friends.len(),
+            }];
+        }
+
+        let mut changes = Vec::new();
+        for data in friends {
+            let friend = Friend::from(data);
+            let existing = self
+                .friends
+                .iter()
+                .position(|f| f.friend_id == friend.friend_id);
+            let (friend_id, name) = (friend.friend_id, friend.name.clone());
+
+            if update.contains(FriendsUpdateType::REMOVED) {
+                if let Some(index) = existing {
+                    self.friends.remove(index);
+                }
+                changes.push(SocialChange::FriendRemoved { friend_id, name });
+                continue;
+            }
+
+            if update.contains(FriendsUpdateType::ADDED) && existing.is_none() {
+                changes.push(SocialChange::FriendAdded {
+                    friend_id,
+                    name: name.clone(),
+                });
+            }
+            let was_online = existing.map(|index| self.friends[index].online);
+            if update.contains(FriendsUpdateType::LOGIN_CHANGE) && was_online != Some(friend.online)
+            {
+                changes.push(if friend.online {
+                    SocialChange::FriendOnline { friend_id, name }
+                } else {
+                    SocialChange::FriendOffline { friend_id, name }
+                });
+            }
+
+            match existing {
+                Some(index) => self.friends[index] = friend,
+                None => self.friends.push(friend),
+            }
+        }
+        changes
+    }
+}
+
+/// Something that changed in a character's social state
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum SocialChange {
+    /// The full title list arrived (`Social_CharacterTitleTable`)
+    TitlesReceived {
+        count: usize,
+        display_title: u32,
+    },
+    TitleAdded {
+        title_id: u32,
+    },
+    DisplayTitleSet {
+        title_id: u32,
+    },
+    /// The client asked to show a title (`Social_SetDisplayCharacterTitle`)
+    DisplayTitleRequested {
+        title_id: u32,
+    },
+    /// The first squelch database arrived
+    SquelchesReceived {
+        accounts: usize,
+        characters: usize,
+    },
+    SquelchAdded {
+        object_id: Option<u32>,
+        name: String,
+        account: bool,
+    },
+    SquelchRemoved {
+        object_id: Option<u32>,
+        name: String,
+        account: bool,
+    },
+    GlobalFiltersChanged {
+        filters: Vec<LogTextType>,
+    },
+    /// The client asked to (un)squelch an account
+    AccountSquelchRequested {
+        name: String,
+        add: bool,
+    },
+    /// The client asked to (un)squelch a character
+    CharacterSquelchRequested {
+        object_id: u32,
+        name: String,
+        add: bool,
+        kind: ChatFragmentType,
+    },
+    /// The client asked to (un)squelch a kind of text from everyone
+    GlobalSquelchRequested {
+        add: bool,
+        kind: ChatFragmentType,
+    },
+    /// The full friends list arrived
+    FriendsReceived {
+        count: usize,
+    },
+    FriendAdded {
+        friend_id: u32,
+        name: String,
+    },
+    FriendRemoved {
+        friend_id: u32,
+        name: String,
+    },
+    FriendOnline {
+        friend_id: u32,
+        name: String,
+    },
+    FriendOffline {
+        friend_id: u32,
+        name: String,
+    },
+    AddFriendRequested {
+        name: String,
+    },
+    RemoveFriendRequested {
+        friend_id: u32,
+        name: Option<String>,
+    },
+    ClearFriendsRequested,
+}
+
+impl SocialChange {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::TitlesReceived { .. } => "TitlesReceived",
+            Self::TitleAdded { .. } => "TitleAdded",
+            Self::DisplayTitleSet { .. } => "DisplayTitleSet",
+            Self::DisplayTitleRequested { .. } => "DisplayTitleRequested",
+            Self::SquelchesReceived { .. } => "SquelchesReceived",
+            Self::SquelchAdded { .. } => "SquelchAdded",
+            Self::SquelchRemoved { .. } => "SquelchRemoved",
+            Self::GlobalFiltersChanged { .. } => "GlobalFiltersChanged",
+            Self::AccountSquelchRequested { .. } => "AccountSquelchRequested",
+            Self::CharacterSquelchRequested { .. } => "CharacterSquelchRequested",
+            Self::GlobalSquelchRequested { .. } => "GlobalSquelchRequested",
+            Self::FriendsReceived { .. } => "FriendsReceived",
+            Self::FriendAdded { .. } => "FriendAdded",
+            Self::FriendRemoved { .. } => "FriendRemoved",
+            Self::FriendOnline { .. } => "FriendOnline",
+            Self::FriendOffline { .. } => "FriendOffline",
+            Self::AddFriendRequested { .. } => "AddFriendRequested",
+            Self::RemoveFriendRequested { .. } => "RemoveFriendRequested",
+            Self::ClearFriendsRequested => "ClearFriendsRequested",
+        }
+    }
+
+    /// The object and name the change is about, for the CSV columns
+    fn subject(&self) -> (Option<u32>, Option<String>, String) {
+        match self {
+            Self::TitlesReceived {
+                count,
+                display_title,
+            } => (Some(*display_title), None, format!("{count} titles")),
+            Self::TitleAdded { title_id }
+            | Self::DisplayTitleSet { title_id }
+            | Self::DisplayTitleRequested { title_id } => (Some(*title_id), None, String::new()),
+            Self::SquelchesReceived {
+                accounts,
+                characters,
+            } => (
+                None,
+                None,
+                format!("{accounts} accounts, {characters} characters"),
+            ),
+            Self::SquelchAdded {
+                object_id,
+                name,
+                account,
+            }
+            | Self::SquelchRemoved {
+                object_id,
+                name,
+                account,
+            } => (
+                *object_id,
+                Some(name.clone()),
+                if *account { "account" } else { "character" }.to_string(),
+            ),
+            Self::GlobalFiltersChanged { filters } => (
+                None,
+                None,
+                filters
+                    .iter()
+                    .map(|f| format!("{f:?}"))
+                    .collect::<Vec<_>>()
+                    .join("|"),
+            ),
+            Self::AccountSquelchRequested { name, add } => {
+                (None, Some(name.clone()), format!("add={add}"))
+            }
+            Self::CharacterSquelchRequested {
+                object_id,
+                name,
+                add,
+                kind,
+            } => (
+                Some(*object_id),
+                Some(name.clone()),
+                format!("add={add} {kind:?}"),
+            ),
+            Self::GlobalSquelchRequested { add, kind } => {
+                (None, None, format!("add={add} {kind:?}"))
+            }
+            Self::FriendsReceived { count } => (None, None, format!("{count} friends")),
+            Self::FriendAdded { friend_id, name }
+            | Self::FriendRemoved { friend_id, name }
+            | Self::FriendOnline { friend_id, name }
+            | Self::FriendOffline { friend_id, name } => {
+                (Some(*friend_id), Some(name.clone()), String::new())
+            }
+            Self::AddFriendRequested { name } => (None, Some(name.clone()), String::new()),
+            Self::RemoveFriendRequested { friend_id, name } => {
+                (Some(*friend_id), name.clone(), String::new())
+            }
+            Self::ClearFriendsRequested => (None, None, String::new()),
+        }
+    }
+}
+
+/// A change and when it happened
+#[derive(Clone, Debug, Serialize)]
+pub struct SocialEvent {
+    pub time: f64,
+    pub character_id: u32,
+    pub change: SocialChange,
+}
+
+/// Builds social state from messages fed to it in capture order
+#[derive(Default)]
+pub struct SocialTracker {
+    states: Vec<SocialState>,
+    changes: Vec<SocialEvent>,
+    /// Character who most recently received a social event, which client actions are attributed to
+    observer: Option<u32>,
+    objects: ObjectIndex,
+}
+
+impl SocialTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.objects.process(message);
+
+        if let Some(action) = game_action(message) {
+            self.process_action(time, action);
+        }
+
+        let Some((object_id, event)) = game_event(message) else {
+            return;
+        };
+
+        let mut changes = Vec::new();
+        match event {
+            GameEventMessage::SocialCharacterTitleTable(msg) => {
+                let state = self.state(object_id);
+                state.titles = msg.titles.list.clone();
+                state.display_title = Some(msg.display_title);
+                changes.push(SocialChange::TitlesReceived {
+                    count: state.titles.len(),
+                    display_title: msg.display_title,
+                });
+            }
+            GameEventMessage::SocialAddOrSetCharacterTitle(msg) => {
+                let state = self.state(object_id);
+                if !state.titles.contains(&msg.new_title) {
+                    state.titles.push(msg.new_title);
+                    changes.push(SocialChange::TitleAdded {
+                        title_id: msg.new_title,
+                    });
+                }
+                if msg.set_as_display_title {
+                    state.display_title = Some(msg.new_title);
+                    changes.push(SocialChange::DisplayTitleSet {
+                        title_id: msg.new_title,
+                    });
+                }
+            }
+            GameEventMessage::CommunicationSetSquelchDB(msg) => {
+                changes = self.state(object_id).set_squelches(&msg.squelch_db);
+            }
+            GameEventMessage::SocialFriendsUpdate(msg) => {
+                changes = self
+                    .state(object_id)
+                    .update_friends(&msg.friends.list, msg.type_);
+            }
+            _ => return,
+        }
+
+        self.observer = Some(object_id);
+        for change in changes {
+            self.changes.push(SocialEvent {
+                time,
+                character_id: object_id,
+                change,
+            });
+        }
+    }
+
+    pub fn finish(self) -> SocialReport {
+        let objects = self.objects;
+        let mut characters = self.states;
+        for state in &mut characters {
+            state.character_name = objects.name(state.character_id).map(str::to_string);
+        }
+
+        SocialReport {
+            characters,
+            changes: self.changes,
+        }
+    }
+
+    fn process_action(&mut self, time: f64, action: &GameActionMessage) {
+        let Some(observer) = self.observer else {
+            return;
+        };
+
+        let change = match action {
+            GameActionMessage::SocialSetDisplayCharacterTitle(msg) => {
+                SocialChange::DisplayTitleRequested {
+                    title_id: msg.title_id,
+                }
+            }
+            GameActionMessage::CommunicationModifyAccountSquelch(msg) => {
+                SocialChange::AccountSquelchRequested {
+                    name: msg.character_name.clone(),
+                    add: msg.add,
+                }
+            }
+            GameActionMessage::CommunicationModifyCharacterSquelch(msg) => {
+                SocialChange::CharacterSquelchRequested {
+                    object_id: msg.object_id.0,
+                    name: msg.character_name.clone(),
+                    add: msg.add,
+                    kind: msg.type_.clone(),
+                }
+            }
+            GameActionMessage::CommunicationModifyGlobalSquelch(msg) => {
+                SocialChange::GlobalSquelchRequested {
+                    add: msg.add,
+                    kind: msg.type_.clone(),
+                }
+            }
+            GameActionMessage::SocialAddFriend(msg) => SocialChange::AddFriendRequested {
+                name: msg.character_name.clone(),
+            },
+            GameActionMessage::SocialRemoveFriend(msg) => SocialChange::RemoveFriendRequested {
+                friend_id: msg.object_id.0,
+                name: self.state(observer).friend_name(msg.object_id.0),
+            },
+            GameActionMessage::SocialClearFriends(_) => SocialChange::ClearFriendsRequested,
+            _ => return,
+        };
+
+        self.changes.push(SocialEvent {
+            time,
+            character_id: observer,
+            change,
+        });
+    }
+
+    fn state(&mut self, character_id: u32) -> &mut SocialState {
+        let index = match self
+            .states
+            .iter()
+            .position(|state| state.character_id == character_id)
+        {
+            Some(index) => index,
+            None => {
+                self.states.push(SocialState::new(character_id));
+                self.states.len() - 1
+            }
+        };
+        &mut self.states[index]
+    }
+}
+
+/// Social state at the end of a capture and the changes that led to it
+#[derive(Clone, Debug, Serialize)]
+pub struct SocialReport {
+    pub characters: Vec<SocialState>,
+    /// Changes in capture order
+    pub changes: Vec<SocialEvent>,
+}
+
+impl SocialReport {
+    /// Build the report from the social messages in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut tracker = SocialTracker::new();
+        for (raw, message) in parse_all(messages) {
+            tracker.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        tracker.finish()
+    }
+
+    /// Write the change log as CSV, one row per change
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &["time", "character_id", "change", "id", "name", "detail"],
+        )?;
+
+        for event in &self.changes {
+            let (id, name, detail) = event.change.subject();
+            write_row(
+                &mut writer,
+                &[
+                    event.time.to_string(),
+                    format!("{:#010x}", event.character_id),
+                    event.change.kind().to_string(),
+                    opt(id),
+                    name.unwrap_or_default(),
+                    detail,
+                ],
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{PLAYER, action, event, list};
+    use crate::gameactions::{SocialRemoveFriend, SocialSetDisplayCharacterTitle};
+    use crate::gameevents::{
+        CommunicationSetSquelchDB, SocialAddOrSetCharacterTitle, SocialCharacterTitleTable,
+        SocialFriendsUpdate,
+    };
+
+    use crate::types::{ObjectId, PackableHashTable, SquelchInfo};
+
+    const FRIEND: u32 = 0x50000002;
+    const PEST: u32 = 0x50000003;
+
+    fn friends(online: bool, type_: FriendsUpdateType) -> MessageKind {
+        event(GameEventMessage::SocialFriendsUpdate(SocialFriendsUpdate {
+            friends: list(vec![FriendData {
+                friend_id: ObjectId(FRIEND),
+                online,
+                appear_offline: false,
+                name: "Buddy".to_string(),
+                out_friends: list(Vec::new()),
+                in_friends: list(Vec::new()),
+            }]),
+            type_,
+        }))
+    }
+
+    fn squelches(characters: &[(u32, &str)], accounts: &[&str]) -> MessageKind {
+        let info = |name: &str, account: bool| SquelchInfo {
+            filters: list(vec![LogTextType::Speech, LogTextType::Tell]),
+            name: name.to_string(),
+            account,
+        };
+        let character_hash = characters
+            .iter()
+            .map(|&(id, name)| (ObjectId(id), info(name, false)))
+            .collect::<std::collections::HashMap<_, _>>();
+        let account_hash = accounts
+            .iter()
+            .map(|name| (name.to_string(), 0))
+            .collect::<std::collections::HashMap<_, _>>();
+        event(GameEventMessage::CommunicationSetSquelchDB(
+            CommunicationSetSquelchDB {
+                squelch_db: SquelchDB {
+                    account_hash: PackableHashTable {
+                        count: account_hash.len() as u16,
+                        max_size: 0,
+                        table: account_hash,
+                    },
+                    character_hash: PackableHashTable {
+                        count: character_hash.len() as u16,
+                        max_size: 0,
+                        table: character_hash,
+                    },
+                    global_info: SquelchInfo {
+                        filters: list(Vec::new()),
+                        name: String::new(),
+                        account: false,
+                    },
+                },
+            },
+        ))
+    }
+
+    #[test]
+    fn test_titles() {
+        let mut tracker = SocialTracker::new();
+        tracker.process(
+            0.0,
+            &event(GameEventMessage::SocialCharacterTitleTable(
+                SocialCharacterTitleTable {
+                    display_title: 1,
+                    titles: list(vec![1, 5]),
+                },
+            )),
+        );
+        tracker.process(
+            1.0,
+            &action(GameActionMessage::SocialSetDisplayCharacterTitle(
+                SocialSetDisplayCharacterTitle { title_id: 5 },
+            )),
+        );
+        tracker.process(
+            2.0,
+            &event(GameEventMessage::SocialAddOrSetCharacterTitle(
+                SocialAddOrSetCharacterTitle {
+                    new_title: 9,
+                    set_as_display_title: true,
+                },
+            )),
+        );
+        let report = tracker.finish();
+
+        let state = &report.characters[0];
+        assert_eq!(state.titles, vec![1, 5, 9]);
+        assert_eq!(state.display_title, Some(9));
+        let kinds: Vec<_> = report.changes.iter().map(|e| e.change.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "TitlesReceived",
+                "DisplayTitleRequested",
+                "TitleAdded",
+                "DisplayTitleSet"
+            ]
+        );
+        assert_eq!(report.changes[1].character_id, PLAYER);
+    }
+
+    #[test]
+    fn test_friends_online_status() {
+        let mut tracker = SocialTracker::new();
+        tracker.process(0.0, &friends(false, FriendsUpdateType::FULL));
+        tracker.process(5.0, &friends(true, FriendsUpdateType::LOGIN_CHANGE));
+        // A repeated status is not a change
+        tracker.process(6.0, &friends(true, FriendsUpdateType::LOGIN_CHANGE));
+        tracker.process(
+            7.0,
+            &action(GameActionMessage::SocialRemoveFriend(SocialRemoveFriend {
+                object_id: ObjectId(FRIEND),
+            })),
+        );
+        tracker.process(8.0, &friends(true, FriendsUpdateType::REMOVED));
+        let report = tracker.finish();
+
+        let kinds: Vec<_> = report.changes.iter().map(|e| e.change.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "FriendsReceived",
+                "FriendOnline",
+                "RemoveFriendRequested",
+                "FriendRemoved"
+            ]
+        );
+        assert!(matches!(
+            &report.changes[2].change,
+            SocialChange::RemoveFriendRequested { name: Some(name), .. } if name == "Buddy"
+        ));
+        assert!(report.characters[0].friends.is_empty());
+    }
+
+    #[test]
+    fn test_squelch_changes() {
+        let mut tracker = SocialTracker::new();
+        tracker.process(0.0, &squelches(&[], &["Spammer"]));
+        tracker.process(1.0, &squelches(&[(PEST, "Pest")], &[]));
+        let report = tracker.finish();
+
+        let state = &report.characters[0];
+        assert!(state.squelched_accounts.is_empty());
+        assert_eq!(state.squelched_characters[0].name, "Pest");
+        assert_eq!(
+            state.squelched_characters[0].filters,
+            vec![LogTextType::Speech, LogTextType::Tell]
+        );
+
+        let kinds: Vec<_> = report.changes.iter().map(|e| e.change.kind()).collect();
+        assert_eq!(
+            kinds,
+            vec!["SquelchesReceived", "SquelchRemoved", "SquelchAdded"]
+        );
+        assert!(matches!(
+            &report.changes[1].change,
+            SocialChange::SquelchRemoved { name, account: true, .. } if name == "Spammer"
+        ));
+    }
+
+    #[test]
+    fn test_friend_updates_before_the_full_list() {
+        let mut tracker = SocialTracker::new();
+        // The capture started after the friends list was sent
+        tracker.process(0.0, &friends(true, FriendsUpdateType::LOGIN_CHANGE));
+        tracker.process(1.0, &friends(false, FriendsUpdateType::REMOVED));
+        let report = tracker.finish();
+
+        let kinds: Vec<_> = report.changes.iter().map(|e| e.change.kind()).collect();
+        assert_eq!(kinds, vec!["FriendOnline", "FriendRemoved"]);
+        assert!(report.characters[0].friends.is_empty());
+    }
+}
@@ -0,0 +1,426 @@
+//! Capture statistics and parse coverage
+//!
+//! Counts messages and bytes by message type, queue and direction, buckets
+//! them over time, and records how well each message type parses. A type
+//! that often fails or leaves trailing bytes points at a definition in
+//! protocol.xml that doesn't match what the server sends.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::network::RawMessage;
+
+/// Width of the time buckets in the message rate timeline, in seconds
+pub const DEFAULT_BUCKET: f64 = 10.0;
+
+/// Number of largest messages to list
+pub const DEFAULT_TOP: usize = 10;
+
+/// Number of distinct errors kept per message type
+const MAX_ERRORS: usize = 5;
+
+/// How well a message parsed
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum ParseOutcome {
+    /// Every byte was consumed
+    Ok,
+    /// Parsing failed with an error
+    Failed(String),
+    /// Parsing succeeded but left this many bytes unread
+    Trailing(usize),
+    /// The opcode (or the game event or action type inside it) isn't known
+    Unknown,
+}
+
+impl ParseOutcome {
+    pub fn of(message: &RawMessage) -> Self {
+        if matches!(
+            message.message_type.as_str(),
+            "Unknown" | "OrderedGameEvent" | "OrderedGameAction"
+        ) {
+            return Self::Unknown;
+        }
+
+        match message.parse_with_remainder() {
+            Ok((_, 0)) => Self::Ok,
+            Ok((_, remainder)) => Self::Trailing(remainder),
+            Err(e) => Self::Failed(e.to_string()),
+        }
+    }
+}
+
+/// Parse result counts
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ParseTotals {
+    pub ok: usize,
+    pub failed: usize,
+    pub trailing: usize,
+    pub unknown: usize,
+}
+
+impl ParseTotals {
+    pub(super) fn add(&mut self, outcome: &ParseOutcome) {
+        match outcome {
+            ParseOutcome::Ok => self.ok += 1,
+            ParseOutcome::Failed(_) => self.failed += 1,
+            ParseOutcome::Trailing(_) => self.trailing += 1,
+            ParseOutcome::Unknown => self.unknown += 1,
+        }
+    }
+
+    pub(super) fn merge(&mut self, other: &ParseTotals) {
+        self.ok += other.ok;
+        self.failed += other.failed;
+        self.trailing += other.trailing;
+        self.unknown += other.unknown;
+    }
+
+    pub fn total(&self) -> usize {
+        self.ok + self.failed + self.trailing + self.unknown
+    }
+
+    /// Share of messages that parsed cleanly, from 0 to 1
+    pub fn ok_rate(&self) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            0.0
+        } else {
+            self.ok as f64 / total as f64
+        }
+    }
+}
+
+/// An error message and how often it occurred
+#[derive(Clone, Debug, Serialize)]
+pub struct ErrorCount {
+    pub error: String,
+    pub count: usize,
+}
+
+/// Counts for one message type
+#[derive(Clone, Debug, Serialize)]
+pub struct TypeStats {
+    pub message_type: String,
+    /// Outer message opcode
+    pub opcode: u32,
+    /// Game event or action type, for ordered messages
+    pub inner_opcode: Option<u32>,
+    pub direction: String,
+    pub count: usize,
+    pub bytes: usize,
+    pub largest: usize,
+    pub parse: ParseTotals,
+    /// Total unread bytes across messages that parsed with trailing data
+    pub trailing_bytes: usize,
+    /// The most common parse errors
+    pub errors: Vec<ErrorCount>,
+}
+
+/// Counts for a queue or direction
+#[derive(Clone, Debug, Serialize)]
+pub struct GroupStats {
+    pub name: String,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+/// Messages seen during one time bucket
+#[derive(Clone, Debug, Serialize)]
+pub struct Bucket {
+    /// Seconds since capture start
+    pub start: f64,
+    pub count: usize,
+    pub bytes: usize,
+    pub per_second: f64,
+}
+
+/// One of the largest messages in the capture
+#[derive(Clone, Debug, Serialize)]
+pub struct LargeMessage {
+    pub id: u32,
+    pub message_type: String,
+    pub direction: String,
+    pub size: usize,
+}
+
+/// Statistics for a whole capture
+#[derive(Clone, Debug, Serialize)]
+pub struct CaptureStats {
+    pub messages: usize,
+    pub bytes: usize,
+    /// Seconds between the first and last timestamped message
+    pub duration: Option<f64>,
+    pub parse: ParseTotals,
+    pub directions: Vec<GroupStats>,
+    pub queues: Vec<GroupStats>,
+    /// Message types, most frequent first
+    pub types: Vec<TypeStats>,
+    pub bucket_seconds: f64,
+    pub timeline: Vec<Bucket>,
+    pub largest: Vec<LargeMessage>,
+}
+
+/// The game event or action type carried by an ordered message
+pub(super) fn inner_opcode(message: &RawMessage) -> Option<u32> {
+    let offset = match message.opcode {
+        0xF7B0 => 12,
+        0xF7B1 => 8,
+        _ => return None,
+    };
+    let bytes = message.data.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn add_group(groups: &mut HashMap<String, GroupStats>, name: String, bytes: usize) {
+    let group = groups.entry(name.clone()).or_insert(GroupStats {
+        name,
+        count: 0,
+        bytes: 0,
+    });
+    group.count += 1;
+    group.bytes += bytes;
+}
+
+fn sorted_groups(groups: HashMap<String, GroupStats>) -> Vec<GroupStats> {
+    let mut groups: Vec<_> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    groups
+}
+
+impl CaptureStats {
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        Self::with_options(messages, DEFAULT_BUCKET, DEFAULT_TOP)
+    }
+
+    /// Gather statistics with a given timeline bucket width and number of largest messages
+    pub fn with_options(messages: &[RawMessage], bucket_seconds: f64, top: usize) -> Self {
+        let bucket_seconds = if bucket_seconds > 0.0 {
+            bucket_seconds
+        } else {
+            DEFAULT_BUCKET
+        };
+
+        let mut parse = ParseTotals::default();
+        let mut types: HashMap<(u32, String), TypeStats> = HashMap::new();
+        let mut errors: HashMap<(u32, String), HashMap<String, usize>> = HashMap::new();
+        let mut directions = HashMap::new();
+        let mut queues = HashMap::new();
+
+        for message in messages {
+            let size = message.data.len();
+            let outcome = ParseOutcome::of(message);
+            parse.add(&outcome);
+
+            let key = (message.opcode, message.message_type.clone());
+            let stats = types.entry(key.clone()).or_insert_with(|| TypeStats {
+                message_type: message.message_type.clone(),
+                opcode: message.opcode,
+                inner_opcode: inner_opcode(message),
+                direction: message.direction.clone(),
+                count: 0,
+                bytes: 0,
+                largest: 0,
+                parse: ParseTotals::default(),
+                trailing_bytes: 0,
+                errors: Vec::new(),
+            });
+            stats.count += 1;
+            stats.bytes += size;
+            stats.largest = stats.largest.max(size);
+            stats.parse.add(&outcome);
+            match outcome {
+                ParseOutcome::Trailing(remainder) => stats.trailing_bytes += remainder,
+                ParseOutcome::Failed(error) => {
+                    *errors.entry(key).or_default().entry(error).or_insert(0) += 1;
+                }
+                ParseOutcome::Ok | ParseOutcome::Unknown => {}
+            }
+
+            add_group(&mut directions, message.direction.clone(), size);
+            let queue = match &message.queue {
+                Some(queue) => format!("{queue:?}"),
+                None => "None".to_string(),
+            };
+            add_group(&mut queues, queue, size);
+        }
+
+        for (key, counts) in errors {
+            let mut counts: Vec<_> = counts
+                .into_iter()
+                .map(|(error, count)| ErrorCount { error, count })
+                .collect();
+            counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.error.cmp(&b.error)));
+            counts.truncate(MAX_ERRORS);
+            if let Some(stats) = types.get_mut(&key) {
+                stats.errors = counts;
+            }
+        }
+
+        let mut types: Vec<_> = types.into_values().collect();
+        types.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.message_type.cmp(&b.message_type))
+        });
+
+        let times: Vec<f64> = messages.iter().filter_map(|m| m.timestamp).collect();
+        let start = times.iter().copied().reduce(f64::min);
+        let end = times.iter().copied().reduce(f64::max);
+        let duration = start.zip(end).map(|(start, end)| end - start);
+
+        let mut timeline = Vec::new();
+        if let (Some(start), Some(duration)) = (start, duration) {
+            let buckets = (duration / bucket_seconds).floor() as usize + 1;
+            timeline = (0..buckets)
+                .map(|i| Bucket {
+                    start: i as f64 * bucket_seconds,
+                    count: 0,
+                    bytes: 0,
+                    per_second: 0.0,
+                })
+                .collect();
+            for message in messages {
+                if let Some(time) = message.timestamp {
+                    let index = (((time - start) / bucket_seconds) as usize).min(buckets - 1);
+                    timeline[index].count += 1;
+                    timeline[index].bytes += message.data.len();
+                }
+            }
+            for bucket in &mut timeline {
+                bucket.per_second = bucket.count as f64 / bucket_seconds;
+            }
+        }
+
+        let mut largest: Vec<_> = messages
+            .iter()
+            .map(|m| LargeMessage {
+                id: m.id,
+                message_type: m.message_type.clone(),
+                direction: m.direction.clone(),
+                size: m.data.len(),
+            })
+            .collect();
+        largest.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.id.cmp(&b.id)));
+        largest.truncate(top);
+
+        Self {
+            messages: messages.len(),
+            bytes: messages.iter().map(|m| m.data.len()).sum(),
+            duration,
+            parse,
+            directions: sorted_groups(directions),
+            queues: sorted_groups(queues),
+            types,
+            bucket_seconds,
+            timeline,
+            largest,
+        }
+    }
+
+    /// Message types that didn't always parse cleanly
+    pub fn problems(&self) -> impl Iterator<Item = &TypeStats> {
+        self.types
+            .iter()
+            .filter(|t| t.parse.failed + t.parse.trailing + t.parse.unknown > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn delete_object(id: u32, extra: &[u8], time: f64) -> RawMessage {
+        let mut data = 0xF747u32.to_le_bytes().to_vec();
+        data.extend(0x80000001u32.to_le_bytes());
+        data.extend(7u16.to_le_bytes());
+        // Padding to the next 4-byte boundary
+        data.extend([0, 0]);
+        data.extend(extra);
+        let mut message = RawMessage::from_fragment(data, id, id).unwrap();
+        message.timestamp = Some(time);
+        message
+    }
+
+    fn truncated(id: u32, time: f64) -> RawMessage {
+        let mut data = 0xF747u32.to_le_bytes().to_vec();
+        data.extend([1, 2]);
+        let mut message = RawMessage::from_fragment(data, id, id).unwrap();
+        message.timestamp = Some(time);
+        message
+    }
+
+    fn unknown(id: u32, time: f64) -> RawMessage {
+        let mut message = RawMessage::from_fragment(vec![0xEF, 0xBE, 0xAD, 0xDE], id, id).unwrap();
+        message.timestamp = Some(time);
+        message
+    }
+
+    #[test]
+    fn test_parse_outcomes() {
+        assert_eq!(
+            ParseOutcome::of(&delete_object(1, &[], 0.0)),
+            ParseOutcome::Ok
+        );
+        assert_eq!(
+            ParseOutcome::of(&delete_object(2, &[0, 0, 0], 0.0)),
+            ParseOutcome::Trailing(3)
+        );
+        assert!(matches!(
+            ParseOutcome::of(&truncated(3, 0.0)),
+            ParseOutcome::Failed(_)
+        ));
+        assert_eq!(ParseOutcome::of(&unknown(4, 0.0)), ParseOutcome::Unknown);
+    }
+
+    #[test]
+    fn test_counts_by_type_and_direction() {
+        let messages = vec![
+            delete_object(1, &[], 100.0),
+            delete_object(2, &[0xFF], 101.0),
+            truncated(3, 102.0),
+            unknown(4, 103.0),
+        ];
+        let stats = CaptureStats::from_messages(&messages);
+
+        assert_eq!(stats.messages, 4);
+        assert_eq!(stats.bytes, 12 + 13 + 6 + 4);
+        assert_eq!(stats.duration, Some(3.0));
+        assert_eq!(
+            (
+                stats.parse.ok,
+                stats.parse.trailing,
+                stats.parse.failed,
+                stats.parse.unknown
+            ),
+            (1, 1, 1, 1)
+        );
+
+        let delete = &stats.types[0];
+        assert_eq!(delete.message_type, "ItemDeleteObject");
+        assert_eq!((delete.count, delete.largest), (3, 13));
+        assert_eq!(delete.trailing_bytes, 1);
+        assert_eq!(delete.errors.len(), 1);
+        assert_eq!(stats.problems().count(), 2);
+
+        assert_eq!(stats.directions[0].name, "Recv");
+        assert_eq!(stats.directions[0].count, 3);
+        assert_eq!(stats.largest[0].id, 2);
+    }
+
+    #[test]
+    fn test_timeline_buckets() {
+        let messages: Vec<_> = [0.0, 1.0, 4.5, 9.0, 25.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &t)| delete_object(i as u32, &[], 1000.0 + t))
+            .collect();
+        let stats = CaptureStats::with_options(&messages, 10.0, 2);
+
+        let counts: Vec<_> = stats.timeline.iter().map(|b| b.count).collect();
+        assert_eq!(counts, vec![4, 0, 1]);
+        assert_eq!(stats.timeline[0].per_second, 0.4);
+        assert_eq!(stats.timeline[2].start, 20.0);
+        assert_eq!(stats.largest.len(), 2);
+    }
+}
@@ -0,0 +1,710 @@
+//! Secure trade sessions
+//!
+//! Groups the `Trade_*` events and the client's trade actions into sessions.
+//! A session starts when the trade window opens, or when items are added to a
+//! window whose previous trade completed, and ends when both sides have
+//! accepted or the window is closed. A request to trade sent by the client
+//! before the window opened starts the session it led to.
+//!
+//! The server only sends the ids of traded items, so names are filled in from
+//! the `Item_CreateObject` descriptions seen anywhere in the capture.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::enums::{EndTradeReason, TradeSide};
+use crate::message::{GameActionMessage, GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+
+use super::csv::{opt, write_row};
+use super::objects::ObjectIndex;
+use super::{game_action, game_event, parse_all};
+
+/// Which players have accepted the current contents of the trade
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum AcceptanceState {
+    #[default]
+    Open,
+    SelfAccepted,
+    PartnerAccepted,
+    BothAccepted,
+}
+
+impl AcceptanceState {
+    fn accept(self, side: TradeSide) -> Self {
+        match (self, side) {
+            (Self::Open | Self::SelfAccepted, TradeSide::Self_) => Self::SelfAccepted,
+            (Self::Open | Self::PartnerAccepted, TradeSide::Partner) => Self::PartnerAccepted,
+            _ => Self::BothAccepted,
+        }
+    }
+}
+
+/// Something that happened during a trade
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum TradeEvent {
+    /// The trade window opened (`Trade_RegisterTrade` or `Trade_OpenTrade`)
+    Opened {
+        partner_id: Option<u32>,
+    },
+    /// The client asked to trade with someone (`Trade_OpenTradeNegotiations`)
+    OpenRequested {
+        target_id: u32,
+    },
+    ItemAdded {
+        item_id: u32,
+        side: TradeSide,
+    },
+    ItemRemoved {
+        item_id: u32,
+        side: TradeSide,
+    },
+    /// The client asked to accept (`Trade_AcceptTrade` action)
+    AcceptRequested,
+    Accepted {
+        by: u32,
+    },
+    /// Acceptance was cleared, usually because the contents changed
+    AcceptanceCleared,
+    /// The client asked to decline (`Trade_DeclineTrade` action)
+    DeclineRequested,
+    Declined {
+        by: u32,
+    },
+    /// The client asked to clear the window (`Trade_ResetTrade` action)
+    ResetRequested,
+    Reset {
+        by: u32,
+    },
+    /// An item could not be added (`Trade_TradeFailure`)
+    ItemFailed {
+        item_id: u32,
+        reason: u32,
+    },
+    /// The client closed the window (`Trade_CloseTradeNegotiations`)
+    CloseRequested,
+    Closed {
+        reason: EndTradeReason,
+    },
+}
+
+/// A step in a trade, with the acceptance state after it
+#[derive(Clone, Debug, Serialize)]
+pub struct TradeStep {
+    pub time: f64,
+    pub event: TradeEvent,
+    pub state: AcceptanceState,
+}
+
+/// How a trade session ended
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum TradeOutcome {
+    /// Both sides accepted
+    Completed,
+    /// The window closed after a decline
+    Declined { by: u32 },
+    /// The window closed for a reason other than a normal close
+    Failed { reason: EndTradeReason },
+    /// The window closed without anyone accepting or declining
+    Closed,
+}
+
+impl std::fmt::Display for TradeOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Completed => write!(f, "Completed"),
+            Self::Declined { by } => write!(f, "Declined by {by:#010x}"),
+            Self::Failed { reason } => write!(f, "Failed ({reason})"),
+            Self::Closed => write!(f, "Closed"),
+        }
+    }
+}
+
+/// An item placed in the trade window
+#[derive(Clone, Debug, Serialize)]
+pub struct TradeItem {
+    pub object_id: u32,
+    pub name: Option<String>,
+    pub added: f64,
+}
+
+/// One trade, from opening the window until it completed or was closed
+#[derive(Clone, Debug, Serialize)]
+pub struct TradeSession {
+    /// Player the trade events were sent to
+    pub observer_id: u32,
+    pub partner_id: Option<u32>,
+    pub partner_name: Option<String>,
+    pub start: f64,
+    pub end: Option<f64>,
+    /// How the trade ended, or `None` if it was still open when the capture ended
+    pub outcome: Option<TradeOutcome>,
+    /// Items offered by the observer, as of the end of the trade
+    pub self_items: Vec<TradeItem>,
+    /// Items offered by the partner, as of the end of the trade
+    pub partner_items: Vec<TradeItem>,
+    pub steps: Vec<TradeStep>,
+}
+
+impl TradeSession {
+    fn new(observer_id: u32, partner_id: Option<u32>, time: f64) -> Self {
+        Self {
+            observer_id,
+            partner_id,
+            partner_name: None,
+            start: time,
+            end: None,
+            outcome: None,
+            self_items: Vec::new(),
+            partner_items: Vec::new(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Acceptance state after the most recent step
+    pub fn state(&self) -> AcceptanceState {
+        self.steps.last().map(|s| s.state).unwrap_or_default()
+    }
+
+    fn items_mut(&mut self, side: &TradeSide) -> &mut Vec<TradeItem> {
+        match side {
+            TradeSide::Self_ => &mut self.self_items,
+            TradeSide::Partner => &mut self.partner_items,
+        }
+    }
+
+    fn step(&mut self, time: f64, event: TradeEvent, state: AcceptanceState) {
+        self.steps.push(TradeStep { time, event, state });
+    }
+
+    fn finish(&mut self, time: f64, outcome: TradeOutcome) {
+        self.end = Some(time);
+        self.outcome = Some(outcome);
+    }
+
+    /// Who declined the trade, unless someone accepted again afterwards
+    fn declined_by(&self) -> Option<u32> {
+        for step in self.steps.iter().rev() {
+            match step.event {
+                TradeEvent::Declined { by } => return Some(by),
+                TradeEvent::Accepted { .. } => return None,
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Builds trade sessions from messages fed to it in capture order
+#[derive(Default)]
+pub struct TradeTracker {
+    sessions: Vec<TradeSession>,
+    /// Open trade per player, by index into `sessions`
+    active: HashMap<u32, usize>,
+    /// Partner of each player whose trade window is open
+    windows: HashMap<u32, Option<u32>>,
+    /// Player who most recently received a trade event, which client actions are attributed to
+    observer: Option<u32>,
+    /// The client's last request to trade, kept until a window opens with its target
+    requested: Option<(f64, u32)>,
+    objects: ObjectIndex,
+}
+
+impl TradeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.objects.process(message);
+
+        if let Some(action) = game_action(message) {
+            self.process_action(time, action);
+        }
+
+        let Some((object_id, event)) = game_event(message) else {
+            return;
+        };
+
+        match event {
+            GameEventMessage::TradeRegisterTrade(msg) => {
+                let partner = if msg.initiator_id.0 == object_id {
+                    msg.partner_id.0
+                } else {
+                    msg.initiator_id.0
+                };
+                self.open(object_id, time, Some(partner));
+            }
+            GameEventMessage::TradeOpenTrade(msg) => {
+                let partner = (msg.object_id.0 != object_id).then_some(msg.object_id.0);
+                self.open(object_id, time, partner);
+            }
+            GameEventMessage::TradeAddToTrade(msg) => {
+                let session = self.session(object_id, time);
+                session.items_mut(&msg.side).push(TradeItem {
+                    object_id: msg.object_id.0,
+                    name: None,
+                    added: time,
+                });
+                let state = session.state();
+                session.step(
+                    time,
+                    TradeEvent::ItemAdded {
+                        item_id: msg.object_id.0,
+                        side: msg.side.clone(),
+                    },
+                    state,
+                );
+            }
+            GameEventMessage::TradeRemoveFromTrade(msg) => {
+                let session = self.session(object_id, time);
+                session
+                    .items_mut(&msg.side)
+                    .retain(|item| item.object_id != msg.object_id.0);
+                let state = session.state();
+                session.step(
+                    time,
+                    TradeEvent::ItemRemoved {
+                        item_id: msg.object_id.0,
+                        side: msg.side.clone(),
+                    },
+                    state,
+                );
+            }
+            GameEventMessage::TradeAcceptTrade(msg) => {
+                let side = if msg.object_id.0 == object_id {
+                    TradeSide::Self_
+                } else {
+                    TradeSide::Partner
+                };
+                let session = self.session(object_id, time);
+                let state = session.state().accept(side);
+                session.step(
+                    time,
+                    TradeEvent::Accepted {
+                        by: msg.object_id.0,
+                    },
+                    state,
+                );
+                if state == AcceptanceState::BothAccepted {
+                    session.finish(time, TradeOutcome::Completed);
+                    self.active.remove(&object_id);
+                }
+            }
+            GameEventMessage::TradeClearTradeAcceptance(_) => {
+                let session = self.session(object_id, time);
+                session.step(time, TradeEvent::AcceptanceCleared, AcceptanceState::Open);
+            }
+            GameEventMessage::TradeDeclineTrade(msg) => {
+                let session = self.session(object_id, time);
+                session.step(
+                    time,
+                    TradeEvent::Declined {
+                        by: msg.object_id.0,
+                    },
+                    AcceptanceState::Open,
+                );
+            }
+            GameEventMessage::TradeResetTrade(msg) => {
+                // A reset after a completed trade just clears the window for the next one
+                if let Some(&index) = self.active.get(&object_id) {
+                    let session = &mut self.sessions[index];
+                    session.self_items.clear();
+                    session.partner_items.clear();
+                    session.step(
+                        time,
+                        TradeEvent::Reset {
+                            by: msg.object_id.0,
+                        },
+                        AcceptanceState::Open,
+                    );
+                }
+            }
+            GameEventMessage::TradeTradeFailure(msg) => {
+                let session = self.session(object_id, time);
+                let state = session.state();
+                session.step(
+                    time,
+                    TradeEvent::ItemFailed {
+                        item_id: msg.object_id.0,
+                        reason: msg.reason,
+                    },
+                    state,
+                );
+            }
+            GameEventMessage::TradeCloseTrade(msg) => {
+                self.windows.remove(&object_id);
+                if let Some(index) = self.active.remove(&object_id) {
+                    let session = &mut self.sessions[index];
+                    let state = session.state();
+                    session.step(
+                        time,
+                        TradeEvent::Closed {
+                            reason: msg.reason.clone(),
+                        },
+                        state,
+                    );
+
+                    let outcome = match (&msg.reason, session.declined_by()) {
+                        (EndTradeReason::Normal, Some(by)) => TradeOutcome::Declined { by },
+                        (EndTradeReason::Normal, None) => TradeOutcome::Closed,
+                        (reason, _) => TradeOutcome::Failed {
+                            reason: reason.clone(),
+                        },
+                    };
+                    session.finish(time, outcome);
+                }
+            }
+            _ => return,
+        }
+        self.observer = Some(object_id);
+    }
+
+    pub fn finish(self) -> TradeLog {
+        let objects = self.objects;
+        let mut sessions = self.sessions;
+        for session in &mut sessions {
+            session.partner_name = session
+                .partner_id
+                .and_then(|id| objects.name(id))
+                .map(str::to_string);
+            for item in session
+                .self_items
+                .iter_mut()
+                .chain(session.partner_items.iter_mut())
+            {
+                item.name = objects.name(item.object_id).map(str::to_string);
+            }
+        }
+
+        TradeLog { sessions }
+    }
+
+    fn process_action(&mut self, time: f64, action: &GameActionMessage) {
+        let event = match action {
+            GameActionMessage::TradeOpenTradeNegotiations(msg) => TradeEvent::OpenRequested {
+                target_id: msg.object_id.0,
+            },
+            GameActionMessage::TradeAcceptTrade(_) => TradeEvent::AcceptRequested,
+            GameActionMessage::TradeDeclineTrade(_) => TradeEvent::DeclineRequested,
+            GameActionMessage::TradeResetTrade(_) => TradeEvent::ResetRequested,
+            GameActionMessage::TradeCloseTradeNegotiations(_) => TradeEvent::CloseRequested,
+            _ => return,
+        };
+
+        if let Some(observer) = self.observer
+            && let Some(&index) = self.active.get(&observer)
+        {
+            let session = &mut self.sessions[index];
+            let state = session.state();
+            session.step(time, event, state);
+        } else if let TradeEvent::OpenRequested { target_id } = event {
+            // No window is open yet, so wait for the one this request opens
+            self.requested = Some((time, target_id));
+        }
+    }
+
+    fn open(&mut self, object_id: u32, time: f64, partner_id: Option<u32>) {
+        self.windows.insert(object_id, partner_id);
+
+        // The server sends both RegisterTrade and OpenTrade for the same window
+        if let Some(&index) = self.active.get(&object_id) {
+            let session = &mut self.sessions[index];
+            if session.partner_id.is_none() {
+                session.partner_id = partner_id;
+            }
+            return;
+        }
+
+        let index = self.sessions.len();
+        let request = self
+            .requested
+            .take()
+            .filter(|&(_, target_id)| partner_id.is_none_or(|id| id == target_id));
+        let mut session = match request {
+            Some((requested, target_id)) => {
+                let mut session = TradeSession::new(object_id, partner_id, requested);
+                session.step(
+                    requested,
+                    TradeEvent::OpenRequested { target_id },
+                    AcceptanceState::Open,
+                );
+                session
+            }
+            None => TradeSession::new(object_id, partner_id, time),
+        };
+        session.step(
+            time,
+            TradeEvent::Opened { partner_id },
+            AcceptanceState::Open,
+        );
+        self.sessions.push(session);
+        self.active.insert(object_id, index);
+    }
+
+    /// The open session for a player, starting one if their window has none
+    fn session(&mut self, object_id: u32, time: f64) -> &mut TradeSession {
+        let index = match self.active.get(&object_id) {
+            Some(&index) => index,
+            None => {
+                let partner_id = self.windows.get(&object_id).copied().flatten();
+                self.sessions
+                    .push(TradeSession::new(object_id, partner_id, time));
+                self.active.insert(object_id, self.sessions.len() - 1);
+                self.sessions.len() - 1
+            }
+        };
+        &mut self.sessions[index]
+    }
+}
+
+/// Every trade seen in a capture
+#[derive(Clone, Debug, Serialize)]
+pub struct TradeLog {
+    pub sessions: Vec<TradeSession>,
+}
+
+impl TradeLog {
+    /// Build the log from the trade messages in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut tracker = TradeTracker::new();
+        for (raw, message) in parse_all(messages) {
+            tracker.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+        tracker.finish()
+    }
+
+    /// Write the log as CSV, one row per traded item
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &[
+                "session",
+                "observer_id",
+                "partner_id",
+                "partner_name",
+                "start",
+                "end",
+                "outcome",
+                "side",
+                "item_id",
+                "item_name",
+            ],
+        )?;
+
+        for (index, session) in self.sessions.iter().enumerate() {
+            let sides = [
+                ("Self", &session.self_items),
+                ("Partner", &session.partner_items),
+            ];
+            for (side, items) in sides {
+                for item in items {
+                    write_row(
+                        &mut writer,
+                        &[
+                            index.to_string(),
+                            format!("{:#010x}", session.observer_id),
+                            opt(session.partner_id.map(|id| format!("{id:#010x}"))),
+                            session.partner_name.clone().unwrap_or_default(),
+                            session.start.to_string(),
+                            opt(session.end),
+                            opt(session.outcome.as_ref()),
+                            side.to_string(),
+                            format!("{:#010x}", item.object_id),
+                            item.name.clone().unwrap_or_default(),
+                        ],
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::testing::{PLAYER, action, event};
+    use crate::gameactions::{TradeDeclineTrade as DeclineAction, TradeOpenTradeNegotiations};
+    use crate::gameevents::{
+        TradeAcceptTrade, TradeAddToTrade, TradeClearTradeAcceptance, TradeCloseTrade,
+        TradeDeclineTrade, TradeRegisterTrade, TradeResetTrade,
+    };
+
+    use crate::types::ObjectId;
+
+    const PARTNER: u32 = 0x50000002;
+    const SWORD: u32 = 0x80000001;
+    const PYREALS: u32 = 0x80000002;
+
+    fn register() -> MessageKind {
+        event(GameEventMessage::TradeRegisterTrade(TradeRegisterTrade {
+            initiator_id: ObjectId(PLAYER),
+            partner_id: ObjectId(PARTNER),
+            stamp: 0,
+        }))
+    }
+
+    fn add(item: u32, side: TradeSide) -> MessageKind {
+        event(GameEventMessage::TradeAddToTrade(TradeAddToTrade {
+            object_id: ObjectId(item),
+            side,
+        }))
+    }
+
+    fn accept(by: u32) -> MessageKind {
+        event(GameEventMessage::TradeAcceptTrade(TradeAcceptTrade {
+            object_id: ObjectId(by),
+        }))
+    }
+
+    fn close(reason: EndTradeReason) -> MessageKind {
+        event(GameEventMessage::TradeCloseTrade(TradeCloseTrade {
+            reason,
+        }))
+    }
+
+    #[test]
+    fn test_completed_trade() {
+        let mut tracker = TradeTracker::new();
+        tracker.objects.insert(SWORD, "Sword");
+        tracker.objects.insert(PARTNER, "Partner");
+        tracker.process(1.0, &register());
+        tracker.process(2.0, &add(SWORD, TradeSide::Self_));
+        tracker.process(3.0, &add(PYREALS, TradeSide::Partner));
+        tracker.process(4.0, &accept(PLAYER));
+        tracker.process(5.0, &accept(PARTNER));
+        tracker.process(
+            6.0,
+            &event(GameEventMessage::TradeResetTrade(TradeResetTrade {
+                object_id: ObjectId(PLAYER),
+            })),
+        );
+        tracker.process(7.0, &close(EndTradeReason::Normal));
+        let log = tracker.finish();
+
+        assert_eq!(log.sessions.len(), 1);
+        let session = &log.sessions[0];
+        assert_eq!(session.outcome, Some(TradeOutcome::Completed));
+        assert_eq!(session.end, Some(5.0));
+        assert_eq!(session.partner_name.as_deref(), Some("Partner"));
+        assert_eq!(session.self_items[0].name.as_deref(), Some("Sword"));
+        assert_eq!(session.partner_items[0].object_id, PYREALS);
+        assert_eq!(
+            session.steps.iter().map(|s| s.state).collect::<Vec<_>>(),
+            vec![
+                AcceptanceState::Open,
+                AcceptanceState::Open,
+                AcceptanceState::Open,
+                AcceptanceState::SelfAccepted,
+                AcceptanceState::BothAccepted,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_declined_trade() {
+        let mut tracker = TradeTracker::new();
+        tracker.process(1.0, &register());
+        tracker.process(2.0, &add(SWORD, TradeSide::Self_));
+        tracker.process(3.0, &accept(PLAYER));
+        tracker.process(
+            4.0,
+            &event(GameEventMessage::TradeClearTradeAcceptance(
+                TradeClearTradeAcceptance {},
+            )),
+        );
+        tracker.process(
+            5.0,
+            &event(GameEventMessage::TradeDeclineTrade(TradeDeclineTrade {
+                object_id: ObjectId(PARTNER),
+            })),
+        );
+        tracker.process(6.0, &close(EndTradeReason::Normal));
+        let log = tracker.finish();
+
+        let session = &log.sessions[0];
+        assert_eq!(
+            session.outcome,
+            Some(TradeOutcome::Declined { by: PARTNER })
+        );
+        assert_eq!(session.state(), AcceptanceState::Open);
+    }
+
+    #[test]
+    fn test_failed_trade_and_second_session() {
+        let mut tracker = TradeTracker::new();
+        tracker.process(1.0, &register());
+        tracker.process(2.0, &accept(PLAYER));
+        tracker.process(3.0, &accept(PARTNER));
+        tracker.process(4.0, &add(SWORD, TradeSide::Self_));
+        tracker.process(5.0, &close(EndTradeReason::EnteredCombat));
+        let log = tracker.finish();
+
+        assert_eq!(log.sessions.len(), 2);
+        assert_eq!(log.sessions[1].partner_id, Some(PARTNER));
+        assert_eq!(
+            log.sessions[1].outcome,
+            Some(TradeOutcome::Failed {
+                reason: EndTradeReason::EnteredCombat
+            })
+        );
+    }
+
+    fn request(target: u32) -> MessageKind {
+        action(GameActionMessage::TradeOpenTradeNegotiations(
+            TradeOpenTradeNegotiations {
+                object_id: ObjectId(target),
+            },
+        ))
+    }
+
+    #[test]
+    fn test_open_request_before_window() {
+        let mut tracker = TradeTracker::new();
+        // A request nobody answered, then one that opens a window
+        tracker.process(1.0, &request(0x50000009));
+        tracker.process(2.0, &request(PARTNER));
+        tracker.process(3.0, &register());
+        tracker.process(
+            4.0,
+            &action(GameActionMessage::TradeDeclineTrade(DeclineAction {})),
+        );
+        let log = tracker.finish();
+
+        let session = &log.sessions[0];
+        assert_eq!(session.start, 2.0);
+        assert!(matches!(
+            session.steps[0].event,
+            TradeEvent::OpenRequested { target_id: PARTNER }
+        ));
+        assert!(matches!(session.steps[1].event, TradeEvent::Opened { .. }));
+        assert!(matches!(
+            session.steps[2].event,
+            TradeEvent::DeclineRequested
+        ));
+    }
+
+    #[test]
+    fn test_request_for_other_partner_and_truncated_session() {
+        let mut tracker = TradeTracker::new();
+        tracker.process(1.0, &request(0x50000009));
+        tracker.process(2.0, &register());
+        tracker.process(3.0, &add(SWORD, TradeSide::Self_));
+        // The capture ends with the window still open
+        let log = tracker.finish();
+
+        let session = &log.sessions[0];
+        assert_eq!(session.start, 2.0);
+        assert!(matches!(session.steps[0].event, TradeEvent::Opened { .. }));
+        assert_eq!(session.outcome, None);
+        assert_eq!(session.end, None);
+        assert_eq!(session.self_items.len(), 1);
+    }
+}
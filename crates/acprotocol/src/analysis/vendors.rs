@@ -0,0 +1,519 @@
+//! Vendor catalogs
+//!
+//! Each `Vendor_VendorInfo` carries the vendor's `VendorProfile` and the items
+//! it has for sale. This collects those catalogs per vendor, along with where
+//! the vendor stands (from its `Item_CreateObject`) and what the player bought
+//! and sold with `Vendor_Buy` and `Vendor_Sell`.
+//!
+//! Vendors are keyed by object id, so catalogs from several captures merge
+//! into one database. Catalog items are keyed by weenie class id, since the
+//! server gives the same stock new object ids every session; an item's object
+//! id is only used to match purchases and sales within the capture it came
+//! from. Items that drop out of a later catalog are kept, with the time they
+//! were last listed.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::enums::ItemType;
+use crate::message::{GameActionMessage, GameEventMessage, MessageKind};
+use crate::network::RawMessage;
+use crate::types::{ItemProfile, PackableList, Position, VendorProfile};
+
+use super::csv::{flags, opt, write_row};
+use super::objects::ObjectIndex;
+use super::{game_action, game_event, parse_all};
+
+/// Stock amount meaning the vendor never runs out
+const UNLIMITED_STOCK: u32 = 0xFFFFFF;
+
+/// Read an item profile's stock amount and public description
+fn item_profile(profile: &ItemProfile) -> (u32, VendorItem) {
+    let (packed_amount, item) = match profile {
+        ItemProfile::TypeNeg1(p) => {
+            let desc = &p.weenie_description;
+            let item = VendorItem::new(
+                desc.weenie_class_id.0,
+                p.object_id.0,
+                &desc.name,
+                desc.type_,
+            );
+            (
+                p.packed_amount,
+                item.with_stack(desc.value, desc.stack_size, desc.max_stack_size),
+            )
+        }
+        ItemProfile::Type1(p) => {
+            let desc = &p.old_weenie_description;
+            let item = VendorItem::new(
+                desc.weenie_class_id.0,
+                p.object_id.0,
+                &desc.name,
+                desc.type_,
+            );
+            (
+                p.packed_amount,
+                item.with_stack(desc.value, desc.stack_size, desc.max_stack_size),
+            )
+        }
+    };
+
+    (packed_amount & 0xFFFFFF, item)
+}
+
+/// An item listed in a vendor's catalog
+#[derive(Clone, Debug, Serialize)]
+pub struct VendorItem {
+    pub weenie_class_id: u32,
+    /// Object id in the capture the item was last listed in
+    pub object_id: u32,
+    pub name: Option<String>,
+    pub item_type: ItemType,
+    /// Value of the item as listed
+    pub value: Option<u32>,
+    pub stack_size: Option<u16>,
+    pub max_stack_size: Option<u16>,
+    /// Number in stock, or `None` if the vendor never runs out
+    pub stock: Option<u32>,
+    /// What the vendor charges for the item: its value at the profile's sell rate
+    pub sell_price: Option<u32>,
+    /// What the vendor pays for an item like it: its value at the profile's buy rate
+    pub buy_price: Option<u32>,
+    pub last_seen: f64,
+}
+
+impl VendorItem {
+    fn new(weenie_class_id: u32, object_id: u32, name: &str, item_type: ItemType) -> Self {
+        Self {
+            weenie_class_id,
+            object_id,
+            name: (!name.is_empty()).then(|| name.to_string()),
+            item_type,
+            value: None,
+            stack_size: None,
+            max_stack_size: None,
+            stock: None,
+            sell_price: None,
+            buy_price: None,
+            last_seen: 0.0,
+        }
+    }
+
+    fn with_stack(
+        self,
+        value: Option<u32>,
+        stack_size: Option<u16>,
+        max_stack_size: Option<u16>,
+    ) -> Self {
+        Self {
+            value,
+            stack_size,
+            max_stack_size,
+            ..self
+        }
+    }
+
+    fn price(&mut self, profile: &VendorProfile) {
+        let at_rate = |rate: f32| {
+            self.value
+                .map(|value| (f64::from(value) * f64::from(rate)).round() as u32)
+        };
+        self.sell_price = at_rate(profile.sell_price);
+        self.buy_price = at_rate(profile.buy_price);
+    }
+}
+
+/// Whether the player bought from or sold to a vendor
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum TransactionKind {
+    Buy,
+    Sell,
+}
+
+/// An item in a purchase or sale
+#[derive(Clone, Debug, Serialize)]
+pub struct TransactionItem {
+    pub object_id: u32,
+    pub name: Option<String>,
+    pub amount: u32,
+}
+
+/// A `Vendor_Buy` or `Vendor_Sell` sent by the player
+#[derive(Clone, Debug, Serialize)]
+pub struct VendorTransaction {
+    pub time: f64,
+    pub kind: TransactionKind,
+    pub items: Vec<TransactionItem>,
+}
+
+/// A vendor and everything it was seen to offer
+#[derive(Clone, Debug, Serialize)]
+pub struct Vendor {
+    pub object_id: u32,
+    pub name: Option<String>,
+    /// Where the vendor was when it was created
+    pub location: Option<Position>,
+    /// Profile from the most recent catalog
+    pub profile: VendorProfile,
+    pub catalog: Vec<VendorItem>,
+    pub transactions: Vec<VendorTransaction>,
+    /// Number of catalogs received
+    pub visits: u32,
+}
+
+impl Vendor {
+    fn update(&mut self, time: f64, profile: &VendorProfile, items: &PackableList<ItemProfile>) {
+        self.profile = profile.clone();
+        self.visits += 1;
+
+        for profile_item in &items.list {
+            let (amount, mut item) = item_profile(profile_item);
+            let weenie_class_id = item.weenie_class_id;
+            item.stock = (amount != UNLIMITED_STOCK).then_some(amount);
+            item.last_seen = time;
+            item.price(profile);
+
+            match self
+                .catalog
+                .iter_mut()
+                .find(|i| i.weenie_class_id == weenie_class_id)
+            {
+                Some(existing) => *existing = item,
+                None => self.catalog.push(item),
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Vendor) {
+        self.name = other.name.or(self.name.take());
+        self.location = other.location.or(self.location.take());
+        self.profile = other.profile;
+        self.visits += other.visits;
+        self.transactions.extend(other.transactions);
+        for item in other.catalog {
+            match self
+                .catalog
+                .iter_mut()
+                .find(|i| i.weenie_class_id == item.weenie_class_id)
+            {
+                Some(existing) => *existing = item,
+                None => self.catalog.push(item),
+            }
+        }
+    }
+}
+
+/// Vendors seen across one or more captures
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct VendorDatabase {
+    vendors: Vec<Vendor>,
+    #[serde(skip)]
+    objects: ObjectIndex,
+}
+
+impl VendorDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a database from the vendor catalogs in a capture
+    pub fn from_messages(messages: &[RawMessage]) -> Self {
+        let mut database = Self::new();
+        database.add_messages(messages);
+        database
+    }
+
+    /// Add the vendors seen in another capture
+    pub fn add_messages(&mut self, messages: &[RawMessage]) {
+        for (raw, message) in parse_all(messages) {
+            self.process(raw.timestamp.unwrap_or_default(), &message);
+        }
+    }
+
+    /// Process a message received at the given time
+    pub fn process(&mut self, time: f64, message: &MessageKind) {
+        self.objects.process(message);
+
+        if let Some((_, GameEventMessage::VendorVendorInfo(msg))) = game_event(message) {
+            let vendor_id = msg.object_id.0;
+            let name = self.objects.name(vendor_id).map(str::to_string);
+            let location = self.objects.position(vendor_id).cloned();
+
+            let vendor = self.vendor(vendor_id, &msg.profile);
+            vendor.name = name.or(vendor.name.take());
+            vendor.location = location.or(vendor.location.take());
+            vendor.update(time, &msg.profile, &msg.items);
+        }
+
+        let (vendor_id, kind, items) = match game_action(message) {
+            Some(GameActionMessage::VendorBuy(msg)) => {
+                (msg.object_id.0, TransactionKind::Buy, &msg.items)
+            }
+            Some(GameActionMessage::VendorSell(msg)) => {
+                (msg.object_id.0, TransactionKind::Sell, &msg.items)
+            }
+            _ => return,
+        };
+
+        let objects = &self.objects;
+        let Some(vendor) = self.vendors.iter_mut().find(|v| v.object_id == vendor_id) else {
+            return;
+        };
+        let items = items
+            .list
+            .iter()
+            .map(|profile| {
+                let (amount, item) = item_profile(profile);
+                let object_id = item.object_id;
+                let name = vendor
+                    .catalog
+                    .iter()
+                    .find(|i| i.object_id == object_id)
+                    .and_then(|i| i.name.clone())
+                    .or_else(|| objects.name(object_id).map(str::to_string));
+                TransactionItem {
+                    object_id,
+                    name,
+                    amount,
+                }
+            })
+            .collect();
+        vendor
+            .transactions
+            .push(VendorTransaction { time, kind, items });
+    }
+
+    /// Merge another database into this one, deduplicating by vendor and item
+    pub fn merge(&mut self, other: VendorDatabase) {
+        for vendor in other.vendors {
+            match self
+                .vendors
+                .iter_mut()
+                .find(|v| v.object_id == vendor.object_id)
+            {
+                Some(existing) => existing.merge(vendor),
+                None => self.vendors.push(vendor),
+            }
+        }
+    }
+
+    pub fn get(&self, object_id: u32) -> Option<&Vendor> {
+        self.vendors.iter().find(|v| v.object_id == object_id)
+    }
+
+    pub fn vendors(&self) -> impl Iterator<Item = &Vendor> {
+        self.vendors.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vendors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vendors.is_empty()
+    }
+
+    /// Write every catalog as CSV, one row per vendor item
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        write_row(
+            &mut writer,
+            &[
+                "vendor_id",
+                "vendor_name",
+                "landcell",
+                "x",
+                "y",
+                "z",
+                "weenie_class_id",
+                "item_id",
+                "item_name",
+                "item_type",
+                "value",
+                "stack_size",
+                "stock",
+                "sell_price",
+                "buy_price",
+                "currency",
+                "last_seen",
+            ],
+        )?;
+
+        for vendor in &self.vendors {
+            let location = vendor.location.as_ref();
+            let origin = location.map(|p| &p.frame.origin);
+            let currency = if vendor.profile.currency_name.is_empty() {
+                "Pyreal".to_string()
+            } else {
+                vendor.profile.currency_name.clone()
+            };
+
+            for item in &vendor.catalog {
+                write_row(
+                    &mut writer,
+                    &[
+                        format!("{:#010x}", vendor.object_id),
+                        vendor.name.clone().unwrap_or_default(),
+                        opt(location.map(|p| format!("{:#010x}", p.landcell.0))),
+                        opt(origin.map(|o| o.x)),
+                        opt(origin.map(|o| o.y)),
+                        opt(origin.map(|o| o.z)),
+                        item.weenie_class_id.to_string(),
+                        format!("{:#010x}", item.object_id),
+                        item.name.clone().unwrap_or_default(),
+                        flags(&item.item_type)?,
+                        opt(item.value),
+                        opt(item.stack_size),
+                        opt(item.stock),
+                        opt(item.sell_price),
+                        opt(item.buy_price),
+                        currency.clone(),
+                        item.last_seen.to_string(),
+                    ],
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn vendor(&mut self, object_id: u32, profile: &VendorProfile) -> &mut Vendor {
+        let index = match self.vendors.iter().position(|v| v.object_id == object_id) {
+            Some(index) => index,
+            None => {
+                self.vendors.push(Vendor {
+                    object_id,
+                    name: None,
+                    location: None,
+                    profile: profile.clone(),
+                    catalog: Vec::new(),
+                    transactions: Vec::new(),
+                    visits: 0,
+                });
+                self.vendors.len() - 1
+            }
+        };
+        &mut self.vendors[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameactions::VendorBuy;
+    use crate::gameevents::VendorVendorInfo;
+    use crate::message::{C2SMessage, S2CMessage};
+    use crate::types::ObjectId;
+    use std::io::Cursor;
+
+    const VENDOR: u32 = 0x70000001;
+    const SWORD: u32 = 0x80000001;
+    const SWORD_CLASS: u16 = 351;
+
+    /// An item profile as the server sends it, with a -1 description type in the top byte
+    fn sword(object_id: u32, amount: u32) -> ItemProfile {
+        let mut bytes = Vec::new();
+        bytes.extend((0xFF000000 | amount).to_le_bytes());
+        bytes.extend(object_id.to_le_bytes());
+        bytes.extend(0x8u32.to_le_bytes()); // header: value present
+        bytes.extend(5i16.to_le_bytes());
+        bytes.extend(b"Sword\0");
+        bytes.extend(SWORD_CLASS.to_le_bytes()); // weenie class id
+        bytes.extend(1u16.to_le_bytes()); // icon
+        bytes.extend(ItemType::MELEE_WEAPON.bits().to_le_bytes());
+        bytes.extend(0u32.to_le_bytes()); // behavior
+        bytes.extend(1000u32.to_le_bytes()); // value
+
+        ItemProfile::read(&mut Cursor::new(bytes)).unwrap()
+    }
+
+    fn profile() -> VendorProfile {
+        VendorProfile {
+            categories: ItemType::MELEE_WEAPON,
+            min_value: 0,
+            max_value: 100000,
+            deals_magic: false,
+            buy_price: 0.5,
+            sell_price: 1.25,
+            currency_id: 0,
+            currency_amount: 0,
+            currency_name: String::new(),
+        }
+    }
+
+    fn vendor_info(sword_id: u32, amount: u32) -> MessageKind {
+        MessageKind::S2C(Box::new(S2CMessage::OrderedGameEvent {
+            object_id: 0x50000001,
+            sequence: 0,
+            event: Box::new(GameEventMessage::VendorVendorInfo(VendorVendorInfo {
+                object_id: ObjectId(VENDOR),
+                profile: profile(),
+                items: PackableList {
+                    count: 1,
+                    list: vec![sword(sword_id, amount)],
+                },
+            })),
+        }))
+    }
+
+    #[test]
+    fn test_catalog_prices_from_profile() {
+        let mut database = VendorDatabase::new();
+        database.process(1.0, &vendor_info(SWORD, UNLIMITED_STOCK));
+
+        let vendor = database.get(VENDOR).unwrap();
+        let item = &vendor.catalog[0];
+        assert_eq!(item.weenie_class_id, u32::from(SWORD_CLASS));
+        assert_eq!(item.name.as_deref(), Some("Sword"));
+        assert_eq!(item.value, Some(1000));
+        assert_eq!(item.stock, None);
+        assert_eq!(item.sell_price, Some(1250));
+        assert_eq!(item.buy_price, Some(500));
+    }
+
+    #[test]
+    fn test_buy_names_items_from_catalog() {
+        let mut database = VendorDatabase::new();
+        database.process(1.0, &vendor_info(SWORD, 3));
+        database.process(
+            2.0,
+            &MessageKind::C2S(Box::new(C2SMessage::OrderedGameAction {
+                sequence: 0,
+                action: GameActionMessage::VendorBuy(VendorBuy {
+                    object_id: ObjectId(VENDOR),
+                    items: PackableList {
+                        count: 1,
+                        list: vec![sword(SWORD, 2)],
+                    },
+                    alternate_currency_id: 0,
+                }),
+            })),
+        );
+
+        let vendor = database.get(VENDOR).unwrap();
+        assert_eq!(vendor.catalog[0].stock, Some(3));
+        let transaction = &vendor.transactions[0];
+        assert_eq!(transaction.kind, TransactionKind::Buy);
+        assert_eq!(transaction.items[0].name.as_deref(), Some("Sword"));
+        assert_eq!(transaction.items[0].amount, 2);
+    }
+
+    #[test]
+    fn test_merge_deduplicates_vendors() {
+        let mut first = VendorDatabase::from_messages(&[]);
+        first.process(1.0, &vendor_info(SWORD, 3));
+        let mut second = VendorDatabase::new();
+        // The same stock under the object id it has in a later session
+        second.process(5.0, &vendor_info(SWORD + 0x100, 1));
+
+        first.merge(second);
+
+        assert_eq!(first.len(), 1);
+        let vendor = first.get(VENDOR).unwrap();
+        assert_eq!(vendor.visits, 2);
+        assert_eq!(vendor.catalog.len(), 1);
+        assert_eq!(vendor.catalog[0].stock, Some(1));
+        assert_eq!(vendor.catalog[0].object_id, SWORD + 0x100);
+        assert_eq!(vendor.catalog[0].last_seen, 5.0);
+    }
+}
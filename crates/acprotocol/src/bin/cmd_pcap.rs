@@ -1,13 +1,24 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+
+use acprotocol::analysis::latency::DEFAULT_TIMEOUT;
+use acprotocol::analysis::stats::{DEFAULT_BUCKET, DEFAULT_TOP};
+use acprotocol::cli::parse_object_id;
 use acprotocol::cli::pcap::{
-    DirectionFilter, OutputFormat, SortField, format_parsed_messages, format_raw_messages,
-    output_messages, print_summary,
+    ArchiveFormat, DatabaseFormat, DiffFormat, DirectionFilter, ExportFormat, GameFormat,
+    HexdumpFormat, OutputFormat, SortField, SummaryFormat, TranscriptFormat, TreeFormat,
+    anonymize_capture, format_parsed_messages, format_raw_messages, load_messages, output_messages,
+    print_allegiance, print_books, print_casts, print_chat, print_chess, print_corpus,
+    print_crafting, print_diff, print_enchantments, print_fellowships, print_follow, print_hexdump,
+    print_housing, print_items, print_latency, print_quests, print_social, print_stats,
+    print_summary, print_trades, print_vendors, slice_capture, split_capture,
 };
 use acprotocol::cli::tui;
-use acprotocol::network::FragmentAssembler;
-use acprotocol::network::pcap;
+use acprotocol::filter::{FieldSelection, MessageFilter, MessageRange, TimeBound, capture_start};
+use acprotocol::network::anonymize::AnonymizeOptions;
 
 #[derive(Parser)]
 #[command(name = "pcap")]
@@ -41,6 +52,38 @@ pub enum Commands {
         #[arg(short = 'd', long)]
         direction: Option<DirectionFilter>,
 
+        /// Filter expression, e.g. 'type == "ItemCreateObject" && name ~ "Pyreal"'
+        #[arg(short = 'f', long)]
+        filter: Option<MessageFilter>,
+
+        /// Only messages at or after this time (+90s, +2m30s, Unix time or 2025-11-18T18:24:51Z)
+        #[arg(long)]
+        since: Option<TimeBound>,
+
+        /// Only messages at or before this time (same forms as --since)
+        #[arg(long)]
+        until: Option<TimeBound>,
+
+        /// Only messages with this ID or later
+        #[arg(long)]
+        from_id: Option<u32>,
+
+        /// Only messages with this ID or earlier
+        #[arg(long)]
+        to_id: Option<u32>,
+
+        /// Only messages near this message ID (see --context)
+        #[arg(long, value_name = "ID")]
+        around: Option<u32>,
+
+        /// Number of messages to show on each side of --around
+        #[arg(long, default_value = "10", requires = "around")]
+        context: usize,
+
+        /// Show seconds since capture start for each message
+        #[arg(long)]
+        relative_time: bool,
+
         /// Sort by field
         #[arg(short, long, default_value = "id")]
         sort: SortField,
@@ -53,10 +96,20 @@ pub enum Commands {
         #[arg(short, long)]
         limit: Option<usize>,
 
-        /// Output format (json or jsonl)
+        /// Output format (jsonl, json, table, csv or tsv)
         #[arg(short, long, default_value = "jsonl")]
         output: OutputFormat,
 
+        /// Only print these fields, as comma-separated dotted paths, e.g.
+        /// 'id,timestamp,message.object_id,message.weenie_description.name'
+        #[arg(long, value_name = "PATHS")]
+        fields: Option<String>,
+
+        /// Print one row per element of the list at this path; fields under it
+        /// are taken from each element
+        #[arg(long, value_name = "PATH", requires = "fields")]
+        explode: Option<String>,
+
         /// Show summary statistics
         #[arg(long)]
         summary: bool,
@@ -66,11 +119,370 @@ pub enum Commands {
         raw: bool,
     },
 
+    /// Parse every capture under a directory and report parse coverage across all of them
+    Corpus {
+        /// Directory to search for .pcap files
+        #[arg(value_name = "DIR", required = true)]
+        dir: PathBuf,
+
+        /// Output format (json or table)
+        #[arg(short, long, default_value = "json")]
+        output: SummaryFormat,
+
+        /// Number of captures to parse at once (defaults to the number of CPUs)
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+
+    /// Write a smaller capture holding only the frames of matching messages
+    Slice {
+        /// PCAP file to read
+        #[arg(value_name = "FILE", required = true)]
+        file: PathBuf,
+
+        /// PCAP file to write
+        #[arg(value_name = "OUT", required = true)]
+        out: PathBuf,
+
+        /// Filter expression, e.g. 'type == "ItemCreateObject" && name ~ "Pyreal"'
+        #[arg(short = 'f', long)]
+        filter: Option<MessageFilter>,
+
+        /// Only messages at or after this time (+90s, +2m30s, Unix time or 2025-11-18T18:24:51Z)
+        #[arg(long)]
+        since: Option<TimeBound>,
+
+        /// Only messages at or before this time (same forms as --since)
+        #[arg(long)]
+        until: Option<TimeBound>,
+
+        /// Only messages with this ID or later
+        #[arg(long)]
+        from_id: Option<u32>,
+
+        /// Only messages with this ID or earlier
+        #[arg(long)]
+        to_id: Option<u32>,
+
+        /// Only messages near this message ID (see --context)
+        #[arg(long, value_name = "ID")]
+        around: Option<u32>,
+
+        /// Number of messages to keep on each side of --around
+        #[arg(long, default_value = "10", requires = "around")]
+        context: usize,
+
+        /// Only messages from this session (sessions start at each login, from 1)
+        #[arg(long)]
+        session: Option<u32>,
+    },
+
+    /// Split a capture into one file per session or per N minutes
+    Split {
+        /// PCAP file to read
+        #[arg(value_name = "FILE", required = true)]
+        file: PathBuf,
+
+        /// Directory to write the parts to
+        #[arg(long, value_name = "DIR", default_value = ".")]
+        out_dir: PathBuf,
+
+        /// Start a new file at each session (the default without --minutes);
+        /// with --minutes, split each session into parts of its own
+        #[arg(long)]
+        by_session: bool,
+
+        /// Write one file per this many minutes of capture time
+        #[arg(long, value_name = "N")]
+        minutes: Option<f64>,
+    },
+
+    /// Write a copy of a capture with player and account names replaced by pseudonyms
+    Anonymize {
+        /// PCAP file to read
+        #[arg(value_name = "FILE", required = true)]
+        file: PathBuf,
+
+        /// PCAP file to write
+        #[arg(value_name = "OUT", required = true)]
+        out: PathBuf,
+
+        /// Blank chat and tell text instead of only replacing names in it
+        #[arg(long)]
+        blank_chat: bool,
+
+        /// Replace IP addresses with addresses from 10.0.0.0/8
+        #[arg(long)]
+        rewrite_ips: bool,
+
+        /// Keep messages that couldn't be parsed or re-encoded as they were
+        /// instead of zeroing them; they may still hold names
+        #[arg(long)]
+        keep_unredacted: bool,
+    },
+
+    /// Align two captures and show missing, inserted, moved and changed messages
+    Diff {
+        /// The capture to compare from
+        #[arg(value_name = "A", required = true)]
+        a: PathBuf,
+
+        /// The capture to compare to
+        #[arg(value_name = "B", required = true)]
+        b: PathBuf,
+
+        /// Output format (text or json)
+        #[arg(short, long, default_value = "text")]
+        output: DiffFormat,
+
+        /// Ignore fields matching a path glob, e.g. '**.ObjectId' (repeatable)
+        #[arg(long, value_name = "GLOB")]
+        ignore: Vec<String>,
+
+        /// Ignore object ids, sequence numbers and timestamps
+        #[arg(long)]
+        ignore_volatile: bool,
+
+        /// Don't color text output
+        #[arg(long)]
+        no_color: bool,
+    },
+
+    /// Show every message referring to an object and the part the object played
+    Follow {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Object id to follow, in hex (0x80001234) or decimal
+        #[arg(long, value_name = "ID", required = true, value_parser = parse_object_id)]
+        object: u32,
+
+        /// Output format (table or json)
+        #[arg(short, long, default_value = "table")]
+        output: SummaryFormat,
+    },
+
+    /// Show a message's bytes annotated with the offset, length and value of each field
+    Hexdump {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// ID of the message to show
+        #[arg(short = 'i', long, required = true)]
+        id: u32,
+
+        /// Output format (text or json)
+        #[arg(short, long, default_value = "text")]
+        output: HexdumpFormat,
+
+        /// Don't color text output
+        #[arg(long)]
+        no_color: bool,
+    },
+
+    /// Show message counts, sizes, rates and parse results by type, queue and direction
+    Stats {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (table or json)
+        #[arg(short, long, default_value = "table")]
+        output: SummaryFormat,
+
+        /// Width of the message rate buckets, in seconds
+        #[arg(long, default_value_t = DEFAULT_BUCKET)]
+        bucket: f64,
+
+        /// Number of largest messages to list
+        #[arg(long, default_value_t = DEFAULT_TOP)]
+        top: usize,
+    },
+
+    /// Show which enchantments were active on each object over time
+    Enchantments {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (json or csv)
+        #[arg(short, long, default_value = "json")]
+        output: ExportFormat,
+    },
+
+    /// Show fellowship membership and XP sharing over time
+    Fellowships {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (json or csv)
+        #[arg(short, long, default_value = "json")]
+        output: ExportFormat,
+    },
+
+    /// Show the allegiance tree and XP passed up between updates
+    Allegiance {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (json or dot)
+        #[arg(short, long, default_value = "json")]
+        output: TreeFormat,
+    },
+
+    /// Pair requests with their responses and measure server latency
+    Latency {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (json or csv)
+        #[arg(short, long, default_value = "json")]
+        output: ExportFormat,
+
+        /// Seconds a request may wait before it is counted as unanswered
+        #[arg(long, default_value_t = DEFAULT_TIMEOUT)]
+        timeout: f64,
+    },
+
+    /// Follow spell casts to their effects, with fizzle rates and timings
+    Casts {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (json or csv)
+        #[arg(short, long, default_value = "json")]
+        output: ExportFormat,
+    },
+
+    /// Show salvage results and tinkering attempts with success rates by material
+    Crafting {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (json or csv)
+        #[arg(short, long, default_value = "json")]
+        output: ExportFormat,
+    },
+
+    /// Show each character's contract (quest) stages and timers
+    Quests {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (json or csv)
+        #[arg(short, long, default_value = "json")]
+        output: ExportFormat,
+    },
+
+    /// Show the chat transcript, with error and status codes as the client's text
+    Chat {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (text or json)
+        #[arg(short, long, default_value = "text")]
+        output: TranscriptFormat,
+    },
+
+    /// Show titles, squelches and friends, and how they changed
+    Social {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (json or csv)
+        #[arg(short, long, default_value = "json")]
+        output: ExportFormat,
+    },
+
+    /// Show secure trades, their items and how they ended
+    Trades {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (json or csv)
+        #[arg(short, long, default_value = "json")]
+        output: ExportFormat,
+    },
+
+    /// Replay chess games, checking each move against the rules
+    Chess {
+        /// PCAP file to parse
+        #[arg(value_name = "FILE", required = true)]
+        file: String,
+
+        /// Output format (json or pgn)
+        #[arg(short, long, default_value = "pgn")]
+        output: GameFormat,
+    },
+
+    /// Extract book pages and item inscriptions
+    Books {
+        /// PCAP files to parse, merged into one archive
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+
+        /// Output format (markdown or json)
+        #[arg(short, long, default_value = "markdown")]
+        output: ArchiveFormat,
+    },
+
+    /// Build house records, or list houses for sale by type and landblock
+    Housing {
+        /// PCAP files to parse, merged into one report
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+
+        /// Output format (json or csv)
+        #[arg(short, long, default_value = "json")]
+        output: ExportFormat,
+
+        /// Show houses for sale instead of house records
+        #[arg(long)]
+        available: bool,
+    },
+
+    /// Build an item and creature database from object descriptions and appraisals
+    Items {
+        /// PCAP files to parse, merged into one database
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+
+        /// Output format (jsonl or csv)
+        #[arg(short, long, default_value = "jsonl")]
+        output: DatabaseFormat,
+    },
+
+    /// Build a vendor database from vendor catalogs, with prices and locations
+    Vendors {
+        /// PCAP files to parse, merged into one database
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+
+        /// Output format (jsonl or csv)
+        #[arg(short, long, default_value = "jsonl")]
+        output: DatabaseFormat,
+    },
+
     /// Launch interactive TUI
     Tui {
         /// PCAP file to parse
         #[arg(value_name = "FILE", required = true)]
         file: String,
+
+        /// Start with a filter expression in the filter bar
+        #[arg(short = 'f', long)]
+        filter: Option<MessageFilter>,
     },
 }
 
@@ -100,25 +512,36 @@ fn main() -> Result<()> {
             filter_type,
             filter_opcode,
             direction,
+            filter,
+            since,
+            until,
+            from_id,
+            to_id,
+            around,
+            context,
+            relative_time,
             sort,
             reverse,
             limit,
             output,
+            fields,
+            explode,
             summary,
             raw,
         }) => {
-            let file_path = file;
-
             // Load PCAP file and parse packets
-            let mut assembler = FragmentAssembler::new();
-            let mut messages = Vec::new();
-
-            let pcap_iter = pcap::open(&file_path)?;
-            for packet_result in pcap_iter {
-                let packet = packet_result?;
-                let parsed_messages = assembler.parse_packet_payload(&packet.data)?;
-                messages.extend(parsed_messages);
-            }
+            let messages = load_messages(Path::new(&file))?;
+            let range = MessageRange {
+                since,
+                until,
+                from_id,
+                to_id,
+                around: around.map(|id| (id, context)),
+            };
+            let start = capture_start(&messages).filter(|_| relative_time);
+            let fields = fields
+                .map(|fields| FieldSelection::parse(&fields, explode.as_deref()))
+                .transpose()?;
 
             if summary {
                 print_summary(&messages);
@@ -126,13 +549,15 @@ fn main() -> Result<()> {
                 && filter_type.is_none()
                 && filter_opcode.is_none()
                 && direction.is_none()
+                && filter.is_none()
+                && range.is_empty()
                 && limit.is_none()
             {
                 // If no filters are applied, print all messages (like the original cat command)
                 if raw {
-                    format_raw_messages(&messages, output);
+                    format_raw_messages(&messages, output, start, fields.as_ref());
                 } else {
-                    format_parsed_messages(&messages, output);
+                    format_parsed_messages(&messages, output, start, fields.as_ref());
                 }
             } else {
                 // If any filters are applied, use the filtering logic
@@ -142,20 +567,172 @@ fn main() -> Result<()> {
                     filter_type.as_deref(),
                     filter_opcode.as_deref(),
                     direction,
+                    filter.as_ref(),
+                    &range,
                     sort,
                     reverse,
                     limit,
                     output,
+                    fields.as_ref(),
                     raw,
-                );
+                    relative_time,
+                )?;
             }
         }
-        Some(Commands::Tui { file }) => {
+        Some(Commands::Corpus { dir, output, jobs }) => {
+            print_corpus(&dir, output, jobs)?;
+        }
+        Some(Commands::Slice {
+            file,
+            out,
+            filter,
+            since,
+            until,
+            from_id,
+            to_id,
+            around,
+            context,
+            session,
+        }) => {
+            let range = MessageRange {
+                since,
+                until,
+                from_id,
+                to_id,
+                around: around.map(|id| (id, context)),
+            };
+            slice_capture(&file, &out, filter.as_ref(), &range, session)?;
+        }
+        Some(Commands::Split {
+            file,
+            out_dir,
+            by_session,
+            minutes,
+        }) => {
+            split_capture(&file, &out_dir, by_session, minutes)?;
+        }
+        Some(Commands::Anonymize {
+            file,
+            out,
+            blank_chat,
+            rewrite_ips,
+            keep_unredacted,
+        }) => {
+            let options = AnonymizeOptions {
+                blank_chat,
+                rewrite_ips,
+                keep_unredacted,
+            };
+            anonymize_capture(&file, &out, options)?;
+        }
+        Some(Commands::Diff {
+            a,
+            b,
+            output,
+            ignore,
+            ignore_volatile,
+            no_color,
+        }) => {
+            let color = !no_color && io::stdout().is_terminal();
+            print_diff(&a, &b, output, ignore, ignore_volatile, color)?;
+        }
+        Some(Commands::Follow {
+            file,
+            object,
+            output,
+        }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_follow(&messages, object, output)?;
+        }
+        Some(Commands::Hexdump {
+            file,
+            id,
+            output,
+            no_color,
+        }) => {
+            let messages = load_messages(Path::new(&file))?;
+            let color = !no_color && io::stdout().is_terminal();
+            print_hexdump(&messages, id, output, color)?;
+        }
+        Some(Commands::Stats {
+            file,
+            output,
+            bucket,
+            top,
+        }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_stats(&messages, output, bucket, top)?;
+        }
+        Some(Commands::Enchantments { file, output }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_enchantments(&messages, output)?;
+        }
+        Some(Commands::Fellowships { file, output }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_fellowships(&messages, output)?;
+        }
+        Some(Commands::Allegiance { file, output }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_allegiance(&messages, output)?;
+        }
+        Some(Commands::Latency {
+            file,
+            output,
+            timeout,
+        }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_latency(&messages, output, timeout)?;
+        }
+        Some(Commands::Casts { file, output }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_casts(&messages, output)?;
+        }
+        Some(Commands::Crafting { file, output }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_crafting(&messages, output)?;
+        }
+        Some(Commands::Quests { file, output }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_quests(&messages, output)?;
+        }
+        Some(Commands::Chat { file, output }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_chat(&messages, output)?;
+        }
+        Some(Commands::Social { file, output }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_social(&messages, output)?;
+        }
+        Some(Commands::Trades { file, output }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_trades(&messages, output)?;
+        }
+        Some(Commands::Chess { file, output }) => {
+            let messages = load_messages(Path::new(&file))?;
+            print_chess(&messages, output)?;
+        }
+        Some(Commands::Books { files, output }) => {
+            print_books(&files, output)?;
+        }
+        Some(Commands::Housing {
+            files,
+            output,
+            available,
+        }) => {
+            print_housing(&files, output, available)?;
+        }
+        Some(Commands::Items { files, output }) => {
+            print_items(&files, output)?;
+        }
+        Some(Commands::Vendors { files, output }) => {
+            print_vendors(&files, output)?;
+        }
+        Some(Commands::Tui { file, filter }) => {
             // Launch the TUI
             let file_path = file;
 
             let path = std::path::Path::new(&file_path);
-            tui::run(path)?;
+            tui::run(path, filter)?;
         }
         None => {}
     }
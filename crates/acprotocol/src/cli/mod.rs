@@ -133,6 +133,19 @@ pub fn parse_opcode_filter(s: &str) -> Result<u32> {
     }
 }
 
+/// Parse an object id, in hex ("0x80001234") or decimal
+pub fn parse_object_id(s: &str) -> Result<u32> {
+    let s = s.trim();
+
+    if let Some(stripped) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        u32::from_str_radix(stripped, 16)
+            .map_err(|e| anyhow::anyhow!("Invalid hex object id '{s}': {e}"))
+    } else {
+        s.parse::<u32>()
+            .map_err(|e| anyhow::anyhow!("Invalid decimal object id '{s}': {e}"))
+    }
+}
+
 /// Convert opcode string (hex format like "F7B1") to u32
 pub fn opcode_str_to_u32(opcode_str: &str) -> Option<u32> {
     u32::from_str_radix(opcode_str, 16).ok()
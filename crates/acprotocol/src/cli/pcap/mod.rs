@@ -1,7 +1,20 @@
 mod output;
 mod processing;
+mod reports;
 mod types;
 
 pub use output::{format_parsed_messages, format_raw_messages, print_summary};
-pub use processing::output_messages;
-pub use types::{DirectionFilter, OutputFormat, RawMessageOutput, SortField};
+pub use processing::{
+    anonymize_capture, load_messages, output_messages, slice_capture, split_capture,
+};
+pub use reports::{
+    print_allegiance, print_books, print_casts, print_chat, print_chess, print_corpus,
+    print_crafting, print_diff, print_enchantments, print_fellowships, print_follow, print_hexdump,
+    print_housing, print_items, print_latency, print_quests, print_social, print_stats,
+    print_trades, print_vendors,
+};
+pub use types::{
+    ArchiveFormat, DatabaseFormat, DiffFormat, DirectionFilter, ExportFormat, GameFormat,
+    HexdumpFormat, OutputFormat, RawMessageOutput, SortField, SummaryFormat, TranscriptFormat,
+    TreeFormat,
+};
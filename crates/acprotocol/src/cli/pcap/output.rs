@@ -1,9 +1,47 @@
 use std::collections::HashMap;
+use std::io::{self, Write};
 
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::analysis::csv::write_row;
+use crate::filter::{FieldSelection, cell_text};
 use crate::network::RawMessage;
 
 use super::types::{OutputFormat, RawMessageOutput};
 
+/// A parsed message with its time since capture start
+#[derive(Serialize)]
+struct TimedMessage<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    relative_time: Option<f64>,
+    #[serde(flatten)]
+    message: &'a RawMessage,
+}
+
+/// Seconds between the capture start and a message, to the millisecond
+fn relative_time(msg: &RawMessage, start: Option<f64>) -> Option<f64> {
+    let elapsed = msg.timestamp? - start?;
+    Some((elapsed * 1000.0).round() / 1000.0)
+}
+
+/// The relative time column for table output, empty when not requested
+fn time_column(msg: &RawMessage, start: Option<f64>) -> String {
+    match (start, relative_time(msg, start)) {
+        (None, _) => String::new(),
+        (Some(_), Some(t)) => format!("{t:>10.3}  "),
+        (Some(_), None) => format!("{:>10}  ", "-"),
+    }
+}
+
+/// The relative time column header for table output
+fn time_header(start: Option<f64>) -> String {
+    match start {
+        Some(_) => format!("{:>10}  ", "Time"),
+        None => String::new(),
+    }
+}
+
 /// Truncate a string to a maximum length, adding "..." if truncated
 pub fn truncate(s: &str, max_len: usize) -> String {
     if s.len() <= max_len {
@@ -44,54 +82,62 @@ pub fn print_summary(messages: &[RawMessage]) {
 }
 
 /// Helper function to format and output messages in raw format (with hex data)
-pub fn format_raw_messages<'a, I>(messages: I, output: OutputFormat)
-where
+///
+/// When `start` is given, each message also shows its seconds since that time.
+/// With `fields`, or for CSV and TSV, only the selected fields are printed.
+pub fn format_raw_messages<'a, I>(
+    messages: I,
+    output: OutputFormat,
+    start: Option<f64>,
+    fields: Option<&FieldSelection>,
+) where
     I: IntoIterator<Item = &'a RawMessage>,
 {
     let messages: Vec<_> = messages.into_iter().collect();
+    let raw_output = |msg: &RawMessage| RawMessageOutput {
+        id: msg.id,
+        opcode: msg.opcode,
+        message_type: msg.message_type.clone(),
+        direction: msg.direction.clone(),
+        queue: msg.queue.as_ref().map(|q| format!("{:?}", q)),
+        data_len: msg.data.len(),
+        raw: hex::encode(&msg.data),
+        sequence: msg.sequence,
+        iteration: msg.iteration,
+        header_flags: msg.header_flags,
+        timestamp: msg.timestamp,
+        relative_time: relative_time(msg, start),
+    };
+    if fields.is_some() || output.is_tabular() {
+        let values = messages
+            .iter()
+            .map(|msg| serde_json::to_value(raw_output(msg)).unwrap());
+        print_fields(values, output, fields);
+        return;
+    }
 
     match output {
+        OutputFormat::Csv | OutputFormat::Tsv => unreachable!("printed as fields"),
         OutputFormat::Jsonl => {
             for msg in messages {
-                let raw_output = RawMessageOutput {
-                    id: msg.id,
-                    opcode: msg.opcode,
-                    message_type: msg.message_type.clone(),
-                    direction: msg.direction.clone(),
-                    queue: msg.queue.as_ref().map(|q| format!("{:?}", q)),
-                    data_len: msg.data.len(),
-                    raw: hex::encode(&msg.data),
-                    sequence: msg.sequence,
-                    iteration: msg.iteration,
-                    header_flags: msg.header_flags,
-                };
-                println!("{}", serde_json::to_string(&raw_output).unwrap());
+                println!("{}", serde_json::to_string(&raw_output(msg)).unwrap());
             }
         }
         OutputFormat::Json => {
-            let raw_outputs: Vec<_> = messages
-                .iter()
-                .map(|msg| RawMessageOutput {
-                    id: msg.id,
-                    opcode: msg.opcode,
-                    message_type: msg.message_type.clone(),
-                    direction: msg.direction.clone(),
-                    queue: msg.queue.as_ref().map(|q| format!("{:?}", q)),
-                    data_len: msg.data.len(),
-                    raw: hex::encode(&msg.data),
-                    sequence: msg.sequence,
-                    iteration: msg.iteration,
-                    header_flags: msg.header_flags,
-                })
-                .collect();
+            let raw_outputs: Vec<_> = messages.iter().map(|msg| raw_output(msg)).collect();
             println!("{}", serde_json::to_string_pretty(&raw_outputs).unwrap());
         }
         OutputFormat::Table => {
             println!(
-                "{:>6}  {:40}  {:>6}  {:>10}  {:>6}  Raw Data",
-                "ID", "Type", "Dir", "OpCode", "Len"
+                "{:>6}  {}{:40}  {:>6}  {:>10}  {:>6}  Raw Data",
+                "ID",
+                time_header(start),
+                "Type",
+                "Dir",
+                "OpCode",
+                "Len"
             );
-            println!("{}", "-".repeat(140));
+            println!("{}", "-".repeat(140 + time_header(start).len()));
             for msg in messages {
                 let hex_data = hex::encode(&msg.data);
                 let truncated_hex = if hex_data.len() > 50 {
@@ -100,8 +146,9 @@ where
                     hex_data
                 };
                 println!(
-                    "{:>6}  {:40}  {:>6}  {:#06x}  {:>6}  {}",
+                    "{:>6}  {}{:40}  {:>6}  {:#06x}  {:>6}  {}",
                     msg.id,
+                    time_column(msg, start),
                     truncate(&msg.message_type, 40),
                     msg.direction,
                     msg.opcode,
@@ -114,13 +161,34 @@ where
 }
 
 /// Helper function to format and output messages in parsed format (JSON serialization)
-pub fn format_parsed_messages<'a, I>(messages: I, output: OutputFormat)
-where
+///
+/// When `start` is given, each message also shows its seconds since that time.
+/// With `fields`, or for CSV and TSV, only the selected fields are printed.
+pub fn format_parsed_messages<'a, I>(
+    messages: I,
+    output: OutputFormat,
+    start: Option<f64>,
+    fields: Option<&FieldSelection>,
+) where
     I: IntoIterator<Item = &'a RawMessage>,
 {
-    let messages: Vec<_> = messages.into_iter().collect();
+    let messages: Vec<_> = messages
+        .into_iter()
+        .map(|message| TimedMessage {
+            relative_time: relative_time(message, start),
+            message,
+        })
+        .collect();
+    if fields.is_some() || output.is_tabular() {
+        let values = messages
+            .iter()
+            .map(|msg| serde_json::to_value(msg).unwrap());
+        print_fields(values, output, fields);
+        return;
+    }
 
     match output {
+        OutputFormat::Csv | OutputFormat::Tsv => unreachable!("printed as fields"),
         OutputFormat::Jsonl => {
             for msg in messages {
                 println!("{}", serde_json::to_string(&msg).unwrap());
@@ -130,12 +198,20 @@ where
             println!("{}", serde_json::to_string_pretty(&messages).unwrap());
         }
         OutputFormat::Table => {
-            println!("{:>6}  {:40}  {:>6}  {:>10}", "ID", "Type", "Dir", "OpCode");
-            println!("{}", "-".repeat(70));
-            for msg in messages {
+            println!(
+                "{:>6}  {}{:40}  {:>6}  {:>10}",
+                "ID",
+                time_header(start),
+                "Type",
+                "Dir",
+                "OpCode"
+            );
+            println!("{}", "-".repeat(70 + time_header(start).len()));
+            for TimedMessage { message: msg, .. } in messages {
                 println!(
-                    "{:>6}  {:40}  {:>6}  {:#06x}",
+                    "{:>6}  {}{:40}  {:>6}  {:#06x}",
                     msg.id,
+                    time_column(msg, start),
                     truncate(&msg.message_type, 40),
                     msg.direction,
                     msg.opcode
@@ -144,3 +220,98 @@ where
         }
     }
 }
+
+/// Print the selected fields of messages serialized to JSON, using the
+/// default fields if none were selected
+fn print_fields(
+    messages: impl Iterator<Item = Value>,
+    output: OutputFormat,
+    fields: Option<&FieldSelection>,
+) {
+    let default = FieldSelection::default();
+    let fields = fields.unwrap_or(&default);
+    let result = write_fields(io::stdout().lock(), messages, output, fields);
+    // A closed pipe (e.g. `| head`) just ends the output
+    if let Err(e) = result
+        && e.kind() != io::ErrorKind::BrokenPipe
+    {
+        eprintln!("Error writing output: {e}");
+    }
+}
+
+fn write_fields(
+    mut out: impl Write,
+    messages: impl Iterator<Item = Value>,
+    output: OutputFormat,
+    fields: &FieldSelection,
+) -> io::Result<()> {
+    let headers = fields.headers();
+    match output {
+        OutputFormat::Jsonl => {
+            for object in messages.flat_map(|m| fields.objects(&m)) {
+                writeln!(out, "{object}")?;
+            }
+        }
+        OutputFormat::Json => {
+            let objects: Vec<_> = messages.flat_map(|m| fields.objects(&m)).collect();
+            writeln!(out, "{}", serde_json::to_string_pretty(&objects)?)?;
+        }
+        OutputFormat::Csv => {
+            write_row(&mut out, &headers)?;
+            for row in messages.flat_map(|m| fields.rows(&m)) {
+                let cells: Vec<_> = row.iter().map(cell_text).collect();
+                write_row(&mut out, &cells)?;
+            }
+        }
+        OutputFormat::Tsv => {
+            writeln!(out, "{}", headers.join("\t"))?;
+            for row in messages.flat_map(|m| fields.rows(&m)) {
+                let cells: Vec<_> = row
+                    .iter()
+                    .map(|value| cell_text(value).replace(['\t', '\n', '\r'], " "))
+                    .collect();
+                writeln!(out, "{}", cells.join("\t"))?;
+            }
+        }
+        OutputFormat::Table => {
+            let rows: Vec<Vec<String>> = messages
+                .flat_map(|m| fields.rows(&m))
+                .map(|row| row.iter().map(cell_text).collect())
+                .collect();
+            let widths: Vec<usize> = headers
+                .iter()
+                .enumerate()
+                .map(|(i, header)| {
+                    rows.iter()
+                        .map(|row| row[i].chars().count())
+                        .chain([header.len()])
+                        .max()
+                        .unwrap_or(0)
+                        .min(60)
+                })
+                .collect();
+            let line = |cells: Vec<String>| {
+                cells
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, &width)| {
+                        let cell: String = cell.chars().take(width).collect();
+                        format!("{cell:width$}")
+                    })
+                    .collect::<Vec<_>>()
+                    .join("  ")
+                    .trim_end()
+                    .to_string()
+            };
+            writeln!(
+                out,
+                "{}",
+                line(headers.iter().map(|h| h.to_string()).collect())
+            )?;
+            for row in rows {
+                writeln!(out, "{}", line(row))?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,9 +1,27 @@
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+
 use crate::cli::parse_opcode_filter;
-use crate::network::RawMessage;
+use crate::filter::{FieldSelection, MessageFilter, MessageRange, capture_start};
+use crate::network::anonymize::{AnonymizeOptions, anonymize};
+use crate::network::{Capture, FragmentAssembler, RawMessage, pcap};
 
 use super::output::{format_parsed_messages, format_raw_messages};
 use super::types::{DirectionFilter, OutputFormat, SortField};
 
+/// Load a PCAP file and assemble its packets into messages
+pub fn load_messages(path: &Path) -> Result<Vec<RawMessage>> {
+    let mut assembler = FragmentAssembler::new();
+    let mut messages = Vec::new();
+
+    for packet_result in pcap::open(path)? {
+        let packet = packet_result?;
+        messages.extend(assembler.parse_packet(&packet)?);
+    }
+
+    Ok(messages)
+}
+
 /// Filter, sort, and output messages based on provided criteria
 #[allow(clippy::too_many_arguments)]
 pub fn output_messages(
@@ -12,17 +30,24 @@ pub fn output_messages(
     filter_type: Option<&str>,
     filter_opcode: Option<&str>,
     direction: Option<DirectionFilter>,
+    filter: Option<&MessageFilter>,
+    range: &MessageRange,
     sort: SortField,
     reverse: bool,
     limit: Option<usize>,
     output: OutputFormat,
+    fields: Option<&FieldSelection>,
     raw: bool,
-) {
+    relative_time: bool,
+) -> Result<()> {
     // Parse opcode filter if provided
     let opcode_filter: Option<u32> = filter_opcode.and_then(|s| parse_opcode_filter(s).ok());
 
-    let mut filtered: Vec<&RawMessage> = messages
-        .iter()
+    let start = capture_start(messages).filter(|_| relative_time);
+
+    let mut filtered: Vec<&RawMessage> = range
+        .select(messages)?
+        .into_iter()
         .filter(|m| {
             if let Some(msg_id) = id
                 && m.id != msg_id
@@ -53,6 +78,11 @@ pub fn output_messages(
                     }
                 }
             }
+            if let Some(f) = filter
+                && !f.matches(m)
+            {
+                return false;
+            }
             true
         })
         .collect();
@@ -71,8 +101,243 @@ pub fn output_messages(
     }
 
     if raw {
-        format_raw_messages(filtered, output);
+        format_raw_messages(filtered, output, start, fields);
     } else {
-        format_parsed_messages(filtered, output);
+        format_parsed_messages(filtered, output, start, fields);
+    }
+
+    Ok(())
+}
+
+/// Write the frames carrying the selected messages to a new capture
+///
+/// Messages must pass the filter, fall within the range and, if given, belong
+/// to the session. Every frame carrying a fragment of a selected message is
+/// kept so the output still reassembles.
+pub fn slice_capture(
+    path: &Path,
+    out: &Path,
+    filter: Option<&MessageFilter>,
+    range: &MessageRange,
+    session: Option<u32>,
+) -> Result<()> {
+    let capture = Capture::load(path)?;
+    let sessions: std::collections::HashMap<u32, u32> = capture
+        .messages
+        .iter()
+        .enumerate()
+        .map(|(i, m)| (m.id, capture.message_session(i)))
+        .collect();
+
+    let selected: Vec<&RawMessage> = range
+        .select(&capture.messages)?
+        .into_iter()
+        .filter(|m| filter.is_none_or(|f| f.matches(m)))
+        .filter(|m| session.is_none_or(|s| sessions[&m.id] == s))
+        .collect();
+    let frames = capture.frames_for(selected.iter().copied());
+
+    capture
+        .save(out, &frames)
+        .with_context(|| format!("Failed to write {}", out.display()))?;
+    println!(
+        "Wrote {} of {} frames ({} messages) to {}",
+        frames.len(),
+        capture.packets.len(),
+        selected.len(),
+        out.display()
+    );
+
+    Ok(())
+}
+
+/// Split a capture into one file per session, per `minutes` of capture time,
+/// or per `minutes` within each session when `by_session` is also set
+///
+/// Files are named after the input, e.g. `capture-session-1.pcap`,
+/// `capture-part-001.pcap` or `capture-session-1-part-001.pcap`, and each
+/// still reassembles on its own.
+pub fn split_capture(
+    path: &Path,
+    out_dir: &Path,
+    by_session: bool,
+    minutes: Option<f64>,
+) -> Result<()> {
+    if minutes.is_some_and(|m| m <= 0.0 || !m.is_finite()) {
+        bail!("--minutes must be a positive number");
+    }
+
+    let capture = Capture::load(path)?;
+    let stem = path
+        .file_stem()
+        .map_or("capture".into(), |s| s.to_string_lossy());
+    std::fs::create_dir_all(out_dir)
+        .with_context(|| format!("Failed to create {}", out_dir.display()))?;
+
+    for (name, frames) in &split_parts(&capture, &stem, by_session, minutes) {
+        let out = out_dir.join(name);
+        capture
+            .save(&out, frames)
+            .with_context(|| format!("Failed to write {}", out.display()))?;
+        println!("Wrote {} frames to {}", frames.len(), out.display());
+    }
+
+    Ok(())
+}
+
+/// The file name and frames of each part of a split capture
+///
+/// Splitting by session is the default when no `minutes` are given. With both,
+/// each session's parts are counted from the start of that session.
+fn split_parts(
+    capture: &Capture,
+    stem: &str,
+    by_session: bool,
+    minutes: Option<f64>,
+) -> Vec<(String, Vec<usize>)> {
+    let by_session = by_session || minutes.is_none();
+    let session = |frame: usize| {
+        if by_session {
+            capture.session(frame)
+        } else {
+            0
+        }
+    };
+
+    let mut starts = std::collections::HashMap::new();
+    for (frame, packet) in capture.packets.iter().enumerate() {
+        starts.entry(session(frame)).or_insert(packet.timestamp());
+    }
+    let part = |frame: usize| {
+        minutes.map(|minutes| {
+            let start = starts[&session(frame)];
+            ((capture.packets[frame].timestamp() - start) / (minutes * 60.0)).max(0.0) as u64
+        })
+    };
+
+    capture
+        .partition(|frame| (session(frame), part(frame)))
+        .into_iter()
+        .map(|((session, part), frames)| {
+            let name = match (by_session, part) {
+                (true, Some(part)) => format!("{stem}-session-{session}-part-{:03}.pcap", part + 1),
+                (true, None) => format!("{stem}-session-{session}.pcap"),
+                (false, part) => format!("{stem}-part-{:03}.pcap", part.unwrap_or_default() + 1),
+            };
+            (name, frames)
+        })
+        .collect()
+}
+
+/// Write a copy of a capture with names, character ids and optionally chat
+/// and addresses replaced by pseudonyms
+pub fn anonymize_capture(path: &Path, out: &Path, options: AnonymizeOptions) -> Result<()> {
+    let capture = Capture::load(path)?;
+    let (packets, report) = anonymize(&capture, options)?;
+
+    let mut writer = pcap::create(out, &capture.header)
+        .with_context(|| format!("Failed to create {}", out.display()))?;
+    for packet in &packets {
+        writer.write_packet(packet)?;
+    }
+    writer.into_inner()?;
+
+    println!(
+        "Wrote {} frames ({} rewritten) to {}",
+        report.frames,
+        report.frames_rewritten,
+        out.display()
+    );
+    println!(
+        "Replaced {} names and {} character ids in {} messages and {} logins",
+        report.names, report.ids, report.messages_rewritten, report.logins_rewritten
+    );
+    if options.rewrite_ips {
+        println!("Replaced {} IP addresses", report.addresses);
+    }
+    let action = if options.keep_unredacted {
+        "left unredacted"
+    } else {
+        "zeroed"
+    };
+    for (messages, reason) in [
+        (&report.unparsed, "couldn't be parsed"),
+        (&report.unencodable, "couldn't be re-encoded"),
+    ] {
+        if messages.is_empty() {
+            continue;
+        }
+        eprintln!(
+            "Warning: {} messages {reason} and were {action}:",
+            messages.len()
+        );
+        for message in messages {
+            eprintln!("  #{} {}", message.id, message.message_type);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::capture::tests::{fragment, login};
+    use crate::network::pcap::{Packet, PcapHeader};
+
+    fn at(seconds: u32, mut packet: Packet) -> Packet {
+        packet.ts_sec = seconds;
+        packet
+    }
+
+    /// Two sessions, each with a message at its start and one 90 or 100 seconds in
+    fn capture() -> Capture {
+        let message = |sequence: u32| {
+            let mut data = 0xF747u32.to_le_bytes().to_vec();
+            data.extend(0x5000_0001u32.to_le_bytes());
+            data.extend([1, 0, 0, 0]);
+            fragment(sequence, 1, 0, &data)
+        };
+        let packets = vec![
+            at(0, login()),
+            at(0, message(1)),
+            at(90, message(2)),
+            at(100, login()),
+            at(100, message(3)),
+            at(200, message(4)),
+        ];
+        Capture::from_packets(PcapHeader::default(), packets).unwrap()
+    }
+
+    #[test]
+    fn test_split_by_session_minutes_or_both() {
+        let capture = capture();
+        assert_eq!(capture.messages.len(), 4);
+
+        assert_eq!(
+            split_parts(&capture, "c", false, None),
+            vec![
+                ("c-session-1.pcap".to_string(), vec![0, 1, 2]),
+                ("c-session-2.pcap".to_string(), vec![3, 4, 5]),
+            ]
+        );
+        assert_eq!(
+            split_parts(&capture, "c", false, Some(1.0)),
+            vec![
+                ("c-part-001.pcap".to_string(), vec![0, 1]),
+                ("c-part-002.pcap".to_string(), vec![2, 3, 4]),
+                ("c-part-004.pcap".to_string(), vec![5]),
+            ]
+        );
+        // Parts restart at each session
+        assert_eq!(
+            split_parts(&capture, "c", true, Some(1.0)),
+            vec![
+                ("c-session-1-part-001.pcap".to_string(), vec![0, 1]),
+                ("c-session-1-part-002.pcap".to_string(), vec![2]),
+                ("c-session-2-part-001.pcap".to_string(), vec![3, 4]),
+                ("c-session-2-part-002.pcap".to_string(), vec![5]),
+            ]
+        );
     }
 }
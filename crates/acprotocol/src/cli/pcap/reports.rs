@@ -0,0 +1,905 @@
+use anyhow::{Context, Result};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+
+use crate::analysis::allegiance::AllegianceTree;
+use crate::analysis::books::BookArchive;
+use crate::analysis::chat::ChatTranscript;
+use crate::analysis::chess::ChessLog;
+use crate::analysis::corpus::{Corpus, CorpusReport, FileSummary};
+use crate::analysis::crafting::CraftingLog;
+use crate::analysis::diff::{CaptureDiff, DiffEntry, FieldDiff, VOLATILE_FIELDS};
+use crate::analysis::enchantments::EnchantmentTimeline;
+use crate::analysis::fellowship::FellowshipTimeline;
+use crate::analysis::follow::ObjectHistory;
+use crate::analysis::housing::HousingReport;
+use crate::analysis::items::ItemDatabase;
+use crate::analysis::latency::{LatencyReport, PAIRINGS};
+use crate::analysis::magic::CastLog;
+use crate::analysis::quests::QuestLogs;
+use crate::analysis::social::SocialReport;
+use crate::analysis::stats::CaptureStats;
+use crate::analysis::trades::TradeLog;
+use crate::analysis::vendors::VendorDatabase;
+use crate::filter::capture_start;
+use crate::network::RawMessage;
+use crate::network::layout::{MessageLayout, PADDING};
+
+use super::output::truncate;
+use super::processing::load_messages;
+use super::types::{
+    ArchiveFormat, DatabaseFormat, DiffFormat, ExportFormat, GameFormat, HexdumpFormat,
+    SummaryFormat, TranscriptFormat, TreeFormat,
+};
+
+/// Print the enchantment timeline for a capture
+pub fn print_enchantments(messages: &[RawMessage], format: ExportFormat) -> Result<()> {
+    let timeline = EnchantmentTimeline::from_messages(messages);
+
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&timeline)?),
+        ExportFormat::Csv => timeline.write_csv(io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Print the fellowship membership and XP-share timeline for a capture
+pub fn print_fellowships(messages: &[RawMessage], format: ExportFormat) -> Result<()> {
+    let timeline = FellowshipTimeline::from_messages(messages);
+
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&timeline)?),
+        ExportFormat::Csv => timeline.write_csv(io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Print the allegiance tree reconstructed from a capture
+pub fn print_allegiance(messages: &[RawMessage], format: TreeFormat) -> Result<()> {
+    let tree = AllegianceTree::from_messages(messages);
+
+    match format {
+        TreeFormat::Json => println!("{}", serde_json::to_string_pretty(&tree)?),
+        TreeFormat::Dot => print!("{}", tree.to_dot()),
+    }
+
+    Ok(())
+}
+
+/// Print the chess games in a capture
+pub fn print_chess(messages: &[RawMessage], format: GameFormat) -> Result<()> {
+    let log = ChessLog::from_messages(messages);
+
+    match format {
+        GameFormat::Json => println!("{}", serde_json::to_string_pretty(&log)?),
+        GameFormat::Pgn => print!("{}", log.to_pgn()),
+    }
+
+    Ok(())
+}
+
+/// Print request/response pairs with server latency
+pub fn print_latency(messages: &[RawMessage], format: ExportFormat, timeout: f64) -> Result<()> {
+    let report = LatencyReport::with_pairings(messages, PAIRINGS, timeout);
+
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        ExportFormat::Csv => report.write_csv(io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Print every spell cast with fizzle rates and timings by spell and category
+pub fn print_casts(messages: &[RawMessage], format: ExportFormat) -> Result<()> {
+    let log = CastLog::from_messages(messages);
+
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&log)?),
+        ExportFormat::Csv => log.write_csv(io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Print salvage operations and tinkering attempts with success rates by material
+pub fn print_crafting(messages: &[RawMessage], format: ExportFormat) -> Result<()> {
+    let log = CraftingLog::from_messages(messages);
+
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&log)?),
+        ExportFormat::Csv => log.write_csv(io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Print each character's contract (quest) history
+pub fn print_quests(messages: &[RawMessage], format: ExportFormat) -> Result<()> {
+    let logs = QuestLogs::from_messages(messages);
+
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&logs)?),
+        ExportFormat::Csv => logs.write_csv(io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Print each character's titles, squelches and friends with the changes to them
+pub fn print_social(messages: &[RawMessage], format: ExportFormat) -> Result<()> {
+    let report = SocialReport::from_messages(messages);
+
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        ExportFormat::Csv => report.write_csv(io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Print the chat lines and error text a client would have shown for a capture
+pub fn print_chat(messages: &[RawMessage], format: TranscriptFormat) -> Result<()> {
+    let transcript = ChatTranscript::from_messages(messages);
+
+    match format {
+        TranscriptFormat::Text => print!("{}", transcript.to_text()),
+        TranscriptFormat::Json => println!("{}", serde_json::to_string_pretty(&transcript)?),
+    }
+
+    Ok(())
+}
+
+/// Print the trade sessions in a capture
+pub fn print_trades(messages: &[RawMessage], format: ExportFormat) -> Result<()> {
+    let log = TradeLog::from_messages(messages);
+
+    match format {
+        ExportFormat::Json => println!("{}", serde_json::to_string_pretty(&log)?),
+        ExportFormat::Csv => log.write_csv(io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Print the item and creature database built from one or more captures
+pub fn print_items(files: &[PathBuf], format: DatabaseFormat) -> Result<()> {
+    let mut database = ItemDatabase::new();
+    for file in files {
+        database.add_messages(&load_messages(file)?);
+    }
+
+    let mut stdout = io::stdout().lock();
+    match format {
+        DatabaseFormat::Jsonl => {
+            for item in database.items() {
+                writeln!(stdout, "{}", serde_json::to_string(item)?)?;
+            }
+        }
+        DatabaseFormat::Csv => database.write_csv(stdout)?,
+    }
+
+    Ok(())
+}
+
+/// Print the vendor catalogs collected from one or more captures
+pub fn print_vendors(files: &[PathBuf], format: DatabaseFormat) -> Result<()> {
+    let mut database = VendorDatabase::new();
+    for file in files {
+        database.add_messages(&load_messages(file)?);
+    }
+
+    let mut stdout = io::stdout().lock();
+    match format {
+        DatabaseFormat::Jsonl => {
+            for vendor in database.vendors() {
+                writeln!(stdout, "{}", serde_json::to_string(vendor)?)?;
+            }
+        }
+        DatabaseFormat::Csv => database.write_csv(stdout)?,
+    }
+
+    Ok(())
+}
+
+/// Print the books and inscriptions read or written in one or more captures
+pub fn print_books(files: &[PathBuf], format: ArchiveFormat) -> Result<()> {
+    let mut archive = BookArchive::new();
+    for file in files {
+        archive.add_messages(&load_messages(file)?);
+    }
+
+    match format {
+        ArchiveFormat::Markdown => print!("{}", archive.to_markdown()),
+        ArchiveFormat::Json => println!("{}", serde_json::to_string_pretty(&archive)?),
+    }
+
+    Ok(())
+}
+
+/// Print the houses seen in one or more captures, or the houses listed for sale
+pub fn print_housing(files: &[PathBuf], format: ExportFormat, available: bool) -> Result<()> {
+    let mut report = HousingReport::new();
+    for file in files {
+        report.add_messages(&load_messages(file)?);
+    }
+
+    match (format, available) {
+        (ExportFormat::Json, false) => println!("{}", serde_json::to_string_pretty(&report)?),
+        (ExportFormat::Json, true) => {
+            println!("{}", serde_json::to_string_pretty(report.available())?)
+        }
+        (ExportFormat::Csv, false) => report.write_csv(io::stdout().lock())?,
+        (ExportFormat::Csv, true) => report.write_available_csv(io::stdout().lock())?,
+    }
+
+    Ok(())
+}
+
+/// Print message counts, rates and parse coverage for a capture
+pub fn print_stats(
+    messages: &[RawMessage],
+    format: SummaryFormat,
+    bucket: f64,
+    top: usize,
+) -> Result<()> {
+    let stats = CaptureStats::with_options(messages, bucket, top);
+
+    match format {
+        SummaryFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        SummaryFormat::Table => write_stats_table(io::stdout().lock(), &stats)?,
+    }
+
+    Ok(())
+}
+
+fn write_stats_table(mut out: impl Write, stats: &CaptureStats) -> io::Result<()> {
+    writeln!(out, "=== Capture ===\n")?;
+    writeln!(out, "Messages: {}", stats.messages)?;
+    writeln!(out, "Bytes:    {}", stats.bytes)?;
+    if let Some(duration) = stats.duration {
+        writeln!(out, "Duration: {duration:.1}s")?;
+    }
+    let parse = &stats.parse;
+    writeln!(
+        out,
+        "Parsed:   {} ok, {} failed, {} with trailing bytes, {} unknown ({:.1}% ok)",
+        parse.ok,
+        parse.failed,
+        parse.trailing,
+        parse.unknown,
+        parse.ok_rate() * 100.0
+    )?;
+
+    for (title, groups) in [
+        ("By Direction", &stats.directions),
+        ("By Queue", &stats.queues),
+    ] {
+        writeln!(out, "\n=== {title} ===\n")?;
+        writeln!(out, "  {:30} {:>8} {:>12}", "Name", "Count", "Bytes")?;
+        for group in groups {
+            writeln!(
+                out,
+                "  {:30} {:>8} {:>12}",
+                group.name, group.count, group.bytes
+            )?;
+        }
+    }
+
+    writeln!(out, "\n=== By Message Type ===\n")?;
+    writeln!(
+        out,
+        "  {:>6} {:>10} {:40} {:>4} {:>7} {:>10} {:>7} {:>6} {:>6} {:>8} {:>7}",
+        "OpCode",
+        "Event",
+        "Type",
+        "Dir",
+        "Count",
+        "Bytes",
+        "Largest",
+        "OK",
+        "Failed",
+        "Trailing",
+        "Unknown"
+    )?;
+    for t in &stats.types {
+        let inner = t
+            .inner_opcode
+            .map(|op| format!("{op:#06x}"))
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "  {:#06x} {:>10} {:40} {:>4} {:>7} {:>10} {:>7} {:>6} {:>6} {:>8} {:>7}",
+            t.opcode,
+            inner,
+            truncate(&t.message_type, 40),
+            t.direction,
+            t.count,
+            t.bytes,
+            t.largest,
+            t.parse.ok,
+            t.parse.failed,
+            t.parse.trailing,
+            t.parse.unknown
+        )?;
+    }
+
+    let problems: Vec<_> = stats.problems().collect();
+    if !problems.is_empty() {
+        writeln!(out, "\n=== Parse Problems ===\n")?;
+        for t in problems {
+            writeln!(
+                out,
+                "  {} ({:#06x}): {} failed, {} trailing ({} bytes), {} unknown of {}",
+                t.message_type,
+                t.inner_opcode.unwrap_or(t.opcode),
+                t.parse.failed,
+                t.parse.trailing,
+                t.trailing_bytes,
+                t.parse.unknown,
+                t.count
+            )?;
+            for error in &t.errors {
+                writeln!(out, "    {:>5}x {}", error.count, error.error)?;
+            }
+        }
+    }
+
+    if !stats.timeline.is_empty() {
+        writeln!(
+            out,
+            "\n=== Messages per Second ({}s buckets) ===\n",
+            stats.bucket_seconds
+        )?;
+        let peak = stats
+            .timeline
+            .iter()
+            .map(|b| b.count)
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        for bucket in &stats.timeline {
+            let bar = "#".repeat(bucket.count * 40 / peak);
+            writeln!(
+                out,
+                "  {:>8.1}s {:>8.1}/s {:>8} B  {bar}",
+                bucket.start, bucket.per_second, bucket.bytes
+            )?;
+        }
+    }
+
+    writeln!(out, "\n=== Largest Messages ===\n")?;
+    for m in &stats.largest {
+        writeln!(
+            out,
+            "  {:>6}  {:40} {:>4} {:>8}",
+            m.id,
+            truncate(&m.message_type, 40),
+            m.direction,
+            m.size
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Find every .pcap file under a directory, in sorted order
+fn find_captures(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_captures(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pcap"))
+        {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(())
+}
+
+/// Print parse coverage for every capture under a directory, parsing them in parallel
+pub fn print_corpus(dir: &Path, format: SummaryFormat, jobs: Option<usize>) -> Result<()> {
+    let mut files = Vec::new();
+    find_captures(dir, &mut files)?;
+
+    let jobs = jobs
+        .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .clamp(1, files.len().max(1));
+    let next = AtomicUsize::new(0);
+    let mut summaries: Vec<Option<Result<FileSummary, String>>> = vec![None; files.len()];
+
+    let (sender, receiver) = mpsc::channel();
+    std::thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (files, next) = (&files, &next);
+            scope.spawn(move || {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(path) = files.get(index) else {
+                        break;
+                    };
+                    let name = path.strip_prefix(dir).unwrap_or(path).display().to_string();
+                    let summary = load_messages(path)
+                        .map(|messages| FileSummary::from_messages(&name, &messages))
+                        .map_err(|e| e.to_string());
+                    if sender.send((index, summary)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for (index, summary) in receiver {
+            summaries[index] = Some(summary);
+        }
+    });
+
+    // Add in path order so examples don't depend on which thread finished first
+    let mut corpus = Corpus::new();
+    for (path, summary) in files.iter().zip(summaries) {
+        match summary {
+            Some(Ok(summary)) => corpus.add(summary),
+            Some(Err(error)) => {
+                let name = path.strip_prefix(dir).unwrap_or(path).display().to_string();
+                corpus.add_error(&name, error);
+            }
+            None => {}
+        }
+    }
+    let report = corpus.report();
+
+    match format {
+        SummaryFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        SummaryFormat::Table => write_corpus_table(io::stdout().lock(), &report)?,
+    }
+
+    Ok(())
+}
+
+fn write_corpus_table(mut out: impl Write, report: &CorpusReport) -> io::Result<()> {
+    writeln!(out, "=== Corpus ===\n")?;
+    writeln!(
+        out,
+        "Files:    {} ({} unreadable)",
+        report.files,
+        report.unreadable.len()
+    )?;
+    writeln!(out, "Messages: {}", report.messages)?;
+    let parse = &report.parse;
+    writeln!(
+        out,
+        "Parsed:   {} ok, {} failed, {} with trailing bytes, {} unknown ({:.1}% ok)",
+        parse.ok,
+        parse.failed,
+        parse.trailing,
+        parse.unknown,
+        parse.ok_rate() * 100.0
+    )?;
+
+    writeln!(out, "\n=== Coverage ===\n")?;
+    for c in &report.coverage {
+        writeln!(
+            out,
+            "  {:12} {:>4}/{:<4} seen ({:5.1}%), {:>4} parsed",
+            c.name,
+            c.seen,
+            c.total,
+            c.coverage * 100.0,
+            c.parsed
+        )?;
+    }
+
+    let problems: Vec<_> = report
+        .types
+        .iter()
+        .filter(|t| t.parse.failed + t.parse.trailing > 0)
+        .collect();
+    if !problems.is_empty() {
+        writeln!(out, "\n=== Parse Problems ===\n")?;
+        for t in problems {
+            writeln!(
+                out,
+                "  {} ({:#06x}): {} failed ({:.1}%), {} trailing of {} in {} files",
+                t.message_type,
+                t.inner_opcode.unwrap_or(t.opcode),
+                t.parse.failed,
+                t.failure_rate * 100.0,
+                t.parse.trailing,
+                t.count,
+                t.files
+            )?;
+            for example in [&t.example_failure, &t.example_trailing]
+                .into_iter()
+                .flatten()
+            {
+                writeln!(
+                    out,
+                    "    e.g. {} #{}: {}",
+                    example.file, example.id, example.detail
+                )?;
+            }
+        }
+    }
+
+    if !report.unknown.is_empty() {
+        writeln!(out, "\n=== Unknown ===\n")?;
+        for t in &report.unknown {
+            let code = match t.inner_opcode {
+                Some(inner) => format!("{:#06x}/{inner:#06x}", t.opcode),
+                None => format!("{:#06x}", t.opcode),
+            };
+            write!(out, "  {code:15} {:>7} in {} files", t.count, t.files)?;
+            match &t.example {
+                Some(example) => writeln!(out, ", e.g. {} #{}", example.file, example.id)?,
+                None => writeln!(out)?,
+            }
+        }
+    }
+
+    if !report.unreadable.is_empty() {
+        writeln!(out, "\n=== Unreadable ===\n")?;
+        for f in &report.unreadable {
+            writeln!(out, "  {}: {}", f.file, f.error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the differences between two captures
+pub fn print_diff(
+    a: &Path,
+    b: &Path,
+    format: DiffFormat,
+    mut ignore: Vec<String>,
+    ignore_volatile: bool,
+    color: bool,
+) -> Result<()> {
+    if ignore_volatile {
+        ignore.extend(VOLATILE_FIELDS.iter().map(|glob| glob.to_string()));
+    }
+    let diff = CaptureDiff::new(&load_messages(a)?, &load_messages(b)?, &ignore);
+
+    match format {
+        DiffFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+        DiffFormat::Text => write_diff_text(io::stdout().lock(), &diff, color)?,
+    }
+
+    Ok(())
+}
+
+fn write_diff_text(mut out: impl Write, diff: &CaptureDiff, color: bool) -> io::Result<()> {
+    let paint = |code: &str, text: String| {
+        if color {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text
+        }
+    };
+    let write_fields = |out: &mut dyn Write, fields: &[FieldDiff]| -> io::Result<()> {
+        for field in fields {
+            writeln!(
+                out,
+                "      {}: {} → {}",
+                field.path,
+                paint("31", field.a.to_string()),
+                paint("32", field.b.to_string())
+            )?;
+        }
+        Ok(())
+    };
+
+    for entry in &diff.entries {
+        match entry {
+            DiffEntry::Changed {
+                a,
+                b,
+                message_type,
+                fields,
+            } => {
+                writeln!(
+                    out,
+                    "{}",
+                    paint("33", format!("~ #{a} ↔ #{b} {message_type}"))
+                )?;
+                write_fields(&mut out, fields)?;
+            }
+            DiffEntry::Missing { a, message_type } => {
+                writeln!(out, "{}", paint("31", format!("- #{a} {message_type}")))?;
+            }
+            DiffEntry::Inserted { b, message_type } => {
+                writeln!(out, "{}", paint("32", format!("+ #{b} {message_type}")))?;
+            }
+            DiffEntry::Moved {
+                a,
+                b,
+                message_type,
+                fields,
+            } => {
+                writeln!(
+                    out,
+                    "{}",
+                    paint("36", format!("> #{a} → #{b} {message_type} (moved)"))
+                )?;
+                write_fields(&mut out, fields)?;
+            }
+        }
+    }
+
+    let s = &diff.summary;
+    writeln!(
+        out,
+        "\n{} vs {} messages: {} equal, {} changed, {} missing, {} inserted, {} moved",
+        diff.a_messages, diff.b_messages, s.equal, s.changed, s.missing, s.inserted, s.moved
+    )?;
+
+    Ok(())
+}
+
+/// Print every message referring to an object, with the part it played
+pub fn print_follow(messages: &[RawMessage], object_id: u32, format: SummaryFormat) -> Result<()> {
+    let history = ObjectHistory::from_messages(messages, object_id);
+
+    match format {
+        SummaryFormat::Json => println!("{}", serde_json::to_string_pretty(&history)?),
+        SummaryFormat::Table => {
+            let start = capture_start(messages).unwrap_or_default();
+            write_follow_table(io::stdout().lock(), &history, start)?
+        }
+    }
+
+    Ok(())
+}
+
+fn write_follow_table(mut out: impl Write, history: &ObjectHistory, start: f64) -> io::Result<()> {
+    let name = history
+        .name
+        .as_ref()
+        .map_or_else(String::new, |name| format!(" \"{name}\""));
+    writeln!(
+        out,
+        "Object {:#010x}{name}: {} messages\n",
+        history.object_id,
+        history.mentions.len()
+    )?;
+    writeln!(
+        out,
+        "  {:>10} {:>6}  {:<4}  {:<40} {:<20} Fields",
+        "Time", "ID", "Dir", "Message", "Roles"
+    )?;
+    for mention in &history.mentions {
+        let time = mention
+            .time
+            .map_or_else(String::new, |t| format!("+{:.3}s", t - start));
+        let roles = mention
+            .roles
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            out,
+            "  {:>10} {:>6}  {:<4}  {:<40} {:<20} {}",
+            time,
+            mention.id,
+            mention.direction,
+            truncate(&mention.message_type, 40),
+            roles,
+            mention.fields.join(", ")
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Bytes shown on each line of a hex dump
+const HEXDUMP_WIDTH: usize = 16;
+
+/// Longest value shown in a hex dump before it is cut short
+const HEXDUMP_VALUE_WIDTH: usize = 60;
+
+/// Print a message's bytes next to the fields they were read into
+pub fn print_hexdump(
+    messages: &[RawMessage],
+    id: u32,
+    format: HexdumpFormat,
+    color: bool,
+) -> Result<()> {
+    let message = messages
+        .iter()
+        .find(|m| m.id == id)
+        .with_context(|| format!("No message with ID {id}"))?;
+    let layout = MessageLayout::of(message);
+
+    match format {
+        HexdumpFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&serde_json::json!({
+                "id": message.id,
+                "message_type": message.message_type,
+                "direction": message.direction,
+                "layout": layout,
+            }))?
+        ),
+        HexdumpFormat::Text => write_hexdump_text(io::stdout().lock(), message, &layout, color)?,
+    }
+
+    Ok(())
+}
+
+fn write_hexdump_text(
+    mut out: impl Write,
+    message: &RawMessage,
+    layout: &MessageLayout,
+    color: bool,
+) -> io::Result<()> {
+    let paint = |code: &str, text: String| {
+        if color && !code.is_empty() {
+            format!("\x1b[{code}m{text}\x1b[0m")
+        } else {
+            text
+        }
+    };
+    let data = &message.data;
+    let failure = layout.failure.as_ref();
+
+    writeln!(
+        out,
+        "#{} {} ({}) {}, {} bytes, {} parsed",
+        message.id,
+        message.message_type,
+        message.opcode_hex(),
+        message.direction,
+        layout.len,
+        layout.consumed.min(layout.len)
+    )?;
+    writeln!(
+        out,
+        "{:<6}  {:>5}  {:<w$}  Field / Value",
+        "Offset",
+        "Len",
+        "Bytes",
+        w = HEXDUMP_WIDTH * 3 - 1
+    )?;
+
+    let row = |out: &mut dyn Write,
+               offset: usize,
+               len: usize,
+               bytes: bool,
+               label: String,
+               code: &str|
+     -> io::Result<()> {
+        let end = (offset + len).min(data.len());
+        let shown = if bytes {
+            &data[offset.min(end)..end]
+        } else {
+            &[][..]
+        };
+        let mut lines = shown.chunks(HEXDUMP_WIDTH);
+        let hex = |chunk: Option<&[u8]>| {
+            chunk.map_or_else(String::new, |chunk| {
+                chunk
+                    .iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+        };
+        writeln!(
+            out,
+            "{}",
+            paint(
+                code,
+                format!(
+                    "{offset:06x}  {len:>5}  {:<w$}  {label}",
+                    hex(lines.next()),
+                    w = HEXDUMP_WIDTH * 3 - 1
+                )
+            )
+        )?;
+        for chunk in lines {
+            writeln!(
+                out,
+                "{}",
+                paint(code, format!("{:6}  {:5}  {}", "", "", hex(Some(chunk))))
+            )?;
+        }
+        Ok(())
+    };
+
+    let mut gaps = layout.gaps().into_iter().peekable();
+    for (i, field) in layout.fields.iter().enumerate() {
+        let leaf = !layout.has_children(i);
+        if leaf {
+            while let Some(gap) = gaps.next_if(|gap| gap.start <= field.offset) {
+                row(
+                    &mut out,
+                    gap.start,
+                    gap.len(),
+                    true,
+                    "(unlabeled)".to_string(),
+                    "33",
+                )?;
+            }
+        }
+
+        let name = field.path.rsplit('.').next().unwrap_or(&field.path);
+        let mut label = format!("{:indent$}{name}", "", indent = field.depth * 2);
+        if let Some(type_name) = &field.type_name {
+            label.push_str(&format!(": {type_name}"));
+        }
+        if let Some(value) = &field.value.as_ref().filter(|_| leaf) {
+            let value = if value.chars().count() > HEXDUMP_VALUE_WIDTH {
+                let cut: String = value.chars().take(HEXDUMP_VALUE_WIDTH - 3).collect();
+                format!("{cut}...")
+            } else {
+                value.to_string()
+            };
+            label.push_str(&format!(" = {value}"));
+        }
+
+        let failed = failure.is_some_and(|f| {
+            f.offset == field.offset && f.path.as_deref() == Some(field.path.as_str())
+        });
+        let code = if failed {
+            "31"
+        } else if name == PADDING {
+            "2"
+        } else {
+            ""
+        };
+        row(&mut out, field.offset, field.len, leaf, label, code)?;
+    }
+    for gap in gaps {
+        row(
+            &mut out,
+            gap.start,
+            gap.len(),
+            true,
+            "(unlabeled)".to_string(),
+            "33",
+        )?;
+    }
+
+    let unparsed = layout.unparsed();
+    if !unparsed.is_empty() {
+        row(
+            &mut out,
+            unparsed.start,
+            unparsed.len(),
+            true,
+            "(unparsed)".to_string(),
+            "31",
+        )?;
+    }
+    if let Some(failure) = failure {
+        writeln!(
+            out,
+            "{}",
+            paint(
+                "31",
+                format!(
+                    "Parse failed at offset {:#06x}{}: {}",
+                    failure.offset,
+                    failure
+                        .path
+                        .as_ref()
+                        .map_or_else(String::new, |path| format!(" in {path}")),
+                    failure.error
+                )
+            )
+        )?;
+    }
+
+    Ok(())
+}
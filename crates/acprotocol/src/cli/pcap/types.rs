@@ -17,6 +17,10 @@ pub struct RawMessageOutput {
     pub iteration: Option<u16>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub header_flags: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub relative_time: Option<f64>,
 }
 
 #[derive(Clone, Copy, ValueEnum)]
@@ -37,4 +41,76 @@ pub enum OutputFormat {
     Jsonl,
     Json,
     Table,
+    Csv,
+    Tsv,
+}
+
+impl OutputFormat {
+    /// Whether the format has a fixed set of columns
+    pub fn is_tabular(self) -> bool {
+        matches!(self, Self::Csv | Self::Tsv)
+    }
+}
+
+/// Output format for analysis reports
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+/// Output format for summary reports
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SummaryFormat {
+    Table,
+    Json,
+}
+
+/// Output format for capture diffs
+#[derive(Clone, Copy, ValueEnum)]
+pub enum DiffFormat {
+    Text,
+    Json,
+}
+
+/// Output format for annotated hex dumps
+#[derive(Clone, Copy, ValueEnum)]
+pub enum HexdumpFormat {
+    Text,
+    Json,
+}
+
+/// Output format for hierarchy reports
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TreeFormat {
+    Json,
+    Dot,
+}
+
+/// Output format for game records
+#[derive(Clone, Copy, ValueEnum)]
+pub enum GameFormat {
+    Json,
+    Pgn,
+}
+
+/// Output format for text archives
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ArchiveFormat {
+    Markdown,
+    Json,
+}
+
+/// Output format for chat transcripts
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TranscriptFormat {
+    Text,
+    Json,
+}
+
+/// Output format for databases built from one or more captures
+#[derive(Clone, Copy, ValueEnum)]
+pub enum DatabaseFormat {
+    Jsonl,
+    Csv,
 }
@@ -15,18 +15,20 @@ use ratatui::{
     layout::{Constraint, Direction, Layout},
     prelude::Stylize,
     style::{Color, Modifier, Style},
-    text::Span,
+    text::{Line, Span},
     widgets::{Block, Borders, Paragraph, Row, Table},
 };
+use regex::Regex;
 use serde_json::Value;
 use std::io;
 
+use crate::filter::MessageFilter;
 use crate::network::{FragmentAssembler, RawMessage};
 
 // Border height in terminal UI (top and bottom borders)
 const BORDER_HEIGHT: usize = 2;
 
-pub fn run(path: &Path) -> Result<()> {
+pub fn run(path: &Path, filter: Option<MessageFilter>) -> Result<()> {
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -37,11 +39,9 @@ pub fn run(path: &Path) -> Result<()> {
     // Load pcap data
     let packets = load_packets(path)?;
 
-    // Create app state
+    // Create app state, with the initial filter in the filter bar
     let mut app = App::new(packets);
-
-    // Apply initial sort by Id ascending
-    app.apply_sort();
+    app.set_filter(filter);
 
     // Run the TUI
     let res = run_app(&mut terminal, &mut app);
@@ -72,11 +72,14 @@ struct PacketInfo {
     opcode: String,
     sequence: u32,
     raw_json: String,
+    value: Value,
 }
 
 struct App {
     packets: Vec<PacketInfo>,
-    selected: usize,
+    /// Indices into `packets` of the messages passing the filter, in sort order
+    rows: Vec<usize>,
+    selected: usize, // Index into `rows`
     scroll_offset: usize,
     tree_expanded: std::collections::HashSet<String>,
     tree_scroll_offset: usize,
@@ -87,6 +90,60 @@ struct App {
     selected_column: usize, // Index of the column header being selected (0-7)
     column_rects: Vec<(u16, u16, SortColumn)>, // (start, end, column)
     list_pane_right: u16,   // Right boundary of list pane
+    filter: Option<MessageFilter>,
+    search: Option<Search>,
+    prompt: Option<Prompt>,
+}
+
+/// A search over message types and decoded JSON
+struct Search {
+    query: String,
+    regex: Option<Regex>,
+    /// Whether each packet matches, by index into `App::packets`
+    hits: Vec<bool>,
+}
+
+impl Search {
+    /// A case-insensitive substring search, or a regular expression search
+    fn new(query: &str, regex: bool, packets: &[PacketInfo]) -> Result<Self> {
+        let regex = if regex {
+            Some(Regex::new(query)?)
+        } else {
+            None
+        };
+        let needle = query.to_lowercase();
+        let hits = packets
+            .iter()
+            .map(|packet| match &regex {
+                Some(regex) => {
+                    regex.is_match(&packet.packet_type) || regex.is_match(&packet.raw_json)
+                }
+                None => {
+                    packet.packet_type.to_lowercase().contains(&needle)
+                        || packet.raw_json.to_lowercase().contains(&needle)
+                }
+            })
+            .collect();
+        Ok(Search {
+            query: query.to_string(),
+            regex,
+            hits,
+        })
+    }
+}
+
+/// Text being typed into the search prompt or the filter bar
+enum Prompt {
+    Search {
+        input: String,
+        regex: bool,
+        error: Option<String>,
+    },
+    Filter {
+        input: String,
+        /// How many messages the input matches, or why it doesn't parse
+        preview: std::result::Result<usize, String>,
+    },
 }
 
 enum FocusedPane {
@@ -121,16 +178,197 @@ impl App {
             selected_column: 0,
             column_rects: Vec::new(),
             list_pane_right: 0,
+            filter: None,
+            search: None,
+            prompt: None,
+            rows: Vec::new(),
+        }
+    }
+
+    /// The selected message
+    fn current(&self) -> Option<&PacketInfo> {
+        self.rows
+            .get(self.selected)
+            .map(|&index| &self.packets[index])
+    }
+
+    /// Show only the messages matching a filter, keeping the selected message
+    /// selected if it still passes
+    fn set_filter(&mut self, filter: Option<MessageFilter>) {
+        let previous = self.rows.get(self.selected).copied();
+        self.rows = (0..self.packets.len())
+            .filter(|&index| {
+                filter
+                    .as_ref()
+                    .is_none_or(|f| f.matches_value(&self.packets[index].value))
+            })
+            .collect();
+        self.filter = filter;
+        self.apply_sort();
+
+        self.selected = previous
+            .and_then(|index| self.rows.iter().position(|&row| row == index))
+            .unwrap_or(0);
+        self.scroll_offset = 0;
+        self.update_scroll(15);
+        self.reset_tree_state();
+    }
+
+    /// How many messages a filter expression matches
+    fn count_matching(&self, input: &str) -> std::result::Result<usize, String> {
+        if input.trim().is_empty() {
+            return Ok(self.packets.len());
+        }
+        let filter = MessageFilter::parse(input).map_err(|e| e.to_string())?;
+        Ok(self
+            .packets
+            .iter()
+            .filter(|packet| filter.matches_value(&packet.value))
+            .count())
+    }
+
+    /// Select the next shown message matching the search, wrapping around
+    fn next_match(&mut self, forward: bool, visible_rows: usize) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let len = self.rows.len();
+        let found = (1..=len)
+            .map(|step| {
+                if forward {
+                    (self.selected + step) % len
+                } else {
+                    (self.selected + len - step % len) % len
+                }
+            })
+            .find(|&row| search.hits[self.rows[row]]);
+        if let Some(row) = found {
+            self.selected = row;
+            self.update_scroll(visible_rows);
+            self.reset_tree_state();
+        }
+    }
+
+    /// The position of the selected message among the shown matches, and how
+    /// many matches are shown
+    fn match_position(&self) -> Option<(Option<usize>, usize)> {
+        let search = self.search.as_ref()?;
+        let mut position = None;
+        let mut total = 0;
+        for (row, &index) in self.rows.iter().enumerate() {
+            if search.hits[index] {
+                total += 1;
+                if row == self.selected {
+                    position = Some(total);
+                }
+            }
+        }
+        Some((position, total))
+    }
+
+    /// Handle a key typed into the open prompt
+    fn prompt_key(&mut self, code: KeyCode, visible_rows: usize) {
+        let Some(prompt) = self.prompt.as_mut() else {
+            return;
+        };
+        match code {
+            KeyCode::Esc => self.prompt = None,
+            KeyCode::Enter => match self.prompt.take() {
+                Some(Prompt::Search { input, regex, .. }) => {
+                    if input.is_empty() {
+                        self.search = None;
+                        return;
+                    }
+                    match Search::new(&input, regex, &self.packets) {
+                        Ok(search) => {
+                            let selected_matches =
+                                self.current().is_some() && search.hits[self.rows[self.selected]];
+                            self.search = Some(search);
+                            if !selected_matches {
+                                self.next_match(true, visible_rows);
+                            }
+                        }
+                        Err(e) => {
+                            self.prompt = Some(Prompt::Search {
+                                input,
+                                regex,
+                                error: Some(e.to_string()),
+                            });
+                        }
+                    }
+                }
+                Some(Prompt::Filter { input, preview }) => {
+                    if input.trim().is_empty() {
+                        self.set_filter(None);
+                    } else if let Ok(filter) = MessageFilter::parse(&input) {
+                        self.set_filter(Some(filter));
+                    } else {
+                        // Keep editing until the expression parses
+                        self.prompt = Some(Prompt::Filter { input, preview });
+                    }
+                }
+                None => {}
+            },
+            KeyCode::Tab => {
+                if let Prompt::Search { regex, error, .. } = prompt {
+                    *regex = !*regex;
+                    *error = None;
+                }
+            }
+            KeyCode::Backspace | KeyCode::Char(_) => {
+                let input = match prompt {
+                    Prompt::Search { input, error, .. } => {
+                        *error = None;
+                        input
+                    }
+                    Prompt::Filter { input, .. } => input,
+                };
+                match code {
+                    KeyCode::Char(c) => input.push(c),
+                    _ => {
+                        input.pop();
+                    }
+                }
+                if let Some(Prompt::Filter { input, .. }) = &self.prompt {
+                    let count = self.count_matching(input);
+                    if let Some(Prompt::Filter { preview, .. }) = self.prompt.as_mut() {
+                        *preview = count;
+                    }
+                }
+            }
+            _ => {}
         }
     }
 
+    fn open_search(&mut self) {
+        let (input, regex) = match &self.search {
+            Some(search) => (search.query.clone(), search.regex.is_some()),
+            None => (String::new(), false),
+        };
+        self.prompt = Some(Prompt::Search {
+            input,
+            regex,
+            error: None,
+        });
+    }
+
+    fn open_filter(&mut self) {
+        let input = self
+            .filter
+            .as_ref()
+            .map(|f| f.source().to_string())
+            .unwrap_or_default();
+        let preview = self.count_matching(&input);
+        self.prompt = Some(Prompt::Filter { input, preview });
+    }
+
     fn reset_tree_state(&mut self) {
         self.tree_scroll_offset = 0;
         self.tree_focused_line = 0;
     }
 
     fn next(&mut self, visible_rows: usize) {
-        if !self.packets.is_empty() && self.selected < self.packets.len() - 1 {
+        if !self.rows.is_empty() && self.selected < self.rows.len() - 1 {
             self.selected += 1;
             self.update_scroll(visible_rows);
             self.reset_tree_state();
@@ -138,7 +376,7 @@ impl App {
     }
 
     fn prev(&mut self, visible_rows: usize) {
-        if !self.packets.is_empty() && self.selected > 0 {
+        if !self.rows.is_empty() && self.selected > 0 {
             self.selected -= 1;
             self.update_scroll(visible_rows);
             self.reset_tree_state();
@@ -146,15 +384,15 @@ impl App {
     }
 
     fn page_down(&mut self, visible_rows: usize) {
-        if !self.packets.is_empty() {
-            self.selected = (self.selected + visible_rows).min(self.packets.len() - 1);
+        if !self.rows.is_empty() {
+            self.selected = (self.selected + visible_rows).min(self.rows.len() - 1);
             self.update_scroll(visible_rows);
             self.reset_tree_state();
         }
     }
 
     fn page_up(&mut self, visible_rows: usize) {
-        if !self.packets.is_empty() {
+        if !self.rows.is_empty() {
             self.selected = self.selected.saturating_sub(visible_rows);
             self.update_scroll(visible_rows);
             self.reset_tree_state();
@@ -202,7 +440,9 @@ impl App {
     }
 
     fn apply_sort(&mut self) {
-        self.packets.sort_by(|a, b| {
+        let packets = &self.packets;
+        self.rows.sort_by(|&a, &b| {
+            let (a, b) = (&packets[a], &packets[b]);
             let cmp = match self.sort_column {
                 SortColumn::Id => a.id.cmp(&b.id),
                 SortColumn::Direction => a.direction.cmp(&b.direction),
@@ -264,14 +504,9 @@ impl App {
 fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App) -> io::Result<()> {
     loop {
         // Get detail lines before drawing (for Enter key handling)
-        let detail_lines = if !app.packets.is_empty() {
-            let packet = &app.packets[app.selected];
-            match serde_json::from_str::<Value>(&packet.raw_json) {
-                Ok(json_val) => tree_display_lines(&json_val, &app.tree_expanded),
-                Err(_) => vec![],
-            }
-        } else {
-            vec![]
+        let detail_lines = match app.current() {
+            Some(packet) => tree_display_lines(&packet.value, &app.tree_expanded),
+            None => vec![],
         };
 
         terminal.draw(|f| ui(f, app))?;
@@ -281,6 +516,10 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App)
                 Event::Key(key) => {
                     // Estimate visible rows (roughly 15-20 depending on screen)
                     let visible_rows = 15;
+                    if app.prompt.is_some() {
+                        app.prompt_key(key.code, visible_rows);
+                        continue;
+                    }
                     match key.code {
                         KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
                         KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
@@ -315,11 +554,9 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App)
                             app.tree_scroll_offset = 0;
                             app.tree_focused_line = 0;
                         }
-                        KeyCode::End => {
-                            if !app.packets.is_empty() {
-                                app.selected = app.packets.len() - 1;
-                                app.update_scroll(visible_rows);
-                            }
+                        KeyCode::End if !app.rows.is_empty() => {
+                            app.selected = app.rows.len() - 1;
+                            app.update_scroll(visible_rows);
                         }
                         KeyCode::Enter => {
                             // Only expand/collapse in details pane
@@ -364,20 +601,19 @@ fn run_app(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App)
                         KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                             // Ctrl+a: expand all nodes
                             if matches!(app.focused_pane, FocusedPane::Details)
-                                && !app.packets.is_empty()
+                                && let Some(&index) = app.rows.get(app.selected)
                             {
-                                let packet = &app.packets[app.selected];
-                                if let Ok(json_val) =
-                                    serde_json::from_str::<Value>(&packet.raw_json)
-                                {
-                                    collect_all_paths(
-                                        &json_val,
-                                        String::new(),
-                                        &mut app.tree_expanded,
-                                    );
-                                }
+                                collect_all_paths(
+                                    &app.packets[index].value,
+                                    String::new(),
+                                    &mut app.tree_expanded,
+                                );
                             }
                         }
+                        KeyCode::Char('/') => app.open_search(),
+                        KeyCode::Char('n') => app.next_match(true, visible_rows),
+                        KeyCode::Char('N') => app.next_match(false, visible_rows),
+                        KeyCode::Char('f') => app.open_filter(),
                         _ => {}
                     }
                 }
@@ -420,10 +656,10 @@ fn handle_mouse_click(mouse: MouseEvent, app: &mut App) {
         // Data row click - select packet
         // Row 2 onwards are data rows (row 0=top border, row 1=header)
         let row_index = (mouse.row - 2) as usize;
-        if row_index < app.packets.len() {
+        if row_index < app.rows.len() {
             let visible_rows = 15; // Estimate from render
             let selected_in_view = row_index + app.scroll_offset;
-            if selected_in_view < app.packets.len() {
+            if selected_in_view < app.rows.len() {
                 app.selected = selected_in_view;
                 // Auto-scroll to keep selected visible
                 app.update_scroll(visible_rows);
@@ -552,7 +788,11 @@ fn collect_all_paths(
 fn ui(f: &mut ratatui::Frame, app: &mut App) {
     let outer_chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Min(10), Constraint::Length(2)])
+        .constraints([
+            Constraint::Min(10),
+            Constraint::Length(1),
+            Constraint::Length(2),
+        ])
         .split(f.area());
 
     let chunks = Layout::default()
@@ -620,12 +860,13 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
     let visible_rows = chunks[1].height.saturating_sub(BORDER_HEIGHT as u16) as usize;
 
     let rows: Vec<Row> = app
-        .packets
+        .rows
         .iter()
         .enumerate()
         .skip(app.scroll_offset)
         .take(visible_rows)
-        .map(|(i, packet)| {
+        .map(|(i, &index)| {
+            let packet = &app.packets[index];
             let cells = vec![
                 packet.id.to_string(),
                 packet.direction.clone(),
@@ -637,10 +878,13 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
                 packet.sequence.to_string(),
             ];
 
-            let style = get_focused_style(
+            let mut style = get_focused_style(
                 i == app.selected,
                 matches!(app.focused_pane, FocusedPane::List),
             );
+            if app.search.as_ref().is_some_and(|s| s.hits[index]) {
+                style = style.fg(Color::Yellow);
+            }
             Row::new(cells).style(style)
         })
         .collect();
@@ -699,19 +943,8 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
     // Details panel
     let detail_title = "Details";
 
-    if !app.packets.is_empty() {
-        let packet = &app.packets[app.selected];
-
-        // Parse JSON and build display
-        let detail_lines = match serde_json::from_str::<Value>(&packet.raw_json) {
-            Ok(json_val) => tree_display_lines(&json_val, &app.tree_expanded),
-            Err(e) => {
-                vec![(
-                    format!("Failed to parse JSON: {}\n\nRaw:\n{}", e, &packet.raw_json),
-                    String::new(),
-                )]
-            }
-        };
+    if let Some(packet) = app.current() {
+        let detail_lines = tree_display_lines(&packet.value, &app.tree_expanded);
 
         let visible_rows = chunks[1].height.saturating_sub(BORDER_HEIGHT as u16) as usize;
 
@@ -751,7 +984,12 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
             Style::default().add_modifier(Modifier::DIM)
         };
 
-        let detail_para = Paragraph::new("No messages loaded")
+        let empty_text = if app.packets.is_empty() {
+            "No messages loaded"
+        } else {
+            "No messages match the filter"
+        };
+        let detail_para = Paragraph::new(empty_text)
             .block({
                 let style = if matches!(app.focused_pane, FocusedPane::Details) {
                     Style::default().add_modifier(Modifier::BOLD)
@@ -767,10 +1005,99 @@ fn ui(f: &mut ratatui::Frame, app: &mut App) {
         f.render_widget(detail_para, chunks[1]);
     }
 
-    // Footer with controls
-    let controls_text = "q/Esc: Quit | Tab: Switch pane | ↑/↓: Navigate | Enter: Expand/collapse | Ctrl+a: Expand all";
-    let controls = Paragraph::new(controls_text).block(Block::default().borders(Borders::TOP));
-    f.render_widget(controls, outer_chunks[1]);
+    f.render_widget(Paragraph::new(filter_bar(app)), outer_chunks[1]);
+
+    // Status line with the active filter and search, or the search prompt
+    let status = Paragraph::new(status_line(app)).block(Block::default().borders(Borders::TOP));
+    f.render_widget(status, outer_chunks[2]);
+}
+
+fn filter_bar(app: &App) -> Line<'static> {
+    let label = Span::styled("Filter: ", Style::default().bold());
+    if let Some(Prompt::Filter { input, preview }) = &app.prompt {
+        let result = match preview {
+            Ok(count) => Span::styled(
+                format!("  {} of {} messages match", count, app.packets.len()),
+                Style::default().fg(Color::Green),
+            ),
+            Err(e) => Span::styled(format!("  {}", e), Style::default().fg(Color::Red)),
+        };
+        return Line::from(vec![
+            label,
+            Span::raw(input.clone()),
+            Span::styled("█", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+            result,
+        ]);
+    }
+
+    let expr = match &app.filter {
+        Some(filter) => Span::raw(filter.source().to_string()),
+        None => Span::styled(
+            "none (f to edit)",
+            Style::default().add_modifier(Modifier::DIM),
+        ),
+    };
+    Line::from(vec![
+        label,
+        expr,
+        Span::raw(format!(
+            "  {} of {} messages",
+            app.rows.len(),
+            app.packets.len()
+        )),
+    ])
+}
+
+fn status_line(app: &App) -> Line<'static> {
+    match &app.prompt {
+        Some(Prompt::Search {
+            input,
+            regex,
+            error,
+        }) => {
+            let mut spans = vec![
+                Span::styled(
+                    if *regex { "Regex /" } else { "Search /" },
+                    Style::default().bold(),
+                ),
+                Span::raw(input.clone()),
+                Span::styled("█", Style::default().add_modifier(Modifier::SLOW_BLINK)),
+            ];
+            match error {
+                Some(e) => spans.push(Span::styled(
+                    format!("  {}", e.lines().last().unwrap_or_default()),
+                    Style::default().fg(Color::Red),
+                )),
+                None => spans.push(Span::raw(
+                    "  Enter: Search | Tab: Toggle regex | Esc: Cancel",
+                )),
+            }
+            Line::from(spans)
+        }
+        Some(Prompt::Filter { .. }) => {
+            Line::from("Enter: Apply filter (empty to clear) | Esc: Cancel")
+        }
+        None => {
+            let mut spans = Vec::new();
+            if let Some(filter) = &app.filter {
+                spans.push(Span::styled(
+                    format!("[filter: {}] ", filter.source()),
+                    Style::default().fg(Color::Cyan),
+                ));
+            }
+            if let (Some(search), Some((position, total))) = (&app.search, app.match_position()) {
+                let position = position.map_or("-".to_string(), |p| p.to_string());
+                spans.push(Span::styled(
+                    format!("[/{}: {}/{}] ", search.query, position, total),
+                    Style::default().fg(Color::Yellow),
+                ));
+            }
+            spans.push(Span::raw(
+                "q/Esc: Quit | Tab: Switch pane | ↑/↓: Navigate | Enter: Expand/collapse | Ctrl+a: Expand all | /: Search | n/N: Next/prev match | f: Filter",
+            ));
+            Line::from(spans)
+        }
+    }
 }
 
 fn load_packets(path: &Path) -> Result<Vec<PacketInfo>> {
@@ -782,13 +1109,14 @@ fn load_packets(path: &Path) -> Result<Vec<PacketInfo>> {
     let pcap_iter = pcap::open(path)?;
     for packet_result in pcap_iter {
         let packet = packet_result?;
-        let parsed_messages = assembler.parse_packet_payload(&packet.data)?;
+        let parsed_messages = assembler.parse_packet(&packet)?;
         messages.extend(parsed_messages);
     }
 
     let mut packet_infos = Vec::new();
 
     for msg in messages {
+        let value = serde_json::to_value(&msg).unwrap_or_default();
         let info = PacketInfo {
             id: msg.id,
             direction: msg.direction.clone(),
@@ -798,10 +1126,145 @@ fn load_packets(path: &Path) -> Result<Vec<PacketInfo>> {
             size: msg.data.len(),
             opcode: format!("{:#06x}", msg.opcode),
             sequence: msg.sequence,
-            raw_json: serde_json::to_string(&msg).unwrap_or_default(),
+            raw_json: value.to_string(),
+            value,
         };
         packet_infos.push(info);
     }
 
     Ok(packet_infos)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn packet(id: u32, direction: &str, message_type: &str, data: Value) -> PacketInfo {
+        let value = json!({
+            "id": id,
+            "opcode": 0xf7b0,
+            "message_type": message_type,
+            "direction": direction,
+            "data": data,
+            "sequence": id,
+        });
+        PacketInfo {
+            id,
+            direction: direction.to_string(),
+            timestamp: String::new(),
+            flags: String::new(),
+            packet_type: message_type.to_string(),
+            size: 0,
+            opcode: "0xf7b0".to_string(),
+            sequence: id,
+            raw_json: value.to_string(),
+            value,
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new(vec![
+            packet(1, "Send", "CharacterLoginRequest", json!({"Name": "Alice"})),
+            packet(
+                2,
+                "Recv",
+                "ItemCreateObject",
+                json!({"Name": "Pyreal Mote"}),
+            ),
+            packet(3, "Recv", "ItemDeleteObject", json!({"ObjectId": 5})),
+            packet(
+                4,
+                "Recv",
+                "ItemCreateObject",
+                json!({"Name": "Pyreal Sword"}),
+            ),
+        ]);
+        app.set_filter(None);
+        app
+    }
+
+    fn type_keys(app: &mut App, text: &str) {
+        for c in text.chars() {
+            app.prompt_key(KeyCode::Char(c), 15);
+        }
+    }
+
+    fn selected_id(app: &App) -> u32 {
+        app.current().unwrap().id
+    }
+
+    #[test]
+    fn test_search_jumps_between_matches() {
+        let mut app = app();
+        app.open_search();
+        type_keys(&mut app, "pyreal");
+        app.prompt_key(KeyCode::Enter, 15);
+
+        assert!(app.prompt.is_none());
+        assert_eq!(selected_id(&app), 2);
+        assert_eq!(app.match_position(), Some((Some(1), 2)));
+        app.next_match(true, 15);
+        assert_eq!(selected_id(&app), 4);
+        app.next_match(true, 15);
+        assert_eq!(selected_id(&app), 2);
+        app.next_match(false, 15);
+        assert_eq!(selected_id(&app), 4);
+    }
+
+    #[test]
+    fn test_regex_search() {
+        let mut app = app();
+        app.open_search();
+        app.prompt_key(KeyCode::Tab, 15);
+        type_keys(&mut app, "Item(Delete|Login)");
+        app.prompt_key(KeyCode::Enter, 15);
+        assert_eq!(selected_id(&app), 3);
+        assert_eq!(app.match_position(), Some((Some(1), 1)));
+
+        // An invalid pattern keeps the prompt open with the error
+        app.open_search();
+        type_keys(&mut app, "(");
+        app.prompt_key(KeyCode::Enter, 15);
+        assert!(matches!(
+            app.prompt,
+            Some(Prompt::Search { error: Some(_), .. })
+        ));
+    }
+
+    #[test]
+    fn test_filter_bar() {
+        let mut app = app();
+        app.open_filter();
+        type_keys(&mut app, "direction == \"Recv\" && name ~ \"Sword\"");
+        assert!(matches!(
+            app.prompt,
+            Some(Prompt::Filter { preview: Ok(1), .. })
+        ));
+        type_keys(&mut app, " ||");
+        assert!(matches!(
+            app.prompt,
+            Some(Prompt::Filter {
+                preview: Err(_),
+                ..
+            })
+        ));
+        for _ in 0.." ||".len() {
+            app.prompt_key(KeyCode::Backspace, 15);
+        }
+        app.prompt_key(KeyCode::Enter, 15);
+
+        assert_eq!(app.rows.len(), 1);
+        assert_eq!(selected_id(&app), 4);
+
+        // Clearing the bar shows everything, keeping the selection
+        app.open_filter();
+        while matches!(&app.prompt, Some(Prompt::Filter { input, .. }) if !input.is_empty()) {
+            app.prompt_key(KeyCode::Backspace, 15);
+        }
+        app.prompt_key(KeyCode::Enter, 15);
+        assert!(app.filter.is_none());
+        assert_eq!(app.rows.len(), 4);
+        assert_eq!(selected_id(&app), 4);
+    }
+}
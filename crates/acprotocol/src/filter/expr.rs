@@ -0,0 +1,709 @@
+//! Display filter expressions
+//!
+//! A Wireshark-style language for selecting messages by their decoded
+//! content:
+//!
+//! ```text
+//! type == "ItemCreateObject" && weenie_description.name ~ "Pyreal"
+//! object_id == 0x50000001 || sender_id == 0x50000001
+//! key in {"Value" "Burden"} and not direction == "Send"
+//! all int_properties.value > 0
+//! ```
+//!
+//! Fields are dotted paths. Names are matched without regard to case or
+//! underscores, so `weenie_description` finds the `WeenieDescription` field.
+//! The first segment names either a message attribute (`id`, `type`,
+//! `opcode`, `direction`, `queue`, `sequence`, `timestamp`, `display_text`)
+//! or a field anywhere in the decoded message; later segments must follow
+//! directly. Packable lists and tables are looked through, so `friends.name`
+//! reaches the name of every friend.
+//!
+//! A path can match several values. A comparison holds if any of them
+//! satisfies it, or every one of them when prefixed with `all`. `!=` is the
+//! negation of `==`. A path on its own tests that the field is present and
+//! not `false`.
+//!
+//! Operators are `==`, `!=`, `<`, `<=`, `>`, `>=`, `~` (or `matches`, a
+//! regular expression), `contains` and `in {...}`, combined with `&&`/`and`,
+//! `||`/`or`, `!`/`not` and parentheses. Numbers can be decimal or `0x` hex.
+
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow, bail};
+use regex::Regex;
+use serde_json::Value;
+
+use crate::network::RawMessage;
+
+/// A parsed filter expression
+#[derive(Clone, Debug)]
+pub struct MessageFilter {
+    source: String,
+    expr: Expr,
+}
+
+impl MessageFilter {
+    /// Parse a filter expression
+    pub fn parse(source: &str) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        if let Some((token, at)) = parser.tokens.get(parser.pos) {
+            bail!("Unexpected {token} at position {at}");
+        }
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    /// The expression as it was written
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether a message matches the filter
+    pub fn matches(&self, message: &RawMessage) -> bool {
+        serde_json::to_value(message).is_ok_and(|value| self.matches_value(&value))
+    }
+
+    /// Whether a message already serialized to JSON matches the filter
+    pub fn matches_value(&self, message: &Value) -> bool {
+        self.expr.eval(message)
+    }
+}
+
+impl FromStr for MessageFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl std::fmt::Display for MessageFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(f64),
+    Str(String),
+    Op(CompareOp),
+    And,
+    Or,
+    Not,
+    In,
+    Any,
+    All,
+    True,
+    False,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ident(name) => write!(f, "field '{name}'"),
+            Self::Number(n) => write!(f, "number {n}"),
+            Self::Str(s) => write!(f, "string {s:?}"),
+            Self::Op(op) => write!(f, "operator {op:?}"),
+            other => write!(f, "'{other:?}'"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Matches,
+    Contains,
+}
+
+/// Split an expression into tokens, each with the position it starts at
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>> {
+    let chars: Vec<(usize, char)> = source.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (at, c) = chars[i];
+        let next = chars.get(i + 1).map(|&(_, c)| c);
+        let two = |a: char, b: char| c == a && next == Some(b);
+
+        let token = if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if two('&', '&') {
+            i += 2;
+            Token::And
+        } else if two('|', '|') {
+            i += 2;
+            Token::Or
+        } else if two('=', '=') {
+            i += 2;
+            Token::Op(CompareOp::Eq)
+        } else if two('!', '=') {
+            i += 2;
+            Token::Op(CompareOp::Ne)
+        } else if two('<', '=') {
+            i += 2;
+            Token::Op(CompareOp::Le)
+        } else if two('>', '=') {
+            i += 2;
+            Token::Op(CompareOp::Ge)
+        } else if let Some(token) = match c {
+            '<' => Some(Token::Op(CompareOp::Lt)),
+            '>' => Some(Token::Op(CompareOp::Gt)),
+            '~' => Some(Token::Op(CompareOp::Matches)),
+            '!' => Some(Token::Not),
+            '(' => Some(Token::LParen),
+            ')' => Some(Token::RParen),
+            '{' | '[' => Some(Token::LBrace),
+            '}' | ']' => Some(Token::RBrace),
+            ',' => Some(Token::Comma),
+            _ => None,
+        } {
+            i += 1;
+            token
+        } else if c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                let Some(&(_, c)) = chars.get(i) else {
+                    bail!("Unterminated string starting at position {at}");
+                };
+                i += 1;
+                match c {
+                    '"' => break,
+                    '\\' => {
+                        let Some(&(_, escaped)) = chars.get(i) else {
+                            bail!("Unterminated string starting at position {at}");
+                        };
+                        i += 1;
+                        text.push(match escaped {
+                            'n' => '\n',
+                            't' => '\t',
+                            other => other,
+                        });
+                    }
+                    other => text.push(other),
+                }
+            }
+            Token::Str(text)
+        } else if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) {
+            let start = i;
+            i += 1;
+            while chars
+                .get(i)
+                .is_some_and(|&(_, c)| c.is_ascii_alphanumeric() || c == '.')
+            {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().map(|&(_, c)| c).collect();
+            Token::Number(
+                parse_number(&text)
+                    .ok_or_else(|| anyhow!("Invalid number '{text}' at position {at}"))?,
+            )
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while chars
+                .get(i)
+                .is_some_and(|&(_, c)| c.is_alphanumeric() || c == '_' || c == '.')
+            {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().map(|&(_, c)| c).collect();
+            match word.to_lowercase().as_str() {
+                "and" => Token::And,
+                "or" => Token::Or,
+                "not" => Token::Not,
+                "in" => Token::In,
+                "any" => Token::Any,
+                "all" => Token::All,
+                "true" => Token::True,
+                "false" => Token::False,
+                "matches" => Token::Op(CompareOp::Matches),
+                "contains" => Token::Op(CompareOp::Contains),
+                _ => {
+                    if word.split('.').any(str::is_empty) {
+                        bail!("Invalid field '{word}' at position {at}");
+                    }
+                    Token::Ident(word)
+                }
+            }
+        } else {
+            bail!("Unexpected character '{c}' at position {at}");
+        };
+        tokens.push((token, at));
+    }
+
+    Ok(tokens)
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()? as f64,
+        None => digits.parse::<f64>().ok()?,
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[derive(Clone, Debug)]
+enum Literal {
+    Number(f64),
+    Str(String),
+    Bool(bool),
+}
+
+#[derive(Clone, Debug)]
+enum Quantifier {
+    Any,
+    All,
+}
+
+#[derive(Clone, Debug)]
+enum Test {
+    Exists,
+    Compare(CompareOp, Literal),
+    Regex(Regex),
+    In(Vec<Literal>),
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Field {
+        quantifier: Quantifier,
+        path: Vec<String>,
+        test: Test,
+    },
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let (token, _) = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unexpected end of filter"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let at = self.position();
+        let token = self.next()?;
+        if token != expected {
+            bail!("Expected {expected} but found {token} at position {at}");
+        }
+        Ok(())
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map_or(0, |&(_, at)| at)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut left = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let at = self.position();
+        let quantifier = match self.peek() {
+            Some(Token::Any) => {
+                self.pos += 1;
+                Quantifier::Any
+            }
+            Some(Token::All) => {
+                self.pos += 1;
+                Quantifier::All
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let expr = self.or()?;
+                self.expect(Token::RParen)?;
+                return Ok(expr);
+            }
+            _ => Quantifier::Any,
+        };
+
+        let path = match self.next()? {
+            Token::Ident(name) => name.split('.').map(normalize).collect(),
+            token => bail!("Expected a field but found {token} at position {at}"),
+        };
+
+        let test = match self.peek() {
+            Some(&Token::Op(op)) => {
+                self.pos += 1;
+                let at = self.position();
+                let literal = self.literal()?;
+                match (op, literal) {
+                    (CompareOp::Matches, Literal::Str(pattern)) => {
+                        let regex = Regex::new(&pattern)
+                            .map_err(|e| anyhow!("Invalid regex at position {at}: {e}"))?;
+                        Test::Regex(regex)
+                    }
+                    (CompareOp::Matches | CompareOp::Contains, literal)
+                        if !matches!(literal, Literal::Str(_)) =>
+                    {
+                        bail!("Expected a string at position {at}");
+                    }
+                    (op, literal) => Test::Compare(op, literal),
+                }
+            }
+            Some(Token::In) => {
+                self.pos += 1;
+                self.expect(Token::LBrace)?;
+                let mut values = Vec::new();
+                while self.peek() != Some(&Token::RBrace) {
+                    values.push(self.literal()?);
+                    if self.peek() == Some(&Token::Comma) {
+                        self.pos += 1;
+                    }
+                }
+                self.pos += 1;
+                Test::In(values)
+            }
+            _ => Test::Exists,
+        };
+
+        Ok(Expr::Field {
+            quantifier,
+            path,
+            test,
+        })
+    }
+
+    fn literal(&mut self) -> Result<Literal> {
+        let at = self.position();
+        match self.next()? {
+            Token::Number(n) => Ok(Literal::Number(n)),
+            Token::Str(s) => Ok(Literal::Str(s)),
+            Token::True => Ok(Literal::Bool(true)),
+            Token::False => Ok(Literal::Bool(false)),
+            token => bail!("Expected a value but found {token} at position {at}"),
+        }
+    }
+}
+
+/// Field names compare without case or underscores
+pub(super) fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|&c| c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Message attributes that can be named directly, with the key they are serialized under
+pub(super) const ATTRIBUTES: &[(&str, &str)] = &[
+    ("id", "id"),
+    ("type", "message_type"),
+    ("messagetype", "message_type"),
+    ("opcode", "opcode"),
+    ("direction", "direction"),
+    ("queue", "queue"),
+    ("sequence", "sequence"),
+    ("timestamp", "timestamp"),
+    ("displaytext", "display_text"),
+];
+
+impl Expr {
+    fn eval(&self, message: &Value) -> bool {
+        match self {
+            Self::And(left, right) => left.eval(message) && right.eval(message),
+            Self::Or(left, right) => left.eval(message) || right.eval(message),
+            Self::Not(inner) => !inner.eval(message),
+            Self::Field {
+                quantifier,
+                path,
+                test,
+            } => {
+                let values = resolve(message, path);
+                if let Test::Compare(CompareOp::Ne, literal) = test {
+                    let eq = Test::Compare(CompareOp::Eq, literal.clone());
+                    return !values.iter().any(|v| eq.holds(v));
+                }
+                match quantifier {
+                    Quantifier::Any => values.iter().any(|v| test.holds(v)),
+                    Quantifier::All => !values.is_empty() && values.iter().all(|v| test.holds(v)),
+                }
+            }
+        }
+    }
+}
+
+/// Every value a path refers to in a serialized message
+fn resolve<'a>(message: &'a Value, path: &[String]) -> Vec<&'a Value> {
+    let Some((first, rest)) = path.split_first() else {
+        return Vec::new();
+    };
+
+    let mut values = Vec::new();
+    match ATTRIBUTES.iter().find(|(name, _)| name == first) {
+        Some((_, key)) => values.extend(message.get(key)),
+        None => {
+            if let Some(data) = message.get("data") {
+                find_all(data, first, &mut values);
+            }
+        }
+    }
+
+    for segment in rest {
+        let mut next = Vec::new();
+        for value in values {
+            descend(value, segment, &mut next);
+        }
+        values = next;
+    }
+
+    values.into_iter().flat_map(elements).collect()
+}
+
+/// Collect every field with a matching name at any depth
+pub(super) fn find_all<'a>(value: &'a Value, name: &str, out: &mut Vec<&'a Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, child) in map {
+                if normalize(key) == name {
+                    out.push(child);
+                }
+                find_all(child, name, out);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                find_all(item, name, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Follow one path segment, looking through arrays and packable containers
+pub(super) fn descend<'a>(value: &'a Value, name: &str, out: &mut Vec<&'a Value>) {
+    match value {
+        Value::Object(map) => {
+            let before = out.len();
+            out.extend(
+                map.iter()
+                    .filter(|(key, _)| normalize(key) == name)
+                    .map(|(_, child)| child),
+            );
+            if out.len() == before
+                && let Some(inner) = container(value)
+            {
+                descend(inner, name, out);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                descend(item, name, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The list or table inside a `PackableList` or `PackableHashTable`
+fn container(value: &Value) -> Option<&Value> {
+    value.get("List").or_else(|| value.get("Table"))
+}
+
+/// Expand arrays and packable lists into their elements
+fn elements(value: &Value) -> Vec<&Value> {
+    match value {
+        Value::Array(items) => items.iter().collect(),
+        Value::Object(_) => match value.get("List") {
+            Some(Value::Array(items)) => items.iter().collect(),
+            _ => vec![value],
+        },
+        _ => vec![value],
+    }
+}
+
+/// A scalar as text, for string comparisons and regular expressions
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+impl Test {
+    fn holds(&self, value: &Value) -> bool {
+        match self {
+            Self::Exists => !matches!(value, Value::Null | Value::Bool(false)),
+            Self::Regex(regex) => text(value).is_some_and(|s| regex.is_match(&s)),
+            Self::In(literals) => literals
+                .iter()
+                .any(|literal| compare(CompareOp::Eq, value, literal)),
+            Self::Compare(op, literal) => compare(*op, value, literal),
+        }
+    }
+}
+
+fn compare(op: CompareOp, value: &Value, literal: &Literal) -> bool {
+    use std::cmp::Ordering;
+
+    if op == CompareOp::Contains {
+        let Literal::Str(needle) = literal else {
+            return false;
+        };
+        return text(value).is_some_and(|s| s.contains(needle.as_str()));
+    }
+
+    let ordering = match (value, literal) {
+        (Value::Number(n), Literal::Number(l)) => n.as_f64().and_then(|n| n.partial_cmp(l)),
+        (Value::String(s), Literal::Number(l)) => parse_number(s).and_then(|n| n.partial_cmp(l)),
+        (Value::Number(n), Literal::Str(l)) => match parse_number(l) {
+            Some(l) => n.as_f64().and_then(|n| n.partial_cmp(&l)),
+            None => Some(n.to_string().as_str().cmp(l.as_str())),
+        },
+        (Value::String(s), Literal::Str(l)) => Some(s.as_str().cmp(l.as_str())),
+        (Value::Bool(b), Literal::Bool(l)) => Some(b.cmp(l)),
+        (Value::Bool(b), Literal::Str(l)) => Some(b.to_string().as_str().cmp(l.as_str())),
+        _ => None,
+    };
+    let Some(ordering) = ordering else {
+        return false;
+    };
+
+    match op {
+        CompareOp::Eq => ordering == Ordering::Equal,
+        CompareOp::Ne => ordering != Ordering::Equal,
+        CompareOp::Lt => ordering == Ordering::Less,
+        CompareOp::Le => ordering != Ordering::Greater,
+        CompareOp::Gt => ordering == Ordering::Greater,
+        CompareOp::Ge => ordering != Ordering::Less,
+        CompareOp::Matches | CompareOp::Contains => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn create_object() -> Value {
+        json!({
+            "id": 7,
+            "opcode": 0xF745,
+            "message_type": "ItemCreateObject",
+            "direction": "Recv",
+            "data": {"S2C": {"ItemCreateObject": {
+                "ObjectId": 0x80000001u32,
+                "WeenieDescription": {"Name": "Pyreal Mote", "Value": 25},
+                "Spells": {"Count": 2, "List": [{"Id": 1}, {"Id": 3}]},
+            }}},
+        })
+    }
+
+    fn matches(filter: &str, message: &Value) -> bool {
+        MessageFilter::parse(filter).unwrap().matches_value(message)
+    }
+
+    #[test]
+    fn test_attributes_and_nested_fields() {
+        let message = create_object();
+        assert!(matches(
+            r#"type == "ItemCreateObject" && weenie_description.name ~ "Pyreal""#,
+            &message
+        ));
+        assert!(matches(
+            "object_id == 0x80000001 || sender_id == 1",
+            &message
+        ));
+        assert!(matches(
+            "weenie_description.value >= 25 and id < 10",
+            &message
+        ));
+        assert!(matches(r#"direction in {"Send", "Recv"}"#, &message));
+        assert!(matches(r#"name contains "Mote""#, &message));
+        assert!(!matches(r#"not type == "ItemCreateObject""#, &message));
+        assert!(!matches("sender_id", &message));
+        assert!(matches("opcode == 63301", &message));
+    }
+
+    #[test]
+    fn test_any_and_all_over_lists() {
+        let message = create_object();
+        assert!(matches("spells.id == 3", &message));
+        assert!(matches("any spells.id == 1", &message));
+        assert!(!matches("all spells.id == 1", &message));
+        assert!(matches("all spells.id in [1 3]", &message));
+        assert!(matches("spells.id != 2", &message));
+        assert!(!matches("spells.id != 3", &message));
+        assert!(!matches("all missing.id == 1", &message));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for filter in [
+            "",
+            "type ==",
+            "(type == 1",
+            r#"type ~ "(""#,
+            r#"name == "open"#,
+            "type == 1 1",
+            "id == 0xZZ",
+            "name ~ 5",
+        ] {
+            assert!(MessageFilter::parse(filter).is_err(), "{filter}");
+        }
+    }
+}
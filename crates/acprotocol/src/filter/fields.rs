@@ -0,0 +1,359 @@
+//! Field projection for tabular output
+//!
+//! Picks columns out of serialized messages by dotted path:
+//!
+//! ```text
+//! id,timestamp,message.object_id,message.weenie_description.name
+//! ```
+//!
+//! Paths follow the same rules as filter expressions: names match without
+//! regard to case or underscores, and the first segment names a message
+//! attribute (`id`, `type`, `timestamp`, ...) or a field anywhere in the
+//! decoded message. A first segment of `message` (or `data`) instead starts at
+//! the decoded message's own fields, looking through its direction and variant
+//! wrappers and, for ordered messages, the game event or action inside. Fields
+//! of the ordered envelope, such as an event's `object_id`, are used when the
+//! event itself has no such field.
+//!
+//! A path that reaches several values, say through a list, produces all of
+//! them as an array. To get one row per list element instead, explode the
+//! list: columns under the exploded path are then taken from each element.
+
+use anyhow::{Result, bail};
+use serde_json::{Map, Value};
+
+use super::expr::{ATTRIBUTES, descend, find_all, normalize};
+
+/// Columns used when none are asked for
+pub const DEFAULT_FIELDS: &str = "id,timestamp,direction,type,opcode";
+
+/// Path segments that start at the decoded message
+const MESSAGE_ROOTS: &[&str] = &["message", "data"];
+
+/// A dotted path, as written and normalized
+#[derive(Clone, Debug)]
+struct FieldPath {
+    source: String,
+    segments: Vec<String>,
+}
+
+impl FieldPath {
+    fn parse(source: &str) -> Result<Self> {
+        let source = source.trim();
+        let segments: Vec<String> = source.split('.').map(normalize).collect();
+        if segments.iter().any(String::is_empty) {
+            bail!("Invalid field path '{source}'");
+        }
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    /// The rest of this path below `prefix`, if it is under it
+    fn strip_prefix(&self, prefix: &FieldPath) -> Option<&[String]> {
+        self.segments.strip_prefix(prefix.segments.as_slice())
+    }
+}
+
+/// The columns to take from each message, and the list to explode into rows
+#[derive(Clone, Debug)]
+pub struct FieldSelection {
+    columns: Vec<FieldPath>,
+    explode: Option<FieldPath>,
+}
+
+impl FieldSelection {
+    /// Parse a comma-separated list of paths, and the path of a list to explode
+    pub fn parse(fields: &str, explode: Option<&str>) -> Result<Self> {
+        let columns = fields
+            .split(',')
+            .filter(|field| !field.trim().is_empty())
+            .map(FieldPath::parse)
+            .collect::<Result<Vec<_>>>()?;
+        if columns.is_empty() {
+            bail!("No fields given");
+        }
+        Ok(Self {
+            columns,
+            explode: explode.map(FieldPath::parse).transpose()?,
+        })
+    }
+
+    /// The column headers, as the paths were written
+    pub fn headers(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.source.as_str()).collect()
+    }
+
+    /// The selected values of a serialized message, one row per exploded
+    /// element
+    ///
+    /// Without an explode path there is always one row. With one, messages
+    /// where the path doesn't reach a list produce no rows.
+    pub fn rows(&self, message: &Value) -> Vec<Vec<Value>> {
+        let Some(explode) = &self.explode else {
+            return vec![
+                self.columns
+                    .iter()
+                    .map(|column| cell(resolve(message, &column.segments)))
+                    .collect(),
+            ];
+        };
+
+        let elements: Vec<Value> = resolve(message, &explode.segments)
+            .into_iter()
+            .flat_map(elements)
+            .collect();
+        elements
+            .iter()
+            .map(|element| {
+                self.columns
+                    .iter()
+                    .map(|column| match column.strip_prefix(explode) {
+                        Some([]) => element.clone(),
+                        Some(rest) => cell(follow(vec![element], rest)),
+                        None => cell(resolve(message, &column.segments)),
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The rows of a message as objects keyed by header
+    pub fn objects(&self, message: &Value) -> Vec<Value> {
+        let headers = self.headers();
+        self.rows(message)
+            .into_iter()
+            .map(|row| {
+                let map: Map<String, Value> =
+                    headers.iter().map(|h| h.to_string()).zip(row).collect();
+                Value::Object(map)
+            })
+            .collect()
+    }
+}
+
+impl Default for FieldSelection {
+    fn default() -> Self {
+        Self::parse(DEFAULT_FIELDS, None).expect("default fields are valid")
+    }
+}
+
+/// A value as the text of a CSV or TSV cell
+///
+/// Strings are written as they are, missing values as nothing, and lists and
+/// objects as compact JSON.
+pub fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Every value a path refers to in a serialized message
+fn resolve<'a>(message: &'a Value, path: &[String]) -> Vec<&'a Value> {
+    let Some((first, rest)) = path.split_first() else {
+        return Vec::new();
+    };
+    let data = message.get("data");
+
+    if let Some((_, key)) = ATTRIBUTES.iter().find(|(name, _)| name == first) {
+        return follow(message.get(key).into_iter().collect(), rest);
+    }
+    if MESSAGE_ROOTS.contains(&first.as_str()) {
+        let Some(data) = data else {
+            return Vec::new();
+        };
+        let (body, envelope) = unwrap(data);
+        if rest.is_empty() {
+            return vec![body];
+        }
+        let values = follow(vec![body], rest);
+        if values.is_empty()
+            && let Some(envelope) = envelope
+        {
+            return follow(vec![envelope], rest);
+        }
+        return values;
+    }
+    // Other attributes, such as `relative_time` or `raw`
+    if let Some((_, value)) = message
+        .as_object()
+        .and_then(|map| map.iter().find(|(key, _)| normalize(key) == *first))
+    {
+        return follow(vec![value], rest);
+    }
+
+    let mut values = Vec::new();
+    if let Some(data) = data {
+        find_all(data, first, &mut values);
+    }
+    follow(values, rest)
+}
+
+fn follow<'a>(mut values: Vec<&'a Value>, path: &[String]) -> Vec<&'a Value> {
+    for segment in path {
+        let mut next = Vec::new();
+        for value in values {
+            descend(value, segment, &mut next);
+        }
+        values = next;
+    }
+    values
+}
+
+/// The message's own fields inside its direction and variant wrappers, and
+/// the ordered-message envelope around them if there is one
+fn unwrap(data: &Value) -> (&Value, Option<&Value>) {
+    let mut value = data;
+    let mut envelope = None;
+    loop {
+        let Some(map) = value.as_object() else {
+            return (value, envelope);
+        };
+        if let Some(inner) = map.get("event").or_else(|| map.get("action")) {
+            envelope = Some(value);
+            value = inner;
+        } else if map.len() == 1
+            && let Some(inner) = map.values().next().filter(|v| v.is_object())
+        {
+            value = inner;
+        } else {
+            return (value, envelope);
+        }
+    }
+}
+
+/// One cell from the values a path reached
+fn cell(values: Vec<&Value>) -> Value {
+    match values.as_slice() {
+        [] => Value::Null,
+        [value] => (*value).clone(),
+        _ => Value::Array(values.into_iter().cloned().collect()),
+    }
+}
+
+/// The elements of a list, packable list or table, with table entries as
+/// `Key`/`Value` objects
+fn elements(value: &Value) -> Vec<Value> {
+    let inner = value
+        .get("List")
+        .or_else(|| value.get("Table"))
+        .unwrap_or(value);
+    match inner {
+        Value::Array(items) => items.clone(),
+        Value::Object(table) if inner != value => table
+            .iter()
+            .map(|(key, value)| {
+                serde_json::json!({
+                    "Key": key,
+                    "Value": value,
+                })
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn create_object() -> Value {
+        json!({
+            "id": 7,
+            "timestamp": 1000.5,
+            "message_type": "ItemCreateObject",
+            "direction": "Recv",
+            "data": {"S2C": {"ItemCreateObject": {
+                "ObjectId": 0x80000001u32,
+                "WeenieDescription": {"Name": "Pyreal Mote", "Value": 25},
+                "Spells": {"Count": 2, "List": [{"Id": 1}, {"Id": 3}]},
+            }}},
+        })
+    }
+
+    fn wear_item() -> Value {
+        json!({
+            "id": 8,
+            "message_type": "ItemWearItem",
+            "data": {"S2C": {"OrderedGameEvent": {
+                "object_id": 0x50000001u32,
+                "sequence": 4,
+                "event": {"ItemWearItem": {"ObjectId": 0x80000002u32, "Slot": 1}},
+            }}},
+        })
+    }
+
+    fn rows(fields: &str, explode: Option<&str>, message: &Value) -> Vec<Vec<Value>> {
+        FieldSelection::parse(fields, explode)
+            .unwrap()
+            .rows(message)
+    }
+
+    #[test]
+    fn test_attributes_and_message_fields() {
+        let message = create_object();
+        let selection = FieldSelection::parse(
+            "id, timestamp, type, message.object_id, message.weenie_description.name, name",
+            None,
+        )
+        .unwrap();
+        assert_eq!(selection.headers()[3], "message.object_id");
+        assert_eq!(
+            selection.rows(&message),
+            vec![vec![
+                json!(7),
+                json!(1000.5),
+                json!("ItemCreateObject"),
+                json!(0x80000001u32),
+                json!("Pyreal Mote"),
+                json!("Pyreal Mote"),
+            ]]
+        );
+
+        // A path reaching every element of a list gives them all
+        assert_eq!(
+            rows("message.spells.id,missing", None, &message),
+            vec![vec![json!([1, 3]), Value::Null]]
+        );
+    }
+
+    #[test]
+    fn test_ordered_event_fields() {
+        let message = wear_item();
+        assert_eq!(
+            rows(
+                "message.object_id,message.slot,message.sequence",
+                None,
+                &message
+            ),
+            vec![vec![json!(0x80000002u32), json!(1), json!(4)]]
+        );
+    }
+
+    #[test]
+    fn test_explode_list() {
+        let message = create_object();
+        assert_eq!(
+            rows("id,message.spells.id", Some("message.spells"), &message),
+            vec![vec![json!(7), json!(1)], vec![json!(7), json!(3)]]
+        );
+        assert!(rows("id", Some("message.missing"), &message).is_empty());
+
+        let objects = FieldSelection::parse("id,message.spells", Some("message.spells"))
+            .unwrap()
+            .objects(&message);
+        assert_eq!(objects[1], json!({"id": 7, "message.spells": {"Id": 3}}));
+    }
+
+    #[test]
+    fn test_cell_text() {
+        assert_eq!(cell_text(&Value::Null), "");
+        assert_eq!(cell_text(&json!("a,b")), "a,b");
+        assert_eq!(cell_text(&json!([1, 3])), "[1,3]");
+        assert!(FieldSelection::parse("id,,message..name", None).is_err());
+    }
+}
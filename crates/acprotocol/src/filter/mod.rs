@@ -1,5 +1,17 @@
 use anyhow::Result;
 
+#[cfg(feature = "filter")]
+mod expr;
+#[cfg(feature = "filter")]
+mod fields;
+mod range;
+
+#[cfg(feature = "filter")]
+pub use expr::MessageFilter;
+#[cfg(feature = "filter")]
+pub use fields::{FieldSelection, cell_text};
+pub use range::{MessageRange, TimeBound, capture_start};
+
 /// Parse an opcode filter string to u32
 ///
 /// Supports:
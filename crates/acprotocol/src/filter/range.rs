@@ -0,0 +1,285 @@
+use std::str::FromStr;
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::network::RawMessage;
+
+/// A point in a capture, either absolute or relative to its first message
+///
+/// Parses:
+/// - Offsets from capture start: "+90s", "+3m", "+1m30s", "+1.5h", "+250ms", "+90"
+/// - Unix timestamps: "1763490291.25"
+/// - UTC date and time: "2025-11-18T18:24:51Z", "2025-11-18 18:24:51.5"
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeBound {
+    Offset(f64),
+    Absolute(f64),
+}
+
+impl TimeBound {
+    /// Resolve to a Unix timestamp given the capture's start time
+    pub fn resolve(self, start: f64) -> f64 {
+        match self {
+            Self::Offset(seconds) => start + seconds,
+            Self::Absolute(timestamp) => timestamp,
+        }
+    }
+}
+
+impl FromStr for TimeBound {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(offset) = s.strip_prefix('+') {
+            return parse_duration(offset)
+                .map(Self::Offset)
+                .ok_or_else(|| anyhow!("Invalid offset '{s}', expected e.g. +90s or +2m30s"));
+        }
+        if let Ok(timestamp) = s.parse::<f64>() {
+            return Ok(Self::Absolute(timestamp));
+        }
+        parse_datetime(s).map(Self::Absolute).ok_or_else(|| {
+            anyhow!("Invalid time '{s}', expected +90s, a Unix timestamp or YYYY-MM-DDTHH:MM:SS")
+        })
+    }
+}
+
+/// Parse a duration made of number/unit pairs, e.g. "1m30s"; a bare number is seconds
+fn parse_duration(s: &str) -> Option<f64> {
+    if let Ok(seconds) = s.parse::<f64>() {
+        return Some(seconds);
+    }
+
+    if s.is_empty() {
+        return None;
+    }
+
+    let mut total = 0.0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+        let (number, tail) = rest.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        total += number
+            * match unit {
+                "ms" => 0.001,
+                "s" => 1.0,
+                "m" => 60.0,
+                "h" => 3600.0,
+                _ => return None,
+            };
+        rest = tail;
+    }
+    Some(total)
+}
+
+/// Parse "YYYY-MM-DD[T ]HH:MM[:SS[.fff]][Z]" as UTC
+fn parse_datetime(s: &str) -> Option<f64> {
+    let s = s.strip_suffix('Z').unwrap_or(s);
+    let (date, time) = s.split_once(['T', ' '])?;
+
+    let mut date = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    if date.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut time = time.split(':');
+    let hour = time.next()?.parse::<i64>().ok()?;
+    let minute = time.next()?.parse::<i64>().ok()?;
+    let second = match time.next() {
+        Some(s) => s.parse::<f64>().ok()?,
+        None => 0.0,
+    };
+    if time.next().is_some() || hour > 23 || minute > 59 || second >= 61.0 {
+        return None;
+    }
+
+    // Days since the epoch in the proleptic Gregorian calendar
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    Some((days * 86400 + hour * 3600 + minute * 60) as f64 + second)
+}
+
+/// Selects a contiguous part of a capture by time, message id or neighbourhood
+#[derive(Clone, Debug, Default)]
+pub struct MessageRange {
+    pub since: Option<TimeBound>,
+    pub until: Option<TimeBound>,
+    pub from_id: Option<u32>,
+    pub to_id: Option<u32>,
+    /// A message id and how many messages to keep on either side of it
+    pub around: Option<(u32, usize)>,
+}
+
+impl MessageRange {
+    /// Whether the range keeps every message
+    pub fn is_empty(&self) -> bool {
+        self.since.is_none()
+            && self.until.is_none()
+            && self.from_id.is_none()
+            && self.to_id.is_none()
+            && self.around.is_none()
+    }
+
+    /// The messages within the range, in capture order
+    ///
+    /// Time bounds are inclusive and skip messages without a timestamp. Offsets
+    /// are measured from [`capture_start`].
+    pub fn select<'a>(&self, messages: &'a [RawMessage]) -> Result<Vec<&'a RawMessage>> {
+        let start = capture_start(messages).unwrap_or(0.0);
+        let since = self.since.map(|b| b.resolve(start));
+        let until = self.until.map(|b| b.resolve(start));
+        let in_time = |m: &RawMessage| match m.timestamp {
+            Some(t) => since.is_none_or(|s| t >= s) && until.is_none_or(|u| t <= u),
+            None => since.is_none() && until.is_none(),
+        };
+
+        let window = match self.around {
+            Some((id, context)) => {
+                let Some(index) = messages.iter().position(|m| m.id == id) else {
+                    bail!("Message {id} not found");
+                };
+                index.saturating_sub(context)..(index + context + 1).min(messages.len())
+            }
+            None => 0..messages.len(),
+        };
+
+        Ok(messages[window]
+            .iter()
+            .filter(|m| self.from_id.is_none_or(|id| m.id >= id))
+            .filter(|m| self.to_id.is_none_or(|id| m.id <= id))
+            .filter(|m| in_time(m))
+            .collect())
+    }
+}
+
+/// The timestamp of the earliest message in a capture
+pub fn capture_start(messages: &[RawMessage]) -> Option<f64> {
+    messages
+        .iter()
+        .filter_map(|m| m.timestamp)
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: u32, timestamp: f64) -> RawMessage {
+        RawMessage {
+            id,
+            opcode: 0,
+            message_type: String::new(),
+            direction: String::new(),
+            queue: None,
+            data: Vec::new(),
+            display_text: None,
+            sequence: id,
+            iteration: None,
+            header_flags: None,
+            timestamp: Some(timestamp),
+        }
+    }
+
+    fn capture() -> Vec<RawMessage> {
+        (0..10)
+            .map(|i| message(i, 1000.0 + 30.0 * i as f64))
+            .collect()
+    }
+
+    fn ids(selected: Vec<&RawMessage>) -> Vec<u32> {
+        selected.iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn test_parse_time_bounds() {
+        assert_eq!(
+            "+90s".parse::<TimeBound>().unwrap(),
+            TimeBound::Offset(90.0)
+        );
+        assert_eq!("+90".parse::<TimeBound>().unwrap(), TimeBound::Offset(90.0));
+        assert_eq!(
+            "+2m30s".parse::<TimeBound>().unwrap(),
+            TimeBound::Offset(150.0)
+        );
+        assert_eq!(
+            "+1.5h".parse::<TimeBound>().unwrap(),
+            TimeBound::Offset(5400.0)
+        );
+        assert_eq!(
+            "+250ms".parse::<TimeBound>().unwrap(),
+            TimeBound::Offset(0.25)
+        );
+        assert_eq!(
+            "1763490291.5".parse::<TimeBound>().unwrap(),
+            TimeBound::Absolute(1763490291.5)
+        );
+        assert_eq!(
+            "2025-11-18T18:24:51Z".parse::<TimeBound>().unwrap(),
+            TimeBound::Absolute(1763490291.0)
+        );
+        assert_eq!(
+            "1970-01-01 00:01".parse::<TimeBound>().unwrap(),
+            TimeBound::Absolute(60.0)
+        );
+        for bad in ["+3x", "+", "yesterday", "2025-13-01T00:00:00"] {
+            assert!(bad.parse::<TimeBound>().is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn test_select_by_time_and_id() {
+        let messages = capture();
+
+        let range = MessageRange {
+            since: Some(TimeBound::Offset(60.0)),
+            until: Some(TimeBound::Absolute(1150.0)),
+            ..Default::default()
+        };
+        assert_eq!(ids(range.select(&messages).unwrap()), vec![2, 3, 4, 5]);
+
+        let range = MessageRange {
+            from_id: Some(4),
+            to_id: Some(6),
+            ..Default::default()
+        };
+        assert_eq!(ids(range.select(&messages).unwrap()), vec![4, 5, 6]);
+        assert!(MessageRange::default().is_empty());
+        assert_eq!(MessageRange::default().select(&messages).unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_select_around() {
+        let messages = capture();
+
+        let range = MessageRange {
+            around: Some((5, 2)),
+            ..Default::default()
+        };
+        assert_eq!(ids(range.select(&messages).unwrap()), vec![3, 4, 5, 6, 7]);
+
+        let range = MessageRange {
+            around: Some((1, 3)),
+            ..Default::default()
+        };
+        assert_eq!(ids(range.select(&messages).unwrap()), vec![0, 1, 2, 3, 4]);
+
+        let range = MessageRange {
+            around: Some((42, 1)),
+            ..Default::default()
+        };
+        assert!(range.select(&messages).is_err());
+    }
+}
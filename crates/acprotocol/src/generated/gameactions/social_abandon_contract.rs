@@ -20,7 +20,7 @@ use tracing::{span, Level};
 #[serde(rename = "Social_AbandonContract")]
 pub struct SocialAbandonContract {
     #[serde(rename = "ContractId")]
-    pub contract_id: ContractId,
+    pub contract_id: u32,
 }
 
 impl crate::readers::ACDataType for SocialAbandonContract {
@@ -33,7 +33,7 @@ impl crate::readers::ACDataType for SocialAbandonContract {
             let pos = reader.stream_position().unwrap_or(0);
             tracing::span!(tracing::Level::TRACE, "field", name = "ContractId", position = pos).entered()
         };
-        let contract_id = ContractId::try_from(read_u32(reader)?)?;
+        let contract_id = read_u32(reader)?;
         #[cfg(feature = "tracing")]
         drop(_field_span_contract_id);
 
@@ -48,7 +48,7 @@ impl crate::writers::ACWritable for SocialAbandonContract {
         #[cfg(feature = "tracing")]
         let _span = tracing::span!(tracing::Level::DEBUG, "write", r#type = "SocialAbandonContract").entered();
 
-        write_u32(writer, self.contract_id.clone() as u32)?;
+        write_u32(writer, self.contract_id)?;
         Ok(())
     }
 }
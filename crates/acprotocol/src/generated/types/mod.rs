@@ -54,8 +54,9 @@ pub type DWORD = u32;
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PackedWORD {}
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-pub struct PackedDWORD {}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PackedDWORD(pub u32);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -2600,9 +2601,9 @@ pub struct ContractTracker {
     #[serde(rename = "Version")]
     pub version: u32,
     #[serde(rename = "ContractId")]
-    pub contract_id: ContractId,
+    pub contract_id: u32,
     #[serde(rename = "ContractStage")]
-    pub contract_stage: ContractStage,
+    pub contract_stage: u32,
     #[serde(rename = "TimeWhenDone")]
     pub time_when_done: i64,
     #[serde(rename = "TimeWhenRepeats")]
@@ -2668,8 +2669,7 @@ impl crate::writers::ACWritable for PackedWORD {
 
 impl PackedDWORD {
     pub fn read(reader: &mut dyn ACReader) -> Result<Self, Box<dyn std::error::Error>> {
-        crate::readers::read_packed_dword(reader)?;
-        Ok(Self {})
+        Ok(Self(crate::readers::read_packed_dword(reader)?))
     }
 }
 
@@ -2680,8 +2680,8 @@ impl crate::readers::ACDataType for PackedDWORD {
 }
 
 impl PackedDWORD {
-    pub fn write(&self, _writer: &mut dyn ACWriter) -> Result<(), Box<dyn std::error::Error>> {
-        Ok(())
+    pub fn write(&self, writer: &mut dyn ACWriter) -> Result<(), Box<dyn std::error::Error>> {
+        crate::writers::write_packed_dword(writer, self.0)
     }
 }
 
@@ -9079,7 +9079,7 @@ impl ItemProfile {
         let packed_amount = read_u32(reader)?;
         #[allow(unused_variables)]
         let amount = (packed_amount & 0xFFFFFF) as i32;
-        let pwd_type = (packed_amount >> 24) as i32;
+        let pwd_type = (packed_amount as i32) >> 24;
         let object_id = ObjectId::read(reader)?;
 
         match pwd_type {
@@ -15025,7 +15025,7 @@ impl crate::readers::ACDataType for ContractTracker {
             let pos = reader.stream_position().unwrap_or(0);
             tracing::span!(tracing::Level::TRACE, "field", name = "ContractId", position = pos).entered()
         };
-        let contract_id = ContractId::try_from(read_u32(reader)?)?;
+        let contract_id = read_u32(reader)?;
         #[cfg(feature = "tracing")]
         drop(_field_span_contract_id);
         #[cfg(feature = "tracing")]
@@ -15033,7 +15033,7 @@ impl crate::readers::ACDataType for ContractTracker {
             let pos = reader.stream_position().unwrap_or(0);
             tracing::span!(tracing::Level::TRACE, "field", name = "ContractStage", position = pos).entered()
         };
-        let contract_stage = ContractStage::try_from(read_u32(reader)?)?;
+        let contract_stage = read_u32(reader)?;
         #[cfg(feature = "tracing")]
         drop(_field_span_contract_stage);
         #[cfg(feature = "tracing")]
@@ -15069,8 +15069,8 @@ impl crate::writers::ACWritable for ContractTracker {
         let _span = tracing::span!(tracing::Level::DEBUG, "write", r#type = "ContractTracker").entered();
 
         write_u32(writer, self.version)?;
-        write_u32(writer, self.contract_id.clone() as u32)?;
-        write_u32(writer, self.contract_stage.clone() as u32)?;
+        write_u32(writer, self.contract_id)?;
+        write_u32(writer, self.contract_stage)?;
         write_i64(writer, self.time_when_done)?;
         write_i64(writer, self.time_when_repeats)?;
         Ok(())
@@ -10,11 +10,13 @@ pub use generated::messages;
 pub use generated::packets;
 pub use generated::types;
 
+pub mod analysis;
 pub mod constants;
 pub mod dat;
 pub mod filter;
 pub mod network;
 pub mod readers;
+pub mod text;
 pub mod writers;
 
 #[cfg(feature = "cli")]
@@ -0,0 +1,751 @@
+//! Capture anonymizer
+//!
+//! Rewrites a capture so it can be shared: player and account names and
+//! character ids are replaced with pseudonyms, and chat and IP addresses can be
+//! blanked or rewritten. Messages are decoded to find what to redact, then
+//! re-encoded into the frames that carried them. Messages that can't be
+//! decoded, or that don't re-encode to what they decoded as, may still hold
+//! names, so they are zeroed unless the caller asks to keep them.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::message::MessageKind;
+use crate::types::{LoginRequestHeader, WString};
+use crate::writers::ACWritable;
+
+use super::FRAGMENT_CHUNK_SIZE;
+use super::capture::Capture;
+use super::frame::{Frame, FrameFragment};
+use super::pcap::Packet;
+
+/// Fields holding the name of a player or account
+const NAME_FIELDS: &[&str] = &[
+    "Account",
+    "AccountName",
+    "AccountToLoginAs",
+    "AllegianceName",
+    "AuthorName",
+    "BooteeName",
+    "CharacterName",
+    "CraftsmanName",
+    "DisplayName",
+    "GuestName",
+    "HouseOwnerName",
+    "ImbuerName",
+    "MonarchsName",
+    "OwnerName",
+    "RestoredCharName",
+    "ScribeName",
+    "SenderName",
+    "TargetName",
+    "TinkerName",
+];
+
+/// Name fields that hold an account rather than a character
+const ACCOUNT_FIELDS: &[&str] = &["Account", "AccountName", "AccountToLoginAs"];
+
+/// Fields always holding a character's id, and the name field that sits
+/// beside them when there is one
+const CHARACTER_ID_FIELDS: &[(&str, &str)] = &[
+    ("CharacterId", "Name"),
+    ("FriendId", "Name"),
+    ("SenderId", "SenderName"),
+    ("SpeakerId", "DisplayName"),
+];
+
+/// Fields holding a character's id only when the matching name field is present
+const NAMED_ID_FIELDS: &[(&str, &str)] = &[("TargetId", "TargetName")];
+
+/// Messages whose text is chat, and the fields holding it
+const CHAT_MESSAGES: &[&str] = &[
+    "CommunicationChannelBroadcast",
+    "CommunicationEmote",
+    "CommunicationHearDirectSpeech",
+    "CommunicationHearEmote",
+    "CommunicationHearRangedSpeech",
+    "CommunicationHearSoulEmote",
+    "CommunicationHearSpeech",
+    "CommunicationSetAFKMessage",
+    "CommunicationSoulEmote",
+    "CommunicationTalk",
+    "CommunicationTalkDirect",
+    "CommunicationTalkDirectByName",
+    "CommunicationTurbineChat",
+];
+const CHAT_FIELDS: &[&str] = &["Message", "Text"];
+
+/// Textbox message types that echo chat
+const CHAT_TEXTBOX_TYPES: &[&str] = &[
+    "Speech",
+    "Tell",
+    "OutgoingTell",
+    "Channels",
+    "OutgoingChannel",
+    "Social",
+    "OutgoingSocial",
+    "Emote",
+    "Allegiance",
+    "Fellowship",
+    "AdminTell",
+];
+
+/// Size fields that count bytes of chat text, kept consistent after redaction
+const SIZE_FIELDS: &[&str] = &["MessageSize", "PayloadSize"];
+
+/// Pseudonymous character ids are allocated from here up
+const FIRST_PSEUDONYM_ID: u32 = 0x5FFF_0001;
+
+/// What to redact besides names and character ids
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnonymizeOptions {
+    /// Blank chat text rather than only replacing names within it
+    pub blank_chat: bool,
+    /// Replace IPv4 addresses with addresses from 10.0.0.0/8
+    pub rewrite_ips: bool,
+    /// Copy messages that couldn't be parsed or re-encoded as they were,
+    /// rather than zeroing them
+    pub keep_unredacted: bool,
+}
+
+/// What was redacted from a capture
+#[derive(Debug, Default, Serialize)]
+pub struct AnonymizeReport {
+    pub frames: usize,
+    pub frames_rewritten: usize,
+    pub messages_rewritten: usize,
+    pub logins_rewritten: usize,
+    pub names: usize,
+    pub ids: usize,
+    pub addresses: usize,
+    /// Messages that couldn't be parsed, so weren't searched for names
+    pub unparsed: Vec<SkippedMessage>,
+    /// Messages that needed redacting but didn't re-encode to what they
+    /// decoded as
+    pub unencodable: Vec<SkippedMessage>,
+    /// Unparsed and unencodable messages whose data was zeroed
+    pub messages_zeroed: usize,
+}
+
+/// A message that couldn't be redacted
+#[derive(Debug, Serialize)]
+pub struct SkippedMessage {
+    pub id: u32,
+    pub message_type: String,
+}
+
+/// Consistent replacements: the same input always maps to the same output
+#[derive(Default)]
+struct Pseudonyms {
+    names: HashMap<String, String>,
+    /// Learned names, longest first, for replacing within free text
+    by_length: Vec<String>,
+    characters: usize,
+    accounts: usize,
+    ids: HashMap<u32, u32>,
+    addresses: HashMap<[u8; 4], [u8; 4]>,
+}
+
+impl Pseudonyms {
+    fn learn_name(&mut self, name: &str, account: bool) {
+        if name.trim().is_empty() || self.names.contains_key(name) {
+            return;
+        }
+        let pseudonym = if account {
+            self.accounts += 1;
+            format!("Account{}", self.accounts)
+        } else {
+            self.characters += 1;
+            format!("Player{}", self.characters)
+        };
+        self.names.insert(name.to_string(), pseudonym);
+        self.by_length.push(name.to_string());
+        self.by_length.sort_by_key(|n| std::cmp::Reverse(n.len()));
+    }
+
+    fn learn_id(&mut self, id: u32) {
+        if id != 0 && !self.ids.contains_key(&id) {
+            let pseudonym = FIRST_PSEUDONYM_ID + self.ids.len() as u32;
+            self.ids.insert(id, pseudonym);
+        }
+    }
+
+    fn address(&mut self, address: [u8; 4]) -> [u8; 4] {
+        let n = self.addresses.len() as u32 + 1;
+        *self
+            .addresses
+            .entry(address)
+            .or_insert_with(|| (0x0A00_0000 | n).to_be_bytes())
+    }
+
+    /// Learn names and ids from a decoded message
+    fn learn(&mut self, value: &Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, field) in map {
+                    if let Some(name) = field.as_str()
+                        && NAME_FIELDS.contains(&key.as_str())
+                    {
+                        self.learn_name(name, ACCOUNT_FIELDS.contains(&key.as_str()));
+                    }
+                }
+                let fields = CHARACTER_ID_FIELDS
+                    .iter()
+                    .map(|&fields| (fields, true))
+                    .chain(NAMED_ID_FIELDS.iter().map(|&fields| (fields, false)));
+                for ((id_field, name_field), always) in fields {
+                    let named = map.get(name_field).and_then(Value::as_str);
+                    if let Some(id) = map.get(id_field).and_then(as_u32)
+                        && (always || named.is_some())
+                    {
+                        self.learn_id(id);
+                        if let Some(name) = named {
+                            self.learn_name(name, false);
+                        }
+                    }
+                }
+                // Game events are addressed to the player's own character
+                if map.contains_key("event")
+                    && let Some(id) = map.get("object_id").and_then(as_u32)
+                {
+                    self.learn_id(id);
+                }
+                map.values().for_each(|field| self.learn(field));
+            }
+            Value::Array(items) => items.iter().for_each(|item| self.learn(item)),
+            _ => {}
+        }
+    }
+
+    /// Learn the names of objects whose ids are known characters
+    fn learn_object_names(&mut self, value: &Value) {
+        match value {
+            Value::Object(map) => {
+                let id = map.get("ObjectId").and_then(as_u32);
+                if let Some(name) = map.get("Name").and_then(Value::as_str)
+                    && id.is_some_and(|id| self.ids.contains_key(&id))
+                {
+                    self.learn_name(name, false);
+                }
+                map.values()
+                    .for_each(|field| self.learn_object_names(field));
+            }
+            Value::Array(items) => items.iter().for_each(|item| self.learn_object_names(item)),
+            _ => {}
+        }
+    }
+
+    /// Replace learned names within text, matching whole words only
+    fn replace_names(&self, text: &str) -> Option<String> {
+        if let Some(pseudonym) = self.names.get(text) {
+            return Some(pseudonym.clone());
+        }
+
+        let mut result = text.to_string();
+        for name in &self.by_length {
+            let mut replaced = String::with_capacity(result.len());
+            let mut rest = result.as_str();
+            while let Some(at) = rest.find(name.as_str()) {
+                let end = at + name.len();
+                let before = rest[..at].chars().next_back();
+                let after = rest[end..].chars().next();
+                replaced.push_str(&rest[..at]);
+                if before.is_some_and(char::is_alphanumeric)
+                    || after.is_some_and(char::is_alphanumeric)
+                {
+                    replaced.push_str(name);
+                } else {
+                    replaced.push_str(&self.names[name]);
+                }
+                rest = &rest[end..];
+            }
+            replaced.push_str(rest);
+            result = replaced;
+        }
+        (result != text).then_some(result)
+    }
+
+    /// Redact a decoded message in place, returning whether anything changed
+    fn redact(&self, value: &mut Value, blank_chat: bool, in_chat: bool) -> bool {
+        match value {
+            Value::Object(map) => {
+                let chat_textbox = map
+                    .get("Type")
+                    .and_then(Value::as_str)
+                    .is_some_and(|t| CHAT_TEXTBOX_TYPES.contains(&t));
+                let mut changed = false;
+                for (key, field) in map.iter_mut() {
+                    let chat = in_chat || CHAT_MESSAGES.contains(&key.as_str());
+                    if blank_chat
+                        && (chat || chat_textbox)
+                        && CHAT_FIELDS.contains(&key.as_str())
+                        && field.as_str().is_some_and(|s| !s.is_empty())
+                    {
+                        *field = Value::String(String::new());
+                        changed = true;
+                    } else {
+                        changed |= self.redact(field, blank_chat, chat);
+                    }
+                }
+                changed
+            }
+            Value::Array(items) => items.iter_mut().fold(false, |changed, item| {
+                self.redact(item, blank_chat, in_chat) | changed
+            }),
+            Value::String(s) => match self.replace_names(s) {
+                Some(replaced) => {
+                    *s = replaced;
+                    true
+                }
+                None => false,
+            },
+            Value::Number(_) => match as_u32(value).and_then(|id| self.ids.get(&id)) {
+                Some(&pseudonym) => {
+                    *value = Value::from(pseudonym);
+                    true
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Redact a login request header, returning its new bytes if anything changed
+    fn redact_login(&self, bytes: &[u8]) -> Option<Vec<u8>> {
+        let mut cursor = Cursor::new(bytes);
+        let mut header = LoginRequestHeader::read(&mut cursor).ok()?;
+        let rest = &bytes[cursor.position() as usize..];
+
+        let pseudonym = |name: &str| self.names.get(name).cloned().unwrap_or_default();
+        match &mut header {
+            LoginRequestHeader::Type2(login) => {
+                login.account = pseudonym(&login.account);
+                login.account_to_login_as = pseudonym(&login.account_to_login_as);
+                login.password = WString(String::new());
+            }
+            LoginRequestHeader::Type40000002(login) => {
+                login.account = pseudonym(&login.account);
+                login.account_to_login_as = pseudonym(&login.account_to_login_as);
+                login.gls_ticket = String::new();
+            }
+        }
+
+        let mut out = Cursor::new(Vec::new());
+        header.write(&mut out).ok()?;
+        let mut out = out.into_inner();
+        // Length counts the bytes after it, so shift it by the change in size.
+        // It follows the client version string, padded to a 4-byte boundary.
+        let delta = out.len() as i64 - (bytes.len() - rest.len()) as i64;
+        let length_at = (2 + u16::from_le_bytes([bytes[0], bytes[1]]) as usize + 3) & !3;
+        let length = u32::from_le_bytes(out[length_at..length_at + 4].try_into().ok()?);
+        let length = (length as i64 + delta) as u32;
+        out[length_at..length_at + 4].copy_from_slice(&length.to_le_bytes());
+        out.extend(rest);
+        (out != bytes).then_some(out)
+    }
+
+    fn learn_login(&mut self, bytes: &[u8]) {
+        let Ok(header) = LoginRequestHeader::read(&mut Cursor::new(bytes)) else {
+            return;
+        };
+        let (account, login_as) = match &header {
+            LoginRequestHeader::Type2(login) => (&login.account, &login.account_to_login_as),
+            LoginRequestHeader::Type40000002(login) => (&login.account, &login.account_to_login_as),
+        };
+        self.learn_name(account, true);
+        self.learn_name(login_as, true);
+    }
+}
+
+fn as_u32(value: &Value) -> Option<u32> {
+    value.as_u64().and_then(|n| u32::try_from(n).ok())
+}
+
+fn encode(message: &MessageKind) -> Option<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+    match message {
+        MessageKind::C2S(message) => message.write(&mut out).ok()?,
+        MessageKind::S2C(message) => message.write(&mut out).ok()?,
+    }
+    Some(out.into_inner())
+}
+
+/// Re-encode a redacted message, checking it decodes back to the same value
+fn reencode(
+    original: &Value,
+    redacted: &Value,
+    trailing: &[u8],
+    old_len: usize,
+) -> Option<Vec<u8>> {
+    let mut redacted = redacted.clone();
+    let message: MessageKind = serde_json::from_value(redacted.clone()).ok()?;
+    let mut data = encode(&message)?;
+
+    // Sizes embedded in chat payloads count the text, so follow its change
+    let delta = data.len() as i64 + trailing.len() as i64 - old_len as i64;
+    if delta != 0 && adjust_sizes(&mut redacted, original, delta) {
+        let message: MessageKind = serde_json::from_value(redacted.clone()).ok()?;
+        data = encode(&message)?;
+    }
+    data.extend(trailing);
+
+    let raw = super::RawMessage::from_fragment(data.clone(), 0, 0).ok()?;
+    let decoded = serde_json::to_value(raw.parse().ok()?).ok()?;
+    (decoded == redacted).then_some(data)
+}
+
+fn adjust_sizes(value: &mut Value, original: &Value, delta: i64) -> bool {
+    match (value, original) {
+        (Value::Object(map), Value::Object(original)) => {
+            let mut changed = false;
+            for (key, field) in map.iter_mut() {
+                let Some(before) = original.get(key) else {
+                    continue;
+                };
+                if SIZE_FIELDS.contains(&key.as_str())
+                    && let Some(size) = before.as_i64()
+                {
+                    *field = Value::from(size + delta);
+                    changed = true;
+                } else {
+                    changed |= adjust_sizes(field, before, delta);
+                }
+            }
+            changed
+        }
+        _ => false,
+    }
+}
+
+/// Redact names, character ids, chat and addresses from a capture
+///
+/// Every message is decoded and searched for player and account names and
+/// character ids, which are then replaced everywhere they appear with stable
+/// pseudonyms ("Player1", "Account1", ids from 0x5FFF0001). Changed messages
+/// are re-encoded and split back into fragments in the frames that carried
+/// them, so the result reassembles and parses the same apart from the
+/// redacted values. Frames with nothing to redact are copied unchanged.
+///
+/// Messages that can't be parsed or re-encoded are zeroed, keeping their
+/// length, unless [`AnonymizeOptions::keep_unredacted`] is set. Either way
+/// they are listed in the report.
+pub fn anonymize(
+    capture: &Capture,
+    options: AnonymizeOptions,
+) -> Result<(Vec<Packet>, AnonymizeReport)> {
+    let mut pseudonyms = Pseudonyms::default();
+    let mut report = AnonymizeReport {
+        frames: capture.packets.len(),
+        ..Default::default()
+    };
+
+    let frames: Vec<Option<Frame>> = capture
+        .packets
+        .iter()
+        .map(|p| Frame::split(&p.data))
+        .collect();
+    for frame in frames.iter().flatten() {
+        for packet in &frame.packets {
+            if let Some(login) = &packet.login {
+                pseudonyms.learn_login(login);
+            }
+        }
+    }
+
+    let decoded: Vec<Option<(Value, usize)>> = capture
+        .messages
+        .iter()
+        .map(|m| {
+            let (message, remainder) = m.parse_with_remainder().ok()?;
+            Some((serde_json::to_value(message).ok()?, remainder))
+        })
+        .collect();
+    for (value, _) in decoded.iter().flatten() {
+        pseudonyms.learn(value);
+    }
+    for (value, _) in decoded.iter().flatten() {
+        pseudonyms.learn_object_names(value);
+    }
+    report.names = pseudonyms.names.len();
+    report.ids = pseudonyms.ids.len();
+
+    // New data for each redacted message, by the frames and fragment
+    // sequence that carried it
+    let mut rewritten: HashMap<(usize, u32), usize> = HashMap::new();
+    let mut replacements: Vec<Vec<u8>> = Vec::new();
+    for (i, (message, decoded)) in capture.messages.iter().zip(&decoded).enumerate() {
+        let skipped = SkippedMessage {
+            id: message.id,
+            message_type: message.message_type.clone(),
+        };
+        let data = match decoded {
+            Some((value, remainder)) => {
+                let mut redacted = value.clone();
+                if !pseudonyms.redact(&mut redacted, options.blank_chat, false) {
+                    continue;
+                }
+                let trailing = &message.data[message.data.len() - remainder..];
+                let data = reencode(value, &redacted, trailing, message.data.len());
+                match &data {
+                    Some(_) => report.messages_rewritten += 1,
+                    None => report.unencodable.push(skipped),
+                }
+                data
+            }
+            None => {
+                report.unparsed.push(skipped);
+                None
+            }
+        };
+        let data = match data {
+            Some(data) => data,
+            None if options.keep_unredacted => continue,
+            None => {
+                report.messages_zeroed += 1;
+                vec![0; message.data.len()]
+            }
+        };
+        for &frame in capture.message_frames(i) {
+            rewritten.insert((frame, message.sequence), replacements.len());
+        }
+        replacements.push(data);
+    }
+
+    let mut packets = Vec::with_capacity(capture.packets.len());
+    for (index, (packet, frame)) in capture.packets.iter().zip(frames).enumerate() {
+        let Some(mut frame) = frame else {
+            packets.push(packet.clone());
+            continue;
+        };
+        let mut changed = false;
+
+        for ac_packet in &mut frame.packets {
+            if let Some(login) = &mut ac_packet.login
+                && let Some(redacted) = pseudonyms.redact_login(login)
+            {
+                *login = redacted;
+                report.logins_rewritten += 1;
+                changed = true;
+            }
+
+            let mut fragments = Vec::with_capacity(ac_packet.fragments.len());
+            for fragment in ac_packet.fragments.drain(..) {
+                let Some(&replacement) = rewritten.get(&(index, fragment.sequence)) else {
+                    fragments.push(fragment);
+                    continue;
+                };
+                changed = true;
+                let chunks: Vec<&[u8]> = replacements[replacement]
+                    .chunks(FRAGMENT_CHUNK_SIZE)
+                    .collect();
+                let count = chunks.len() as u16;
+                let mut indexes = vec![fragment.index];
+                // Chunks beyond the original count ride along with its last one
+                if fragment.index + 1 == fragment.count {
+                    indexes.extend(fragment.count..count);
+                }
+                for index in indexes.into_iter().filter(|&i| i < count) {
+                    fragments.push(FrameFragment {
+                        sequence: fragment.sequence,
+                        id: fragment.id,
+                        count,
+                        index,
+                        group: fragment.group,
+                        data: chunks[index as usize].to_vec(),
+                    });
+                }
+            }
+            ac_packet.fragments = fragments;
+        }
+
+        if options.rewrite_ips {
+            let (source, destination) = frame.addresses_mut();
+            for address in [source, destination] {
+                let new = pseudonyms.address(address.try_into().expect("4-byte address"));
+                address.copy_from_slice(&new);
+            }
+            changed = true;
+        }
+
+        if changed {
+            report.frames_rewritten += 1;
+            packets.push(Packet {
+                data: frame.to_bytes(),
+                ..packet.clone()
+            });
+        } else {
+            packets.push(packet.clone());
+        }
+    }
+    report.addresses = pseudonyms.addresses.len();
+
+    Ok((packets, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enums::{AuthFlags, ChatFragmentType, PacketHeaderFlags};
+    use crate::message::S2CMessage;
+    use crate::messages::s2c::CommunicationHearSpeech;
+    use crate::network::capture::tests::{fragment, frame};
+    use crate::network::pcap::PcapHeader;
+    use crate::types::{LoginRequestHeaderType2, ObjectId};
+
+    fn pseudonyms() -> Pseudonyms {
+        let mut pseudonyms = Pseudonyms::default();
+        pseudonyms.learn(&serde_json::json!({
+            "Characters": [{"CharacterId": 0x50000001u32, "Name": "Al", "SecondsGreyedOut": 0}],
+            "Account": "alfred",
+            "SenderName": "Holy Macaroni",
+            "SenderId": 0x50000002u32,
+        }));
+        pseudonyms
+    }
+
+    fn speech(sender: &str, id: u32, text: &str) -> Vec<u8> {
+        let message = S2CMessage::CommunicationHearSpeech(CommunicationHearSpeech {
+            message: text.to_string(),
+            sender_name: sender.to_string(),
+            sender_id: ObjectId(id),
+            type_: ChatFragmentType::Speech,
+        });
+        encode(&MessageKind::S2C(Box::new(message))).unwrap()
+    }
+
+    #[test]
+    fn test_pseudonyms_are_consistent() {
+        let pseudonyms = pseudonyms();
+        assert_eq!(pseudonyms.names["Holy Macaroni"], "Player1");
+        assert_eq!(pseudonyms.names["Al"], "Player2");
+        assert_eq!(pseudonyms.names["alfred"], "Account1");
+        assert_eq!(pseudonyms.ids[&0x50000002], FIRST_PSEUDONYM_ID);
+        assert_eq!(pseudonyms.ids[&0x50000001], FIRST_PSEUDONYM_ID + 1);
+
+        // Whole words only, longest name first
+        assert_eq!(
+            pseudonyms
+                .replace_names("Al tells you, \"Holy Macaroni, meet Alice\"")
+                .unwrap(),
+            "Player2 tells you, \"Player1, meet Alice\""
+        );
+        assert_eq!(pseudonyms.replace_names("Always"), None);
+    }
+
+    #[test]
+    fn test_redact_blanks_chat_and_replaces_ids() {
+        let pseudonyms = pseudonyms();
+        let mut value = serde_json::json!({"S2C": {"CommunicationHearSpeech": {
+            "Message": "hi Al", "SenderName": "Holy Macaroni", "SenderId": 0x50000002u32, "Type": "Speech",
+        }}});
+
+        let mut kept = value.clone();
+        assert!(pseudonyms.redact(&mut kept, false, false));
+        assert_eq!(
+            kept["S2C"]["CommunicationHearSpeech"]["Message"],
+            "hi Player2"
+        );
+
+        assert!(pseudonyms.redact(&mut value, true, false));
+        let speech = &value["S2C"]["CommunicationHearSpeech"];
+        assert_eq!(speech["Message"], "");
+        assert_eq!(speech["SenderName"], "Player1");
+        assert_eq!(speech["SenderId"], FIRST_PSEUDONYM_ID);
+
+        let mut textbox = serde_json::json!({"Text": "You say, \"hello\"", "Type": "Speech"});
+        assert!(pseudonyms.redact(&mut textbox, true, false));
+        assert_eq!(textbox["Text"], "");
+        let mut system = serde_json::json!({"Text": "You have 5 pyreals", "Type": "System"});
+        assert!(!pseudonyms.redact(&mut system, true, false));
+    }
+
+    #[test]
+    fn test_anonymized_capture_reparses() {
+        let login = LoginRequestHeader::Type2(LoginRequestHeaderType2 {
+            client_version: "1802".to_string(),
+            length: 0,
+            flags: AuthFlags::None,
+            sequence: 0,
+            account: "alfred".to_string(),
+            account_to_login_as: String::new(),
+            password: WString("hunter2".to_string()),
+        });
+        let mut login_bytes = Cursor::new(Vec::new());
+        login.write(&mut login_bytes).unwrap();
+        let mut login_frame = frame(
+            PacketHeaderFlags::LOGIN_REQUEST.bits(),
+            login_bytes.get_ref(),
+        );
+        login_frame.data[26..30].copy_from_slice(&[192, 168, 1, 20]);
+
+        // A message filling one fragment, so the longer pseudonym needs another
+        let text = "x".repeat(430);
+        let data = speech("Al", 0x50000002, &text);
+        assert_eq!(data.len(), FRAGMENT_CHUNK_SIZE);
+        let packets = vec![login_frame, fragment(0xA0A0_0001, 1, 0, &data)];
+        let capture = Capture::from_packets(PcapHeader::default(), packets).unwrap();
+
+        let options = AnonymizeOptions {
+            blank_chat: false,
+            rewrite_ips: true,
+            keep_unredacted: false,
+        };
+        let (packets, report) = anonymize(&capture, options).unwrap();
+        assert_eq!(report.messages_rewritten, 1);
+        assert_eq!(report.logins_rewritten, 1);
+        assert!(report.unparsed.is_empty());
+        assert!(report.unencodable.is_empty());
+        assert_eq!(&packets[0].data[26..30], &[10, 0, 0, 1]);
+
+        let frame = Frame::split(&packets[0].data).unwrap();
+        let login =
+            LoginRequestHeader::read(&mut Cursor::new(frame.packets[0].login.as_deref().unwrap()))
+                .unwrap();
+        let LoginRequestHeader::Type2(login) = login else {
+            panic!("expected a password login");
+        };
+        assert_eq!(login.account, "Account1");
+        assert_eq!(login.password.0, "");
+
+        let packets = packets[1..].to_vec();
+        let anonymized = Capture::from_packets(PcapHeader::default(), packets).unwrap();
+        assert_eq!(anonymized.messages.len(), 1);
+        assert_eq!(anonymized.message_frames(0), &[0]);
+        assert_eq!(
+            anonymized.messages[0].data,
+            speech("Player1", FIRST_PSEUDONYM_ID, &text)
+        );
+    }
+
+    #[test]
+    fn test_unparsed_messages_are_zeroed_unless_kept() {
+        let garbage = [0xEF, 0xBE, 0xAD, 0xDE, b'A', b'l'];
+        let packets = vec![
+            fragment(0xA0A0_0001, 1, 0, &garbage),
+            fragment(0xA0A0_0002, 1, 0, &speech("Al", 0x50000002, "hi")),
+        ];
+        let capture = Capture::from_packets(PcapHeader::default(), packets).unwrap();
+
+        let (packets, report) = anonymize(&capture, AnonymizeOptions::default()).unwrap();
+        assert_eq!(report.unparsed.len(), 1);
+        assert_eq!(report.unparsed[0].id, capture.messages[0].id);
+        assert_eq!(report.messages_zeroed, 1);
+        assert_eq!(report.messages_rewritten, 1);
+        let anonymized = Capture::from_packets(PcapHeader::default(), packets).unwrap();
+        assert_eq!(anonymized.messages[0].data, vec![0; garbage.len()]);
+
+        let options = AnonymizeOptions {
+            keep_unredacted: true,
+            ..Default::default()
+        };
+        let (packets, report) = anonymize(&capture, options).unwrap();
+        assert_eq!(report.unparsed.len(), 1);
+        assert_eq!(report.messages_zeroed, 0);
+        let kept = Capture::from_packets(PcapHeader::default(), packets).unwrap();
+        assert_eq!(kept.messages[0].data, garbage);
+    }
+}
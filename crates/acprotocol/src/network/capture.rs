@@ -0,0 +1,254 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Read, Write};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+use crate::enums::PacketHeaderFlags;
+
+use super::packet_parser::{FragmentAssembler, packet_flags};
+use super::pcap::{Packet, PcapHeader, PcapIterator, PcapWriter};
+use super::raw_message::RawMessage;
+
+/// A capture held in memory, with its assembled messages traced back to the
+/// packets that carried them
+///
+/// Used to cut captures down without breaking reassembly: any selection of
+/// messages maps to the full set of frames carrying their fragments.
+pub struct Capture {
+    pub header: PcapHeader,
+    pub packets: Vec<Packet>,
+    pub messages: Vec<RawMessage>,
+    /// Frames carrying each message, parallel to `messages`
+    frames: Vec<Vec<usize>>,
+    /// Session of each packet, parallel to `packets`
+    sessions: Vec<u32>,
+}
+
+impl Capture {
+    /// Read every packet and assemble its messages
+    pub fn read<R: Read>(iter: PcapIterator<R>) -> io::Result<Self> {
+        let header = iter.header();
+        let packets = iter.collect::<io::Result<Vec<_>>>()?;
+        Self::from_packets(header, packets)
+    }
+
+    /// Load a capture from a pcap file
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(super::pcap::open(path)?)
+    }
+
+    /// Assemble messages from packets already in memory
+    ///
+    /// A new session starts at every packet carrying a login request; packets
+    /// before the first login belong to session 0.
+    pub fn from_packets(header: PcapHeader, packets: Vec<Packet>) -> io::Result<Self> {
+        let mut assembler = FragmentAssembler::new();
+        let mut messages = Vec::new();
+        let mut sessions = Vec::with_capacity(packets.len());
+        let mut session = 0;
+
+        for packet in &packets {
+            if packet_flags(&packet.data)
+                .is_some_and(|flags| flags.contains(PacketHeaderFlags::LOGIN_REQUEST))
+            {
+                session += 1;
+            }
+            sessions.push(session);
+            messages.extend(assembler.parse_packet(packet)?);
+        }
+
+        let frames = messages
+            .iter()
+            .map(|m| assembler.message_frames(m.id).to_vec())
+            .collect();
+
+        Ok(Self {
+            header,
+            packets,
+            messages,
+            frames,
+            sessions,
+        })
+    }
+
+    /// The frames carrying the message at `index` in `messages`
+    pub fn message_frames(&self, index: usize) -> &[usize] {
+        &self.frames[index]
+    }
+
+    /// The session a frame belongs to
+    pub fn session(&self, frame: usize) -> u32 {
+        self.sessions[frame]
+    }
+
+    /// The session a message belongs to, which is that of its last frame
+    pub fn message_session(&self, index: usize) -> u32 {
+        self.frames[index]
+            .last()
+            .map_or(0, |&frame| self.sessions[frame])
+    }
+
+    /// The frames needed to reassemble the given messages, in capture order
+    pub fn frames_for<'a>(&self, messages: impl IntoIterator<Item = &'a RawMessage>) -> Vec<usize> {
+        let index: BTreeMap<u32, usize> = self
+            .messages
+            .iter()
+            .enumerate()
+            .map(|(i, m)| (m.id, i))
+            .collect();
+        messages
+            .into_iter()
+            .filter_map(|m| index.get(&m.id))
+            .flat_map(|&i| self.frames[i].iter().copied())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Split the capture into parts by a key per frame
+    ///
+    /// Each part holds the frames with its key, plus every frame carrying a
+    /// fragment of a message completed in the part, so that each part still
+    /// reassembles on its own. Frames shared by messages straddling a boundary
+    /// are written to both parts.
+    pub fn partition<K: Ord>(&self, key: impl Fn(usize) -> K) -> BTreeMap<K, Vec<usize>> {
+        let mut parts: BTreeMap<K, BTreeSet<usize>> = BTreeMap::new();
+        for frame in 0..self.packets.len() {
+            parts.entry(key(frame)).or_default().insert(frame);
+        }
+        for frames in &self.frames {
+            if let Some(&last) = frames.last() {
+                parts
+                    .entry(key(last))
+                    .or_default()
+                    .extend(frames.iter().copied());
+            }
+        }
+        parts
+            .into_iter()
+            .map(|(k, frames)| (k, frames.into_iter().collect()))
+            .collect()
+    }
+
+    /// Write the given frames as a pcap file with this capture's header
+    pub fn write_frames<W: Write>(&self, writer: W, frames: &[usize]) -> io::Result<W> {
+        let mut writer = PcapWriter::new(writer, &self.header)?;
+        for &frame in frames {
+            writer.write_packet(&self.packets[frame])?;
+        }
+        writer.into_inner()
+    }
+
+    /// Save the given frames to a new pcap file
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save<P: AsRef<Path>>(&self, path: P, frames: &[usize]) -> io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.write_frames(io::BufWriter::new(file), frames)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// An Ethernet/IPv4/UDP frame wrapping one AC packet
+    pub(crate) fn frame(flags: u32, body: &[u8]) -> Packet {
+        let mut data = vec![0u8; 14];
+        data.push(0x45);
+        data.extend([0u8; 19]);
+        data.extend([0u8; 8]);
+        data.extend(0u32.to_le_bytes()); // sequence
+        data.extend(flags.to_le_bytes());
+        data.extend(0u32.to_le_bytes()); // checksum
+        data.extend(0u16.to_le_bytes()); // recipient id
+        data.extend(0u16.to_le_bytes()); // time since last packet
+        data.extend((body.len() as u16).to_le_bytes());
+        data.extend(0u16.to_le_bytes()); // iteration
+        data.extend(body);
+        Packet {
+            ts_sec: 1000,
+            ts_usec: 0,
+            data,
+        }
+    }
+
+    /// A blob fragment holding `chunk` as part `index` of `count`
+    pub(crate) fn fragment(sequence: u32, count: u16, index: u16, chunk: &[u8]) -> Packet {
+        let mut body = Vec::new();
+        body.extend(sequence.to_le_bytes());
+        body.extend(0x8000_0000u32.to_le_bytes());
+        body.extend(count.to_le_bytes());
+        body.extend((chunk.len() as u16 + 16).to_le_bytes());
+        body.extend(index.to_le_bytes());
+        body.extend(5u16.to_le_bytes());
+        body.extend(chunk);
+        frame(PacketHeaderFlags::BLOB_FRAGMENTS.bits(), &body)
+    }
+
+    pub(crate) fn login() -> Packet {
+        frame(PacketHeaderFlags::LOGIN_REQUEST.bits(), &[])
+    }
+
+    /// An ItemDeleteObject message split into two fragments
+    fn split_message(sequence: u32) -> (Packet, Packet) {
+        let mut data = 0xF747u32.to_le_bytes().to_vec();
+        data.extend(0x5000_0001u32.to_le_bytes());
+        data.extend([1, 0, 0, 0]);
+        let mut first = data.clone();
+        first.resize(448, 0);
+        (
+            fragment(sequence, 2, 0, &first),
+            fragment(sequence, 2, 1, &data[..4]),
+        )
+    }
+
+    /// Two messages whose fragments interleave across two sessions
+    fn capture(base: u32) -> Capture {
+        let (a1, a2) = split_message(base);
+        let (b1, b2) = split_message(base + 1);
+        let packets = vec![login(), a1, b1, a2, login(), b2];
+        Capture::from_packets(PcapHeader::default(), packets).unwrap()
+    }
+
+    #[test]
+    fn test_frames_for_messages() {
+        let capture = capture(0xCA00_0010);
+        assert_eq!(capture.messages.len(), 2);
+        assert_eq!(capture.message_frames(0), &[1, 3]);
+        assert_eq!(capture.message_frames(1), &[2, 5]);
+        assert_eq!(capture.frames_for(&capture.messages), vec![1, 2, 3, 5]);
+        assert_eq!(capture.frames_for(&capture.messages[1..]), vec![2, 5]);
+    }
+
+    #[test]
+    fn test_sliced_capture_reassembles() {
+        let capture = capture(0xCA00_0020);
+        let frames = capture.frames_for(&capture.messages[1..]);
+        let bytes = capture.write_frames(Vec::new(), &frames).unwrap();
+
+        let iter = PcapIterator::<io::Cursor<&[u8]>>::from_bytes(&bytes).unwrap();
+        let sliced = Capture::read(iter).unwrap();
+        assert_eq!(sliced.packets.len(), 2);
+        assert_eq!(sliced.messages.len(), 1);
+        assert_eq!(sliced.messages[0].data, capture.messages[1].data);
+    }
+
+    #[test]
+    fn test_partition_by_session() {
+        let capture = capture(0xCA00_0030);
+        assert_eq!(
+            (0..6).map(|f| capture.session(f)).collect::<Vec<_>>(),
+            vec![1, 1, 1, 1, 2, 2]
+        );
+        assert_eq!(capture.message_session(0), 1);
+        assert_eq!(capture.message_session(1), 2);
+
+        let parts = capture.partition(|frame| capture.session(frame));
+        assert_eq!(parts[&1], vec![0, 1, 2, 3]);
+        // The second message started in session 1 but completes in session 2
+        assert_eq!(parts[&2], vec![2, 4, 5]);
+    }
+}
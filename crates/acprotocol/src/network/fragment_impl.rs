@@ -26,31 +26,30 @@ impl FragmentMetadata {
     }
 }
 
-// We use a thread-local or module-level storage approach since we can't add fields to generated struct
-// This maps sequence IDs to metadata
-use std::collections::HashMap;
-use std::sync::Mutex;
-use std::sync::OnceLock;
-
-fn get_metadata_store() -> &'static Mutex<HashMap<u32, FragmentMetadata>> {
-    static STORE: OnceLock<Mutex<HashMap<u32, FragmentMetadata>>> = OnceLock::new();
-    STORE.get_or_init(|| Mutex::new(HashMap::new()))
+/// A message being assembled from its fragments
+///
+/// The generated `Fragment` has no room for what has been received so far,
+/// so each pending fragment carries its own metadata alongside it.
+#[derive(Debug, Clone)]
+pub struct PendingFragment {
+    pub fragment: Fragment,
+    pub metadata: FragmentMetadata,
 }
 
-impl Fragment {
+impl PendingFragment {
     /// Create a new fragment with the given sequence and chunk count
     pub fn new(sequence: u32, count: u16) -> Self {
-        let mut store = get_metadata_store().lock().unwrap();
-        store.insert(sequence, FragmentMetadata::new(count));
-
         Self {
-            header: FragmentHeader {
-                sequence,
-                id: 0,
-                count,
-                index: 0,
+            fragment: Fragment {
+                header: FragmentHeader {
+                    sequence,
+                    id: 0,
+                    count,
+                    index: 0,
+                },
+                data: vec![0; count as usize * FRAGMENT_CHUNK_SIZE],
             },
-            data: vec![0; count as usize * FRAGMENT_CHUNK_SIZE],
+            metadata: FragmentMetadata::new(count),
         }
     }
 
@@ -59,74 +58,55 @@ impl Fragment {
     pub fn add_chunk(&mut self, data: &[u8], index: usize, chunk_size: usize) {
         let start = index * FRAGMENT_CHUNK_SIZE;
         let end = start + data.len();
-        if end <= self.data.len() {
-            self.data[start..end].copy_from_slice(data);
+        if end <= self.fragment.data.len() {
+            self.fragment.data[start..end].copy_from_slice(data);
 
             // Track received chunks and update total length to the maximum written position
-            let mut store = get_metadata_store().lock().unwrap();
-            if let Some(metadata) = store.get_mut(&self.header.sequence) {
-                if !metadata.chunks[index] {
-                    metadata.chunks[index] = true;
-                    metadata.received_chunks += 1;
-                }
-                // Track the maximum end position (start of this chunk + its actual size)
-                let chunk_end = start + chunk_size;
-                if chunk_end > metadata.total_length {
-                    metadata.total_length = chunk_end;
-                }
+            let metadata = &mut self.metadata;
+            if !metadata.chunks[index] {
+                metadata.chunks[index] = true;
+                metadata.received_chunks += 1;
+            }
+            // Track the maximum end position (start of this chunk + its actual size)
+            let chunk_end = start + chunk_size;
+            if chunk_end > metadata.total_length {
+                metadata.total_length = chunk_end;
             }
         }
     }
 
     /// Check if all fragments have been received
     pub fn is_complete(&self) -> bool {
-        let store = get_metadata_store().lock().unwrap();
-        if let Some(metadata) = store.get(&self.header.sequence) {
-            metadata.received_chunks == self.header.count as usize
-        } else {
-            false
-        }
+        self.metadata.received_chunks == self.fragment.header.count as usize
     }
 
     /// Get the assembled data
     /// Like C#, return the full buffer - message parsers are self-delimiting
     pub fn get_data(&self) -> &[u8] {
-        &self.data
+        &self.fragment.data
+    }
+
+    /// Get the length of the message data received so far, without the buffer's padding
+    pub fn get_total_length(&self) -> usize {
+        self.metadata.total_length
     }
 
     /// Set size and group on the fragment
     pub fn set_fragment_info(&mut self, size: u16, group: u16) {
-        let mut store = get_metadata_store().lock().unwrap();
-        if let Some(metadata) = store.get_mut(&self.header.sequence) {
-            metadata.size = size;
-            metadata.group = group;
-        }
+        self.metadata.size = size;
+        self.metadata.group = group;
     }
 
     /// Get size for this fragment
     #[allow(dead_code)]
     pub fn get_size(&self) -> u16 {
-        let store = get_metadata_store().lock().unwrap();
-        store
-            .get(&self.header.sequence)
-            .map(|m| m.size)
-            .unwrap_or(0)
+        self.metadata.size
     }
 
     /// Get group for this fragment
     #[allow(dead_code)]
     pub fn get_group(&self) -> u16 {
-        let store = get_metadata_store().lock().unwrap();
-        store
-            .get(&self.header.sequence)
-            .map(|m| m.group)
-            .unwrap_or(0)
-    }
-
-    /// Clean up metadata when fragment is done
-    pub fn cleanup(&self) {
-        let mut store = get_metadata_store().lock().unwrap();
-        store.remove(&self.header.sequence);
+        self.metadata.group
     }
 }
 
@@ -141,20 +121,17 @@ mod tests {
 
     #[test]
     fn test_fragment_new() {
-        let fragment = Fragment::new(12345, 3);
+        let fragment = PendingFragment::new(12345, 3);
 
-        assert_eq!(fragment.header.sequence, 12345);
-        assert_eq!(fragment.header.count, 3);
-        assert_eq!(fragment.data.len(), 3 * FRAGMENT_CHUNK_SIZE);
+        assert_eq!(fragment.fragment.header.sequence, 12345);
+        assert_eq!(fragment.fragment.header.count, 3);
+        assert_eq!(fragment.get_data().len(), 3 * FRAGMENT_CHUNK_SIZE);
         assert!(!fragment.is_complete());
-
-        // Cleanup
-        fragment.cleanup();
     }
 
     #[test]
     fn test_fragment_add_chunk() {
-        let mut fragment = Fragment::new(100, 2);
+        let mut fragment = PendingFragment::new(100, 2);
 
         // Add first chunk
         let chunk1 = vec![0xAA; 100];
@@ -177,14 +154,11 @@ mod tests {
         assert_eq!(data[99], 0xAA); // First chunk ends at 99
         assert_eq!(data[FRAGMENT_CHUNK_SIZE], 0xBB); // Second chunk starts at 448
         assert_eq!(data[FRAGMENT_CHUNK_SIZE + 199], 0xBB); // Second chunk ends at 647
-
-        // Cleanup
-        fragment.cleanup();
     }
 
     #[test]
     fn test_fragment_is_complete() {
-        let mut fragment = Fragment::new(200, 3);
+        let mut fragment = PendingFragment::new(200, 3);
 
         assert!(!fragment.is_complete());
 
@@ -196,14 +170,11 @@ mod tests {
 
         fragment.add_chunk(&[3; 10], 2, 10);
         assert!(fragment.is_complete());
-
-        // Cleanup
-        fragment.cleanup();
     }
 
     #[test]
     fn test_fragment_duplicate_chunk() {
-        let mut fragment = Fragment::new(300, 2);
+        let mut fragment = PendingFragment::new(300, 2);
 
         // Add same chunk twice
         fragment.add_chunk(&[0xFF; 50], 0, 50);
@@ -214,27 +185,21 @@ mod tests {
 
         fragment.add_chunk(&[0xDD; 50], 1, 50);
         assert!(fragment.is_complete());
-
-        // Cleanup
-        fragment.cleanup();
     }
 
     #[test]
     fn test_fragment_set_and_get_info() {
-        let mut fragment = Fragment::new(400, 1);
+        let mut fragment = PendingFragment::new(400, 1);
 
         fragment.set_fragment_info(1024, 5);
 
         assert_eq!(fragment.get_size(), 1024);
         assert_eq!(fragment.get_group(), 5);
-
-        // Cleanup
-        fragment.cleanup();
     }
 
     #[test]
     fn test_fragment_get_data() {
-        let mut fragment = Fragment::new(500, 1);
+        let mut fragment = PendingFragment::new(500, 1);
 
         let test_data = vec![0x12, 0x34, 0x56, 0x78];
         fragment.add_chunk(&test_data, 0, test_data.len());
@@ -245,29 +210,20 @@ mod tests {
         assert_eq!(data[1], 0x34);
         assert_eq!(data[2], 0x56);
         assert_eq!(data[3], 0x78);
-
-        // Cleanup
-        fragment.cleanup();
     }
 
     #[test]
-    fn test_fragment_cleanup() {
-        let fragment = Fragment::new(600, 1);
-
-        // Verify metadata exists
-        {
-            let store = get_metadata_store().lock().unwrap();
-            assert!(store.contains_key(&600));
-        }
+    fn test_fragments_with_the_same_sequence_are_independent() {
+        let mut first = PendingFragment::new(600, 2);
+        let mut second = PendingFragment::new(600, 1);
 
-        // Cleanup
-        fragment.cleanup();
+        first.add_chunk(&[1; 10], 0, 10);
+        second.add_chunk(&[2; 20], 0, 20);
 
-        // Verify metadata removed
-        {
-            let store = get_metadata_store().lock().unwrap();
-            assert!(!store.contains_key(&600));
-        }
+        assert!(!first.is_complete());
+        assert!(second.is_complete());
+        assert_eq!(first.get_total_length(), 10);
+        assert_eq!(second.get_total_length(), 20);
     }
 
     #[test]
@@ -0,0 +1,281 @@
+use std::io::Cursor;
+
+use crate::enums::PacketHeaderFlags;
+use crate::readers::ACDataType;
+use crate::writers::ACWritable;
+
+use super::packet::PacketHeader;
+use super::packet_parser::{network_offsets, skip_optional_headers};
+use super::packet_reader::PacketReader;
+
+/// Size of a blob fragment's header
+const FRAGMENT_HEADER_SIZE: usize = 16;
+
+/// Value the checksum field holds while a packet header is hashed
+const HEADER_CHECKSUM_SEED: u32 = 0xBADD_70DD;
+
+/// A captured frame split into the parts needed to rewrite and rebuild it
+pub(crate) struct Frame {
+    /// Link, IP and UDP headers, up to the first AC packet
+    pub prefix: Vec<u8>,
+    ip_offset: usize,
+    pub packets: Vec<FramePacket>,
+}
+
+/// One AC packet within a frame
+pub(crate) struct FramePacket {
+    pub header: PacketHeader,
+    /// Optional headers before the fragments, or before the login request
+    pub optional: Vec<u8>,
+    /// The login request header, for packets flagged LOGIN_REQUEST
+    pub login: Option<Vec<u8>>,
+    pub fragments: Vec<FrameFragment>,
+    /// Anything between the last fragment and the end of the packet
+    pub trailing: Vec<u8>,
+    /// What the body hash is XORed with in the checksum: the ISAAC key for
+    /// packets flagged ENCRYPTED_CHECKSUM, otherwise 0
+    checksum_key: u32,
+}
+
+/// One blob fragment within an AC packet
+pub(crate) struct FrameFragment {
+    pub sequence: u32,
+    pub id: u32,
+    pub count: u16,
+    pub index: u16,
+    pub group: u16,
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Split a captured frame, or None if it doesn't hold well-formed AC packets
+    pub fn split(data: &[u8]) -> Option<Self> {
+        let (ip_offset, ac_start) = network_offsets(data)?;
+        let payload = &data[ac_start..];
+        let mut reader = PacketReader::new(payload);
+        let mut packets = Vec::new();
+
+        while reader.remaining() > 0 {
+            let start = reader.position();
+            let header = PacketHeader::read(&mut reader).ok()?;
+            let end = start + PacketHeader::BASE_SIZE + header.size as usize;
+            if end > payload.len() {
+                return None;
+            }
+
+            let optional_start = reader.position();
+            let has_fragments = skip_optional_headers(&mut reader, header.flags).ok()?;
+            let optional = payload[optional_start..reader.position()].to_vec();
+            if reader.position() > end {
+                return None;
+            }
+
+            let mut login = None;
+            let mut fragments = Vec::new();
+            if !has_fragments {
+                login = Some(payload[reader.position()..end].to_vec());
+                reader.set_position(end);
+            } else if header.flags.contains(PacketHeaderFlags::BLOB_FRAGMENTS) {
+                while reader.position() + FRAGMENT_HEADER_SIZE <= end {
+                    let sequence = reader.read_u32().ok()?;
+                    let id = reader.read_u32().ok()?;
+                    let count = reader.read_u16().ok()?;
+                    let size = reader.read_u16().ok()? as usize;
+                    let index = reader.read_u16().ok()?;
+                    let group = reader.read_u16().ok()?;
+                    let len = size.checked_sub(FRAGMENT_HEADER_SIZE)?;
+                    if reader.position() + len > end {
+                        return None;
+                    }
+                    fragments.push(FrameFragment {
+                        sequence,
+                        id,
+                        count,
+                        index,
+                        group,
+                        data: reader.read_bytes(len).ok()?,
+                    });
+                }
+            }
+
+            let trailing = payload[reader.position()..end].to_vec();
+            reader.set_position(end);
+            let mut packet = FramePacket {
+                header,
+                optional,
+                login,
+                fragments,
+                trailing,
+                checksum_key: 0,
+            };
+            packet.checksum_key = packet
+                .header
+                .checksum
+                .wrapping_sub(header_hash(&packet.header))
+                ^ packet.body_hash();
+            packets.push(packet);
+        }
+
+        Some(Self {
+            prefix: data[..ac_start].to_vec(),
+            ip_offset,
+            packets,
+        })
+    }
+
+    /// The IPv4 source and destination addresses
+    pub fn addresses_mut(&mut self) -> (&mut [u8], &mut [u8]) {
+        let ip = &mut self.prefix[self.ip_offset + 12..self.ip_offset + 20];
+        ip.split_at_mut(4)
+    }
+
+    /// Rebuild the frame, fixing packet sizes and checksums, the IP and UDP
+    /// lengths and the IP header checksum
+    ///
+    /// AC packet checksums are recomputed with the key recovered from the
+    /// original, so encrypted checksums stay valid for the same ISAAC stream.
+    /// The UDP checksum is cleared, which IPv4 treats as "no checksum".
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut payload = Cursor::new(Vec::new());
+        for packet in &self.packets {
+            let mut body = packet.optional.clone();
+            if let Some(login) = &packet.login {
+                body.extend(login);
+            }
+            for fragment in &packet.fragments {
+                body.extend(fragment.header());
+                body.extend(&fragment.data);
+            }
+            body.extend(&packet.trailing);
+
+            let mut header = packet.header.clone();
+            header.size = body.len() as u16;
+            if packet.fragments.is_empty() {
+                header.flags.remove(PacketHeaderFlags::BLOB_FRAGMENTS);
+            }
+            header.checksum =
+                header_hash(&header).wrapping_add(packet.body_hash() ^ packet.checksum_key);
+            header
+                .write(&mut payload)
+                .expect("writing to a Vec can't fail");
+            payload.get_mut().extend(body);
+        }
+        let payload = payload.into_inner();
+
+        let mut data = self.prefix.clone();
+        let ip = self.ip_offset;
+        let ihl = (data[ip] & 0x0f) as usize * 4;
+        let udp = ip + ihl;
+        let total = (ihl + 8 + payload.len()) as u16;
+        data[ip + 2..ip + 4].copy_from_slice(&total.to_be_bytes());
+        data[ip + 10..ip + 12].fill(0);
+        let checksum = ip_checksum(&data[ip..udp]);
+        data[ip + 10..ip + 12].copy_from_slice(&checksum.to_be_bytes());
+        data[udp + 4..udp + 6].copy_from_slice(&((8 + payload.len()) as u16).to_be_bytes());
+        data[udp + 6..udp + 8].fill(0);
+
+        data.extend(payload);
+        data
+    }
+}
+
+impl FramePacket {
+    /// Sum of the hashes the checksum covers after the header: the optional
+    /// headers or login request, then each fragment's header and data
+    fn body_hash(&self) -> u32 {
+        let mut data = self.optional.clone();
+        if let Some(login) = &self.login {
+            data.extend(login);
+        }
+        let mut hash = hash32(&data);
+        for fragment in &self.fragments {
+            hash = hash
+                .wrapping_add(hash32(&fragment.header()))
+                .wrapping_add(hash32(&fragment.data));
+        }
+        hash.wrapping_add(hash32(&self.trailing))
+    }
+}
+
+impl FrameFragment {
+    /// The fragment's header as it is sent
+    fn header(&self) -> [u8; FRAGMENT_HEADER_SIZE] {
+        let size = (FRAGMENT_HEADER_SIZE + self.data.len()) as u16;
+        let mut header = [0; FRAGMENT_HEADER_SIZE];
+        header[0..4].copy_from_slice(&self.sequence.to_le_bytes());
+        header[4..8].copy_from_slice(&self.id.to_le_bytes());
+        header[8..10].copy_from_slice(&self.count.to_le_bytes());
+        header[10..12].copy_from_slice(&size.to_le_bytes());
+        header[12..14].copy_from_slice(&self.index.to_le_bytes());
+        header[14..16].copy_from_slice(&self.group.to_le_bytes());
+        header
+    }
+}
+
+/// Hash of a packet header, taken with the checksum field set to its seed
+fn header_hash(header: &PacketHeader) -> u32 {
+    let mut header = header.clone();
+    header.checksum = HEADER_CHECKSUM_SEED;
+    let mut data = Cursor::new(Vec::new());
+    header
+        .write(&mut data)
+        .expect("writing to a Vec can't fail");
+    hash32(data.get_ref())
+}
+
+/// AC's checksum hash: the length in the high word plus the data as
+/// little-endian u32s, with leftover bytes added from the top byte down
+fn hash32(data: &[u8]) -> u32 {
+    let mut hash = (data.len() as u32) << 16;
+    let words = data.chunks_exact(4);
+    for (shift, &byte) in words.remainder().iter().enumerate() {
+        hash = hash.wrapping_add(u32::from(byte) << (24 - 8 * shift));
+    }
+    for word in words {
+        hash = hash.wrapping_add(u32::from_le_bytes(word.try_into().expect("4-byte chunk")));
+    }
+    hash
+}
+
+/// The Internet checksum of an IPv4 header
+fn ip_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::capture::tests::fragment;
+
+    #[test]
+    fn test_hash32() {
+        assert_eq!(hash32(&[]), 0);
+        assert_eq!(
+            hash32(&[1, 0, 0, 0, 0xAA, 0xBB]),
+            (6 << 16) + 1 + 0xAA00_0000 + 0x00BB_0000
+        );
+    }
+
+    #[test]
+    fn test_checksum_follows_rewritten_fragments() {
+        let packet = fragment(0xA0A0_0001, 1, 0, b"hello");
+        let mut frame = Frame::split(&packet.data).unwrap();
+        // Start from a valid unencrypted checksum
+        frame.packets[0].checksum_key = 0;
+        let frame = Frame::split(&frame.to_bytes()).unwrap();
+        assert_eq!(frame.packets[0].checksum_key, 0);
+
+        let mut frame = frame;
+        frame.packets[0].fragments[0].data = b"goodbye".to_vec();
+        let rewritten = Frame::split(&frame.to_bytes()).unwrap();
+        assert_eq!(rewritten.packets[0].fragments[0].data, b"goodbye");
+        assert_eq!(rewritten.packets[0].checksum_key, 0);
+    }
+}
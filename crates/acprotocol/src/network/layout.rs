@@ -0,0 +1,629 @@
+use std::collections::HashMap;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use serde_json::Value;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+use crate::message::MessageKind;
+
+use super::RawMessage;
+
+/// Prefix of the field names generated readers give to alignment padding
+const ALIGNMENT_MARKER: &str = "__alignment_marker";
+
+/// Label for alignment padding in field paths
+pub const PADDING: &str = "(padding)";
+
+/// Opcodes of the ordered messages, whose envelopes are read without field spans
+const ORDERED_GAME_EVENT: u32 = 0xF7B0;
+const ORDERED_GAME_ACTION: u32 = 0xF7B1;
+
+/// One field of a message and the bytes it was read from
+#[derive(Clone, Debug, Serialize)]
+pub struct FieldSpan {
+    pub offset: usize,
+    pub len: usize,
+    /// Nesting depth, 0 for the message's own fields
+    pub depth: usize,
+    /// Field names from the message down, e.g. "Characters.List[0].Name"
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub type_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+/// Where and why a message failed to parse
+#[derive(Debug, Serialize)]
+pub struct ParseFailure {
+    /// Offset of the field being read when parsing failed
+    pub offset: usize,
+    /// Path of the field being read, if any
+    pub path: Option<String>,
+    pub error: String,
+}
+
+/// The byte ranges of every field in a message, recorded while parsing it
+///
+/// Generated readers open a tracing span for each field they read; the layout
+/// is built by collecting those spans while the message is parsed from a
+/// reader that tracks its position.
+#[derive(Debug, Serialize)]
+pub struct MessageLayout {
+    pub len: usize,
+    /// Bytes read by the parser; anything after is unparsed
+    pub consumed: usize,
+    /// Fields in the order they were read, parents before their children
+    pub fields: Vec<FieldSpan>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<ParseFailure>,
+}
+
+impl MessageLayout {
+    /// Parse a message, recording the span of each field
+    pub fn of(message: &RawMessage) -> Self {
+        let position = Arc::new(AtomicU64::new(0));
+        let recorder = Recorder {
+            position: position.clone(),
+            state: Mutex::default(),
+        };
+        let state = Arc::new(recorder);
+        let mut reader = Tracking {
+            cursor: Cursor::new(&message.data),
+            position,
+        };
+
+        let result = tracing::subscriber::with_default(RecorderHandle(state.clone()), || {
+            MessageKind::read(&mut reader, message.determine_direction_enum())
+        });
+        let consumed = reader.cursor.position() as usize;
+        let nodes = std::mem::take(&mut state.state.lock().unwrap().nodes);
+
+        let mut layout = Self {
+            len: message.data.len(),
+            consumed,
+            fields: envelope(message),
+            failure: None,
+        };
+
+        let value = match &result {
+            Ok(kind) => serde_json::to_value(kind).ok(),
+            Err(_) => None,
+        };
+        let body = value.as_ref().map(body);
+
+        layout.fields.extend(nodes.iter().map(|node| {
+            let end = node.end.unwrap_or(consumed as u64) as usize;
+            FieldSpan {
+                offset: node.start as usize,
+                len: end.saturating_sub(node.start as usize),
+                depth: node.depth,
+                path: node.path(),
+                type_name: node.type_name.clone(),
+                value: match node.entry_key() {
+                    Some(key) => Some(key.to_string()),
+                    None => body
+                        .and_then(|body| lookup(body, &node.segments))
+                        .map(display_value),
+                },
+            }
+        }));
+        if let Some(value) = &value {
+            fill_envelope_values(&mut layout.fields, value, message.opcode);
+        }
+
+        if let Err(e) = result {
+            let failing = nodes.last();
+            layout.failure = Some(ParseFailure {
+                offset: failing.map_or(consumed, |n| n.start as usize),
+                path: failing.map(Node::path),
+                error: e.to_string(),
+            });
+        }
+
+        layout
+    }
+
+    /// Whether the field at `index` in `fields` has fields inside it
+    pub fn has_children(&self, index: usize) -> bool {
+        let field = &self.fields[index];
+        self.fields
+            .get(index + 1)
+            .is_some_and(|next| next.depth > field.depth && next.offset < field.offset + field.len)
+    }
+
+    /// Fields with no other fields inside them
+    pub fn leaves(&self) -> impl Iterator<Item = &FieldSpan> {
+        (0..self.fields.len())
+            .filter(|&i| !self.has_children(i))
+            .map(|i| &self.fields[i])
+    }
+
+    /// Byte ranges read by the parser that no field covers
+    pub fn gaps(&self) -> Vec<Range<usize>> {
+        let mut covered: Vec<Range<usize>> = self
+            .leaves()
+            .filter(|f| f.len > 0)
+            .map(|f| f.offset..f.offset + f.len)
+            .collect();
+        covered.sort_by_key(|r| r.start);
+
+        let mut gaps = Vec::new();
+        let mut at = 0;
+        for range in covered {
+            if range.start > at {
+                gaps.push(at..range.start);
+            }
+            at = at.max(range.end);
+        }
+        if at < self.consumed {
+            gaps.push(at..self.consumed);
+        }
+        gaps
+    }
+
+    /// Bytes after the last one the parser read
+    pub fn unparsed(&self) -> Range<usize> {
+        self.consumed.min(self.len)..self.len
+    }
+}
+
+/// The opcode and ordered-message header fields that are read without spans
+fn envelope(message: &RawMessage) -> Vec<FieldSpan> {
+    let field = |offset, path: &str| FieldSpan {
+        offset,
+        len: 4,
+        depth: 0,
+        path: path.to_string(),
+        type_name: Some("u32".to_string()),
+        value: None,
+    };
+    let mut fields = vec![FieldSpan {
+        value: Some(format!("0x{:04X}", message.opcode)),
+        ..field(0, "opcode")
+    }];
+    match message.opcode {
+        ORDERED_GAME_EVENT => fields.extend([
+            field(4, "object_id"),
+            field(8, "sequence"),
+            field(12, "event"),
+        ]),
+        ORDERED_GAME_ACTION => fields.extend([field(4, "sequence"), field(8, "action")]),
+        _ => {}
+    }
+    fields.retain(|f| f.offset + f.len <= message.data.len());
+    fields
+}
+
+fn fill_envelope_values(fields: &mut [FieldSpan], value: &Value, opcode: u32) {
+    let Some(envelope) = value
+        .as_object()
+        .and_then(|m| m.values().next())
+        .and_then(|v| {
+            v.get("OrderedGameEvent")
+                .or_else(|| v.get("OrderedGameAction"))
+        })
+    else {
+        return;
+    };
+    let inner = if opcode == ORDERED_GAME_EVENT {
+        "event"
+    } else {
+        "action"
+    };
+    let names = ["object_id", "sequence", inner];
+    for field in fields
+        .iter_mut()
+        .filter(|f| f.depth == 0 && names.contains(&f.path.as_str()))
+    {
+        field.value = if field.path == inner {
+            envelope
+                .get(inner)
+                .and_then(Value::as_object)
+                .and_then(|m| m.keys().next().cloned())
+        } else {
+            envelope.get(&field.path).map(display_value)
+        };
+    }
+}
+
+/// The object holding a message's own fields, inside its direction, variant
+/// and ordered-message wrappers
+fn body(value: &Value) -> &Value {
+    let mut value = value;
+    loop {
+        let Some(map) = value.as_object() else {
+            return value;
+        };
+        if let Some(inner) = map.get("event").or_else(|| map.get("action")) {
+            value = inner;
+        } else if map.len() == 1
+            && let Some(inner) = map.values().next().filter(|v| v.is_object())
+        {
+            value = inner;
+        } else {
+            return value;
+        }
+    }
+}
+
+fn lookup<'v>(mut value: &'v Value, segments: &[Segment]) -> Option<&'v Value> {
+    let mut in_entry = false;
+    for segment in segments {
+        // A table entry's value is the entry itself in the JSON
+        let skip = segment.name.is_empty() || (in_entry && segment.name == "Value");
+        if !skip {
+            value = field(value, &segment.name)?;
+        }
+        in_entry = false;
+        if let Some(index) = segment.index {
+            value = match (&segment.key, value) {
+                (Some(key), Value::Object(table)) => {
+                    in_entry = true;
+                    table.get(&json_key(key))?
+                }
+                _ => value.as_array()?.get(index as usize)?,
+            };
+        }
+    }
+    Some(value)
+}
+
+/// The JSON object key of a table key recorded with its Debug form
+fn json_key(key: &str) -> String {
+    let key = key
+        .split_once('(')
+        .and_then(|(name, inner)| {
+            let inner = inner.strip_suffix(')')?;
+            name.chars().all(char::is_alphanumeric).then_some(inner)
+        })
+        .unwrap_or(key);
+    key.trim_matches('"').to_string()
+}
+
+/// Find a field, looking through single-variant wrappers
+fn field<'v>(value: &'v Value, name: &str) -> Option<&'v Value> {
+    let map = value.as_object()?;
+    match map.get(name) {
+        Some(found) => Some(found),
+        None if map.len() == 1 => field(map.values().next()?, name),
+        None => None,
+    }
+}
+
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::Array(items) => format!("[{} items]", items.len()),
+        Value::Object(map) => format!("{{{} fields}}", map.len()),
+        other => other.to_string(),
+    }
+}
+
+/// A reader that publishes its position after every read, so the recorder
+/// knows where each field ended
+struct Tracking<'a> {
+    cursor: Cursor<&'a Vec<u8>>,
+    position: Arc<AtomicU64>,
+}
+
+impl Read for Tracking<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.cursor.read(buf)?;
+        self.position
+            .store(self.cursor.position(), Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl Seek for Tracking<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let at = self.cursor.seek(pos)?;
+        self.position.store(at, Ordering::Relaxed);
+        Ok(at)
+    }
+}
+
+/// One step in a field's path
+#[derive(Clone)]
+struct Segment {
+    name: String,
+    index: Option<u64>,
+    /// Key of the hash table entry, once it has been read
+    key: Option<String>,
+}
+
+/// A field span as it was entered
+struct Node {
+    segments: Vec<Segment>,
+    depth: usize,
+    start: u64,
+    end: Option<u64>,
+    type_name: Option<String>,
+}
+
+impl Node {
+    fn path(&self) -> String {
+        let mut path = String::new();
+        for segment in &self.segments {
+            if !path.is_empty() && !segment.name.is_empty() {
+                path.push('.');
+            }
+            path.push_str(&segment.name);
+            if let Some(index) = segment.index {
+                path.push_str(&format!("[{index}]"));
+            }
+        }
+        path
+    }
+
+    /// The key of the table entry, for the span of an entry's key
+    fn entry_key(&self) -> Option<&str> {
+        match self.segments.as_slice() {
+            [.., entry, last] if last.name == "Key" => entry.key.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+enum SpanKind {
+    Field {
+        name: String,
+        index: Option<u64>,
+        start: u64,
+    },
+    Type(String),
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    spans: HashMap<u64, SpanKind>,
+    /// Entered spans, innermost last, with the node of each field span
+    stack: Vec<(u64, Option<usize>)>,
+    nodes: Vec<Node>,
+}
+
+struct Recorder {
+    position: Arc<AtomicU64>,
+    state: Mutex<State>,
+}
+
+/// Collects the "field" and "read" spans opened by readers
+struct RecorderHandle(Arc<Recorder>);
+
+#[derive(Default)]
+struct SpanFields {
+    name: Option<String>,
+    type_name: Option<String>,
+    index: Option<u64>,
+    position: Option<u64>,
+}
+
+impl Visit for SpanFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "name" => self.name = Some(value.to_string()),
+            "type" => self.type_name = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match field.name() {
+            "index" => self.index = Some(value),
+            "position" => self.position = Some(value),
+            _ => {}
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.record_u64(field, value as u64);
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn std::fmt::Debug) {}
+}
+
+/// Picks up the key recorded for a hash table entry
+struct KeyVisitor(Option<String>);
+
+impl Visit for KeyVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "key" {
+            self.0 = Some(format!("{value:?}"));
+        }
+    }
+}
+
+impl Subscriber for RecorderHandle {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        if metadata.is_event() {
+            return metadata.fields().field("key").is_some();
+        }
+        matches!(metadata.name(), "field" | "read")
+    }
+
+    fn new_span(&self, span: &Attributes<'_>) -> Id {
+        let mut fields = SpanFields::default();
+        span.record(&mut fields);
+        let kind = match span.metadata().name() {
+            "field" => SpanKind::Field {
+                name: fields
+                    .name
+                    .map(|name| {
+                        if name.starts_with(ALIGNMENT_MARKER) {
+                            PADDING.to_string()
+                        } else {
+                            name
+                        }
+                    })
+                    .unwrap_or_default(),
+                index: fields.index,
+                start: fields
+                    .position
+                    .unwrap_or_else(|| self.0.position.load(Ordering::Relaxed)),
+            },
+            _ => SpanKind::Type(fields.type_name.unwrap_or_default()),
+        };
+
+        let mut state = self.0.state.lock().unwrap();
+        state.next_id += 1;
+        let id = state.next_id;
+        state.spans.insert(id, kind);
+        Id::from_u64(id)
+    }
+
+    fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut visitor = KeyVisitor(None);
+        event.record(&mut visitor);
+        let Some(key) = visitor.0 else {
+            return;
+        };
+
+        // Name the entry being read, and its key if that was read as a field
+        let mut state = self.0.state.lock().unwrap();
+        let state = &mut *state;
+        let Some(entry) = state.stack.iter().rev().find_map(|&(_, node)| node) else {
+            return;
+        };
+        let depth = state.nodes[entry].segments.len() - 1;
+        for node in &mut state.nodes[entry..] {
+            if let Some(segment) = node.segments.get_mut(depth) {
+                segment.key = Some(key.clone());
+            }
+        }
+    }
+
+    fn enter(&self, span: &Id) {
+        let mut state = self.0.state.lock().unwrap();
+        let state = &mut *state;
+        let id = span.into_u64();
+        let parent = state.stack.iter().rev().find_map(|&(_, node)| node);
+
+        let node = match state.spans.get(&id) {
+            Some(SpanKind::Field { name, index, start }) => {
+                let mut segments =
+                    parent.map_or_else(Vec::new, |p| state.nodes[p].segments.clone());
+                segments.push(Segment {
+                    name: name.clone(),
+                    index: *index,
+                    key: None,
+                });
+                state.nodes.push(Node {
+                    segments,
+                    depth: parent.map_or(0, |p| state.nodes[p].depth + 1),
+                    start: *start,
+                    end: None,
+                    type_name: None,
+                });
+                Some(state.nodes.len() - 1)
+            }
+            Some(SpanKind::Type(type_name)) => {
+                // A type read directly inside a field names the field's type
+                if let Some(&(_, Some(field))) = state.stack.last()
+                    && state.nodes[field].type_name.is_none()
+                {
+                    state.nodes[field].type_name = Some(type_name.clone());
+                }
+                None
+            }
+            None => None,
+        };
+        state.stack.push((id, node));
+    }
+
+    fn exit(&self, span: &Id) {
+        let mut state = self.0.state.lock().unwrap();
+        let id = span.into_u64();
+        if let Some(at) = state.stack.iter().rposition(|&(s, _)| s == id) {
+            let (_, node) = state.stack.remove(at);
+            if let Some(node) = node {
+                state.nodes[node].end = Some(self.0.position.load(Ordering::Relaxed));
+            }
+        }
+    }
+
+    fn try_close(&self, id: Id) -> bool {
+        self.0.state.lock().unwrap().spans.remove(&id.into_u64());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An ItemDeleteObject message, followed by `extra` bytes
+    fn delete_object(extra: &[u8]) -> RawMessage {
+        let mut data = 0xF747u32.to_le_bytes().to_vec();
+        data.extend(0x5000_0001u32.to_le_bytes());
+        data.extend(7u16.to_le_bytes());
+        data.extend([0, 0]); // padding to a dword boundary
+        data.extend(extra);
+        RawMessage::from_fragment(data, 0, 1).unwrap()
+    }
+
+    fn field<'l>(layout: &'l MessageLayout, path: &str) -> &'l FieldSpan {
+        layout
+            .fields
+            .iter()
+            .find(|f| f.path == path)
+            .unwrap_or_else(|| panic!("no field {path}"))
+    }
+
+    #[test]
+    fn test_field_spans() {
+        let layout = MessageLayout::of(&delete_object(&[]));
+        assert_eq!((layout.len, layout.consumed), (12, 12));
+        assert!(layout.failure.is_none());
+
+        let opcode = field(&layout, "opcode");
+        assert_eq!((opcode.offset, opcode.len), (0, 4));
+        assert_eq!(opcode.value.as_deref(), Some("0xF747"));
+        let object = field(&layout, "ObjectId");
+        assert_eq!((object.offset, object.len), (4, 4));
+        assert_eq!(object.value.as_deref(), Some("1342177281"));
+        let sequence = field(&layout, "ObjectInstanceSequence");
+        assert_eq!((sequence.offset, sequence.len), (8, 2));
+        assert_eq!(sequence.value.as_deref(), Some("7"));
+        let padding = field(&layout, PADDING);
+        assert_eq!((padding.offset, padding.len), (10, 2));
+
+        assert!(layout.gaps().is_empty());
+        assert!(layout.unparsed().is_empty());
+    }
+
+    #[test]
+    fn test_unparsed_tail() {
+        let layout = MessageLayout::of(&delete_object(&[0xAA, 0xBB]));
+        assert!(layout.failure.is_none());
+        assert_eq!(layout.consumed, 12);
+        assert_eq!(layout.unparsed(), 12..14);
+    }
+
+    #[test]
+    fn test_failure_point() {
+        let mut message = delete_object(&[]);
+        message.data.truncate(6);
+        let layout = MessageLayout::of(&message);
+
+        let failure = layout.failure.expect("truncated message should fail");
+        assert_eq!(failure.offset, 4);
+        assert_eq!(failure.path.as_deref(), Some("ObjectId"));
+        assert!(
+            layout
+                .fields
+                .iter()
+                .all(|f| f.value.is_none() || f.path == "opcode")
+        );
+    }
+}
@@ -1,4 +1,11 @@
+#[cfg(feature = "anonymize")]
+pub mod anonymize;
+pub mod capture;
 pub mod fragment_impl;
+#[cfg(feature = "anonymize")]
+mod frame;
+#[cfg(feature = "layout")]
+pub mod layout;
 pub mod message;
 pub mod packet;
 pub mod packet_parser;
@@ -7,6 +14,7 @@ pub mod pcap;
 pub mod raw_message;
 
 pub use crate::generated::network::{Fragment, FragmentHeader};
+pub use capture::Capture;
 pub use fragment_impl::FRAGMENT_CHUNK_SIZE;
 pub use message::Message;
 pub use packet_parser::FragmentAssembler;
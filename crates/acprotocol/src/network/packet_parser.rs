@@ -1,11 +1,12 @@
 use std::collections::HashMap;
 use std::io;
 
-use crate::generated::network::Fragment;
 use crate::readers::ACDataType;
 
+use super::fragment_impl::PendingFragment;
 use super::packet::PacketHeader;
 use super::packet_reader::PacketReader;
+use super::pcap::Packet;
 use super::raw_message::RawMessage;
 use crate::enums::PacketHeaderFlags;
 
@@ -22,8 +23,14 @@ pub struct ExtractedFragment {
 
 /// Parses packets and assembles fragments into complete messages
 pub struct FragmentAssembler {
-    pending_fragments: HashMap<u32, Fragment>,
+    pending_fragments: HashMap<u32, PendingFragment>,
     next_message_id: u32,
+    /// Index of the next packet passed to `parse_packet`
+    frame: usize,
+    /// Frames carrying each pending fragment, by fragment sequence
+    pending_frames: HashMap<u32, Vec<usize>>,
+    /// Frames carrying each completed message, by message id
+    message_frames: HashMap<u32, Vec<usize>>,
 }
 
 impl FragmentAssembler {
@@ -31,9 +38,33 @@ impl FragmentAssembler {
         Self {
             pending_fragments: HashMap::new(),
             next_message_id: 0,
+            frame: 0,
+            pending_frames: HashMap::new(),
+            message_frames: HashMap::new(),
         }
     }
 
+    /// Parse a captured packet, stamping any completed messages with the
+    /// packet's capture time.
+    pub fn parse_packet(&mut self, packet: &Packet) -> io::Result<Vec<RawMessage>> {
+        let result = self.parse_packet_payload(&packet.data);
+        self.frame += 1;
+        let mut messages = result?;
+        let timestamp = packet.timestamp();
+        for message in &mut messages {
+            message.timestamp = Some(timestamp);
+        }
+        Ok(messages)
+    }
+
+    /// The packets that carried a message's fragments, as indexes in the
+    /// order packets were passed to [`Self::parse_packet`]
+    pub fn message_frames(&self, message_id: u32) -> &[usize] {
+        self.message_frames
+            .get(&message_id)
+            .map_or(&[], Vec::as_slice)
+    }
+
     /// Parse a network packet's payload and extract fragments, returning any
     /// completed messages.
     ///
@@ -62,32 +93,7 @@ impl FragmentAssembler {
     ///                                     └──────────────┘
     ///
     pub fn parse_packet_payload(&mut self, payload: &[u8]) -> io::Result<Vec<RawMessage>> {
-        // PCAP packets include network stack headers that we need to skip to get to AC protocol data
-        // Standard PCAP: Ethernet (14) + IP (20) + UDP (8) = 42 bytes
-        // Some custom PCAP exports prepend 4 bytes before Ethernet
-
-        // Detect 4-byte prefix by checking if IPv4 header is at offset 4
-        let has_4byte_prefix = payload.len() > 4 && payload[4] == 0x45;
-        let ip_offset = if has_4byte_prefix { 4 } else { 14 };
-
-        // Parse IP header to find UDP payload offset
-        let ac_payload = if payload.len() > ip_offset + 2 {
-            // Extract IP IHL (Internet Header Length) from first byte's lower 4 bits
-            let ihl_words = (payload[ip_offset] & 0x0f) as usize;
-            let ihl_bytes = ihl_words * 4;
-
-            // UDP header is 8 bytes (src port, dst port, length, checksum)
-            let udp_offset = ip_offset + ihl_bytes;
-            let ac_start = udp_offset + 8;
-
-            if payload.len() > ac_start {
-                &payload[ac_start..]
-            } else {
-                &[]
-            }
-        } else {
-            &[]
-        };
+        let ac_payload = ac_payload(payload);
 
         let mut completed_messages = Vec::new();
         let mut reader = PacketReader::new(ac_payload);
@@ -104,92 +110,12 @@ impl FragmentAssembler {
 
             // Parse optional headers based on flags
             // NOTE: We must parse ALL optional headers to advance reader correctly!
-            if header.flags.contains(PacketHeaderFlags::SERVER_SWITCH) {
-                reader.read_u32()?; // SeqNo
-                reader.read_u32()?; // Type
-            }
-            if header.flags.contains(PacketHeaderFlags::LOGON_SERVER_ADDR) {
-                reader.read_u16()?; // Family
-                reader.read_u16()?; // Port
-                reader.read_bytes(4)?; // Address (IPv4)
-                reader.read_bytes(8)?; // Zero padding
-            }
-            if header.flags.contains(PacketHeaderFlags::REQUEST_RETRANSMIT) {
-                let num = reader.read_u32()?;
-                for _ in 0..num {
-                    reader.read_u32()?; // sequence id
-                }
-            }
-            if header.flags.contains(PacketHeaderFlags::REFERRAL) {
-                reader.read_bytes(8)?; // Cookie (u64)
-                // SocketAddr: Family (i16), Port (u16), Address (4 bytes), Zero (8 bytes)
-                reader.read_u16()?; // Family
-                reader.read_u16()?; // Port
-                reader.read_bytes(4)?; // Address
-                reader.read_bytes(8)?; // Zero padding
-                reader.read_u16()?; // IdServer
-                reader.read_u16()?; // Padding
-                reader.read_u32()?; // Unknown
-            }
-            if header.flags.contains(PacketHeaderFlags::ACK_SEQUENCE) {
-                reader.read_u32()?; // sequence
-            }
-            if header.flags.contains(PacketHeaderFlags::LOGIN_REQUEST) {
-                // LoginRequest has variable-length strings - skip the entire payload
-                // as we can't reliably parse it without proper error recovery.
-                // C# implementation parses these fields but we don't need them for fragment assembly.
+            if !skip_optional_headers(&mut reader, header.flags)? {
                 // Jump to the end of this packet's data.
                 reader.set_position(packet_end);
                 // No fragments can follow LOGIN_REQUEST in the same packet
                 continue;
             }
-            if header
-                .flags
-                .contains(PacketHeaderFlags::WORLD_LOGIN_REQUEST)
-            {
-                reader.read_bytes(8)?; // Prim (u64)
-            }
-            if header.flags.contains(PacketHeaderFlags::CONNECT_REQUEST) {
-                reader.read_bytes(8)?; // ServerTime (u64)
-                reader.read_bytes(8)?; // Cookie (u64)
-                reader.read_u32()?; // NetID
-                reader.read_u32()?; // OutgoingSeed
-                reader.read_u32()?; // IncomingSeed
-                reader.read_u32()?; // Unknown
-            }
-            if header.flags.contains(PacketHeaderFlags::CONNECT_RESPONSE) {
-                reader.read_bytes(8)?; // Prim (u64)
-            }
-            if header.flags.contains(PacketHeaderFlags::NET_ERROR) {
-                reader.read_u32()?; // StringId
-                reader.read_u32()?; // TableId
-            }
-            if header
-                .flags
-                .contains(PacketHeaderFlags::NET_ERROR_DISCONNECT)
-            {
-                reader.read_u32()?; // StringId
-                reader.read_u32()?; // TableId
-            }
-            if header.flags.contains(PacketHeaderFlags::CICMDCOMMAND) {
-                // CICMDCommand: Command (u32) + Param (u32)
-                reader.read_u32()?; // Command
-                reader.read_u32()?; // Param
-            }
-            if header.flags.contains(PacketHeaderFlags::TIME_SYNC) {
-                reader.read_bytes(8)?; // Time (f64)
-            }
-            if header.flags.contains(PacketHeaderFlags::ECHO_REQUEST) {
-                reader.read_bytes(4)?; // LocalTime (f32)
-            }
-            if header.flags.contains(PacketHeaderFlags::ECHO_RESPONSE) {
-                reader.read_bytes(4)?; // LocalTime (f32)
-                reader.read_bytes(4)?; // HoldingTime (f32)
-            }
-            if header.flags.contains(PacketHeaderFlags::FLOW) {
-                reader.read_u32()?; // DataReceived
-                reader.read_u16()?; // Interval
-            }
 
             // If this packet has fragments, parse them
             if header.flags.contains(PacketHeaderFlags::BLOB_FRAGMENTS) {
@@ -252,26 +178,34 @@ impl FragmentAssembler {
 
         let data = reader.read_bytes(frag_length)?;
 
+        let frames = self.pending_frames.entry(sequence).or_default();
+        if frames.last() != Some(&self.frame) {
+            frames.push(self.frame);
+        }
+
         // Update or create fragment entry
         let fragment = self
             .pending_fragments
             .entry(sequence)
-            .or_insert_with(|| Fragment::new(sequence, count));
+            .or_insert_with(|| PendingFragment::new(sequence, count));
 
         fragment.add_chunk(&data, index as usize, frag_length); // Pass chunk size
-        fragment.header.id = id;
-        fragment.header.index = index;
+        fragment.fragment.header.id = id;
+        fragment.fragment.header.index = index;
         fragment.set_fragment_info(size, group);
 
         // Check if this completes the fragment assembly
         if fragment.is_complete() {
-            let assembled_data = fragment.get_data().to_vec();
-            fragment.cleanup();
+            let mut assembled_data = fragment.get_data().to_vec();
+            assembled_data.truncate(fragment.get_total_length());
             self.pending_fragments.remove(&sequence);
 
             // Try to parse as a message
             let msg_id = self.next_message_id;
             self.next_message_id += 1;
+            if let Some(frames) = self.pending_frames.remove(&sequence) {
+                self.message_frames.insert(msg_id, frames);
+            }
 
             let parsed_msg = RawMessage::from_fragment_with_iteration(
                 assembled_data,
@@ -294,8 +228,162 @@ impl FragmentAssembler {
     }
 }
 
+/// Find the AC protocol data inside a captured frame
+///
+/// PCAP packets include network stack headers that we need to skip to get to AC protocol data.
+/// Standard PCAP: Ethernet (14) + IP (20) + UDP (8) = 42 bytes.
+/// Some custom PCAP exports prepend 4 bytes before Ethernet.
+fn ac_payload(payload: &[u8]) -> &[u8] {
+    match network_offsets(payload) {
+        Some((_, ac_start)) => &payload[ac_start..],
+        None => &[],
+    }
+}
+
+/// The offsets of the IP header and of the AC protocol data in a captured frame
+pub(super) fn network_offsets(payload: &[u8]) -> Option<(usize, usize)> {
+    // Detect 4-byte prefix by checking if IPv4 header is at offset 4
+    let has_4byte_prefix = payload.len() > 4 && payload[4] == 0x45;
+    let ip_offset = if has_4byte_prefix { 4 } else { 14 };
+
+    // Parse IP header to find UDP payload offset
+    if payload.len() > ip_offset + 2 {
+        // Extract IP IHL (Internet Header Length) from first byte's lower 4 bits
+        let ihl_words = (payload[ip_offset] & 0x0f) as usize;
+        let ihl_bytes = ihl_words * 4;
+
+        // UDP header is 8 bytes (src port, dst port, length, checksum)
+        let udp_offset = ip_offset + ihl_bytes;
+        let ac_start = udp_offset + 8;
+
+        if payload.len() > ac_start {
+            return Some((ip_offset, ac_start));
+        }
+    }
+    None
+}
+
+/// Skip the optional headers selected by a packet's flags
+///
+/// Returns false for login requests, whose variable-length header runs to the
+/// end of the packet with no fragments after it.
+pub(super) fn skip_optional_headers(
+    reader: &mut PacketReader,
+    flags: PacketHeaderFlags,
+) -> io::Result<bool> {
+    if flags.contains(PacketHeaderFlags::SERVER_SWITCH) {
+        reader.read_u32()?; // SeqNo
+        reader.read_u32()?; // Type
+    }
+    if flags.contains(PacketHeaderFlags::LOGON_SERVER_ADDR) {
+        reader.read_u16()?; // Family
+        reader.read_u16()?; // Port
+        reader.read_bytes(4)?; // Address (IPv4)
+        reader.read_bytes(8)?; // Zero padding
+    }
+    if flags.contains(PacketHeaderFlags::REQUEST_RETRANSMIT) {
+        let num = reader.read_u32()?;
+        for _ in 0..num {
+            reader.read_u32()?; // sequence id
+        }
+    }
+    if flags.contains(PacketHeaderFlags::REFERRAL) {
+        reader.read_bytes(8)?; // Cookie (u64)
+        // SocketAddr: Family (i16), Port (u16), Address (4 bytes), Zero (8 bytes)
+        reader.read_u16()?; // Family
+        reader.read_u16()?; // Port
+        reader.read_bytes(4)?; // Address
+        reader.read_bytes(8)?; // Zero padding
+        reader.read_u16()?; // IdServer
+        reader.read_u16()?; // Padding
+        reader.read_u32()?; // Unknown
+    }
+    if flags.contains(PacketHeaderFlags::ACK_SEQUENCE) {
+        reader.read_u32()?; // sequence
+    }
+    if flags.contains(PacketHeaderFlags::LOGIN_REQUEST) {
+        // LoginRequest has variable-length strings - skip the entire payload
+        // as we can't reliably parse it without proper error recovery.
+        // C# implementation parses these fields but we don't need them for fragment assembly.
+        return Ok(false);
+    }
+    if flags.contains(PacketHeaderFlags::WORLD_LOGIN_REQUEST) {
+        reader.read_bytes(8)?; // Prim (u64)
+    }
+    if flags.contains(PacketHeaderFlags::CONNECT_REQUEST) {
+        reader.read_bytes(8)?; // ServerTime (u64)
+        reader.read_bytes(8)?; // Cookie (u64)
+        reader.read_u32()?; // NetID
+        reader.read_u32()?; // OutgoingSeed
+        reader.read_u32()?; // IncomingSeed
+        reader.read_u32()?; // Unknown
+    }
+    if flags.contains(PacketHeaderFlags::CONNECT_RESPONSE) {
+        reader.read_bytes(8)?; // Prim (u64)
+    }
+    if flags.contains(PacketHeaderFlags::NET_ERROR) {
+        reader.read_u32()?; // StringId
+        reader.read_u32()?; // TableId
+    }
+    if flags.contains(PacketHeaderFlags::NET_ERROR_DISCONNECT) {
+        reader.read_u32()?; // StringId
+        reader.read_u32()?; // TableId
+    }
+    if flags.contains(PacketHeaderFlags::CICMDCOMMAND) {
+        // CICMDCommand: Command (u32) + Param (u32)
+        reader.read_u32()?; // Command
+        reader.read_u32()?; // Param
+    }
+    if flags.contains(PacketHeaderFlags::TIME_SYNC) {
+        reader.read_bytes(8)?; // Time (f64)
+    }
+    if flags.contains(PacketHeaderFlags::ECHO_REQUEST) {
+        reader.read_bytes(4)?; // LocalTime (f32)
+    }
+    if flags.contains(PacketHeaderFlags::ECHO_RESPONSE) {
+        reader.read_bytes(4)?; // LocalTime (f32)
+        reader.read_bytes(4)?; // HoldingTime (f32)
+    }
+    if flags.contains(PacketHeaderFlags::FLOW) {
+        reader.read_u32()?; // DataReceived
+        reader.read_u16()?; // Interval
+    }
+    Ok(true)
+}
+
+/// The header flags of the first AC packet in a captured frame
+pub fn packet_flags(payload: &[u8]) -> Option<PacketHeaderFlags> {
+    let mut reader = PacketReader::new(ac_payload(payload));
+    PacketHeader::read(&mut reader)
+        .ok()
+        .map(|header| header.flags)
+}
+
 impl Default for FragmentAssembler {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::capture::tests::fragment;
+
+    #[test]
+    fn test_message_length_comes_from_its_own_stream() {
+        let mut first = FragmentAssembler::new();
+        let mut second = FragmentAssembler::new();
+        // Two captures reusing a sequence number, parsed side by side
+        let parse = |assembler: &mut FragmentAssembler, index: u16, len: usize| {
+            assembler
+                .parse_packet(&fragment(7, 2, index, &vec![1; len]))
+                .unwrap()
+        };
+
+        assert!(parse(&mut first, 0, 448).is_empty());
+        assert!(parse(&mut second, 0, 448).is_empty());
+        assert_eq!(parse(&mut first, 1, 4)[0].data.len(), 452);
+        assert_eq!(parse(&mut second, 1, 40)[0].data.len(), 488);
+    }
+}
@@ -1,4 +1,4 @@
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Write};
 
 #[cfg(not(target_arch = "wasm32"))]
 use std::fs::File;
@@ -18,10 +18,43 @@ pub struct Packet {
     pub data: Vec<u8>,
 }
 
+impl Packet {
+    /// Capture time in seconds since the Unix epoch
+    pub fn timestamp(&self) -> f64 {
+        self.ts_sec as f64 + self.ts_usec as f64 / 1_000_000.0
+    }
+}
+
+/// The global header of a pcap file, minus its magic number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PcapHeader {
+    pub version_major: u16,
+    pub version_minor: u16,
+    pub thiszone: i32,
+    pub sigfigs: u32,
+    pub snaplen: u32,
+    /// Link-layer header type, e.g. 1 for Ethernet
+    pub network: u32,
+}
+
+impl Default for PcapHeader {
+    fn default() -> Self {
+        Self {
+            version_major: 2,
+            version_minor: 4,
+            thiszone: 0,
+            sigfigs: 0,
+            snaplen: 65535,
+            network: 1,
+        }
+    }
+}
+
 /// Iterator over packets in a pcap file
 pub struct PcapIterator<R: Read> {
     reader: R,
     is_big_endian: bool,
+    header: PcapHeader,
 }
 
 impl<R: Read> PcapIterator<R> {
@@ -47,12 +80,35 @@ impl<R: Read> PcapIterator<R> {
             ));
         };
 
+        let u16_at = |i: usize| {
+            let val = u16::from_le_bytes([header[i], header[i + 1]]);
+            if is_big_endian { val.swap_bytes() } else { val }
+        };
+        let u32_at = |i: usize| {
+            let val = u32::from_le_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+            if is_big_endian { val.swap_bytes() } else { val }
+        };
+        let header = PcapHeader {
+            version_major: u16_at(4),
+            version_minor: u16_at(6),
+            thiszone: u32_at(8) as i32,
+            sigfigs: u32_at(12),
+            snaplen: u32_at(16),
+            network: u32_at(20),
+        };
+
         Ok(PcapIterator {
             reader,
             is_big_endian,
+            header,
         })
     }
 
+    /// The file's global header
+    pub fn header(&self) -> PcapHeader {
+        self.header
+    }
+
     /// Create a new pcap iterator from a byte slice (WASM-compatible)
     pub fn from_bytes(bytes: &[u8]) -> std::io::Result<PcapIterator<Cursor<&[u8]>>> {
         let cursor = Cursor::new(bytes);
@@ -97,6 +153,41 @@ impl<R: Read> Iterator for PcapIterator<R> {
     }
 }
 
+/// Writes packets to a little-endian pcap file
+pub struct PcapWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Create a new pcap writer, writing the global header immediately
+    pub fn new(mut writer: W, header: &PcapHeader) -> std::io::Result<Self> {
+        writer.write_all(&0xa1b2c3d4u32.to_le_bytes())?;
+        writer.write_all(&header.version_major.to_le_bytes())?;
+        writer.write_all(&header.version_minor.to_le_bytes())?;
+        writer.write_all(&header.thiszone.to_le_bytes())?;
+        writer.write_all(&header.sigfigs.to_le_bytes())?;
+        writer.write_all(&header.snaplen.to_le_bytes())?;
+        writer.write_all(&header.network.to_le_bytes())?;
+        Ok(PcapWriter { writer })
+    }
+
+    /// Write a packet record
+    pub fn write_packet(&mut self, packet: &Packet) -> std::io::Result<()> {
+        let len = packet.data.len() as u32;
+        self.writer.write_all(&packet.ts_sec.to_le_bytes())?;
+        self.writer.write_all(&packet.ts_usec.to_le_bytes())?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(&packet.data)
+    }
+
+    /// Flush and return the underlying writer
+    pub fn into_inner(mut self) -> std::io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
 /// Open a pcap file and return an iterator over its packets
 ///
 /// This function is only available on non-WASM targets.
@@ -107,3 +198,53 @@ pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<PcapIterator<BufReader<F
     let reader = BufReader::new(file);
     PcapIterator::new(reader)
 }
+
+/// Create a pcap file and return a writer for its packets
+#[cfg(not(target_arch = "wasm32"))]
+pub fn create<P: AsRef<Path>>(
+    path: P,
+    header: &PcapHeader,
+) -> std::io::Result<PcapWriter<std::io::BufWriter<File>>> {
+    let file = File::create(path)?;
+    PcapWriter::new(std::io::BufWriter::new(file), header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writer_round_trip() {
+        let header = PcapHeader {
+            snaplen: 1500,
+            ..Default::default()
+        };
+        let packets = vec![
+            Packet {
+                ts_sec: 1763490291,
+                ts_usec: 250_000,
+                data: vec![1, 2, 3],
+            },
+            Packet {
+                ts_sec: 1763490292,
+                ts_usec: 0,
+                data: vec![],
+            },
+        ];
+
+        let mut writer = PcapWriter::new(Vec::new(), &header).unwrap();
+        for packet in &packets {
+            writer.write_packet(packet).unwrap();
+        }
+        let bytes = writer.into_inner().unwrap();
+        assert_eq!(bytes.len(), 24 + 16 + 3 + 16);
+
+        let iter = PcapIterator::<Cursor<&[u8]>>::from_bytes(&bytes).unwrap();
+        assert_eq!(iter.header(), header);
+        let read: Vec<Packet> = iter.map(Result::unwrap).collect();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].data, vec![1, 2, 3]);
+        assert_eq!(read[0].timestamp(), 1763490291.25);
+        assert!(read[1].data.is_empty());
+    }
+}
@@ -1,5 +1,6 @@
 use crate::enums::MessageQueue;
 use crate::message::{Direction, MessageKind};
+use crate::text::display_text;
 use serde::Serialize;
 use std::io::{self, Cursor};
 
@@ -20,6 +21,9 @@ pub struct RawMessage {
     /// Parsed message data as JSON, or raw hex if parsing fails
     #[serde(serialize_with = "serialize_parsed_data")]
     pub data: Vec<u8>,
+    /// Text the client would show for an error or status code, see [`crate::text`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_text: Option<String>,
     /// Position in the message stream
     pub sequence: u32,
     /// Packet iteration counter (from AC packet header)
@@ -28,6 +32,9 @@ pub struct RawMessage {
     /// Packet header flags (Flow, ACK, etc.)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub header_flags: Option<u32>,
+    /// Capture time of the packet that completed this message (seconds since the Unix epoch)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<f64>,
 }
 
 fn serialize_parsed_data<S>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error>
@@ -82,7 +89,7 @@ fn determine_direction(opcode: u32) -> Direction {
 
 impl RawMessage {
     /// Determine the direction enum for this message
-    fn determine_direction_enum(&self) -> Direction {
+    pub(crate) fn determine_direction_enum(&self) -> Direction {
         use crate::enums::C2SMessage;
         if C2SMessage::try_from(self.opcode).is_ok() {
             Direction::ClientToServer
@@ -121,19 +128,21 @@ impl RawMessage {
             direction: String::new(),
             queue: None,
             data,
+            display_text: None,
             sequence,
             iteration,
             header_flags,
+            timestamp: None,
         };
 
         let message_type = message.message_type_name();
         let direction = message.direction().to_string();
 
-        // Try to parse and get queue from the actual message
+        // Try to parse and get queue and display text from the actual message
         let mut cursor = Cursor::new(&message.data);
-        let queue = MessageKind::read(&mut cursor, message.determine_direction_enum())
-            .ok()
-            .and_then(|msg| msg.queue());
+        let parsed = MessageKind::read(&mut cursor, message.determine_direction_enum()).ok();
+        let queue = parsed.as_ref().and_then(|msg| msg.queue());
+        let display_text = parsed.as_ref().and_then(display_text);
 
         Ok(Self {
             id: message.id,
@@ -142,12 +151,28 @@ impl RawMessage {
             direction,
             queue,
             data: message.data,
+            display_text,
             sequence: message.sequence,
             iteration: message.iteration,
             header_flags,
+            timestamp: None,
         })
     }
 
+    /// Parse the message data into a structured message
+    pub fn parse(&self) -> Result<MessageKind, Box<dyn std::error::Error>> {
+        let mut cursor = Cursor::new(&self.data[..]);
+        MessageKind::read(&mut cursor, self.determine_direction_enum())
+    }
+
+    /// Parse the message data, also returning how many bytes were left unread
+    pub fn parse_with_remainder(&self) -> Result<(MessageKind, usize), Box<dyn std::error::Error>> {
+        let mut cursor = Cursor::new(&self.data[..]);
+        let message = MessageKind::read(&mut cursor, self.determine_direction_enum())?;
+        let remainder = self.data.len().saturating_sub(cursor.position() as usize);
+        Ok((message, remainder))
+    }
+
     /// Get the opcode as hex string
     pub fn opcode_hex(&self) -> String {
         format!("0x{:04X}", self.opcode)
@@ -3,6 +3,7 @@ use encoding_rs::WINDOWS_1252;
 use std::cmp::Eq;
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt::Debug;
 use std::hash::Hash;
 
 pub mod alignment;
@@ -10,6 +11,45 @@ pub mod traits;
 pub use alignment::{align, align_dword, align_qword, align_word};
 pub use traits::{ACDataType, ACReader};
 
+/// Enter a "field" span covering the next read, like the ones generated readers
+/// emit, so container counts and elements show up in message layouts
+///
+/// With an index, the span marks one element of a container.
+macro_rules! field_span {
+    ($reader:expr, $name:expr) => {
+        #[cfg(feature = "tracing")]
+        let _field_span = {
+            let pos = std::io::Seek::stream_position($reader).unwrap_or(0);
+            tracing::span!(tracing::Level::TRACE, "field", name = $name, position = pos).entered()
+        };
+    };
+    ($reader:expr, $name:expr, $index:expr) => {
+        #[cfg(feature = "tracing")]
+        let _field_span = {
+            let pos = std::io::Seek::stream_position($reader).unwrap_or(0);
+            tracing::span!(
+                tracing::Level::TRACE,
+                "field",
+                name = $name,
+                index = $index as u64,
+                position = pos
+            )
+            .entered()
+        };
+        #[cfg(not(feature = "tracing"))]
+        let _ = $index;
+    };
+}
+
+/// Record a hash table entry's key on the current field span, so tools
+/// reading the spans can name the entry
+macro_rules! key_event {
+    ($key:expr) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(key = ?$key);
+    };
+}
+
 /// Read an item of type T from the reader
 pub fn read_item<T: ACDataType>(reader: &mut dyn ACReader) -> Result<T, Box<dyn Error>> {
     T::read(reader)
@@ -326,9 +366,13 @@ pub fn read_list_with<T>(
     reader: &mut dyn ACReader,
     mut read_element: impl FnMut(&mut dyn ACReader) -> Result<T, Box<dyn Error>>,
 ) -> Result<Vec<T>, Box<dyn Error>> {
-    let count = read_u32(reader)? as usize;
+    let count = {
+        field_span!(reader, "Count");
+        read_u32(reader)? as usize
+    };
     let mut list = Vec::with_capacity(count);
-    for _ in 0..count {
+    for i in 0..count {
+        field_span!(reader, "", i);
         list.push(read_element(reader)?);
     }
     Ok(list)
@@ -337,9 +381,13 @@ pub fn read_list_with<T>(
 /// Read a `List<T>` where T implements ACDataType
 /// Format: u32 count followed by count items
 pub fn read_list<T: ACDataType>(reader: &mut dyn ACReader) -> Result<Vec<T>, Box<dyn Error>> {
-    let count = read_u32(reader)? as usize;
+    let count = {
+        field_span!(reader, "Count");
+        read_u32(reader)? as usize
+    };
     let mut list = Vec::with_capacity(count);
-    for _ in 0..count {
+    for i in 0..count {
+        field_span!(reader, "", i);
         list.push(read_item::<T>(reader)?);
     }
     Ok(list)
@@ -353,7 +401,8 @@ pub fn read_vec_with<T>(
     mut read_element: impl FnMut(&mut dyn ACReader) -> Result<T, Box<dyn Error>>,
 ) -> Result<Vec<T>, Box<dyn Error>> {
     let mut vec = Vec::with_capacity(count);
-    for _ in 0..count {
+    for i in 0..count {
+        field_span!(reader, "", i);
         vec.push(read_element(reader)?);
     }
     Ok(vec)
@@ -365,7 +414,8 @@ pub fn read_vec<T: ACDataType>(
     count: usize,
 ) -> Result<Vec<T>, Box<dyn Error>> {
     let mut vec = Vec::with_capacity(count);
-    for _ in 0..count {
+    for i in 0..count {
+        field_span!(reader, "", i);
         vec.push(read_item::<T>(reader)?);
     }
     Ok(vec)
@@ -378,10 +428,14 @@ pub fn read_packable_list_with<T>(
     mut read_element: impl FnMut(&mut dyn ACReader) -> Result<T, Box<dyn Error>>,
 ) -> Result<PackableList<T>, Box<dyn Error>> {
     let mut count_buf = [0u8; 4];
-    reader.read_exact(&mut count_buf)?;
+    {
+        field_span!(reader, "Count");
+        reader.read_exact(&mut count_buf)?;
+    }
     let count = u32::from_le_bytes(count_buf) as usize;
     let mut list = Vec::with_capacity(count);
-    for _ in 0..count {
+    for i in 0..count {
+        field_span!(reader, "List", i);
         list.push(read_element(reader)?);
     }
     Ok(PackableList {
@@ -395,9 +449,13 @@ pub fn read_packable_list_with<T>(
 pub fn read_packable_list<T: ACDataType>(
     reader: &mut dyn ACReader,
 ) -> Result<PackableList<T>, Box<dyn Error>> {
-    let count = read_u32(reader)? as usize;
+    let count = {
+        field_span!(reader, "Count");
+        read_u32(reader)? as usize
+    };
     let mut list = Vec::with_capacity(count);
-    for _ in 0..count {
+    for i in 0..count {
+        field_span!(reader, "List", i);
         list.push(read_item::<T>(reader)?);
     }
     Ok(PackableList {
@@ -415,20 +473,34 @@ pub fn read_packable_hash_table_with<K, V>(
     mut read_value: impl FnMut(&mut dyn ACReader) -> Result<V, Box<dyn Error>>,
 ) -> Result<PackableHashTable<K, V>, Box<dyn Error>>
 where
-    K: Eq + Hash,
+    K: Eq + Hash + Debug,
 {
     let mut count_buf = [0u8; 2];
-    reader.read_exact(&mut count_buf)?;
+    {
+        field_span!(reader, "Count");
+        reader.read_exact(&mut count_buf)?;
+    }
     let count = i16::from_le_bytes(count_buf) as usize;
 
     let mut max_size_buf = [0u8; 2];
-    reader.read_exact(&mut max_size_buf)?;
+    {
+        field_span!(reader, "MaxSize");
+        reader.read_exact(&mut max_size_buf)?;
+    }
     let max_size = i16::from_le_bytes(max_size_buf) as u16;
 
     let mut table = HashMap::with_capacity(count);
-    for _ in 0..count {
-        let key = read_key(reader)?;
-        let value = read_value(reader)?;
+    for i in 0..count {
+        field_span!(reader, "Table", i);
+        let key = {
+            field_span!(reader, "Key");
+            read_key(reader)?
+        };
+        key_event!(key);
+        let value = {
+            field_span!(reader, "Value");
+            read_value(reader)?
+        };
         table.insert(key, value);
     }
     Ok(PackableHashTable {
@@ -441,21 +513,35 @@ where
 /// Read a PackableHashTable<K, V> where K and V implement ACDataType
 /// Format: i16 count, i16 max_size, followed by count key-value pairs
 /// Note: Matches C# behavior which reads as int16 and ignores max_size validation
-pub fn read_packable_hash_table<K: ACDataType + Eq + Hash, V: ACDataType>(
+pub fn read_packable_hash_table<K: ACDataType + Eq + Hash + Debug, V: ACDataType>(
     reader: &mut dyn ACReader,
 ) -> Result<PackableHashTable<K, V>, Box<dyn Error>> {
     let mut count_buf = [0u8; 2];
-    reader.read_exact(&mut count_buf)?;
+    {
+        field_span!(reader, "Count");
+        reader.read_exact(&mut count_buf)?;
+    }
     let count = i16::from_le_bytes(count_buf) as usize;
 
     let mut max_size_buf = [0u8; 2];
-    reader.read_exact(&mut max_size_buf)?;
+    {
+        field_span!(reader, "MaxSize");
+        reader.read_exact(&mut max_size_buf)?;
+    }
     let max_size = i16::from_le_bytes(max_size_buf) as u16;
 
     let mut table = HashMap::with_capacity(count);
-    for _ in 0..count {
-        let key = read_item::<K>(reader)?;
-        let value = read_item::<V>(reader)?;
+    for i in 0..count {
+        field_span!(reader, "Table", i);
+        let key = {
+            field_span!(reader, "Key");
+            read_item::<K>(reader)?
+        };
+        key_event!(key);
+        let value = {
+            field_span!(reader, "Value");
+            read_item::<V>(reader)?
+        };
         table.insert(key, value);
     }
     Ok(PackableHashTable {
@@ -473,10 +559,13 @@ pub fn read_phash_table_with<K, V>(
     mut read_value: impl FnMut(&mut dyn ACReader) -> Result<V, Box<dyn Error>>,
 ) -> Result<PHashTable<K, V>, Box<dyn Error>>
 where
-    K: Eq + Hash,
+    K: Eq + Hash + Debug,
 {
     let mut packed_size_buf = [0u8; 4];
-    reader.read_exact(&mut packed_size_buf)?;
+    {
+        field_span!(reader, "PackedSize");
+        reader.read_exact(&mut packed_size_buf)?;
+    }
     let packed_size = u32::from_le_bytes(packed_size_buf);
     let count = (packed_size & 0xFFFFFF) as usize;
 
@@ -485,9 +574,17 @@ where
     let _upper_byte = (packed_size >> 24) & 0xFF;
 
     let mut table = HashMap::with_capacity(count);
-    for _ in 0..count {
-        let key = read_key(reader)?;
-        let value = read_value(reader)?;
+    for i in 0..count {
+        field_span!(reader, "Table", i);
+        let key = {
+            field_span!(reader, "Key");
+            read_key(reader)?
+        };
+        key_event!(key);
+        let value = {
+            field_span!(reader, "Value");
+            read_value(reader)?
+        };
         table.insert(key, value);
     }
     Ok(PHashTable { packed_size, table })
@@ -495,10 +592,13 @@ where
 
 /// Read a PHashTable<K, V> where K and V implement ACDataType
 /// Format: u32 packed_size (with count in lower 24 bits), followed by count key-value pairs
-pub fn read_phash_table<K: ACDataType + Eq + Hash, V: ACDataType>(
+pub fn read_phash_table<K: ACDataType + Eq + Hash + Debug, V: ACDataType>(
     reader: &mut dyn ACReader,
 ) -> Result<PHashTable<K, V>, Box<dyn Error>> {
-    let packed_size = read_u32(reader)?;
+    let packed_size = {
+        field_span!(reader, "PackedSize");
+        read_u32(reader)?
+    };
     let count = (packed_size & 0xFFFFFF) as usize;
 
     // C# reference implementation does not validate upper byte
@@ -506,9 +606,17 @@ pub fn read_phash_table<K: ACDataType + Eq + Hash, V: ACDataType>(
     let _upper_byte = (packed_size >> 24) & 0xFF;
 
     let mut table = HashMap::with_capacity(count);
-    for _ in 0..count {
-        let key = read_item::<K>(reader)?;
-        let value = read_item::<V>(reader)?;
+    for i in 0..count {
+        field_span!(reader, "Table", i);
+        let key = {
+            field_span!(reader, "Key");
+            read_item::<K>(reader)?
+        };
+        key_event!(key);
+        let value = {
+            field_span!(reader, "Value");
+            read_item::<V>(reader)?
+        };
         table.insert(key, value);
     }
     Ok(PHashTable { packed_size, table })
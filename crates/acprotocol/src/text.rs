@@ -0,0 +1,260 @@
+//! User-facing text for error and status codes
+//!
+//! The server reports most failures as `WeenieError` or
+//! `WeenieErrorWithString` codes and leaves the wording to the client's string
+//! table. This module renders those codes, along with the character and login
+//! errors, into the sentences a player would see.
+//!
+//! Only codes whose client wording is known have text here. Any other code is
+//! shown by its enum name, with the argument of a `WeenieErrorWithString`
+//! after it, rather than a guess at what the client would say.
+
+use crate::enums::{CharacterErrorType, WeenieError, WeenieErrorWithString};
+use crate::message::{GameEventMessage, MessageKind, S2CMessage};
+
+/// Text shown for a `WeenieError`, or its enum name when the wording isn't known
+pub fn weenie_error_text(error: &WeenieError) -> String {
+    let text = match error {
+        WeenieError::YoureTooBusy => "You're too busy!",
+        WeenieError::YouAreTooFatiguedToAttack => "You're too fatigued to attack!",
+        WeenieError::YouAreOutOfAmmunition => "You are out of ammunition!",
+        WeenieError::YourAttackMisfired => "Your missile attack misfired!",
+        WeenieError::YouDontKnowThatSpell => "You don't know that spell!",
+        WeenieError::IncorrectTargetType => "Incorrect target type",
+        WeenieError::YouDontHaveAllTheComponents => {
+            "You don't have all the components for this spell."
+        }
+        WeenieError::YouDontHaveEnoughManaToCast => {
+            "You don't have enough Mana to cast this spell."
+        }
+        WeenieError::YourSpellFizzled => "Your spell fizzled.",
+        WeenieError::YourSpellTargetIsMissing => "Your spell's target is missing!",
+        WeenieError::YourProjectileSpellMislaunched => "Your projectile spell mislaunched!",
+        WeenieError::MagicTargetOutOfRange => "Target is out of range!",
+        WeenieError::YourSpellCannotBeCastOutside => "Your spell cannot be cast outside",
+        WeenieError::YourSpellCannotBeCastInside => "Your spell cannot be cast inside",
+        WeenieError::YouAreUnpreparedToCastASpell => "You are unprepared to cast a spell",
+        WeenieError::YouveAlreadySwornAllegiance => "You've already sworn your Allegiance",
+        WeenieError::YouAreNotInAllegiance => "You are not in an allegiance!",
+        _ => return error.to_string(),
+    };
+    text.to_string()
+}
+
+/// Template for a `WeenieErrorWithString`, with `%s` where the argument goes
+pub fn weenie_error_with_string_template(error: &WeenieErrorWithString) -> Option<&'static str> {
+    let template = match error {
+        WeenieErrorWithString::IsTooBusyToAcceptGifts => {
+            "%s is too busy to accept gifts right now."
+        }
+        WeenieErrorWithString::CannotCarryAnymore => "%s cannot carry anymore.",
+        WeenieErrorWithString::YouFailToAffectYouCannotAffectAnyone => {
+            "You fail to affect %s because you cannot affect anyone!"
+        }
+        WeenieErrorWithString::YouFailToAffectTheyCannotBeHarmed => {
+            "You fail to affect %s because they cannot be harmed!"
+        }
+        WeenieErrorWithString::YouFailToAffectWithBeneficialSpells => {
+            "You fail to affect %s with beneficial spells because you are not in the same PK state!"
+        }
+        WeenieErrorWithString::YouFailToAffectYouAreNotPK => {
+            "You fail to affect %s because you are not a player killer!"
+        }
+        WeenieErrorWithString::YouFailToAffectTheyAreNotPK => {
+            "You fail to affect %s because they are not a player killer!"
+        }
+        WeenieErrorWithString::YouFailToAffectNotSamePKType => {
+            "You fail to affect %s because you are not the same sort of player killer as them!"
+        }
+        WeenieErrorWithString::YouFailToAffectAcrossHouseBoundary => {
+            "You fail to affect %s because you are acting across a house boundary!"
+        }
+        _ => return None,
+    };
+    Some(template)
+}
+
+/// Text shown for a `WeenieErrorWithString` with its argument filled in
+///
+/// Codes without known wording are shown as their enum name followed by the argument.
+pub fn weenie_error_with_string_text(error: &WeenieErrorWithString, text: &str) -> String {
+    match weenie_error_with_string_template(error) {
+        Some(template) => substitute(template, &[text]),
+        None => format!("{error} ({text})"),
+    }
+}
+
+/// Text shown when the server refuses a login or character action
+pub fn character_error_text(error: &CharacterErrorType) -> String {
+    let text = match error {
+        CharacterErrorType::Logon => "Cannot have two accounts logged on at the same time.",
+        CharacterErrorType::AccountLogin => {
+            "Server could not access your account information. Please try again in a few minutes."
+        }
+        CharacterErrorType::ServerCrash => {
+            "The server has disconnected. Please try again in a few minutes."
+        }
+        CharacterErrorType::Logoff => "Server could not log off your character.",
+        CharacterErrorType::Delete => "Server could not delete your character.",
+        CharacterErrorType::ServerCrash2 => {
+            "The server has disconnected. Please try again in a few minutes."
+        }
+        CharacterErrorType::AccountInvalid => "The server does not recognize your account.",
+        CharacterErrorType::AccountDoesntExist => "Your account does not exist on this server.",
+        CharacterErrorType::EnterGameGeneric => "Cannot log on to the game with this character.",
+        CharacterErrorType::EnterGameStressAccount => {
+            "You cannot log on with a stress test account."
+        }
+        CharacterErrorType::EnterGameCharacterInWorld => "This character is already in the world.",
+        CharacterErrorType::EnterGamePlayerAccountMissing => {
+            "The server could not find your account information."
+        }
+        CharacterErrorType::EnterGameCharacterNotOwned => "You do not own this character.",
+        CharacterErrorType::EnterGameCharacterInWorldServer => {
+            "This character is already in the world on this server."
+        }
+        CharacterErrorType::EnterGameOldCharacter => "This character is too old to enter the game.",
+        CharacterErrorType::EnterGameCorruptCharacter => "This character's data is corrupt.",
+        CharacterErrorType::EnterGameStartServerDown => {
+            "The starting server is down. Please try again later."
+        }
+        CharacterErrorType::EnterGameCouldntPlaceCharacter => {
+            "Your character could not be placed in the world."
+        }
+        CharacterErrorType::LogonServerFull => "The server is full. Please try again later.",
+        CharacterErrorType::EnterGameCharacterLocked => "This character is locked.",
+        CharacterErrorType::SubscriptionExpired => "Your subscription has expired.",
+    };
+    text.to_string()
+}
+
+/// Replace each `%s` in a template with the next argument
+///
+/// Placeholders without an argument are left as they are.
+pub fn substitute(template: &str, args: &[&str]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut rest = template;
+    while let Some(index) = rest.find("%s") {
+        out.push_str(&rest[..index]);
+        match args.next() {
+            Some(arg) => out.push_str(arg),
+            None => out.push_str("%s"),
+        }
+        rest = &rest[index + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The text a client would display for a message, if it carries an error or status code
+pub fn display_text(message: &MessageKind) -> Option<String> {
+    let MessageKind::S2C(msg) = message else {
+        return None;
+    };
+
+    match msg.as_ref() {
+        S2CMessage::OrderedGameEvent { event, .. } => match event.as_ref() {
+            GameEventMessage::CommunicationWeenieError(msg) => Some(weenie_error_text(&msg.type_)),
+            GameEventMessage::CommunicationWeenieErrorWithString(msg) => {
+                Some(weenie_error_with_string_text(&msg.type_, &msg.text))
+            }
+            _ => None,
+        },
+        S2CMessage::CharacterCharacterError(msg) => Some(character_error_text(&msg.reason)),
+        S2CMessage::CharacterServerSaysAttemptFailed(msg) => Some(weenie_error_text(&msg.reason)),
+        S2CMessage::LoginAccountBooted(msg) => {
+            let text = [&msg.reason_text, &msg.additional_reason_text]
+                .iter()
+                .map(|s| s.trim())
+                .filter(|s| !s.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            Some(text)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameevents::CommunicationWeenieErrorWithString;
+
+    #[test]
+    fn test_known_and_unknown_errors() {
+        assert_eq!(
+            weenie_error_text(&WeenieError::YourSpellFizzled),
+            "Your spell fizzled."
+        );
+        assert_eq!(
+            weenie_error_text(&WeenieError::CantSwearAllegianceInsufficientXp),
+            "CantSwearAllegianceInsufficientXp"
+        );
+    }
+
+    #[test]
+    fn test_with_string_templates() {
+        assert_eq!(
+            weenie_error_with_string_template(&WeenieErrorWithString::CannotCarryAnymore),
+            Some("%s cannot carry anymore.")
+        );
+        assert_eq!(
+            weenie_error_with_string_template(
+                &WeenieErrorWithString::YouMustBeAboveLevelToBuyHouse
+            ),
+            None
+        );
+        assert_eq!(
+            weenie_error_with_string_text(&WeenieErrorWithString::CannotCarryAnymore, "Bob"),
+            "Bob cannot carry anymore."
+        );
+        assert_eq!(
+            weenie_error_with_string_text(
+                &WeenieErrorWithString::YouMustBeAboveLevelToBuyHouse,
+                "20"
+            ),
+            "YouMustBeAboveLevel_ToBuyHouse (20)"
+        );
+        assert_eq!(substitute("%s gave %s", &["Bob"]), "Bob gave %s");
+    }
+
+    #[test]
+    fn test_display_text_for_messages() {
+        let message = MessageKind::S2C(Box::new(S2CMessage::OrderedGameEvent {
+            object_id: 0x50000001,
+            sequence: 0,
+            event: Box::new(GameEventMessage::CommunicationWeenieErrorWithString(
+                CommunicationWeenieErrorWithString {
+                    type_: WeenieErrorWithString::IsTooBusyToAcceptGifts,
+                    text: "Town Crier".to_string(),
+                },
+            )),
+        }));
+        assert_eq!(
+            display_text(&message).as_deref(),
+            Some("Town Crier is too busy to accept gifts right now.")
+        );
+
+        let refused = MessageKind::S2C(Box::new(S2CMessage::CharacterCharacterError(
+            crate::messages::s2c::CharacterCharacterError {
+                reason: CharacterErrorType::LogonServerFull,
+            },
+        )));
+        assert_eq!(
+            display_text(&refused).as_deref(),
+            Some("The server is full. Please try again later.")
+        );
+
+        let booted = MessageKind::S2C(Box::new(S2CMessage::LoginAccountBooted(
+            crate::messages::s2c::LoginAccountBooted {
+                additional_reason_text: "".to_string(),
+                reason_text: " You have been booted. ".to_string(),
+            },
+        )));
+        assert_eq!(
+            display_text(&booted).as_deref(),
+            Some("You have been booted.")
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use acprotocol::network::{FragmentAssembler, RawMessage, pcap};
+use std::path::Path;
+
+fn parse(path: &Path) -> Vec<RawMessage> {
+    let mut assembler = FragmentAssembler::new();
+    let mut messages = Vec::new();
+    for packet in pcap::open(path).expect("Failed to open pcap file") {
+        let packet = packet.expect("Failed to read packet");
+        messages.extend(
+            assembler
+                .parse_packet(&packet)
+                .expect("Failed to parse packet"),
+        );
+    }
+    messages
+}
+
+/// Test that captures parsed at the same time, as `pcap corpus` does, each
+/// reassemble exactly the messages a serial parse does
+#[test]
+fn test_parallel_parses_match_serial() {
+    // Path is relative to the workspace root
+    let pcap_path = Path::new("../../data/pcaps/pkt_2025-11-18_1763490291_log.pcap");
+
+    if !pcap_path.exists() {
+        eprintln!(
+            "Warning: Test pcap file not found at {:?}, skipping test",
+            pcap_path
+        );
+        return;
+    }
+
+    let serial = parse(pcap_path);
+    assert!(!serial.is_empty());
+
+    let parallel: Vec<Vec<RawMessage>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..8).map(|_| scope.spawn(|| parse(pcap_path))).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    for messages in parallel {
+        assert_eq!(messages.len(), serial.len());
+        for (a, b) in messages.iter().zip(&serial) {
+            assert_eq!(a.data, b.data);
+        }
+    }
+}
@@ -16,7 +16,7 @@ use crate::{
     field_gen::get_allow_unused_directive,
     generation::context::ReaderContext,
     identifiers::{IdentifierType, safe_identifier},
-    type_utils::get_rust_type,
+    type_utils::{get_rust_type, subfield_expression},
     types::{Field, IfBranch},
 };
 
@@ -139,8 +139,9 @@ pub fn generate_field_group_reads(
                     let subfield_rust_type = get_rust_type(&subfield.field_type);
                     out.push_str(allow_directive);
                     out.push_str(&format!(
-                        "        let {} = ({}) as {};\n",
-                        subfield_name, subfield_expr, subfield_rust_type
+                        "        let {} = {};\n",
+                        subfield_name,
+                        subfield_expression(&subfield_expr, subfield_rust_type)
                     ));
                 }
             }
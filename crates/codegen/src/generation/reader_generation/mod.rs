@@ -124,7 +124,7 @@ pub fn generate_reader_impl(
         let (impl_code, acdatatype_code) = match safe_type_name.name.as_str() {
             "PackedDWORD" => {
                 let impl_code = format!(
-                    "impl {} {{\n    pub fn read(reader: &mut dyn ACReader) -> Result<Self, Box<dyn std::error::Error>> {{\n        crate::readers::read_packed_dword(reader)?;\n        Ok(Self {{}})\n    }}\n}}\n\n",
+                    "impl {} {{\n    pub fn read(reader: &mut dyn ACReader) -> Result<Self, Box<dyn std::error::Error>> {{\n        Ok(Self(crate::readers::read_packed_dword(reader)?))\n    }}\n}}\n\n",
                     safe_type_name.name
                 );
                 let acdatatype_code = format!(
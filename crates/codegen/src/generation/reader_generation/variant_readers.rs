@@ -3,7 +3,7 @@ use std::collections::BTreeMap;
 use crate::{
     field_gen::get_allow_unused_directive,
     identifiers::{IdentifierType, safe_identifier},
-    type_utils::get_rust_type,
+    type_utils::{get_rust_type, subfield_expression},
     types::{Field, FieldSet, NestedSwitch},
     util::format_hex_value,
 };
@@ -115,8 +115,9 @@ fn generate_enum_reader_impl(
             let subfield_rust_type = get_rust_type(&subfield.field_type);
             out.push_str(allow_directive);
             out.push_str(&format!(
-                "        let {} = ({}) as {};\n",
-                subfield_name, subfield_expr, subfield_rust_type
+                "        let {} = {};\n",
+                subfield_name,
+                subfield_expression(&subfield_expr, subfield_rust_type)
             ));
         }
     }
@@ -97,6 +97,16 @@ pub fn generate_type(protocol_type: &ProtocolType) -> String {
     }
 
     let Some(field_set) = &protocol_type.fields else {
+        // PackedDWORD has no fields in the XML but carries the u32 it encodes
+        if type_name == "PackedDWORD" {
+            out.push_str(
+                "#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]\n",
+            );
+            out.push_str("#[serde(transparent)]\n");
+            out.push_str("pub struct PackedDWORD(pub u32);\n\n");
+            return out;
+        }
+
         // No fields, generate empty struct
         let derives = build_derive_string(&protocol_type.extra_derives);
 
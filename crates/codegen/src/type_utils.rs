@@ -64,6 +64,36 @@ pub fn get_rust_type(xml_type: &str) -> &str {
     }
 }
 
+/// Enums that protocol.xml lists only some values of, with the type they are
+/// sent as. Fields of these types hold the raw value, so an unlisted value
+/// (such as a `ContractStage` above 3) doesn't fail the whole message.
+pub const OPEN_ENUMS: &[(&str, &str)] = &[("ContractId", "uint"), ("ContractStage", "uint")];
+
+/// The type a field is stored as: its declared type, or the underlying type of
+/// an open enum
+pub fn field_storage_type(xml_type: &str) -> &str {
+    OPEN_ENUMS
+        .iter()
+        .find(|(name, _)| *name == xml_type)
+        .map_or(xml_type, |(_, parent)| parent)
+}
+
+/// Build the expression that computes a subfield from the field it is packed into.
+///
+/// Signed subfields taken from the top bits of an unsigned field are shifted as
+/// signed values so they sign-extend the way the client reads them (e.g.
+/// `ItemProfile`'s `PwdType` is -1 when the top byte is 0xFF).
+pub fn subfield_expression(value_expression: &str, rust_type: &str) -> String {
+    if matches!(rust_type, "i8" | "i16" | "i32" | "i64")
+        && let Some((operand, shift)) = value_expression.split_once(" >> ")
+        && !operand.trim().contains(' ')
+    {
+        return format!("({} as {}) >> {}", operand.trim(), rust_type, shift.trim());
+    }
+
+    format!("({}) as {}", value_expression, rust_type)
+}
+
 /// Split a comma-separated list of types, handling nested generics
 /// For example: "string, PackableList<byte>" -> ["string", "PackableList<byte>"]
 fn split_generic_params(params: &str) -> Vec<&str> {
@@ -185,6 +215,29 @@ mod tests {
         assert_eq!(convert_xml_type_to_rust("string"), "String");
     }
 
+    #[test]
+    fn test_open_enum_fields_keep_raw_value() {
+        assert_eq!(field_storage_type("ContractStage"), "uint");
+        assert_eq!(field_storage_type("ContractId"), "uint");
+        assert_eq!(field_storage_type("SpellCategory"), "SpellCategory");
+    }
+
+    #[test]
+    fn test_subfield_expression_unsigned() {
+        assert_eq!(
+            subfield_expression("packed_amount & 0xFFFFFF", "u32"),
+            "(packed_amount & 0xFFFFFF) as u32"
+        );
+    }
+
+    #[test]
+    fn test_subfield_expression_signed_shift_sign_extends() {
+        assert_eq!(
+            subfield_expression("packed_amount >> 24", "i32"),
+            "(packed_amount as i32) >> 24"
+        );
+    }
+
     #[test]
     fn test_convert_custom_types() {
         // Custom types should remain unchanged
@@ -22,6 +22,8 @@ pub fn create_field_from_tag(
     debug!("Processing field {field_name:?}");
 
     if let (Some(fname), Some(mut ftype)) = (field_name, field_type) {
+        ftype = crate::type_utils::field_storage_type(&ftype).to_string();
+
         // Handle generic types
         if let (Some(key), Some(value)) = (generic_key, generic_value) {
             // PackableHashTable<K, V>